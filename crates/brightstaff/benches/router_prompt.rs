@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use brightstaff::router::router_model::RouterModel;
+use brightstaff::router::router_model_v1::{RouterModelV1, RouterSampling, MAX_TOKEN_LEN};
+use common::configuration::RoutingPreference;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hermesllm::providers::openai::types::{ContentType, Message};
+
+fn message(role: &str, content: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: Some(ContentType::Text(content.to_string())),
+        name: None,
+        tool_calls: None,
+    }
+}
+
+fn router_model() -> RouterModelV1 {
+    let route = |name: &str, description: &str| RoutingPreference {
+        name: name.to_string(),
+        description: description.to_string(),
+    };
+    let llm_routes = HashMap::from([
+        (
+            "gpt-4o".to_string(),
+            vec![
+                route(
+                    "code generation",
+                    "generating new code snippets and functions",
+                ),
+                route("code review", "reviewing and explaining existing code"),
+            ],
+        ),
+        (
+            "claude-sonnet".to_string(),
+            vec![route(
+                "creative writing",
+                "stories, poems and marketing copy",
+            )],
+        ),
+    ]);
+    RouterModelV1::new(
+        llm_routes,
+        "Arch-Router".to_string(),
+        MAX_TOKEN_LEN,
+        RouterSampling::default(),
+    )
+}
+
+/// A few turns of a typical conversation, with a system prompt the router skips
+fn conversation(turns: usize) -> Vec<Message> {
+    let mut messages = vec![message("system", "You are a helpful assistant.")];
+    for _ in 0..turns {
+        messages.push(message(
+            "user",
+            "Can you write a function that parses a CSV file and sums up the second column?",
+        ));
+        messages.push(message(
+            "assistant",
+            "Sure, here is a function that reads the file line by line, splits on commas and adds up the values.",
+        ));
+    }
+    messages.push(message("user", "Now make it skip the header row."));
+    messages
+}
+
+fn bench_generate_request(c: &mut Criterion) {
+    let router_model = router_model();
+    let mut group = c.benchmark_group("router_generate_request");
+
+    for turns in [1, 8] {
+        let messages = conversation(turns);
+        group.bench_function(format!("{}_turns", turns), |b| {
+            b.iter(|| {
+                router_model
+                    .generate_request(black_box(&messages), &[], None, &None)
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_generate_request);
+criterion_main!(benches);
@@ -1,11 +1,19 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use bytes::Bytes;
-use common::configuration::ModelUsagePreference;
-use common::consts::ARCH_PROVIDER_HINT_HEADER;
-use hermesllm::providers::openai::types::ChatCompletionsRequest;
+use common::configuration::{ModelUsagePreference, StreamingFallback};
+use common::consts::{
+    ARCH_DRY_RUN_HEADER, ARCH_FORCE_PROVIDER_HEADER, ARCH_PROVIDER_HINT_HEADER,
+    ARCH_ROUTE_METADATA_HEADER, ARCH_TRUNCATED_HEADER,
+};
+use common::utils::{shorten_string, truncate_with_ellipsis};
+use hermesllm::apis::OpenAIApi;
+use hermesllm::clients::streaming::synthesize_sse;
+use hermesllm::clients::{identify_api, validate_image_url, DetectedApi};
+use hermesllm::providers::openai::types::{request_features, ChatCompletionsRequest, ContentType};
 use http_body_util::combinators::BoxBody;
-use http_body_util::{BodyExt, Full, StreamBody};
+use http_body_util::{BodyExt, StreamBody};
 use hyper::body::Frame;
 use hyper::header::{self};
 use hyper::{Request, Response, StatusCode};
@@ -14,38 +22,60 @@ use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tracing::{debug, info, warn};
 
-use crate::router::llm_router::RouterService;
+use crate::handlers::HandlerSettings;
+use crate::router::llm_router::{RouterService, RoutingError};
+use crate::utils::body::full;
+use crate::utils::json::JsonFormat;
+use crate::utils::metrics::{REQUEST_FEATURES, ROUTING_DECISIONS};
+use crate::utils::response::is_truncated;
+use crate::utils::retry::DEFAULT_RETRY_AFTER_SECS;
+use crate::utils::streaming::{
+    disable_streaming, enforce_stream_usage, forward_stream, route_metadata_chunk, tap_stream,
+    TruncationTap,
+};
 
-fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
-    Full::new(chunk.into())
-        .map_err(|never| match never {})
-        .boxed()
-}
+/// `metadata` key carrying per-request routing preferences
+const PREFERENCE_CONFIG_KEY: &str = "archgw_preference_config";
 
-pub async fn chat_completions(
-    request: Request<hyper::body::Incoming>,
+pub async fn chat_completions<B>(
+    request: Request<B>,
     router_service: Arc<RouterService>,
     llm_provider_endpoint: String,
-) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    settings: HandlerSettings,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error>
+where
+    B: hyper::body::Body<Data = Bytes, Error = hyper::Error>,
+{
+    let HandlerSettings {
+        retry_budget,
+        streaming_fallback,
+        json_format,
+        response_rewrites,
+        image_hosts,
+    } = settings;
     let request_path = request.uri().path().to_string();
     let mut request_headers = request.headers().clone();
 
     let chat_request_bytes = request.collect().await?.to_bytes();
+    let log_redaction = router_service.log_redaction();
 
-    debug!("Received request body (raw utf8): {}", String::from_utf8_lossy(&chat_request_bytes));
+    debug!(
+        "Received request body (raw utf8): {}",
+        shorten_string(&log_redaction.body(&chat_request_bytes))
+    );
 
     let chat_request_parsed = serde_json::from_slice::<serde_json::Value>(&chat_request_bytes)
         .inspect_err(|err| {
             warn!(
                 "Failed to parse request body as JSON: err: {}, str: {}",
                 err,
-                String::from_utf8_lossy(&chat_request_bytes)
+                log_redaction.body(&chat_request_bytes)
             )
         })
         .unwrap_or_else(|_| {
             warn!(
                 "Failed to parse request body as JSON: {}",
-                String::from_utf8_lossy(&chat_request_bytes)
+                log_redaction.body(&chat_request_bytes)
             );
             serde_json::Value::Null
         });
@@ -59,29 +89,48 @@ pub async fn chat_completions(
     }
 
     let chat_completion_request: ChatCompletionsRequest =
-        serde_json::from_value(chat_request_parsed.clone()).unwrap();
+        match serde_json::from_value(chat_request_parsed.clone()) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!(
+                    "Request body is not a valid chat completions request: {}",
+                    err
+                );
+                let err_msg = format!("Invalid chat completions request: {}", err);
+                let mut bad_request = Response::new(full(err_msg));
+                *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(bad_request);
+            }
+        };
 
-    // remove metadata from the request
-    let mut chat_request_user_preferences_removed = chat_request_parsed;
-    if let Some(metadata) = chat_request_user_preferences_removed.get_mut("metadata") {
-        debug!("Removing metadata from request");
-        if let Some(m) = metadata.as_object_mut() {
-            m.remove("archgw_preference_config");
-            debug!("Removed archgw_preference_config from metadata");
-        }
+    if let Err(err) = chat_completion_request.validate() {
+        warn!("Rejecting chat completions request: {}", err);
+        let mut bad_request = Response::new(full(err.to_string()));
+        *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(bad_request);
+    }
 
-        // if metadata is empty, remove it
-        if metadata.as_object().map_or(false, |m| m.is_empty()) {
-            debug!("Removing empty metadata from request");
-            chat_request_user_preferences_removed
-                .as_object_mut()
-                .map(|m| m.remove("metadata"));
-        }
+    // upstreams fetch remote images themselves, keep them to the hosts they may reach
+    if let Err(err) = chat_completion_request
+        .messages
+        .iter()
+        .filter_map(|message| message.content.as_ref())
+        .flat_map(ContentType::image_urls)
+        .try_for_each(|url| validate_image_url(url, image_hosts.as_deref()))
+    {
+        warn!("Rejecting chat completions request: {}", err);
+        let mut bad_request = Response::new(full(err.to_string()));
+        *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(bad_request);
     }
+    REQUEST_FEATURES.record(&request_features(&chat_completion_request));
+
+    let mut chat_request_user_preferences_removed = chat_request_parsed;
+    strip_preference_config(&mut chat_request_user_preferences_removed);
 
     debug!(
         "arch-router request received: {}",
-        &serde_json::to_string(&chat_completion_request).unwrap()
+        shorten_string(&log_redaction.value(&chat_completion_request))
     );
 
     let trace_parent = request_headers
@@ -92,13 +141,29 @@ pub async fn chat_completions(
     let usage_preferences_str: Option<String> =
         chat_completion_request.metadata.and_then(|metadata| {
             metadata
-                .get("archgw_preference_config")
+                .get(PREFERENCE_CONFIG_KEY)
                 .and_then(|value| value.as_str().map(String::from))
         });
 
-    let usage_preferences: Option<Vec<ModelUsagePreference>> = usage_preferences_str
-        .as_ref()
-        .and_then(|s| serde_yaml::from_str(s).ok());
+    // unknown fields are ignored so clients on an older or newer layout keep working, but
+    // preferences that can't be read are rejected rather than silently ignored
+    let usage_preferences: Option<Vec<ModelUsagePreference>> = match usage_preferences_str
+        .as_deref()
+        .map(serde_yaml::from_str)
+        .transpose()
+    {
+        Ok(usage_preferences) => usage_preferences,
+        Err(err) => {
+            warn!(
+                "Invalid {} in request metadata: {}",
+                PREFERENCE_CONFIG_KEY, err
+            );
+            let err_msg = format!("Invalid {}: {}", PREFERENCE_CONFIG_KEY, err);
+            let mut bad_request = Response::new(full(err_msg));
+            *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(bad_request);
+        }
+    };
 
     let latest_message_for_log =
         chat_completion_request
@@ -106,16 +171,13 @@ pub async fn chat_completions(
             .last()
             .map_or("None".to_string(), |msg| {
                 msg.content.as_ref().map_or("None".to_string(), |content| {
-                    content.to_string().replace('\n', "\\n")
+                    log_redaction.text(&content.to_string().replace('\n', "\\n"))
                 })
             });
 
     const MAX_MESSAGE_LENGTH: usize = 50;
-    let latest_message_for_log = if latest_message_for_log.len() > MAX_MESSAGE_LENGTH {
-        format!("{}...", &latest_message_for_log[..MAX_MESSAGE_LENGTH])
-    } else {
-        latest_message_for_log
-    };
+    let latest_message_for_log =
+        truncate_with_ellipsis(&latest_message_for_log, MAX_MESSAGE_LENGTH);
 
     info!(
         "request received, request type: chat_completion, usage preferences from request: {}, request path: {}, latest message: {}",
@@ -126,24 +188,27 @@ pub async fn chat_completions(
 
     debug!("usage preferences from request: {:?}", usage_preferences);
 
-    let model_name = match router_service
-        .determine_route(
+    let decision = match router_service
+        .determine_route_for_request(
+            &request_headers,
             &chat_completion_request.messages,
+            chat_completion_request.tools.as_deref().unwrap_or_default(),
+            chat_completion_request
+                .extra
+                .get("seed")
+                .and_then(serde_json::Value::as_i64),
             trace_parent.clone(),
             usage_preferences,
         )
         .await
     {
-        Ok(route) => match route {
-            Some((_, model_name)) => model_name,
-            None => {
-                debug!(
-                    "No route determined, using default model from request: {}",
-                    chat_completion_request.model
-                );
-                chat_completion_request.model.clone()
-            }
-        },
+        Ok(decision) => decision,
+        Err(err @ RoutingError::UnknownForcedProvider(_)) => {
+            warn!("{}", err);
+            let mut bad_request = Response::new(full(err.to_string()));
+            *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(bad_request);
+        }
         Err(err) => {
             let err_msg = format!("Failed to determine route: {}", err);
             let mut internal_error = Response::new(full(err_msg));
@@ -151,12 +216,87 @@ pub async fn chat_completions(
             return Ok(internal_error);
         }
     };
+    ROUTING_DECISIONS.record(&decision);
+
+    let model_name = match &decision.model {
+        Some(model_name) => model_name.clone(),
+        None => {
+            let model_name = router_service.unrouted_provider(&chat_completion_request.model);
+            debug!(
+                "No route determined, model from request: {}, using: {}",
+                chat_completion_request.model, model_name
+            );
+            model_name
+        }
+    };
+
+    if let Err(err) = router_service.check_routing_loop(&model_name) {
+        warn!("{}", err);
+        let mut internal_error = Response::new(full(err.to_string()));
+        *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(internal_error);
+    }
+
+    let llm_provider_endpoint = router_service
+        .upstream_endpoint(&model_name, &request_path)
+        .unwrap_or(llm_provider_endpoint);
+
+    // a provider that can't stream gets a plain request; with the buffer fallback its
+    // complete response is replayed to the client as a stream
+    let client_streaming = chat_completion_request.stream.unwrap_or(false);
+    let can_stream = router_service.supports_streaming(&model_name)
+        && identify_api(&request_path).is_none_or(|api| api.supports_streaming());
+    let replay_as_stream = client_streaming && !can_stream;
+    if replay_as_stream {
+        match streaming_fallback {
+            StreamingFallback::Reject => {
+                let err_msg = format!(
+                    "provider {} does not support streaming, send the request with \"stream\": false",
+                    model_name
+                );
+                warn!("{}", err_msg);
+                let mut bad_request = Response::new(full(err_msg));
+                *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+                return Ok(bad_request);
+            }
+            StreamingFallback::Buffer => {
+                info!(
+                    "provider {} does not support streaming, buffering the response",
+                    model_name
+                );
+                disable_streaming(&mut chat_request_user_preferences_removed);
+            }
+        }
+    }
 
     debug!(
         "sending request to llm provider: {}, with model hint: {}",
         llm_provider_endpoint, model_name
     );
 
+    let header_enabled = |name: &str| {
+        request_headers
+            .get(name)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"true"))
+    };
+    let dry_run = header_enabled(ARCH_DRY_RUN_HEADER);
+    // hyper only sends trailers over HTTP/1.1 to clients that said they accept them
+    let accepts_trailers = request_headers
+        .get_all(header::TE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| coding.trim().eq_ignore_ascii_case("trailers"));
+    // opt-in, as strict stream parsers may choke on a chunk they can't attribute to the model
+    let route_metadata = header_enabled(ARCH_ROUTE_METADATA_HEADER)
+        .then(|| route_metadata_chunk(&model_name, decision.route.as_deref()));
+
+    // the force-provider, dry-run and route metadata headers are only meant for brightstaff,
+    // don't leak them upstream
+    request_headers.remove(ARCH_FORCE_PROVIDER_HEADER);
+    request_headers.remove(ARCH_DRY_RUN_HEADER);
+    request_headers.remove(ARCH_ROUTE_METADATA_HEADER);
+
     request_headers.insert(
         ARCH_PROVIDER_HINT_HEADER,
         header::HeaderValue::from_str(&model_name).unwrap(),
@@ -169,60 +309,192 @@ pub async fn chat_completions(
         );
     }
 
+    let is_streaming = client_streaming && !replay_as_stream;
+    let usage_tap = is_streaming
+        .then(|| {
+            enforce_stream_usage(
+                response_rewrites.stream_usage,
+                &mut chat_request_user_preferences_removed,
+            )
+        })
+        .flatten();
+
     let chat_request_parsed_bytes =
         serde_json::to_string(&chat_request_user_preferences_removed).unwrap();
 
     // remove content-length header if it exists
     request_headers.remove(header::CONTENT_LENGTH);
 
-    let llm_response = match reqwest::Client::new()
-        .post(llm_provider_endpoint)
-        .headers(request_headers)
-        .body(chat_request_parsed_bytes)
-        .send()
-        .await
-    {
-        Ok(res) => res,
-        Err(err) => {
-            let err_msg = format!("Failed to send request: {}", err);
-            let mut internal_error = Response::new(full(err_msg));
-            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-            return Ok(internal_error);
+    if !is_streaming {
+        // buffered responses are re-framed below, so ask upstream for an uncompressed body
+        request_headers.remove(header::ACCEPT_ENCODING);
+    }
+
+    if dry_run {
+        info!(
+            "dry run, not forwarding request to {}",
+            llm_provider_endpoint
+        );
+        return Ok(dry_run_response(
+            json_format,
+            &llm_provider_endpoint,
+            &model_name,
+            &request_headers,
+            chat_request_user_preferences_removed,
+        ));
+    }
+
+    let client = router_service.http_client();
+    let method = router_service.upstream_method(&model_name);
+    let mut attempt = 0;
+    let llm_response = loop {
+        let llm_response = match client
+            .request(method.clone(), &llm_provider_endpoint)
+            .headers(request_headers.clone())
+            .body(chat_request_parsed_bytes.clone())
+            .send()
+            .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Failed to send request: {}", err);
+                return Ok(service_unavailable(format!(
+                    "Failed to send request: {}",
+                    err
+                )));
+            }
+        };
+
+        match retry_budget.retry_delay(llm_response.status(), llm_response.headers(), attempt) {
+            Some(delay) => {
+                info!(
+                    "upstream returned {}, retrying in {:?} (attempt {} of {})",
+                    llm_response.status(),
+                    delay,
+                    attempt + 1,
+                    retry_budget.max_retries
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => break llm_response,
         }
     };
 
-    // copy over the headers from the original response
+    if !is_streaming {
+        let status = llm_response.status();
+        let mut response_headers = llm_response.headers().clone();
+        let body = match llm_response.bytes().await {
+            Ok(body) => body,
+            Err(err) => {
+                let err_msg = format!("Failed to read response body: {}", err);
+                let mut internal_error = Response::new(full(err_msg));
+                *internal_error.status_mut() = StatusCode::BAD_GATEWAY;
+                return Ok(internal_error);
+            }
+        };
+
+        debug!(
+            "Received response body (raw utf8): {}",
+            shorten_string(&log_redaction.body(&body))
+        );
+        let truncated = status.is_success() && is_truncated(&body);
+
+        let body = match status.is_success() {
+            true => response_rewrites.apply(body, &chat_completion_request.model, &model_name),
+            false => body,
+        };
+        let replayed = (replay_as_stream && status.is_success())
+            .then(|| synthesize_sse(&body, &DetectedApi::OpenAI(OpenAIApi::ChatCompletions)));
+        let body = match replayed {
+            Some(Ok(sse)) => {
+                response_headers.insert(
+                    header::CONTENT_TYPE,
+                    header::HeaderValue::from_static("text/event-stream"),
+                );
+                Bytes::from([route_metadata.unwrap_or_default().into_bytes(), sse].concat())
+            }
+            Some(Err(err)) => {
+                warn!(
+                    "can't replay the response as a stream, sending it as is: {}",
+                    err
+                );
+                json_format.format_body(body)
+            }
+            None => json_format.format_body(body),
+        };
+
+        reconcile_buffered_headers(&mut response_headers, body.len());
+        if truncated {
+            response_headers.insert(
+                ARCH_TRUNCATED_HEADER,
+                header::HeaderValue::from_static("true"),
+            );
+        }
+        let mut response = Response::new(full(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = response_headers;
+        return Ok(response);
+    }
+
+    // copy over the status and headers from the original response
+    let upstream_status = llm_response.status();
     let response_headers = llm_response.headers().clone();
-    let mut response = Response::builder();
+    let mut response = Response::builder().status(upstream_status);
     let headers = response.headers_mut().unwrap();
     for (header_name, header_value) in response_headers.iter() {
         headers.insert(header_name, header_value.clone());
     }
+    // the stream gains and loses chunks on the way, so its length isn't the upstream's
+    headers.remove(header::CONTENT_LENGTH);
+    // whether the stream was truncated is only known once it ends: it's flagged in-band right
+    // after the finishing chunk, and as a trailer for clients accepting trailers
+    let truncated = Arc::new(AtomicBool::new(false));
+    let truncation_tap = TruncationTap::new(&model_name, truncated.clone());
+    let accepts_trailers = accepts_trailers && upstream_status.is_success();
+    if accepts_trailers {
+        headers.insert(
+            header::TRAILER,
+            header::HeaderValue::from_static(ARCH_TRUNCATED_HEADER),
+        );
+    }
 
     // channel to create async stream
     let (tx, rx) = mpsc::channel::<Bytes>(16);
 
-    // Spawn a task to send data as it becomes available
+    // Spawn a task to send data as it becomes available, stopping once the client disconnects.
+    // Error bodies aren't a stream of chunks, so they are passed through without the metadata.
+    let route_metadata = route_metadata.filter(|_| upstream_status.is_success());
+    let usage_tap = usage_tap.filter(|_| upstream_status.is_success());
     tokio::spawn(async move {
-        let mut byte_stream = llm_response.bytes_stream();
-
-        while let Some(item) = byte_stream.next().await {
-            let item = match item {
-                Ok(item) => item,
-                Err(err) => {
-                    warn!("Error receiving chunk: {:?}", err);
-                    break;
-                }
-            };
-
-            if tx.send(item).await.is_err() {
-                warn!("Receiver dropped");
-                break;
+        if let Some(route_metadata) = route_metadata {
+            if tx.send(Bytes::from(route_metadata)).await.is_err() {
+                return;
             }
         }
+        let upstream = Box::pin(tap_stream(llm_response.bytes_stream(), truncation_tap));
+        match usage_tap {
+            Some(usage_tap) => forward_stream(Box::pin(tap_stream(upstream, usage_tap)), tx).await,
+            None => forward_stream(upstream, tx).await,
+        }
     });
 
-    let stream = ReceiverStream::new(rx).map(|chunk| Ok::<_, hyper::Error>(Frame::data(chunk)));
+    // evaluated once the channel closes, after the whole upstream stream went through the watch
+    let trailers = std::iter::once_with(move || {
+        (accepts_trailers && truncated.load(Ordering::Relaxed)).then(|| {
+            let mut trailers = header::HeaderMap::new();
+            trailers.insert(
+                ARCH_TRUNCATED_HEADER,
+                header::HeaderValue::from_static("true"),
+            );
+            Frame::trailers(trailers)
+        })
+    })
+    .flatten();
+    let stream = ReceiverStream::new(rx)
+        .map(Frame::data)
+        .chain(tokio_stream::iter(trailers))
+        .map(Ok::<_, hyper::Error>);
 
     let stream_body = BoxBody::new(StreamBody::new(stream));
 
@@ -236,3 +508,847 @@ pub async fn chat_completions(
         }
     }
 }
+
+/// A 503 generated by the gateway itself, telling the client when it may try again
+pub(crate) fn service_unavailable(message: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = Response::new(full(message));
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from(DEFAULT_RETRY_AFTER_SECS),
+    );
+    response
+}
+
+/// Headers whose values are credentials, masked in dry-run output
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "api-key",
+];
+
+/// Remove the routing preferences brightstaff reads from `metadata` before the request goes
+/// upstream. Everything else the client sent, including its own metadata entries and `store`,
+/// is forwarded as is; `metadata` is only dropped when the preferences were all it held.
+fn strip_preference_config(request: &mut serde_json::Value) {
+    let Some(metadata) = request
+        .get_mut("metadata")
+        .and_then(|metadata| metadata.as_object_mut())
+    else {
+        return;
+    };
+
+    if metadata.remove(PREFERENCE_CONFIG_KEY).is_none() {
+        return;
+    }
+    debug!("Removed {} from metadata", PREFERENCE_CONFIG_KEY);
+
+    if metadata.is_empty() {
+        debug!("Removing empty metadata from request");
+        if let Some(request) = request.as_object_mut() {
+            request.remove("metadata");
+        }
+    }
+}
+
+/// Describe the request that would have been sent upstream, without sending it.
+fn dry_run_response(
+    json_format: JsonFormat,
+    endpoint: &str,
+    provider: &str,
+    headers: &header::HeaderMap,
+    body: serde_json::Value,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let headers: serde_json::Map<String, serde_json::Value> = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                "[REDACTED]".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), serde_json::Value::String(value))
+        })
+        .collect();
+
+    let resolved_request = serde_json::json!({
+        "endpoint": endpoint,
+        "provider": provider,
+        "model": body.get("model"),
+        "headers": headers,
+        "body": body,
+    });
+
+    let mut response = Response::new(full(
+        json_format
+            .serialize(&resolved_request)
+            .unwrap_or_else(|_| resolved_request.to_string()),
+    ));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Fix up upstream framing headers for a body that was fully buffered before being sent on.
+///
+/// The upstream may have answered with `transfer-encoding: chunked` or a compressed body, neither
+/// of which describes the bytes we hand back to the client, so the framing headers are dropped and
+/// `content-length` is set to the length of the final body.
+pub(crate) fn reconcile_buffered_headers(headers: &mut header::HeaderMap, body_len: usize) {
+    headers.remove(header::TRANSFER_ENCODING);
+    headers.remove(header::CONTENT_ENCODING);
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(header::CONTENT_LENGTH, header::HeaderValue::from(body_len));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::llm_router::RouterClientConfig;
+    use crate::utils::response::ResponseRewrites;
+    use crate::utils::retry::RetryBudget;
+    use crate::utils::streaming::TRUNCATION_FIELD;
+    use common::configuration::{LlmProvider, StreamUsage};
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn test_reconcile_buffered_headers() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("12"));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+
+        let body = r#"{"id":"chatcmpl-123","object":"chat.completion","choices":[]}"#;
+        reconcile_buffered_headers(&mut headers, body.len());
+
+        assert!(headers.get(header::TRANSFER_ENCODING).is_none());
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(headers.get_all(header::CONTENT_LENGTH).iter().count(), 1);
+        assert_eq!(
+            headers.get(header::CONTENT_LENGTH).unwrap(),
+            &body.len().to_string()
+        );
+        assert_eq!(
+            headers.get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+    }
+
+    // nothing listens on this address, so forwarding to it fails
+    const UNREACHABLE_ENDPOINT: &str = "http://127.0.0.1:1/v1/chat/completions";
+
+    fn router_service() -> Arc<RouterService> {
+        let providers: Vec<LlmProvider> = serde_yaml::from_str(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+- name: batch-llm
+  provider_interface: openai
+  model: batch-llm
+  stream: false
+"#,
+        )
+        .unwrap();
+
+        Arc::new(
+            RouterService::new(
+                providers,
+                UNREACHABLE_ENDPOINT.to_string(),
+                "Arch-Router".to_string(),
+                "arch-router".to_string(),
+                RouterClientConfig::default(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn chat_request() -> hyper::http::request::Builder {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+    }
+
+    fn hello_body() -> String {
+        serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}]
+        })
+        .to_string()
+    }
+
+    const MOCK_COMPLETION: &str = r#"{"id":"chatcmpl-1","object":"chat.completion","created":0,"model":"batch-llm","choices":[{"index":0,"message":{"role":"assistant","content":"hi"},"finish_reason":"stop"}]}"#;
+
+    fn streaming_body() -> String {
+        serde_json::json!({
+            "model": "batch-llm",
+            "messages": [{"role": "user", "content": "hello"}],
+            "stream": true,
+            "stream_options": {"include_usage": true}
+        })
+        .to_string()
+    }
+
+    /// Serve `responses` in order on a local port, one per request, and count the requests
+    async fn mock_upstream(
+        responses: Vec<(StatusCode, Option<&'static str>)>,
+    ) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let responses = Arc::new(responses);
+
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let hits = server_hits.clone();
+                let responses = responses.clone();
+                let service = service_fn(move |_req: Request<hyper::body::Incoming>| {
+                    let hit = hits.fetch_add(1, Ordering::SeqCst);
+                    let (status, retry_after) = responses[hit.min(responses.len() - 1)];
+                    async move {
+                        let mut response = Response::new(full(MOCK_COMPLETION));
+                        *response.status_mut() = status;
+                        if let Some(retry_after) = retry_after {
+                            response
+                                .headers_mut()
+                                .insert(header::RETRY_AFTER, HeaderValue::from_static(retry_after));
+                        }
+                        Ok::<_, hyper::Error>(response)
+                    }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        (format!("http://{}/v1/chat/completions", address), hits)
+    }
+
+    /// Serve `body` with a 200 to every request on a local port
+    async fn mock_upstream_body(body: &'static str) -> String {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = service_fn(move |_req: Request<hyper::body::Incoming>| async move {
+                    Ok::<_, hyper::Error>(Response::new(full(body)))
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        format!("http://{}/v1/chat/completions", address)
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_returns_resolved_request() {
+        let body = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}],
+            "metadata": {"archgw_preference_config": "[]"}
+        });
+        let request = chat_request()
+            .header(ARCH_DRY_RUN_HEADER, "true")
+            .header(header::AUTHORIZATION, "Bearer sk-secret")
+            .body(full(body.to_string()))
+            .unwrap();
+
+        // forwarding to the unreachable endpoint would fail, dry run must not try
+        let response = chat_completions(
+            request,
+            router_service(),
+            UNREACHABLE_ENDPOINT.to_string(),
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resolved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(resolved["endpoint"], UNREACHABLE_ENDPOINT);
+        assert_eq!(resolved["provider"], "gpt-4o");
+        assert_eq!(resolved["model"], "gpt-4o");
+        assert_eq!(resolved["headers"][ARCH_PROVIDER_HINT_HEADER], "gpt-4o");
+        assert_eq!(resolved["headers"]["authorization"], "[REDACTED]");
+        assert!(resolved["headers"].get(ARCH_DRY_RUN_HEADER).is_none());
+        // archgw metadata is stripped before forwarding
+        assert!(resolved["body"].get("metadata").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_image_urls_are_checked_against_allowed_hosts() {
+        let settings = HandlerSettings {
+            image_hosts: Some(vec!["images.example.com".to_string()]),
+            ..Default::default()
+        };
+        let send = |url: &str| {
+            let body = serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": [
+                    {"type": "text", "text": "what is in this picture?"},
+                    {"type": "image_url", "image_url": {"url": url}}
+                ]}]
+            });
+            let request = chat_request()
+                .header(ARCH_DRY_RUN_HEADER, "true")
+                .body(full(body.to_string()))
+                .unwrap();
+            chat_completions(
+                request,
+                router_service(),
+                UNREACHABLE_ENDPOINT.to_string(),
+                settings.clone(),
+            )
+        };
+
+        let response = send("https://images.example.com/cat.png").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "https://10.0.0.1\\@images.example.com/cat.png",
+            "file:///etc/passwd",
+        ] {
+            let response = send(url).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preference_config_layouts() {
+        let request_with = |preference_config: &str| {
+            let body = serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "hello"}],
+                "metadata": {"archgw_preference_config": preference_config}
+            });
+            chat_request()
+                .header(ARCH_DRY_RUN_HEADER, "true")
+                .body(full(body.to_string()))
+                .unwrap()
+        };
+        let send = |request| {
+            chat_completions(
+                request,
+                router_service(),
+                UNREACHABLE_ENDPOINT.to_string(),
+                HandlerSettings::default(),
+            )
+        };
+
+        // fields this version doesn't know about are ignored
+        let unknown_field = r#"
+- model: gpt-4o
+  priority: 1
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+      examples: ["write a quicksort"]
+"#;
+        let response = send(request_with(unknown_field)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // a missing required field is pointed out
+        let missing_field = r#"
+- model: gpt-4o
+  routing_preferences:
+    - name: code generation
+"#;
+        let response = send(request_with(missing_field)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.starts_with("Invalid archgw_preference_config: "),
+            "{}",
+            body
+        );
+        assert!(body.contains("[0].routing_preferences[0]"), "{}", body);
+        assert!(body.contains("missing field `description`"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_stream_usage_is_requested_upstream() {
+        let streaming_hello = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}],
+            "stream": true
+        })
+        .to_string();
+
+        for (stream_usage, stream_options) in [
+            (StreamUsage::Passthrough, None),
+            (
+                StreamUsage::Enforce,
+                Some(serde_json::json!({"include_usage": true})),
+            ),
+        ] {
+            let request = chat_request()
+                .header(ARCH_DRY_RUN_HEADER, "true")
+                .body(full(streaming_hello.clone()))
+                .unwrap();
+            let response = chat_completions(
+                request,
+                router_service(),
+                UNREACHABLE_ENDPOINT.to_string(),
+                HandlerSettings {
+                    response_rewrites: ResponseRewrites {
+                        stream_usage,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let resolved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(
+                resolved["body"].get("stream_options").cloned(),
+                stream_options
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_and_client_metadata_are_forwarded() {
+        let body = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}],
+            "store": true,
+            "metadata": {"archgw_preference_config": "[]", "user_id": "u-42", "session": "s-1"}
+        });
+        let request = chat_request()
+            .header(ARCH_DRY_RUN_HEADER, "true")
+            .body(full(body.to_string()))
+            .unwrap();
+
+        let response = chat_completions(
+            request,
+            router_service(),
+            UNREACHABLE_ENDPOINT.to_string(),
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resolved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(resolved["body"]["store"], true);
+        assert_eq!(
+            resolved["body"]["metadata"],
+            serde_json::json!({"user_id": "u-42", "session": "s-1"})
+        );
+
+        // metadata without routing preferences is left alone, even when empty
+        let mut request = serde_json::json!({"store": false, "metadata": {}});
+        strip_preference_config(&mut request);
+        assert_eq!(request, serde_json::json!({"store": false, "metadata": {}}));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_upstream_returns_503_with_retry_after() {
+        let request = chat_request().body(full(hello_body())).unwrap();
+
+        let response = chat_completions(
+            request,
+            router_service(),
+            UNREACHABLE_ENDPOINT.to_string(),
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers().get(header::RETRY_AFTER).unwrap(),
+            &DEFAULT_RETRY_AFTER_SECS.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upstream_retry_after_is_honored() {
+        let (endpoint, hits) = mock_upstream(vec![
+            (StatusCode::SERVICE_UNAVAILABLE, Some("0")),
+            (StatusCode::OK, None),
+        ])
+        .await;
+        let retry_budget = RetryBudget {
+            max_retries: 1,
+            max_wait: std::time::Duration::from_secs(1),
+        };
+
+        let request = chat_request().body(full(hello_body())).unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings {
+                retry_budget,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_retry_after_forwarded_without_budget() {
+        let (endpoint, hits) =
+            mock_upstream(vec![(StatusCode::TOO_MANY_REQUESTS, Some("30"))]).await;
+
+        let request = chat_request().body(full(hello_body())).unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_response_rewrites_are_applied() {
+        use common::configuration::{MissingUsage, ResponseModel};
+
+        let (endpoint, _) = mock_upstream(vec![(StatusCode::OK, None)]).await;
+        let rewrites = ResponseRewrites {
+            missing_usage: MissingUsage::Null,
+            model: ResponseModel::Requested,
+            ..Default::default()
+        };
+        for (response_rewrites, usage, model) in [
+            (ResponseRewrites::default(), None, "batch-llm"),
+            (rewrites, Some(serde_json::Value::Null), "gpt-4o"),
+        ] {
+            let request = chat_request().body(full(hello_body())).unwrap();
+            let response = chat_completions(
+                request,
+                router_service(),
+                endpoint.clone(),
+                HandlerSettings {
+                    response_rewrites,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let completion: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(completion.get("usage").cloned(), usage);
+            assert_eq!(completion["model"], model);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_metadata_chunk_precedes_stream() {
+        let streaming_hello = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}],
+            "stream": true
+        })
+        .to_string();
+        let (endpoint, _) = mock_upstream(vec![(StatusCode::OK, None)]).await;
+
+        let request = chat_request()
+            .header(ARCH_ROUTE_METADATA_HEADER, "true")
+            .body(full(streaming_hello.clone()))
+            .unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint.clone(),
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        let (first_frame, rest) = body
+            .strip_prefix("data: ")
+            .and_then(|body| body.split_once("\n\n"))
+            .expect("the stream starts with the routing metadata chunk");
+        let chunk: serde_json::Value = serde_json::from_str(first_frame).unwrap();
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["choices"][0]["delta"], serde_json::json!({}));
+        assert_eq!(
+            chunk["x-archgw-route"],
+            serde_json::json!({"model": "gpt-4o", "route": null})
+        );
+        // the upstream content follows untouched
+        assert_eq!(rest, MOCK_COMPLETION);
+
+        // without the header the stream is forwarded as is
+        let request = chat_request().body(full(streaming_hello)).unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, MOCK_COMPLETION);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_responses_are_flagged() {
+        const TRUNCATED_COMPLETION: &str = r#"{"id":"chatcmpl-1","object":"chat.completion","created":0,"model":"gpt-4o","choices":[{"index":0,"message":{"role":"assistant","content":"Once upon a"},"finish_reason":"length"}]}"#;
+        const TRUNCATED_STREAM: &str = "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Once upon a\"},\"finish_reason\":\"length\"}]}\n\ndata: [DONE]\n\n";
+        const FINISHED_STREAM: &str = "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"The end.\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n";
+        let send = |endpoint: String, body: String| {
+            chat_completions(
+                chat_request().body(full(body)).unwrap(),
+                router_service(),
+                endpoint,
+                HandlerSettings::default(),
+            )
+        };
+        let streaming_hello = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hello"}],
+            "stream": true
+        })
+        .to_string();
+
+        // buffered responses carry it as a header
+        let endpoint = mock_upstream_body(TRUNCATED_COMPLETION).await;
+        let response = send(endpoint, hello_body()).await.unwrap();
+        assert_eq!(
+            response.headers().get(ARCH_TRUNCATED_HEADER).unwrap(),
+            "true"
+        );
+
+        let (endpoint, _) = mock_upstream(vec![(StatusCode::OK, None)]).await;
+        let response = send(endpoint, hello_body()).await.unwrap();
+        assert!(response.headers().get(ARCH_TRUNCATED_HEADER).is_none());
+
+        // streams in-band, right after the finishing chunk
+        let endpoint = mock_upstream_body(TRUNCATED_STREAM).await;
+        let response = send(endpoint.clone(), streaming_hello.clone())
+            .await
+            .unwrap();
+        assert!(response.headers().get(header::TRAILER).is_none());
+        let body = response.into_body().collect().await.unwrap();
+        assert!(body.trailers().is_none());
+        let body = String::from_utf8(body.to_bytes().to_vec()).unwrap();
+        let (finish, rest) = TRUNCATED_STREAM.split_once("\n\n").unwrap();
+        let (flag, done) = body
+            .strip_prefix(finish)
+            .and_then(|body| body.strip_prefix("\n\ndata: "))
+            .and_then(|body| body.split_once("\n\n"))
+            .expect("the truncation chunk follows the finishing chunk");
+        let flag: serde_json::Value = serde_json::from_str(flag).unwrap();
+        assert_eq!(flag[TRUNCATION_FIELD], true);
+        assert_eq!(done, rest.trim_start());
+
+        // and as a trailer, announced up front, to clients accepting trailers
+        let request = chat_request()
+            .header(header::TE, "trailers")
+            .body(full(streaming_hello.clone()))
+            .unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response.headers().get(header::TRAILER).unwrap(),
+            ARCH_TRUNCATED_HEADER
+        );
+        let body = response.into_body().collect().await.unwrap();
+        assert_eq!(
+            body.trailers().unwrap().get(ARCH_TRUNCATED_HEADER).unwrap(),
+            "true"
+        );
+
+        let endpoint = mock_upstream_body(FINISHED_STREAM).await;
+        let response = send(endpoint, streaming_hello).await.unwrap();
+        let body = response.into_body().collect().await.unwrap();
+        assert!(body.trailers().is_none());
+        assert_eq!(body.to_bytes(), FINISHED_STREAM);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_stream_is_flagged_over_http1() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+
+        const TRUNCATED_STREAM: &str = "data: {\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Once upon a\"},\"finish_reason\":\"length\"}]}\n\ndata: [DONE]\n\n";
+        let upstream = mock_upstream_body(TRUNCATED_STREAM).await;
+
+        // serve the handler the way main does, so the response goes through hyper's encoder
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let upstream = upstream.clone();
+                let service = service_fn(move |req| {
+                    chat_completions(
+                        req,
+                        router_service(),
+                        upstream.clone(),
+                        HandlerSettings::default(),
+                    )
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        let body = reqwest::Client::new()
+            .post(format!("http://{}/v1/chat/completions", address))
+            .body(
+                serde_json::json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": "hello"}],
+                    "stream": true
+                })
+                .to_string(),
+            )
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let flagged = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+            .any(|chunk| chunk[TRUNCATION_FIELD] == true);
+        assert!(flagged, "no truncation chunk in {:?}", body);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_request_to_non_streaming_provider_is_rejected() {
+        let (endpoint, hits) = mock_upstream(vec![(StatusCode::OK, None)]).await;
+        let request = chat_request()
+            .header(ARCH_FORCE_PROVIDER_HEADER, "batch-llm")
+            .body(full(streaming_body()))
+            .unwrap();
+
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings {
+                streaming_fallback: StreamingFallback::Reject,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("batch-llm does not support streaming"));
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_request_to_non_streaming_provider_is_buffered() {
+        // the provider is asked for a complete response
+        let request = chat_request()
+            .header(ARCH_FORCE_PROVIDER_HEADER, "batch-llm")
+            .header(ARCH_DRY_RUN_HEADER, "true")
+            .body(full(streaming_body()))
+            .unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            UNREACHABLE_ENDPOINT.to_string(),
+            HandlerSettings {
+                streaming_fallback: StreamingFallback::Buffer,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resolved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(resolved["body"]["stream"], false);
+        assert!(resolved["body"].get("stream_options").is_none());
+
+        // and its response is replayed to the client as a stream
+        let endpoint = mock_upstream_body(
+            r#"{"id":"chatcmpl-1","object":"chat.completion","created":0,"model":"batch-llm","choices":[{"index":0,"message":{"role":"assistant","content":"hi"},"finish_reason":"stop"}],"usage":{"prompt_tokens":1,"completion_tokens":1,"total_tokens":2}}"#,
+        )
+        .await;
+        let request = chat_request()
+            .header(ARCH_FORCE_PROVIDER_HEADER, "batch-llm")
+            .body(full(streaming_body()))
+            .unwrap();
+        let response = chat_completions(
+            request,
+            router_service(),
+            endpoint,
+            HandlerSettings {
+                streaming_fallback: StreamingFallback::Buffer,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let frames: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        // role, content and finish reason chunks, then [DONE]
+        assert_eq!(frames.len(), 4);
+        let chunks: Vec<serde_json::Value> = frames[..3]
+            .iter()
+            .map(|frame| serde_json::from_str(frame).unwrap())
+            .collect();
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk["object"] == "chat.completion.chunk"));
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[2]["usage"]["total_tokens"], 2);
+        assert_eq!(frames[3], "[DONE]");
+    }
+}
@@ -0,0 +1,89 @@
+use bytes::Bytes;
+use common::consts::REQUEST_ID_HEADER;
+use hermesllm::apis::OpenAIApi;
+use hermesllm::clients::{identify_api, DetectedApi};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Response, StatusCode};
+
+/// Headers a browser may send along with a cross-origin request, including the ones
+/// Anthropic clients authenticate with
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, x-api-key, anthropic-version";
+
+/// Methods served on `path`, for the endpoints browsers may call cross-origin
+pub fn allowed_methods(path: &str) -> Option<&'static str> {
+    match identify_api(path) {
+        Some(DetectedApi::OpenAI(OpenAIApi::ChatCompletions)) => Some("POST, OPTIONS"),
+        _ if path == "/v1/models" => Some("GET, OPTIONS"),
+        _ => None,
+    }
+}
+
+/// Answer a CORS preflight for `path`, `None` when the path takes no cross-origin requests
+pub fn preflight(path: &str) -> Option<Response<BoxBody<Bytes, hyper::Error>>> {
+    let methods = allowed_methods(path)?;
+    let body = Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed();
+    let mut response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Allow", methods)
+        .header("Access-Control-Allow-Headers", ALLOWED_HEADERS)
+        .header("Access-Control-Allow-Methods", methods)
+        .body(body)
+        .unwrap();
+    set_cors_headers(response.headers_mut());
+    Some(response)
+}
+
+/// Let browsers read a response from any origin, request id included
+pub fn set_cors_headers(headers: &mut HeaderMap) {
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(REQUEST_ID_HEADER),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preflight() {
+        for (path, methods) in [
+            ("/v1/chat/completions", "POST, OPTIONS"),
+            ("/v1/models", "GET, OPTIONS"),
+        ] {
+            let response = preflight(path).unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT, "{}", path);
+            let headers = response.headers();
+            assert_eq!(headers["Access-Control-Allow-Origin"], "*");
+            assert_eq!(headers["Access-Control-Allow-Methods"], methods);
+            assert_eq!(headers["Allow"], methods);
+            assert!(headers["Access-Control-Allow-Headers"]
+                .to_str()
+                .unwrap()
+                .contains("Content-Type"));
+        }
+
+        // not served by brightstaff, so browsers shouldn't be told they may call it
+        assert!(preflight("/v1/messages").is_none());
+        assert!(preflight("/v1/router/preferences").is_none());
+        assert!(preflight("/metrics").is_none());
+    }
+
+    #[test]
+    fn test_set_cors_headers() {
+        let mut headers = HeaderMap::new();
+        set_cors_headers(&mut headers);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            REQUEST_ID_HEADER
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use hermesllm::apis::anthropic::{MessagesCountTokensRequest, MessagesCountTokensResponse};
+use hermesllm::clients::approximate_token_count;
+use http_body_util::{combinators::BoxBody, BodyExt};
+use hyper::{Request, Response, StatusCode};
+use tracing::{debug, warn};
+
+use crate::utils::json::{json_response, JsonFormat};
+
+/// Answer Anthropic's `/v1/messages/count_tokens` locally with the gateway's token estimate,
+/// without calling an LLM. Counts are approximate.
+pub async fn count_tokens<B>(
+    request: Request<B>,
+    json_format: JsonFormat,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error>
+where
+    B: hyper::body::Body<Data = Bytes, Error = hyper::Error>,
+{
+    let request_bytes = request.collect().await?.to_bytes();
+
+    let count_request = match serde_json::from_slice::<MessagesCountTokensRequest>(&request_bytes) {
+        Ok(count_request) => count_request,
+        Err(err) => {
+            warn!("Failed to parse count_tokens request: {}", err);
+            let body = serde_json::json!({ "error": format!("invalid request body: {}", err) });
+            return Ok(json_response(StatusCode::BAD_REQUEST, body.to_string()));
+        }
+    };
+
+    let input_tokens = approximate_token_count(&count_request.prompt_text());
+    debug!(
+        "counted {} input tokens for model {}",
+        input_tokens, count_request.model
+    );
+
+    let response = MessagesCountTokensResponse { input_tokens };
+    Ok(json_response(
+        StatusCode::OK,
+        json_format.serialize(&response).unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+
+    fn count_request(body: String) -> Request<BoxBody<Bytes, hyper::Error>> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/messages/count_tokens")
+            .body(
+                Full::new(Bytes::from(body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens() {
+        let body = serde_json::json!({
+            "model": "claude-3-5-sonnet-20241022",
+            "messages": [{"role": "user", "content": "hello world"}]
+        });
+
+        let response = count_tokens(count_request(body.to_string()), JsonFormat::Pretty)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let count: MessagesCountTokensResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(count, MessagesCountTokensResponse { input_tokens: 3 });
+
+        let response = count_tokens(
+            count_request("{\"messages\": []}".to_string()),
+            JsonFormat::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
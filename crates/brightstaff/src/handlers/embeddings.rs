@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use common::consts::{
+    ARCH_FORCE_PROVIDER_HEADER, ARCH_PROVIDER_HINT_HEADER, CHAT_COMPLETIONS_PATH,
+};
+use hermesllm::apis::openai::EmbeddingsRequest;
+use hermesllm::apis::ApiDefinition;
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::header;
+use hyper::{Request, Response, StatusCode, Uri};
+use tracing::{debug, info, warn};
+
+use crate::handlers::chat_completions::{reconcile_buffered_headers, service_unavailable};
+use crate::router::llm_router::{RouterService, RoutingError};
+use crate::utils::body::full;
+use crate::utils::json::JsonFormat;
+
+/// The global upstream endpoint for embeddings: the chat completions endpoint's sibling, or
+/// `/v1/embeddings` on the same origin when the endpoint doesn't end in the chat completions
+/// path. `None` when the endpoint is not an absolute URL.
+fn embeddings_endpoint(llm_provider_endpoint: &str) -> Option<String> {
+    let embeddings_path = EmbeddingsRequest::api_type().endpoint();
+    if let Some(base) = llm_provider_endpoint.strip_suffix(CHAT_COMPLETIONS_PATH) {
+        return Some(format!("{}{}", base, embeddings_path));
+    }
+    let uri = llm_provider_endpoint.parse::<Uri>().ok()?;
+    Some(format!(
+        "{}://{}{}",
+        uri.scheme_str()?,
+        uri.authority()?,
+        embeddings_path
+    ))
+}
+
+/// Forward an OpenAI `/v1/embeddings` request upstream. There is no conversation to route
+/// on, so it goes to the provider named by its `model` (or the force-provider header), or to
+/// the default provider when the model isn't a configured provider.
+pub async fn embeddings<B>(
+    request: Request<B>,
+    router_service: Arc<RouterService>,
+    llm_provider_endpoint: String,
+    json_format: JsonFormat,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error>
+where
+    B: hyper::body::Body<Data = Bytes, Error = hyper::Error>,
+{
+    let request_path = request.uri().path().to_string();
+    let mut request_headers = request.headers().clone();
+    let request_bytes = request.collect().await?.to_bytes();
+
+    let embeddings_request = match serde_json::from_slice::<EmbeddingsRequest>(&request_bytes) {
+        Ok(embeddings_request) => embeddings_request,
+        Err(err) => {
+            warn!("Request body is not a valid embeddings request: {}", err);
+            let err_msg = format!("Invalid embeddings request: {}", err);
+            let mut bad_request = Response::new(full(err_msg));
+            *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(bad_request);
+        }
+    };
+
+    let model_name = match router_service.forced_provider(&request_headers) {
+        Ok(Some(model_name)) => model_name,
+        Ok(None) => router_service.unrouted_provider(&embeddings_request.model),
+        Err(err @ RoutingError::UnknownForcedProvider(_)) => {
+            warn!("{}", err);
+            let mut bad_request = Response::new(full(err.to_string()));
+            *bad_request.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(bad_request);
+        }
+        Err(err) => {
+            let mut internal_error = Response::new(full(err.to_string()));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(internal_error);
+        }
+    };
+
+    if let Err(err) = router_service.check_routing_loop(&model_name) {
+        warn!("{}", err);
+        let mut internal_error = Response::new(full(err.to_string()));
+        *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(internal_error);
+    }
+
+    let upstream_endpoint = match router_service
+        .upstream_endpoint(&model_name, &request_path)
+        .or_else(|| embeddings_endpoint(&llm_provider_endpoint))
+    {
+        Some(upstream_endpoint) => upstream_endpoint,
+        None => {
+            let err_msg = format!(
+                "No embeddings endpoint can be derived from the upstream endpoint {}",
+                llm_provider_endpoint
+            );
+            warn!("{}", err_msg);
+            let mut internal_error = Response::new(full(err_msg));
+            *internal_error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(internal_error);
+        }
+    };
+    info!(
+        "request received, request type: embeddings, model: {}, provider: {}",
+        embeddings_request.model, model_name
+    );
+
+    request_headers.remove(ARCH_FORCE_PROVIDER_HEADER);
+    request_headers.remove(header::CONTENT_LENGTH);
+    // the response is re-framed below, so ask upstream for an uncompressed body
+    request_headers.remove(header::ACCEPT_ENCODING);
+    request_headers.insert(
+        ARCH_PROVIDER_HINT_HEADER,
+        header::HeaderValue::from_str(&model_name).unwrap(),
+    );
+
+    debug!(
+        "sending embeddings request to {}, with model hint: {}",
+        upstream_endpoint, model_name
+    );
+    let llm_response = match router_service
+        .http_client()
+        .request(
+            router_service.upstream_method(&model_name),
+            &upstream_endpoint,
+        )
+        .headers(request_headers)
+        .body(request_bytes)
+        .send()
+        .await
+    {
+        Ok(llm_response) => llm_response,
+        Err(err) => {
+            warn!("Failed to send request: {}", err);
+            return Ok(service_unavailable(format!(
+                "Failed to send request: {}",
+                err
+            )));
+        }
+    };
+
+    let status = llm_response.status();
+    let mut response_headers = llm_response.headers().clone();
+    let body = match llm_response.bytes().await {
+        Ok(body) => json_format.format_body(body),
+        Err(err) => {
+            let err_msg = format!("Failed to read response body: {}", err);
+            let mut internal_error = Response::new(full(err_msg));
+            *internal_error.status_mut() = StatusCode::BAD_GATEWAY;
+            return Ok(internal_error);
+        }
+    };
+
+    reconcile_buffered_headers(&mut response_headers, body.len());
+    let mut response = Response::new(full(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = response_headers;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::llm_router::RouterClientConfig;
+    use common::configuration::LlmProvider;
+    use std::sync::Mutex;
+
+    const MOCK_EMBEDDINGS: &str = r#"{"object":"list","data":[{"object":"embedding","embedding":[0.5,-0.25],"index":0}],"model":"text-embedding-3-small","usage":{"prompt_tokens":4,"total_tokens":4}}"#;
+
+    /// Serve embeddings on a local port, recording the path and provider hint of each request
+    async fn mock_upstream() -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let server_seen = seen.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let seen = server_seen.clone();
+                let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                    let hint = req
+                        .headers()
+                        .get(ARCH_PROVIDER_HINT_HEADER)
+                        .map(|hint| hint.to_str().unwrap().to_string())
+                        .unwrap_or_default();
+                    seen.lock()
+                        .unwrap()
+                        .push((req.uri().path().to_string(), hint));
+                    async move { Ok::<_, hyper::Error>(Response::new(full(MOCK_EMBEDDINGS))) }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        (format!("http://{}/v1/chat/completions", address), seen)
+    }
+
+    fn router_service(llm_provider_endpoint: &str) -> Arc<RouterService> {
+        let providers: Vec<LlmProvider> = serde_yaml::from_str(
+            r#"
+- name: text-embedding-3-small
+  provider_interface: openai
+  model: text-embedding-3-small
+"#,
+        )
+        .unwrap();
+
+        Arc::new(
+            RouterService::new(
+                providers,
+                llm_provider_endpoint.to_string(),
+                "Arch-Router".to_string(),
+                "arch-router".to_string(),
+                RouterClientConfig::default(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn embeddings_request(body: &str) -> Request<BoxBody<Bytes, hyper::Error>> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .body(full(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_embeddings_endpoint() {
+        assert_eq!(
+            embeddings_endpoint("http://localhost:12001/v1/chat/completions").as_deref(),
+            Some("http://localhost:12001/v1/embeddings")
+        );
+        // never the chat completions URL itself, whatever path the endpoint has
+        assert_eq!(
+            embeddings_endpoint("http://localhost:12001/llm").as_deref(),
+            Some("http://localhost:12001/v1/embeddings")
+        );
+        assert_eq!(embeddings_endpoint("localhost:12001"), None);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_forwarded() {
+        let (endpoint, seen) = mock_upstream().await;
+        let request = embeddings_request(
+            r#"{"input": "The food was delicious", "model": "text-embedding-3-small"}"#,
+        );
+        let response = embeddings(
+            request,
+            router_service(&endpoint),
+            endpoint,
+            JsonFormat::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, MOCK_EMBEDDINGS);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(
+                "/v1/embeddings".to_string(),
+                "text-embedding-3-small".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_embeddings_request_is_rejected() {
+        let (endpoint, seen) = mock_upstream().await;
+        let request = embeddings_request(r#"{"model": "text-embedding-3-small"}"#);
+        let response = embeddings(
+            request,
+            router_service(&endpoint),
+            endpoint,
+            JsonFormat::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("Invalid embeddings request: "), "{}", body);
+        assert!(body.contains("missing field `input`"), "{}", body);
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
@@ -1,2 +1,36 @@
+use common::configuration::{Gateway, StreamingFallback};
+
+use crate::utils::json::JsonFormat;
+use crate::utils::response::ResponseRewrites;
+use crate::utils::retry::RetryBudget;
+
 pub mod chat_completions;
+pub mod cors;
+pub mod count_tokens;
+pub mod embeddings;
 pub mod models;
+pub mod preferences;
+
+/// How the handlers treat requests and responses, read once from the `gateway` section of
+/// the configuration
+#[derive(Debug, Clone, Default)]
+pub struct HandlerSettings {
+    pub retry_budget: RetryBudget,
+    pub streaming_fallback: StreamingFallback,
+    pub json_format: JsonFormat,
+    pub response_rewrites: ResponseRewrites,
+    /// Hosts the remote images of chat completions may point at, any when `None`
+    pub image_hosts: Option<Vec<String>>,
+}
+
+impl HandlerSettings {
+    pub fn from_settings(gateway: &Gateway) -> Self {
+        HandlerSettings {
+            retry_budget: RetryBudget::from_settings(&gateway.upstream.clone().unwrap_or_default()),
+            streaming_fallback: gateway.unstreamable_requests.unwrap_or_default(),
+            json_format: JsonFormat::new(gateway.pretty_json.unwrap_or_default()),
+            response_rewrites: ResponseRewrites::from_settings(gateway),
+            image_hosts: gateway.image_hosts.clone(),
+        }
+    }
+}
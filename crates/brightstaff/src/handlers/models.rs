@@ -3,17 +3,19 @@ use common::configuration::{IntoModels, LlmProvider};
 use hermesllm::providers::openai::types::Models;
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
 use hyper::{Response, StatusCode};
-use serde_json;
 use std::sync::Arc;
 
+use crate::utils::json::JsonFormat;
+
 pub async fn list_models(
     llm_providers: Arc<tokio::sync::RwLock<Vec<LlmProvider>>>,
+    json_format: JsonFormat,
 ) -> Response<BoxBody<Bytes, hyper::Error>> {
     let prov = llm_providers.read().await;
     let providers = prov.clone();
     let openai_models: Models = providers.into_models();
 
-    match serde_json::to_string(&openai_models) {
+    match json_format.serialize(&openai_models) {
         Ok(json) => {
             let body = Full::new(Bytes::from(json))
                 .map_err(|never| match never {})
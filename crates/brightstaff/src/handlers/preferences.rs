@@ -0,0 +1,247 @@
+use bytes::Bytes;
+use common::configuration::{LlmProvider, ModelUsagePreference};
+use http_body_util::combinators::BoxBody;
+use hyper::{Response, StatusCode};
+use std::sync::Arc;
+
+use crate::utils::json::{json_response, JsonFormat};
+
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// Optional `?name=`, `?offset=` and `?limit=` parameters of the preferences listing
+#[derive(Debug, Default, PartialEq)]
+pub struct PreferencesQuery {
+    /// Only providers whose name contains this string
+    pub name: Option<String>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl PreferencesQuery {
+    pub fn parse(query: Option<&str>) -> Result<Self, String> {
+        let mut parsed = PreferencesQuery::default();
+        for pair in query.unwrap_or_default().split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "name" => parsed.name = Some(decode_query_component(value)?),
+                "offset" => parsed.offset = parse_count("offset", value)?,
+                "limit" => parsed.limit = Some(parse_count("limit", value)?),
+                // unknown parameters are ignored, like most list endpoints do
+                _ => {}
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_count(key: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .map_err(|_| format!("{} must be a non-negative integer, got '{}'", key, value))
+}
+
+/// Undo `+` and `%XX` escaping of a query string value
+fn decode_query_component(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [input.next(), input.next()];
+                let decoded = match hex {
+                    [Some(hi), Some(lo)] => std::str::from_utf8(&[hi, lo])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                bytes.push(decoded.ok_or_else(|| format!("invalid escape in '{}'", value))?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| format!("'{}' is not valid utf-8", value))
+}
+
+/// Usage preferences of every provider that declares some, filtered by name and paginated.
+/// Returns the number of matches before pagination along with the requested page.
+pub fn select_preferences(
+    providers: &[LlmProvider],
+    query: &PreferencesQuery,
+) -> (usize, Vec<ModelUsagePreference>) {
+    let matching = providers
+        .iter()
+        .filter(|provider| {
+            query
+                .name
+                .as_ref()
+                .is_none_or(|name| provider.name.contains(name.as_str()))
+        })
+        .filter_map(|provider| {
+            provider
+                .routing_preferences
+                .as_ref()
+                .map(|routing_preferences| ModelUsagePreference {
+                    model: provider.name.clone(),
+                    routing_preferences: routing_preferences.clone(),
+                })
+        })
+        .collect::<Vec<ModelUsagePreference>>();
+
+    let total = matching.len();
+    let page = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    (total, page)
+}
+
+pub async fn list_preferences(
+    llm_providers: Arc<tokio::sync::RwLock<Vec<LlmProvider>>>,
+    query: Option<&str>,
+    json_format: JsonFormat,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let query = match PreferencesQuery::parse(query) {
+        Ok(query) => query,
+        Err(message) => {
+            let body = serde_json::json!({ "error": message }).to_string();
+            return json_response(StatusCode::BAD_REQUEST, body);
+        }
+    };
+
+    let providers = llm_providers.read().await;
+    let (total, preferences) = select_preferences(&providers, &query);
+
+    match json_format.serialize(&preferences) {
+        Ok(json) => {
+            let mut response = json_response(StatusCode::OK, json);
+            response
+                .headers_mut()
+                .insert(TOTAL_COUNT_HEADER, total.into());
+            response
+        }
+        Err(_) => json_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{\"error\":\"Failed to serialize preferences\"}".to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn providers() -> Vec<LlmProvider> {
+        serde_yaml::from_str(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+  routing_preferences:
+    - name: image understanding
+      description: describing pictures
+- name: gpt-4o-mini
+  provider_interface: openai
+  model: gpt-4o-mini
+  routing_preferences:
+    - name: chit chat
+      description: casual conversation
+- name: claude-3-5-sonnet
+  provider_interface: claude
+  model: claude-3-5-sonnet
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+- name: mistral-small
+  provider_interface: mistral
+  model: mistral-small
+"#,
+        )
+        .unwrap()
+    }
+
+    fn models(preferences: &[ModelUsagePreference]) -> Vec<&str> {
+        preferences.iter().map(|pref| pref.model.as_str()).collect()
+    }
+
+    #[test]
+    fn test_no_params_lists_everything() {
+        let query = PreferencesQuery::parse(None).unwrap();
+        assert_eq!(query, PreferencesQuery::default());
+
+        let (total, page) = select_preferences(&providers(), &query);
+        // providers without routing preferences are not listed
+        assert_eq!(total, 3);
+        assert_eq!(
+            models(&page),
+            vec!["gpt-4o", "gpt-4o-mini", "claude-3-5-sonnet"]
+        );
+    }
+
+    #[test]
+    fn test_pagination_boundaries() {
+        let page_of = |query: &str| {
+            let query = PreferencesQuery::parse(Some(query)).unwrap();
+            select_preferences(&providers(), &query)
+        };
+
+        let (total, page) = page_of("offset=1&limit=1");
+        assert_eq!(total, 3);
+        assert_eq!(models(&page), vec!["gpt-4o-mini"]);
+
+        // a page running past the end is cut short
+        let (_, page) = page_of("offset=2&limit=5");
+        assert_eq!(models(&page), vec!["claude-3-5-sonnet"]);
+
+        // an offset past the end is an empty page, not an error
+        let (total, page) = page_of("offset=3");
+        assert_eq!(total, 3);
+        assert!(page.is_empty());
+
+        let (_, page) = page_of("limit=0");
+        assert!(page.is_empty());
+
+        assert!(PreferencesQuery::parse(Some("limit=-1")).is_err());
+        assert!(PreferencesQuery::parse(Some("offset=ten")).is_err());
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let query = PreferencesQuery::parse(Some("name=gpt-4o&limit=1")).unwrap();
+        let (total, page) = select_preferences(&providers(), &query);
+        assert_eq!(total, 2);
+        assert_eq!(models(&page), vec!["gpt-4o"]);
+
+        let query = PreferencesQuery::parse(Some("name=claude%2D3")).unwrap();
+        assert_eq!(query.name.as_deref(), Some("claude-3"));
+        let (total, page) = select_preferences(&providers(), &query);
+        assert_eq!(total, 1);
+        assert_eq!(page[0].routing_preferences[0].name, "code generation");
+
+        let query = PreferencesQuery::parse(Some("name=mistral")).unwrap();
+        assert_eq!(select_preferences(&providers(), &query).0, 0);
+    }
+
+    #[tokio::test]
+    async fn test_list_preferences_response() {
+        let llm_providers = Arc::new(tokio::sync::RwLock::new(providers()));
+
+        let response = list_preferences(
+            llm_providers.clone(),
+            Some("limit=2"),
+            JsonFormat::default(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[TOTAL_COUNT_HEADER], "3");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: Vec<ModelUsagePreference> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(models(&page), vec!["gpt-4o", "gpt-4o-mini"]);
+
+        let response = list_preferences(llm_providers, Some("limit=x"), JsonFormat::Pretty).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,9 +1,23 @@
 use brightstaff::handlers::chat_completions::chat_completions;
+use brightstaff::handlers::cors::{allowed_methods, preflight, set_cors_headers};
+use brightstaff::handlers::count_tokens::count_tokens;
+use brightstaff::handlers::embeddings::embeddings;
 use brightstaff::handlers::models::list_models;
-use brightstaff::router::llm_router::RouterService;
+use brightstaff::handlers::preferences::list_preferences;
+use brightstaff::handlers::HandlerSettings;
+use brightstaff::router::llm_router::{RouterClientConfig, RouterService};
+use brightstaff::router::router_model_v1::{RouterModelOptions, RouterSampling};
+use brightstaff::utils::body::full;
+use brightstaff::utils::config::{check_config, config_path, load_config, CONFIG_CHECK_FLAG};
+use brightstaff::utils::metrics::{REQUEST_FEATURES, ROUTING_DECISIONS, STREAM_USAGE};
+use brightstaff::utils::request_id::{ensure_request_id, set_request_id};
+use brightstaff::utils::tls::UpstreamTls;
 use brightstaff::utils::tracing::init_tracer;
 use bytes::Bytes;
-use common::configuration::Configuration;
+use common::consts::DEFAULT_ROUTING_LLM_PROVIDER;
+use common::pii::LogRedaction;
+use hermesllm::apis::{AnthropicApi, OpenAIApi};
+use hermesllm::clients::{identify_api, DetectedApi};
 use http_body_util::{combinators::BoxBody, BodyExt, Empty};
 use hyper::body::Incoming;
 use hyper::server::conn::http1;
@@ -13,16 +27,14 @@ use hyper_util::rt::TokioIo;
 use opentelemetry::trace::FutureExt;
 use opentelemetry::{global, Context};
 use opentelemetry_http::HeaderExtractor;
+use std::env;
 use std::sync::Arc;
-use std::{env, fs};
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
-
-pub mod router;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 const BIND_ADDRESS: &str = "0.0.0.0:9091";
-const DEFAULT_ROUTING_LLM_PROVIDER: &str = "arch-router";
 const DEFAULT_ROUTING_MODEL_NAME: &str = "Arch-Router";
 
 // Utility function to extract the context from the incoming request headers
@@ -40,6 +52,22 @@ fn empty() -> BoxBody<Bytes, hyper::Error> {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // `brightstaff --config-check [path]` validates the config and exits, e.g. in CI
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(position) = args.iter().position(|arg| arg == CONFIG_CHECK_FLAG) {
+        let path = args.get(position + 1).cloned().unwrap_or_else(config_path);
+        match check_config(&path) {
+            Ok(summary) => {
+                println!("{}", summary);
+                return Ok(());
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let _tracer_provider = init_tracer();
     let bind_address = env::var("BIND_ADDRESS").unwrap_or_else(|_| BIND_ADDRESS.to_string());
 
@@ -48,19 +76,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         env::current_dir().unwrap().display()
     );
     // loading arch_config.yaml file
-    let arch_config_path = env::var("ARCH_CONFIG_PATH_RENDERED")
-        .unwrap_or_else(|_| "./arch_config_rendered.yaml".to_string());
+    let arch_config_path = config_path();
     info!("Loading arch_config.yaml from {}", arch_config_path);
 
-    let config_contents =
-        fs::read_to_string(&arch_config_path).expect("Failed to read arch_config.yaml");
-
-    let config: Configuration =
-        serde_yaml::from_str(&config_contents).expect("Failed to parse arch_config.yaml");
+    let config = match load_config(&arch_config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     let arch_config = Arc::new(config);
 
     let llm_providers = Arc::new(RwLock::new(arch_config.llm_providers.clone()));
+    let enabled_endpoints = Arc::new(arch_config.enabled_endpoints());
 
     debug!(
         "arch_config: {:?}",
@@ -71,8 +101,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .unwrap_or_else(|_| "http://localhost:12001/v1/chat/completions".to_string());
 
     info!("llm provider endpoint: {}", llm_provider_endpoint);
+
+    let gateway = arch_config.gateway.clone().unwrap_or_default();
+    let upstream = gateway.upstream.clone().unwrap_or_default();
+    let upstream_tls = match UpstreamTls::from_settings(&upstream) {
+        Ok(upstream_tls) => upstream_tls,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let http_client = match upstream_tls.build_client() {
+        Ok(http_client) => http_client,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let settings = HandlerSettings::from_settings(&gateway);
+    info!("upstream retry budget: {:?}", settings.retry_budget);
+    let json_format = settings.json_format;
     info!("listening on http://{}", bind_address);
-    let listener = TcpListener::bind(bind_address).await?;
+    let listener = TcpListener::bind(&bind_address).await?;
 
     let routing_model_name: String = arch_config
         .routing
@@ -86,12 +137,73 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .and_then(|r| r.llm_provider.clone())
         .unwrap_or_else(|| DEFAULT_ROUTING_LLM_PROVIDER.to_string());
 
-    let router_service: Arc<RouterService> = Arc::new(RouterService::new(
-        arch_config.llm_providers.clone(),
-        llm_provider_endpoint.clone(),
-        routing_model_name,
-        routing_llm_provider,
-    ));
+    let router_model_options = RouterModelOptions {
+        include_tools: arch_config
+            .routing
+            .as_ref()
+            .and_then(|r| r.include_tools)
+            .unwrap_or_default(),
+        strict_conversation: arch_config
+            .routing
+            .as_ref()
+            .and_then(|r| r.strict_conversation)
+            .unwrap_or_default(),
+        route_match_distance: arch_config
+            .routing
+            .as_ref()
+            .and_then(|r| r.route_match_distance),
+        propagate_seed: arch_config
+            .routing
+            .as_ref()
+            .and_then(|r| r.propagate_seed)
+            .unwrap_or_default(),
+        system_route_directive: arch_config
+            .routing
+            .as_ref()
+            .and_then(|r| r.system_route_directive)
+            .unwrap_or_default(),
+    };
+
+    let routing_client = arch_config
+        .routing
+        .as_ref()
+        .and_then(|r| r.client.clone())
+        .unwrap_or_default();
+    let router_client_config = RouterClientConfig {
+        timeout: routing_client.timeout_ms.map(Duration::from_millis),
+        connect_timeout: routing_client.connect_timeout_ms.map(Duration::from_millis),
+        pool_max_idle_per_host: routing_client.pool_max_idle_per_host,
+        tls: upstream_tls,
+    };
+
+    let routing_sampling = arch_config
+        .routing
+        .as_ref()
+        .and_then(|r| r.sampling.clone())
+        .unwrap_or_default();
+    let router_sampling = RouterSampling {
+        temperature: routing_sampling
+            .temperature
+            .or(RouterSampling::default().temperature),
+        top_p: routing_sampling.top_p,
+        max_tokens: routing_sampling.max_tokens,
+    };
+
+    let router_service: Arc<RouterService> = Arc::new(
+        RouterService::new(
+            arch_config.llm_providers.clone(),
+            llm_provider_endpoint.clone(),
+            routing_model_name,
+            routing_llm_provider,
+            router_client_config,
+        )
+        .expect("Invalid routing configuration in arch_config.yaml")
+        .with_self_address(&bind_address)
+        .with_http_client(http_client)
+        .with_router_model_options(router_model_options)
+        .with_router_sampling(router_sampling)
+        .with_log_redaction(LogRedaction::new(gateway.redact_logs.unwrap_or_default())),
+    );
 
     loop {
         let (stream, _) = listener.accept().await?;
@@ -100,52 +212,88 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
         let router_service = Arc::clone(&router_service);
         let llm_provider_endpoint = llm_provider_endpoint.clone();
+        let settings = settings.clone();
 
         let llm_providers = llm_providers.clone();
-        let service = service_fn(move |req| {
+        let enabled_endpoints = enabled_endpoints.clone();
+        let service = service_fn(move |mut req: Request<Incoming>| {
             let router_service = Arc::clone(&router_service);
             let parent_cx = extract_context_from_request(&req);
+            // correlate every log line of this request, and the upstream call, with one id
+            let request_id = ensure_request_id(req.headers_mut());
+            let span = info_span!("request", request_id = %request_id);
             let llm_provider_endpoint = llm_provider_endpoint.clone();
+            let settings = settings.clone();
             let llm_providers = llm_providers.clone();
+            let enabled_endpoints = enabled_endpoints.clone();
 
             async move {
-                match (req.method(), req.uri().path()) {
-                    (&Method::POST, "/v1/chat/completions") => {
-                        chat_completions(req, router_service, llm_provider_endpoint)
+                let response = match (req.method(), req.uri().path()) {
+                    (_, path)
+                        if identify_api(path).is_some() && !enabled_endpoints.is_enabled(path) =>
+                    {
+                        debug!("endpoint {} is disabled by enabled_endpoints", path);
+                        let mut not_found = Response::new(empty());
+                        *not_found.status_mut() = StatusCode::NOT_FOUND;
+                        Ok(not_found)
+                    }
+                    (&Method::POST, path)
+                        if identify_api(path)
+                            == Some(DetectedApi::OpenAI(OpenAIApi::ChatCompletions)) =>
+                    {
+                        chat_completions(req, router_service, llm_provider_endpoint, settings)
                             .with_context(parent_cx)
                             .await
                     }
-                    (&Method::GET, "/v1/models") => Ok(list_models(llm_providers).await),
-                    (&Method::OPTIONS, "/v1/models") => {
-                        let mut response = Response::new(empty());
-                        *response.status_mut() = StatusCode::NO_CONTENT;
-                        response
-                            .headers_mut()
-                            .insert("Allow", "GET, OPTIONS".parse().unwrap());
-                        response
-                            .headers_mut()
-                            .insert("Access-Control-Allow-Origin", "*".parse().unwrap());
-                        response.headers_mut().insert(
-                            "Access-Control-Allow-Headers",
-                            "Authorization, Content-Type".parse().unwrap(),
-                        );
-                        response.headers_mut().insert(
-                            "Access-Control-Allow-Methods",
-                            "GET, POST, OPTIONS".parse().unwrap(),
-                        );
+                    (&Method::POST, path)
+                        if identify_api(path)
+                            == Some(DetectedApi::Anthropic(AnthropicApi::CountTokens)) =>
+                    {
+                        count_tokens(req, json_format).await
+                    }
+                    (&Method::POST, path)
+                        if identify_api(path)
+                            == Some(DetectedApi::OpenAI(OpenAIApi::Embeddings)) =>
+                    {
+                        embeddings(req, router_service, llm_provider_endpoint, json_format)
+                            .with_context(parent_cx)
+                            .await
+                    }
+                    (&Method::GET, "/v1/models") => {
+                        Ok(list_models(llm_providers, json_format).await)
+                    }
+                    (&Method::GET, "/metrics") => {
+                        let mut response = Response::new(full(format!(
+                            "{}{}{}",
+                            REQUEST_FEATURES.render(),
+                            ROUTING_DECISIONS.render(),
+                            STREAM_USAGE.render()
+                        )));
                         response
                             .headers_mut()
-                            .insert("Content-Type", "application/json".parse().unwrap());
-
+                            .insert("Content-Type", "text/plain; version=0.0.4".parse().unwrap());
                         Ok(response)
                     }
+                    (&Method::GET, "/v1/router/preferences") => {
+                        Ok(list_preferences(llm_providers, req.uri().query(), json_format).await)
+                    }
+                    (&Method::OPTIONS, path) if allowed_methods(path).is_some() => {
+                        Ok(preflight(path).unwrap())
+                    }
                     _ => {
                         let mut not_found = Response::new(empty());
                         *not_found.status_mut() = StatusCode::NOT_FOUND;
                         Ok(not_found)
                     }
-                }
+                };
+
+                response.map(|mut response| {
+                    set_request_id(response.headers_mut(), &request_id);
+                    set_cors_headers(response.headers_mut());
+                    response
+                })
             }
+            .instrument(span)
         });
 
         tokio::task::spawn(async move {
@@ -1,24 +1,137 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use common::{
     configuration::{LlmProvider, ModelUsagePreference, RoutingPreference},
-    consts::ARCH_PROVIDER_HINT_HEADER,
+    consts::{ARCH_FORCE_PROVIDER_HEADER, ARCH_PROVIDER_HINT_HEADER, USER_ROLE},
+    pii::LogRedaction,
+    utils::shorten_string,
 };
+use hermesllm::apis::FinishReason;
 use hermesllm::providers::openai::types::{ChatCompletionsResponse, ContentType, Message};
 use hyper::header;
+use serde_json::Value;
 use thiserror::Error;
-use tracing::{debug, info, warn};
+use tracing::{debug, field, info, info_span, warn, Instrument};
 
-use crate::router::router_model_v1::{self};
+use crate::router::router_model_v1::{self, RouterModelOptions, RouterSampling};
+use crate::utils::tls::{TlsError, UpstreamTls};
 
-use super::router_model::RouterModel;
+use super::router_model::{RouterModel, RoutingModelError};
 
 pub struct RouterService {
     router_url: String,
+    router_client: reqwest::Client,
     client: reqwest::Client,
     router_model: Arc<dyn RouterModel>,
+    router_model_options: RouterModelOptions,
+    router_sampling: RouterSampling,
     routing_provider_name: String,
     llm_usage_defined: bool,
+    provider_names: HashSet<String>,
+    default_provider: Option<String>,
+    provider_addresses: HashMap<String, (String, u16)>,
+    provider_upstreams: HashMap<String, ProviderUpstream>,
+    self_address: Option<(String, u16)>,
+    llm_routes: HashMap<String, Vec<RoutingPreference>>,
+    vision_providers: HashSet<String>,
+    non_streaming_providers: HashSet<String>,
+    providers_without_tools: HashSet<String>,
+    log_redaction: LogRedaction,
+}
+
+/// Settings of the http client calling the router model. The routing hop is short and
+/// latency sensitive, so it is tuned apart from the calls to the upstreams.
+#[derive(Debug, Clone, Default)]
+pub struct RouterClientConfig {
+    /// Limit on a whole routing call, none when unset
+    pub timeout: Option<Duration>,
+    /// Limit on connecting to the router, none when unset
+    pub connect_timeout: Option<Duration>,
+    /// Idle connections to keep open to the router, reqwest's default when unset
+    pub pool_max_idle_per_host: Option<usize>,
+    /// TLS settings, the same as the upstreams' since the router is usually one of them
+    pub tls: UpstreamTls,
+}
+
+impl RouterClientConfig {
+    fn build_client(&self) -> std::result::Result<reqwest::Client, TlsError> {
+        let mut builder = self.tls.client_builder()?;
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(pool_max_idle_per_host) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(pool_max_idle_per_host);
+        }
+        builder.build().map_err(TlsError::Client)
+    }
+}
+
+/// Where and how requests routed to a provider with its own `base_url` are sent
+#[derive(Debug, Clone)]
+struct ProviderUpstream {
+    base_url: String,
+    method: reqwest::Method,
+    /// Rendered `path` template, the client's request path is used when unset
+    path: Option<String>,
+    /// Rendered query parameters, sorted by name
+    query_params: Vec<(String, String)>,
+}
+
+impl ProviderUpstream {
+    fn from_provider(provider: &LlmProvider) -> std::result::Result<Option<Self>, String> {
+        let Some(base_url) = &provider.base_url else {
+            return Ok(None);
+        };
+        let method = match &provider.method {
+            Some(method) => reqwest::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|err| format!("invalid method `{}`: {}", method, err))?,
+            None => reqwest::Method::POST,
+        };
+        let path = provider
+            .path
+            .as_deref()
+            .map(|path| provider.render_template(path))
+            .transpose()?;
+        let mut query_params = provider
+            .query_params
+            .iter()
+            .flatten()
+            .map(|(name, value)| Ok((name.clone(), provider.render_template(value)?)))
+            .collect::<std::result::Result<Vec<(String, String)>, String>>()?;
+        query_params.sort();
+
+        Ok(Some(ProviderUpstream {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            method,
+            path,
+            query_params,
+        }))
+    }
+
+    fn url(&self, request_path: &str) -> String {
+        let url = format!(
+            "{}{}",
+            self.base_url,
+            self.path.as_deref().unwrap_or(request_path)
+        );
+        if self.query_params.is_empty() {
+            return url;
+        }
+        match reqwest::Url::parse(&url) {
+            Ok(mut parsed) => {
+                parsed.query_pairs_mut().extend_pairs(&self.query_params);
+                parsed.to_string()
+            }
+            Err(_) => url,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -31,17 +144,138 @@ pub enum RoutingError {
 
     #[error("Router model error: {0}")]
     RouterModelError(#[from] super::router_model::RoutingModelError),
+
+    #[error("Unknown provider requested via {ARCH_FORCE_PROVIDER_HEADER}: {0}")]
+    UnknownForcedProvider(String),
+
+    #[error("Routing preference {route} is declared by more than one provider: {}", .providers.join(", "))]
+    DuplicateRouteName {
+        route: String,
+        providers: Vec<String>,
+    },
+
+    #[error("Routing loop detected: provider {provider} points back at this gateway ({address})")]
+    RoutingLoop { provider: String, address: String },
+
+    #[error("Invalid upstream request settings for provider {provider}: {reason}")]
+    InvalidUpstreamRequest { provider: String, reason: String },
+
+    #[error("Failed to build routing http client: {0}")]
+    RouterClient(TlsError),
 }
 
 pub type Result<T> = std::result::Result<T, RoutingError>;
 
+/// How the provider of a request was chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionSource {
+    /// The client named the provider in the force-provider header
+    Forced,
+    /// The router model matched a routing preference
+    Router,
+    /// The system prompt named the route, see [`RouterModelOptions::system_route_directive`]
+    Directive,
+    /// Nothing was routed, the request goes to the model it asked for or the default provider
+    Fallback,
+}
+
+impl DecisionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecisionSource::Forced => "forced",
+            DecisionSource::Router => "router",
+            DecisionSource::Directive => "directive",
+            DecisionSource::Fallback => "fallback",
+        }
+    }
+}
+
+/// Outcome of routing a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingDecision {
+    /// The routing preference the router matched
+    pub route: Option<String>,
+    /// The provider to send the request to, `None` on fallback
+    pub model: Option<String>,
+    /// Time spent waiting for the router model, zero when it wasn't called
+    pub latency: Duration,
+    pub source: DecisionSource,
+}
+
+impl RoutingDecision {
+    fn fallback(latency: Duration) -> Self {
+        RoutingDecision {
+            route: None,
+            model: None,
+            latency,
+            source: DecisionSource::Fallback,
+        }
+    }
+}
+
 impl RouterService {
     pub fn new(
         providers: Vec<LlmProvider>,
         router_url: String,
         routing_model_name: String,
         routing_provider_name: String,
-    ) -> Self {
+        router_client: RouterClientConfig,
+    ) -> Result<Self> {
+        validate_unique_route_names(&providers)?;
+        let router_client = router_client
+            .build_client()
+            .map_err(RoutingError::RouterClient)?;
+
+        let provider_names = providers
+            .iter()
+            .map(|provider| provider.name.clone())
+            .collect::<HashSet<String>>();
+
+        let default_provider = providers
+            .iter()
+            .find(|provider| provider.default.unwrap_or_default())
+            .map(|provider| provider.name.clone());
+
+        let provider_addresses = providers
+            .iter()
+            .filter_map(|provider| {
+                let endpoint = provider.endpoint.as_deref()?;
+                parse_host_port(endpoint, provider.port)
+                    .map(|address| (provider.name.clone(), address))
+            })
+            .collect::<HashMap<String, (String, u16)>>();
+
+        let mut provider_upstreams = HashMap::new();
+        for provider in &providers {
+            let upstream = ProviderUpstream::from_provider(provider).map_err(|reason| {
+                RoutingError::InvalidUpstreamRequest {
+                    provider: provider.name.clone(),
+                    reason,
+                }
+            })?;
+            if let Some(upstream) = upstream {
+                provider_upstreams.insert(provider.name.clone(), upstream);
+            }
+        }
+
+        let vision_providers = providers
+            .iter()
+            .filter(|provider| provider.vision.unwrap_or_default())
+            .map(|provider| provider.name.clone())
+            .collect::<HashSet<String>>();
+
+        let non_streaming_providers = providers
+            .iter()
+            .filter(|provider| provider.stream == Some(false))
+            .map(|provider| provider.name.clone())
+            .collect::<HashSet<String>>();
+
+        let providers_without_tools = providers
+            .iter()
+            .filter(|provider| provider.tools == Some(false))
+            .map(|provider| provider.name.clone())
+            .collect::<HashSet<String>>();
+
         let providers_with_usage = providers
             .iter()
             .filter(|provider| provider.routing_preferences.is_some())
@@ -59,33 +293,279 @@ impl RouterService {
             .collect();
 
         let router_model = Arc::new(router_model_v1::RouterModelV1::new(
-            llm_routes,
+            llm_routes.clone(),
             routing_model_name.clone(),
             router_model_v1::MAX_TOKEN_LEN,
+            RouterSampling::default(),
         ));
 
-        RouterService {
+        Ok(RouterService {
             router_url,
+            router_client,
             client: reqwest::Client::new(),
             router_model,
+            router_model_options: RouterModelOptions::default(),
+            router_sampling: RouterSampling::default(),
             routing_provider_name,
             llm_usage_defined: !providers_with_usage.is_empty(),
+            provider_names,
+            default_provider,
+            provider_addresses,
+            provider_upstreams,
+            self_address: None,
+            llm_routes,
+            vision_providers,
+            non_streaming_providers,
+            providers_without_tools,
+            log_redaction: LogRedaction::default(),
+        })
+    }
+
+    /// Change how the routing prompt is built, see [`RouterModelOptions`]
+    pub fn with_router_model_options(mut self, options: RouterModelOptions) -> Self {
+        self.router_model_options = options;
+        self.rebuild_router_model();
+        self
+    }
+
+    /// Change the sampling parameters of the routing call, see [`RouterSampling`]
+    pub fn with_router_sampling(mut self, sampling: RouterSampling) -> Self {
+        self.router_sampling = sampling;
+        self.rebuild_router_model();
+        self
+    }
+
+    fn rebuild_router_model(&mut self) {
+        self.router_model = Arc::new(
+            router_model_v1::RouterModelV1::new(
+                self.llm_routes.clone(),
+                self.router_model.get_model_name(),
+                router_model_v1::MAX_TOKEN_LEN,
+                self.router_sampling,
+            )
+            .with_options(self.router_model_options),
+        );
+    }
+
+    /// Whether the provider can stream responses. Providers are assumed to stream unless
+    /// configured with `stream: false`.
+    pub fn supports_streaming(&self, provider_name: &str) -> bool {
+        !self.non_streaming_providers.contains(provider_name)
+    }
+
+    /// Narrow the routing preferences to the providers able to serve the request: vision
+    /// capable ones when the latest user message carries images, and ones that can call tools
+    /// when the request declares tools. Returns the preferences unchanged otherwise.
+    pub fn capable_usage_preferences(
+        &self,
+        messages: &[Message],
+        tools: &[Value],
+        usage_preferences: Option<Vec<ModelUsagePreference>>,
+    ) -> Option<Vec<ModelUsagePreference>> {
+        let has_images = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == USER_ROLE)
+            .and_then(|message| message.content.as_ref())
+            .is_some_and(|content| content.has_images());
+        let has_tools = !tools.is_empty();
+
+        if !has_images && !has_tools {
+            return usage_preferences;
+        }
+
+        // sorted by model, so the router prompt doesn't change with the map's iteration order
+        let usage_preferences = usage_preferences.unwrap_or_else(|| {
+            let mut preferences: Vec<ModelUsagePreference> = self
+                .llm_routes
+                .iter()
+                .map(|(provider, routing_preferences)| ModelUsagePreference {
+                    model: provider.clone(),
+                    routing_preferences: routing_preferences.clone(),
+                })
+                .collect();
+            preferences.sort_by(|a, b| a.model.cmp(&b.model));
+            preferences
+        });
+
+        Some(
+            usage_preferences
+                .into_iter()
+                .filter(|pref| !has_images || self.vision_providers.contains(&pref.model))
+                .filter(|pref| !has_tools || !self.providers_without_tools.contains(&pref.model))
+                .collect(),
+        )
+    }
+
+    /// Returns the upstream URL for a request routed to the given provider, when the provider
+    /// declares its own base URL. Otherwise the caller falls back to the global endpoint.
+    /// The provider's `path` replaces the request path, and its `query_params` are appended.
+    pub fn upstream_endpoint(&self, provider_name: &str, request_path: &str) -> Option<String> {
+        self.provider_upstreams
+            .get(provider_name)
+            .map(|upstream| upstream.url(request_path))
+    }
+
+    /// HTTP method of requests to the given provider, `POST` unless its config says otherwise
+    pub fn upstream_method(&self, provider_name: &str) -> reqwest::Method {
+        self.provider_upstreams
+            .get(provider_name)
+            .map_or(reqwest::Method::POST, |upstream| upstream.method.clone())
+    }
+
+    /// Record the address this gateway listens on, so providers that resolve back to it are
+    /// refused instead of looping requests through the gateway forever.
+    pub fn with_self_address(mut self, bind_address: &str) -> Self {
+        self.self_address = parse_host_port(bind_address, None);
+        self
+    }
+
+    /// Use `client` for the calls to the upstreams, e.g. one trusting a private CA. The
+    /// routing model is called with its own client, see [`RouterClientConfig`].
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Redact message content from the request bodies this service and its handlers log
+    pub fn with_log_redaction(mut self, log_redaction: LogRedaction) -> Self {
+        self.log_redaction = log_redaction;
+        self
+    }
+
+    pub fn log_redaction(&self) -> LogRedaction {
+        self.log_redaction
+    }
+
+    /// The http client shared by every call to the upstreams
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Returns an error when the provider's endpoint is this gateway's own address.
+    pub fn check_routing_loop(&self, provider_name: &str) -> Result<()> {
+        let (Some((self_host, self_port)), Some((host, port))) = (
+            self.self_address.as_ref(),
+            self.provider_addresses.get(provider_name),
+        ) else {
+            return Ok(());
+        };
+
+        let same_host = host == self_host || (is_local_host(host) && is_local_host(self_host));
+        if same_host && port == self_port {
+            return Err(RoutingError::RoutingLoop {
+                provider: provider_name.to_string(),
+                address: format!("{}:{}", host, port),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The provider for a request the router found no route for: the model the client asked
+    /// for when it names a configured provider, the provider marked `default` otherwise. Without
+    /// a default the client's model is passed on as-is.
+    pub fn unrouted_provider(&self, requested_model: &str) -> String {
+        match &self.default_provider {
+            Some(default_provider) if !self.provider_names.contains(requested_model) => {
+                default_provider.clone()
+            }
+            _ => requested_model.to_string(),
+        }
+    }
+
+    /// Returns the provider the client asked for through the force-provider header, if any.
+    /// A header naming a provider that is not configured is an error.
+    pub fn forced_provider(&self, headers: &header::HeaderMap) -> Result<Option<String>> {
+        let forced_provider = match headers.get(ARCH_FORCE_PROVIDER_HEADER) {
+            Some(value) => value.to_str().unwrap_or_default().trim().to_string(),
+            None => return Ok(None),
+        };
+
+        if !self.provider_names.contains(&forced_provider) {
+            return Err(RoutingError::UnknownForcedProvider(forced_provider));
+        }
+
+        Ok(Some(forced_provider))
+    }
+
+    /// Resolve the provider for a request: a forced provider from the request headers
+    /// takes precedence and skips the router model entirely.
+    pub async fn determine_route_for_request(
+        &self,
+        headers: &header::HeaderMap,
+        messages: &[Message],
+        tools: &[Value],
+        seed: Option<i64>,
+        trace_parent: Option<String>,
+        usage_preferences: Option<Vec<ModelUsagePreference>>,
+    ) -> Result<RoutingDecision> {
+        if let Some(forced_provider) = self.forced_provider(headers)? {
+            info!(
+                "skipping arch-router, provider forced by request header: {}",
+                forced_provider
+            );
+            return Ok(RoutingDecision {
+                route: None,
+                model: Some(forced_provider),
+                latency: Duration::ZERO,
+                source: DecisionSource::Forced,
+            });
         }
+
+        self.determine_route(messages, tools, seed, trace_parent, usage_preferences)
+            .await
     }
 
     pub async fn determine_route(
         &self,
         messages: &[Message],
+        tools: &[Value],
+        seed: Option<i64>,
         trace_parent: Option<String>,
         usage_preferences: Option<Vec<ModelUsagePreference>>,
-    ) -> Result<Option<(String, String)>> {
+    ) -> Result<RoutingDecision> {
         if !self.llm_usage_defined {
-            return Ok(None);
+            return Ok(RoutingDecision::fallback(Duration::ZERO));
         }
 
-        let router_request = self
+        let usage_preferences = self.capable_usage_preferences(messages, tools, usage_preferences);
+        if usage_preferences
+            .as_ref()
+            .is_some_and(|prefs| prefs.is_empty())
+        {
+            info!("no capable provider declares routing preferences for this request, skipping arch-router");
+            return Ok(RoutingDecision::fallback(Duration::ZERO));
+        }
+
+        if let Some((route, model)) = self
             .router_model
-            .generate_request(messages, &usage_preferences);
+            .route_directive(messages, &usage_preferences)
+        {
+            info!(
+                "system prompt routes the request to route: {}, model: {}, skipping arch-router",
+                route, model
+            );
+            return Ok(RoutingDecision {
+                route: Some(route),
+                model: Some(model),
+                latency: Duration::ZERO,
+                source: DecisionSource::Directive,
+            });
+        }
+
+        let router_request =
+            match self
+                .router_model
+                .generate_request(messages, tools, seed, &usage_preferences)
+            {
+                Ok(router_request) => router_request,
+                Err(RoutingModelError::NoUserMessage) => {
+                    info!("conversation has no user message to route on, skipping arch-router");
+                    return Ok(RoutingDecision::fallback(Duration::ZERO));
+                }
+                Err(err) => return Err(err.into()),
+            };
 
         debug!(
             "sending request to arch-router model: {}, endpoint: {}",
@@ -95,7 +575,7 @@ impl RouterService {
 
         debug!(
             "arch request body: {}",
-            &serde_json::to_string(&router_request).unwrap(),
+            shorten_string(&self.log_redaction.value(&router_request)),
         );
 
         let mut llm_route_request_headers = header::HeaderMap::new();
@@ -121,17 +601,31 @@ impl RouterService {
             header::HeaderValue::from_static("arch-router"),
         );
 
+        // the fields left empty are recorded once the router has answered
+        let span = info_span!(
+            "arch_router",
+            routing_model = %self.router_model.get_model_name(),
+            route = field::Empty,
+            selected_model = field::Empty,
+            prompt_tokens = field::Empty,
+            completion_tokens = field::Empty,
+            latency_ms = field::Empty,
+        );
         let start_time = std::time::Instant::now();
-        let res = self
-            .client
-            .post(&self.router_url)
-            .headers(llm_route_request_headers)
-            .body(serde_json::to_string(&router_request).unwrap())
-            .send()
-            .await?;
-
-        let body = res.text().await?;
+        let body = async {
+            self.router_client
+                .post(&self.router_url)
+                .headers(llm_route_request_headers)
+                .body(serde_json::to_string(&router_request).unwrap())
+                .send()
+                .await?
+                .text()
+                .await
+        }
+        .instrument(span.clone())
+        .await?;
         let router_response_time = start_time.elapsed();
+        span.record("latency_ms", router_response_time.as_millis() as u64);
 
         let chat_completion_response: ChatCompletionsResponse = match serde_json::from_str(&body) {
             Ok(response) => response,
@@ -147,32 +641,837 @@ impl RouterService {
                 ));
             }
         };
+        if let Some(usage) = &chat_completion_response.usage {
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+        }
 
         if chat_completion_response.choices.is_empty() {
             warn!("No choices in router response: {}", body);
-            return Ok(None);
+            return Ok(RoutingDecision::fallback(router_response_time));
         }
 
-        if let Some(ContentType::Text(content)) =
-            &chat_completion_response.choices[0].message.content
+        // a reply cut off at the token limit may hold a partial route name, which route
+        // matching could mistake for a different route
+        if chat_completion_response.choices[0].parsed_finish_reason() == Some(FinishReason::Length)
         {
-            let parsed_response = self
-                .router_model
-                .parse_response(content, &usage_preferences)?;
-            info!(
-                "arch-router determined route: {}, selected_model: {:?}, response time: {}ms",
-                content.replace("\n", "\\n"),
-                parsed_response,
-                router_response_time.as_millis()
-            );
+            warn!("router response was cut off at the token limit: {}", body);
+            return Ok(RoutingDecision::fallback(router_response_time));
+        }
+
+        let content = match router_response_text(&chat_completion_response) {
+            Some(content) => content,
+            None => {
+                warn!("router response has no message content: {}", body);
+                return Ok(RoutingDecision::fallback(router_response_time));
+            }
+        };
+
+        let parsed_response = self
+            .router_model
+            .parse_response(&content, &usage_preferences)?;
+        info!(
+            "arch-router determined route: {}, selected_model: {:?}, response time: {}ms",
+            content.replace("\n", "\\n"),
+            parsed_response,
+            router_response_time.as_millis()
+        );
+        if let Some((route, model)) = &parsed_response {
+            span.record("route", route.as_str());
+            span.record("selected_model", model.as_str());
+        }
+
+        Ok(match parsed_response {
+            Some((route, model)) => RoutingDecision {
+                route: Some(route),
+                model: Some(model),
+                latency: router_response_time,
+                source: DecisionSource::Router,
+            },
+            None => RoutingDecision::fallback(router_response_time),
+        })
+    }
+}
+
+/// Text of the first choice in a router response. Multipart content is flattened to its
+/// text parts, the same way messages are rendered for the router prompt.
+fn router_response_text(response: &ChatCompletionsResponse) -> Option<String> {
+    match response.choices.first()?.message.content.as_ref()? {
+        ContentType::Text(content) => Some(content.clone()),
+        multi_part @ ContentType::MultiPart(_) => Some(multi_part.to_string()),
+    }
+}
+
+/// Route names map to exactly one provider, a name declared twice would silently route
+/// all of its traffic to whichever provider happened to be inserted last.
+fn validate_unique_route_names(providers: &[LlmProvider]) -> Result<()> {
+    let mut route_providers: HashMap<&str, Vec<String>> = HashMap::new();
+    for provider in providers {
+        for pref in provider.routing_preferences.iter().flatten() {
+            route_providers
+                .entry(pref.name.as_str())
+                .or_default()
+                .push(provider.name.clone());
+        }
+    }
+
+    let mut duplicates: Vec<(&str, Vec<String>)> = route_providers
+        .into_iter()
+        .filter(|(_, providers)| providers.len() > 1)
+        .collect();
+    duplicates.sort();
+
+    match duplicates.into_iter().next() {
+        Some((route, providers)) => Err(RoutingError::DuplicateRouteName {
+            route: route.to_string(),
+            providers,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Split an endpoint such as `http://host:port/path` or `host` into host and port, using
+/// `default_port` when the endpoint has none. Endpoints without any port are not resolved.
+fn parse_host_port(endpoint: &str, default_port: Option<u16>) -> Option<(String, u16)> {
+    let authority = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest)
+        .split('/')
+        .next()?;
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') => match port.parse::<u16>() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (authority, None),
+        },
+        _ => (authority, None),
+    };
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((host.to_lowercase(), port.or(default_port)?))
+}
+
+/// Hosts that all reach this machine; a gateway bound to 0.0.0.0 is reachable through any of them
+fn is_local_host(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "0.0.0.0" | "::1" | "::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nothing listens on this address, so any call to the router model fails
+    const UNREACHABLE_ROUTER_URL: &str = "http://127.0.0.1:1/v1/chat/completions";
+
+    const PROVIDERS: &str = r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+- name: claude-3-5-sonnet
+  provider_interface: claude
+  model: claude-3-5-sonnet
+"#;
+
+    fn try_router_service_at(
+        providers_yaml: &str,
+        router_url: &str,
+        router_client: RouterClientConfig,
+    ) -> Result<RouterService> {
+        let providers: Vec<LlmProvider> = serde_yaml::from_str(providers_yaml).unwrap();
+        RouterService::new(
+            providers,
+            router_url.to_string(),
+            "Arch-Router".to_string(),
+            "arch-router".to_string(),
+            router_client,
+        )
+    }
+
+    fn try_router_service(providers_yaml: &str) -> Result<RouterService> {
+        try_router_service_at(
+            providers_yaml,
+            UNREACHABLE_ROUTER_URL,
+            RouterClientConfig::default(),
+        )
+    }
+
+    /// A router service for `providers_yaml` whose router model can't be reached
+    fn router_service(providers_yaml: &str) -> RouterService {
+        try_router_service(providers_yaml).unwrap()
+    }
 
-            if let Some(ref parsed_response) = parsed_response {
-                return Ok(Some(parsed_response.clone()));
+    fn router_service_at(router_url: &str, router_client: RouterClientConfig) -> RouterService {
+        try_router_service_at(PROVIDERS, router_url, router_client).unwrap()
+    }
+
+    fn user_messages() -> Vec<Message> {
+        vec![Message::new("write a quicksort in rust".to_string())]
+    }
+
+    #[tokio::test]
+    async fn test_forced_provider_skips_router() {
+        let router_service = router_service(PROVIDERS);
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            ARCH_FORCE_PROVIDER_HEADER,
+            header::HeaderValue::from_static("claude-3-5-sonnet"),
+        );
+
+        let decision = router_service
+            .determine_route_for_request(&headers, &user_messages(), &[], None, None, None)
+            .await
+            .expect("forced provider must not call the router model");
+
+        assert_eq!(
+            decision,
+            RoutingDecision {
+                route: None,
+                model: Some("claude-3-5-sonnet".to_string()),
+                latency: Duration::ZERO,
+                source: DecisionSource::Forced,
             }
+        );
+    }
+
+    /// Answer every routing call with `content` as the router model's reply
+    async fn mock_router(content: &'static str) -> String {
+        mock_router_finishing(content, "stop").await
+    }
+
+    /// Like [`mock_router`], with the reply finishing for `finish_reason`
+    async fn mock_router_finishing(content: &'static str, finish_reason: &'static str) -> String {
+        use http_body_util::Full;
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let body = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": finish_reason
+            }],
+            "usage": {"prompt_tokens": 120, "completion_tokens": 8, "total_tokens": 128}
+        })
+        .to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let body = body.clone();
+                let service = service_fn(move |_req: hyper::Request<hyper::body::Incoming>| {
+                    let body = body.clone();
+                    async move {
+                        Ok::<_, hyper::Error>(hyper::Response::new(Full::new(bytes::Bytes::from(
+                            body,
+                        ))))
+                    }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        format!("http://{}/v1/chat/completions", address)
+    }
+
+    #[tokio::test]
+    async fn test_routing_decision_source() {
+        let decide = |router_url: String| async move {
+            router_service_at(&router_url, RouterClientConfig::default())
+                .determine_route_for_request(
+                    &header::HeaderMap::new(),
+                    &user_messages(),
+                    &[],
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap()
+        };
+
+        let decision = decide(mock_router(r#"{"route": "code generation"}"#).await).await;
+        assert_eq!(decision.route.as_deref(), Some("code generation"));
+        assert_eq!(decision.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(decision.source, DecisionSource::Router);
+        assert!(decision.latency > Duration::ZERO);
 
-            Ok(None)
-        } else {
-            Ok(None)
+        // the router was asked but matched no route
+        let decision = decide(mock_router(r#"{"route": "other"}"#).await).await;
+        assert_eq!(decision.route, None);
+        assert_eq!(decision.model, None);
+        assert_eq!(decision.source, DecisionSource::Fallback);
+
+        // a reply cut off at the token limit isn't trusted, even when it parses
+        let router_url = mock_router_finishing(r#"{"route": "code generation"}"#, "length").await;
+        let decision = decide(router_url).await;
+        assert_eq!(decision.route, None);
+        assert_eq!(decision.source, DecisionSource::Fallback);
+    }
+
+    /// Collects the fields of every `arch_router` span, as given at creation or recorded later
+    #[derive(Clone, Default)]
+    struct RouterSpans(Arc<std::sync::Mutex<HashMap<String, String>>>);
+
+    impl tracing::field::Visit for RouterSpans {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for RouterSpans
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().name() == "arch_router" {
+                attrs.record(&mut self.clone());
+            }
         }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if ctx
+                .span(id)
+                .is_some_and(|span| span.name() == "arch_router")
+            {
+                values.record(&mut self.clone());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_inference_span() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let spans = RouterSpans::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+
+        let router_url = mock_router(r#"{"route": "code generation"}"#).await;
+        router_service_at(&router_url, RouterClientConfig::default())
+            .determine_route(&user_messages(), &[], None, None, None)
+            .await
+            .unwrap();
+
+        let fields = spans.0.lock().unwrap().clone();
+        assert_eq!(fields["routing_model"], "Arch-Router");
+        assert_eq!(fields["route"], "code generation");
+        assert_eq!(fields["selected_model"], "gpt-4o");
+        assert_eq!(fields["prompt_tokens"], "120");
+        assert_eq!(fields["completion_tokens"], "8");
+        assert!(fields["latency_ms"].parse::<u64>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_system_route_directive_skips_router() {
+        use common::consts::SYSTEM_ROLE;
+
+        let messages = vec![
+            Message {
+                role: SYSTEM_ROLE.to_string(),
+                content: Some(ContentType::Text(
+                    "You are a coding assistant.\narchgw-route: code generation".to_string(),
+                )),
+                name: None,
+                tool_calls: None,
+            },
+            Message::new("sort this list for me".to_string()),
+        ];
+        let decide = |router_service: RouterService, messages: Vec<Message>| async move {
+            router_service
+                .determine_route_for_request(
+                    &header::HeaderMap::new(),
+                    &messages,
+                    &[],
+                    None,
+                    None,
+                    None,
+                )
+                .await
+        };
+        let with_directives = || {
+            router_service(PROVIDERS).with_router_model_options(RouterModelOptions {
+                system_route_directive: true,
+                ..Default::default()
+            })
+        };
+
+        let decision = decide(with_directives(), messages.clone())
+            .await
+            .expect("a route directive must not call the router model");
+        assert_eq!(
+            decision,
+            RoutingDecision {
+                route: Some("code generation".to_string()),
+                model: Some("gpt-4o".to_string()),
+                latency: Duration::ZERO,
+                source: DecisionSource::Directive,
+            }
+        );
+
+        // an unknown route is left to the router, as is any directive when the mode is off
+        let mut unknown_route = messages.clone();
+        unknown_route[0].content = Some(ContentType::Text("archgw-route: poetry".to_string()));
+        let result = decide(with_directives(), unknown_route).await;
+        assert!(matches!(result, Err(RoutingError::RequestError(_))));
+        let result = decide(router_service(PROVIDERS), messages).await;
+        assert!(matches!(result, Err(RoutingError::RequestError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_without_forced_provider_router_is_invoked() {
+        let router_service = router_service(PROVIDERS);
+
+        let result = router_service
+            .determine_route_for_request(
+                &header::HeaderMap::new(),
+                &user_messages(),
+                &[],
+                None,
+                None,
+                None,
+            )
+            .await;
+
+        assert!(matches!(result, Err(RoutingError::RequestError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_router_client_timeout() {
+        // a router that accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let router_service = router_service_at(
+            &format!("http://{}/v1/chat/completions", address),
+            RouterClientConfig {
+                timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        );
+        let start = std::time::Instant::now();
+        let result = router_service
+            .determine_route_for_request(
+                &header::HeaderMap::new(),
+                &user_messages(),
+                &[],
+                None,
+                None,
+                None,
+            )
+            .await;
+
+        match result {
+            Err(RoutingError::RequestError(err)) => assert!(err.is_timeout(), "{}", err),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_router_client_uses_upstream_tls() {
+        let config = RouterClientConfig {
+            tls: UpstreamTls::default().with_client_identity(b"not a cert".to_vec(), Vec::new()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.build_client(),
+            Err(TlsError::Invalid {
+                what: "client certificate",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unroutable_requests_use_default_provider() {
+        // no provider is marked default: the requested model goes upstream unchanged
+        assert_eq!(
+            router_service(PROVIDERS).unrouted_provider("gpt-4-turbo"),
+            "gpt-4-turbo"
+        );
+
+        let router_service = router_service(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+- name: claude-3-5-sonnet
+  provider_interface: claude
+  model: claude-3-5-sonnet
+  default: true
+"#,
+        );
+
+        assert_eq!(
+            router_service.unrouted_provider("gpt-4-turbo"),
+            "claude-3-5-sonnet"
+        );
+        // a configured provider is used as asked
+        assert_eq!(router_service.unrouted_provider("gpt-4o"), "gpt-4o");
+    }
+
+    #[test]
+    fn test_unknown_forced_provider_is_rejected() {
+        let router_service = router_service(PROVIDERS);
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            ARCH_FORCE_PROVIDER_HEADER,
+            header::HeaderValue::from_static("does-not-exist"),
+        );
+
+        assert!(matches!(
+            router_service.forced_provider(&headers),
+            Err(RoutingError::UnknownForcedProvider(name)) if name == "does-not-exist"
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_route_names_are_rejected() {
+        let result = try_router_service(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+- name: claude-3-5-sonnet
+  provider_interface: claude
+  model: claude-3-5-sonnet
+  routing_preferences:
+    - name: code generation
+      description: writing code
+"#,
+        );
+
+        match result {
+            Err(RoutingError::DuplicateRouteName { route, providers }) => {
+                assert_eq!(route, "code generation");
+                assert_eq!(providers, vec!["gpt-4o", "claude-3-5-sonnet"]);
+            }
+            _ => panic!("expected duplicate route name error"),
+        }
+    }
+
+    #[test]
+    fn test_self_referential_provider_is_a_routing_loop() {
+        let router_service = router_service(
+            r#"
+- name: arch-loop
+  provider_interface: arch
+  model: arch-loop
+  endpoint: localhost
+  port: 9091
+- name: arch-remote
+  provider_interface: arch
+  model: arch-remote
+  endpoint: http://arch.internal:9091/v1
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+"#,
+        )
+        .with_self_address("0.0.0.0:9091");
+
+        match router_service.check_routing_loop("arch-loop") {
+            Err(RoutingError::RoutingLoop { provider, address }) => {
+                assert_eq!(provider, "arch-loop");
+                assert_eq!(address, "localhost:9091");
+            }
+            other => panic!("expected routing loop error, got {:?}", other),
+        }
+        assert!(router_service.check_routing_loop("arch-remote").is_ok());
+        assert!(router_service.check_routing_loop("gpt-4o").is_ok());
+    }
+
+    #[test]
+    fn test_parse_host_port() {
+        assert_eq!(
+            parse_host_port("http://localhost:9091/v1/chat/completions", None),
+            Some(("localhost".to_string(), 9091))
+        );
+        assert_eq!(
+            parse_host_port("api.openai.com", Some(443)),
+            Some(("api.openai.com".to_string(), 443))
+        );
+        assert_eq!(parse_host_port("api.openai.com", None), None);
+        // a port written in the endpoint wins over the provider's port
+        assert_eq!(
+            parse_host_port("localhost:9091", Some(443)),
+            Some(("localhost".to_string(), 9091))
+        );
+        assert_eq!(
+            parse_host_port("[::1]:9091", None),
+            Some(("::1".to_string(), 9091))
+        );
+    }
+
+    #[test]
+    fn test_upstream_endpoint_per_provider() {
+        let router_service = router_service(
+            r#"
+- name: local-llama
+  provider_interface: openai
+  model: llama3.2
+  base_url: http://localhost:11434
+- name: vllm
+  provider_interface: openai
+  model: qwen
+  base_url: https://vllm.internal:8000/
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+"#,
+        );
+
+        assert_eq!(
+            router_service.upstream_endpoint("local-llama", "/v1/chat/completions"),
+            Some("http://localhost:11434/v1/chat/completions".to_string())
+        );
+        assert_eq!(
+            router_service.upstream_endpoint("vllm", "/v1/chat/completions"),
+            Some("https://vllm.internal:8000/v1/chat/completions".to_string())
+        );
+        // providers without a base url, and unknown models, use the global endpoint
+        assert_eq!(
+            router_service.upstream_endpoint("gpt-4o", "/v1/chat/completions"),
+            None
+        );
+        assert_eq!(
+            router_service.upstream_endpoint("gpt-4o-mini", "/v1/chat/completions"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_upstream_endpoint_template() {
+        let router_service = router_service(
+            r#"
+- name: azure-gpt-4o
+  provider_interface: openai
+  model: gpt-4o-prod
+  base_url: https://example.openai.azure.com/
+  path: /openai/deployments/{model}/chat/completions
+  query_params:
+    api-version: 2024-06-01
+    client: archgw {name}
+- name: ollama
+  provider_interface: openai
+  model: llama3.2
+  base_url: http://localhost:11434
+  method: put
+"#,
+        );
+
+        assert_eq!(
+            router_service.upstream_endpoint("azure-gpt-4o", "/v1/chat/completions"),
+            Some(
+                "https://example.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-06-01&client=archgw+azure-gpt-4o"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            router_service.upstream_method("azure-gpt-4o"),
+            reqwest::Method::POST
+        );
+
+        // without a path the client's request path is kept
+        assert_eq!(
+            router_service.upstream_endpoint("ollama", "/v1/chat/completions"),
+            Some("http://localhost:11434/v1/chat/completions".to_string())
+        );
+        assert_eq!(
+            router_service.upstream_method("ollama"),
+            reqwest::Method::PUT
+        );
+        assert_eq!(
+            router_service.upstream_method("unknown"),
+            reqwest::Method::POST
+        );
+
+        let bad_template = try_router_service(
+            r#"
+- name: azure-gpt-4o
+  provider_interface: openai
+  base_url: https://example.openai.azure.com
+  path: /openai/deployments/{deployment}/chat/completions
+"#,
+        );
+        assert!(matches!(
+            bad_template,
+            Err(RoutingError::InvalidUpstreamRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_image_messages_exclude_text_only_providers() {
+        let router_service = router_service(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+  vision: true
+  routing_preferences:
+    - name: image understanding
+      description: describing or answering questions about pictures
+- name: deepseek-coder
+  provider_interface: deepseek
+  model: deepseek-coder
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+"#,
+        );
+
+        let image_messages: Vec<Message> = serde_json::from_value(serde_json::json!([
+            {
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "write the code shown in this screenshot"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/code.png"}}
+                ]
+            }
+        ]))
+        .unwrap();
+
+        let prefs = router_service
+            .capable_usage_preferences(&image_messages, &[], None)
+            .unwrap();
+        let models: Vec<&str> = prefs.iter().map(|pref| pref.model.as_str()).collect();
+        assert_eq!(models, vec!["gpt-4o"]);
+
+        // preferences sent with the request are narrowed the same way
+        let request_prefs = vec![ModelUsagePreference {
+            model: "deepseek-coder".to_string(),
+            routing_preferences: vec![RoutingPreference {
+                name: "code generation".to_string(),
+                description: "generating new code snippets".to_string(),
+            }],
+        }];
+        let prefs = router_service
+            .capable_usage_preferences(&image_messages, &[], Some(request_prefs.clone()))
+            .unwrap();
+        assert!(prefs.is_empty());
+
+        // text-only conversations keep every preference
+        let prefs = router_service
+            .capable_usage_preferences(&user_messages(), &[], Some(request_prefs))
+            .unwrap();
+        assert_eq!(prefs.len(), 1);
+        assert!(router_service
+            .capable_usage_preferences(&user_messages(), &[], None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_requests_with_tools_exclude_providers_without_tools() {
+        let router_service = router_service(
+            r#"
+- name: gpt-4o
+  provider_interface: openai
+  model: gpt-4o
+  routing_preferences:
+    - name: code generation
+      description: generating new code snippets
+- name: legacy-completions
+  provider_interface: openai
+  model: legacy-completions
+  tools: false
+  routing_preferences:
+    - name: chit chat
+      description: casual conversation
+- name: claude-3-5-sonnet
+  provider_interface: claude
+  model: claude-3-5-sonnet
+  routing_preferences:
+    - name: code review
+      description: reviewing existing code
+"#,
+        );
+
+        let tools = vec![serde_json::json!({
+            "type": "function",
+            "function": {"name": "get_weather", "parameters": {"type": "object"}}
+        })];
+
+        let prefs = router_service
+            .capable_usage_preferences(&user_messages(), &tools, None)
+            .unwrap();
+        let models: Vec<&str> = prefs.iter().map(|pref| pref.model.as_str()).collect();
+        // in a stable order, whatever order the routes are stored in
+        assert_eq!(models, vec!["claude-3-5-sonnet", "gpt-4o"]);
+
+        // requests without tools can still go anywhere
+        assert!(router_service
+            .capable_usage_preferences(&user_messages(), &[], None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_router_response_text_handles_multipart_content() {
+        let router_service = router_service(PROVIDERS);
+        let response: ChatCompletionsResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": [{"type": "text", "text": "{\"route\": \"code generation\"}"}]
+                },
+                "finish_reason": "stop"
+            }]
+        }))
+        .unwrap();
+
+        let content = router_response_text(&response).unwrap();
+        let route = router_service
+            .router_model
+            .parse_response(&content, &None)
+            .unwrap();
+        assert_eq!(
+            route,
+            Some(("code generation".to_string(), "gpt-4o".to_string()))
+        );
+
+        let mut empty = response.clone();
+        empty.choices[0].message.content = None;
+        assert_eq!(router_response_text(&empty), None);
+        empty.choices.clear();
+        assert_eq!(router_response_text(&empty), None);
     }
 }
@@ -1,11 +1,15 @@
 use common::configuration::ModelUsagePreference;
 use hermesllm::providers::openai::types::{ChatCompletionsRequest, Message};
+use serde_json::Value;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum RoutingModelError {
     #[error("Failed to parse JSON: {0}")]
     JsonError(#[from] serde_json::Error),
+
+    #[error("Conversation has no user message to route on")]
+    NoUserMessage,
 }
 
 pub type Result<T> = std::result::Result<T, RoutingModelError>;
@@ -14,12 +18,23 @@ pub trait RouterModel: Send + Sync {
     fn generate_request(
         &self,
         messages: &[Message],
+        tools: &[Value],
+        seed: Option<i64>,
         usage_preferences: &Option<Vec<ModelUsagePreference>>,
-    ) -> ChatCompletionsRequest;
+    ) -> Result<ChatCompletionsRequest>;
     fn parse_response(
         &self,
         content: &str,
         usage_preferences: &Option<Vec<ModelUsagePreference>>,
     ) -> Result<Option<(String, String)>>;
+    /// The route and model the conversation itself names, deciding the route without asking
+    /// the router model. `None` lets the router decide.
+    fn route_directive(
+        &self,
+        _messages: &[Message],
+        _usage_preferences: &Option<Vec<ModelUsagePreference>>,
+    ) -> Option<(String, String)> {
+        None
+    }
     fn get_model_name(&self) -> String;
 }
@@ -4,9 +4,13 @@ use common::{
     configuration::{ModelUsagePreference, RoutingPreference},
     consts::{SYSTEM_ROLE, TOOL_ROLE, USER_ROLE},
 };
-use hermesllm::providers::openai::types::{ChatCompletionsRequest, ContentType, Message};
+use hermesllm::clients::TOKEN_LENGTH_DIVISOR;
+use hermesllm::providers::openai::types::{
+    ChatCompletionsRequest, ContentType, Message, MultiPartContentType,
+};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use serde_json::Value;
+use tracing::{debug, enabled, info, warn, Level};
 
 use super::router_model::{RouterModel, RoutingModelError};
 
@@ -31,18 +35,72 @@ Based on your analysis, provide your response in the following JSON formats if y
 {"route": "route_name"}
 "#;
 
+/// Added right after the conversation, around the tool names, when they are part of the prompt
+const ROUTER_TOOLS_PROMPT_START: &str =
+    "\nThe user can call the tools listed within <tools></tools> XML tags:\n<tools>\n";
+const ROUTER_TOOLS_PROMPT_END: &str = "\n</tools>\n";
+
 pub type Result<T> = std::result::Result<T, RoutingModelError>;
+
+/// Optional changes to how the routing prompt is built and the router's answer is read,
+/// all off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouterModelOptions {
+    /// List the names of the request's tools in the routing prompt, so routes can be
+    /// described in terms of the tools a request brings
+    pub include_tools: bool,
+    /// Trim the conversation so it starts and ends on a user message, and refuse to route
+    /// one without any, instead of only warning about it
+    pub strict_conversation: bool,
+    /// Recover from small deviations in the route name the router answers with: compare names
+    /// ignoring case and `-`, `_` and whitespace differences, and accept the closest configured
+    /// route within this many edits. `None` only accepts exact names.
+    pub route_match_distance: Option<usize>,
+    /// Send the request's `seed` along with the routing call, so a router model that honours
+    /// it routes the same conversation and seed the same way every time
+    pub propagate_seed: bool,
+    /// Route requests whose system prompt carries an `archgw-route: <route name>` line to that
+    /// route directly, without calling the router model
+    pub system_route_directive: bool,
+}
+
+/// Start of the system prompt line naming the route of a request, see
+/// [`RouterModelOptions::system_route_directive`]
+pub const ROUTE_DIRECTIVE_PREFIX: &str = "archgw-route:";
+
+/// Sampling parameters of the routing call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouterSampling {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+impl Default for RouterSampling {
+    /// A near-greedy temperature and the routing model's own defaults otherwise
+    fn default() -> Self {
+        RouterSampling {
+            temperature: Some(0.01),
+            top_p: None,
+            max_tokens: None,
+        }
+    }
+}
+
 pub struct RouterModelV1 {
     llm_route_json_str: String,
     llm_route_to_model_map: HashMap<String, String>,
     routing_model: String,
     max_token_length: usize,
+    sampling: RouterSampling,
+    options: RouterModelOptions,
 }
 impl RouterModelV1 {
     pub fn new(
         llm_routes: HashMap<String, Vec<RoutingPreference>>,
         routing_model: String,
         max_token_length: usize,
+        sampling: RouterSampling,
     ) -> Self {
         let llm_route_values: Vec<RoutingPreference> =
             llm_routes.values().flatten().cloned().collect();
@@ -56,10 +114,17 @@ impl RouterModelV1 {
         RouterModelV1 {
             routing_model,
             max_token_length,
+            sampling,
             llm_route_json_str,
             llm_route_to_model_map,
+            options: RouterModelOptions::default(),
         }
     }
+
+    pub fn with_options(mut self, options: RouterModelOptions) -> Self {
+        self.options = options;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,34 +132,96 @@ struct LlmRouterResponse {
     pub route: Option<String>,
 }
 
-const TOKEN_LENGTH_DIVISOR: usize = 4; // Approximate token length divisor for UTF-8 characters
+/// Tokens of an oversized latest user message kept however little of the budget is left
+const MIN_LATEST_MESSAGE_TOKEN_LEN: usize = 128;
+
+fn text_message(role: &str, content: String) -> Message {
+    Message {
+        role: role.to_string(),
+        content: Some(ContentType::Text(content)),
+        name: None,
+        tool_calls: None,
+    }
+}
+
+/// The text the router sees for a message. Content without any text, e.g. a message that
+/// is only an image, is described by placeholders for its parts rather than left blank.
+fn routing_text(content: &ContentType) -> String {
+    let text = content.to_string();
+    let ContentType::MultiPart(parts) = content else {
+        return text;
+    };
+    if !text.trim().is_empty() {
+        return text;
+    }
+    parts
+        .iter()
+        .filter_map(|part| match part.content_type {
+            MultiPartContentType::ImageUrl => Some("[image]"),
+            MultiPartContentType::InputAudio => Some("[audio]"),
+            MultiPartContentType::File => Some("[file]"),
+            MultiPartContentType::Text | MultiPartContentType::Unknown(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The end of `text` that fits in `token_len` estimated tokens
+fn keep_last_tokens(text: String, token_len: usize) -> String {
+    let max_len = token_len * TOKEN_LENGTH_DIVISOR;
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut start = text.len() - max_len;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
 
 impl RouterModel for RouterModelV1 {
     fn generate_request(
         &self,
         messages: &[Message],
+        tools: &[Value],
+        seed: Option<i64>,
         usage_preferences_from_request: &Option<Vec<ModelUsagePreference>>,
-    ) -> ChatCompletionsRequest {
+    ) -> Result<ChatCompletionsRequest> {
         // remove system prompt, tool calls, tool call response and messages without content
         // if content is empty its likely a tool call
         // when role == tool its tool call response
-        let messages_vec = messages
+        let mut messages_vec = messages
             .iter()
             .filter(|m| m.role != SYSTEM_ROLE && m.role != TOOL_ROLE && m.content.is_some())
             .collect::<Vec<&Message>>();
 
+        if self.options.strict_conversation {
+            // route on the latest user turn, not on whatever the assistant said after it
+            while messages_vec.last().is_some_and(|m| m.role != USER_ROLE) {
+                messages_vec.pop();
+            }
+            if messages_vec.is_empty() {
+                return Err(RoutingModelError::NoUserMessage);
+            }
+        }
+
         // Following code is to ensure that the conversation does not exceed max token length
         // Note: we use a simple heuristic to estimate token count based on character length to optimize for performance
+        let tool_names = match self.options.include_tools {
+            true => tool_names(tools),
+            false => Vec::new(),
+        };
+        let tool_names_str = serde_json::to_string(&tool_names).unwrap_or_default();
+
         let mut token_count = ARCH_ROUTER_V1_SYSTEM_PROMPT.len() / TOKEN_LENGTH_DIVISOR;
-        let mut selected_messages_list_reversed: Vec<&Message> = vec![];
+        if !tool_names.is_empty() {
+            token_count += tool_names_str.len() / TOKEN_LENGTH_DIVISOR;
+        }
+        let mut selected_messages_list_reversed: Vec<Message> = vec![];
         for (selected_messsage_count, message) in messages_vec.iter().rev().enumerate() {
-            let message_token_count = message
-                .content
-                .as_ref()
-                .unwrap_or(&ContentType::Text("".to_string()))
-                .to_string()
-                .len()
-                / TOKEN_LENGTH_DIVISOR;
+            // we can unwrap here because we have already filtered out messages without content
+            let content = routing_text(message.content.as_ref().unwrap());
+            let message_token_count = content.len() / TOKEN_LENGTH_DIVISOR;
             token_count += message_token_count;
             if token_count > self.max_token_length {
                 debug!(
@@ -105,13 +232,24 @@ impl RouterModel for RouterModelV1 {
                       messages_vec.len()
                   );
                 if message.role == USER_ROLE {
-                    // If message that exceeds max token length is from user, we need to keep it
-                    selected_messages_list_reversed.push(message);
+                    // If message that exceeds max token length is from user, we need to keep it.
+                    // The latest one is trimmed to what's left of the budget, keeping its end,
+                    // which is where the user's current ask usually is.
+                    let content = if selected_messsage_count == 0 {
+                        let budget = self
+                            .max_token_length
+                            .saturating_sub(token_count - message_token_count)
+                            .max(MIN_LATEST_MESSAGE_TOKEN_LEN);
+                        keep_last_tokens(content, budget)
+                    } else {
+                        content
+                    };
+                    selected_messages_list_reversed.push(text_message(&message.role, content));
                 }
                 break;
             }
             // If we are here, it means that the message is within the max token length
-            selected_messages_list_reversed.push(message);
+            selected_messages_list_reversed.push(text_message(&message.role, content));
         }
 
         if selected_messages_list_reversed.is_empty() {
@@ -119,7 +257,18 @@ impl RouterModel for RouterModelV1 {
                 "RouterModelV1: no messages selected, using the last message in the conversation"
             );
             if let Some(last_message) = messages_vec.last() {
-                selected_messages_list_reversed.push(last_message);
+                let content = routing_text(last_message.content.as_ref().unwrap());
+                selected_messages_list_reversed.push(text_message(&last_message.role, content));
+            }
+        }
+
+        if self.options.strict_conversation {
+            // the conversation starts at the oldest selected user message
+            while selected_messages_list_reversed
+                .last()
+                .is_some_and(|m| m.role != USER_ROLE)
+            {
+                selected_messages_list_reversed.pop();
             }
         }
 
@@ -136,35 +285,81 @@ impl RouterModel for RouterModelV1 {
         }
 
         // Reverse the selected messages to maintain the conversation order
-        let selected_conversation_list = selected_messages_list_reversed
-            .iter()
-            .rev()
-            .map(|message| {
-                Message {
-                    role: message.role.clone(),
-                    // we can unwrap here because we have already filtered out messages without content
-                    content: Some(ContentType::Text(
-                        message.content.as_ref().unwrap().to_string(),
-                    )),
-                }
-            })
-            .collect::<Vec<Message>>();
+        selected_messages_list_reversed.reverse();
+        let selected_conversation_list = selected_messages_list_reversed;
 
         // Generate the router request message based on the usage preferences.
         // If preferences are passed in request then we use them otherwise we use the default routing model preferences.
+        let tools = match tool_names.is_empty() {
+            true => None,
+            false => Some(tool_names_str.as_str()),
+        };
         let router_message = match convert_to_router_preferences(usage_preferences_from_request) {
-            Some(prefs) => generate_router_message(&prefs, &selected_conversation_list),
-            None => generate_router_message(&self.llm_route_json_str, &selected_conversation_list),
+            Some(prefs) => generate_router_message(&prefs, &selected_conversation_list, tools),
+            None => generate_router_message(
+                &self.llm_route_json_str,
+                &selected_conversation_list,
+                tools,
+            ),
         };
 
-        ChatCompletionsRequest {
+        let mut extra = HashMap::new();
+        if let Some(seed) = seed.filter(|_| self.options.propagate_seed) {
+            extra.insert("seed".to_string(), Value::from(seed));
+        }
+
+        Ok(ChatCompletionsRequest {
             model: self.routing_model.clone(),
             messages: vec![Message {
                 content: Some(ContentType::Text(router_message)),
                 role: USER_ROLE.to_string(),
+                name: None,
+                tool_calls: None,
             }],
-            temperature: Some(0.01),
+            temperature: self.sampling.temperature,
+            top_p: self.sampling.top_p,
+            max_tokens: self.sampling.max_tokens,
+            extra,
             ..Default::default()
+        })
+    }
+
+    fn route_directive(
+        &self,
+        messages: &[Message],
+        usage_preferences: &Option<Vec<ModelUsagePreference>>,
+    ) -> Option<(String, String)> {
+        if !self.options.system_route_directive {
+            return None;
+        }
+        let route = messages
+            .iter()
+            .filter(|m| m.role == SYSTEM_ROLE)
+            .filter_map(|m| m.content.as_ref())
+            .find_map(|content| {
+                content.to_string().lines().find_map(|line| {
+                    line.trim()
+                        .strip_prefix(ROUTE_DIRECTIVE_PREFIX)
+                        .map(|route| route.trim().to_string())
+                })
+            })?;
+
+        let model = match usage_preferences {
+            Some(usage_preferences) => usage_preferences
+                .iter()
+                .find(|pref| pref.routing_preferences.iter().any(|p| p.name == route))
+                .map(|pref| pref.model.clone()),
+            None => self.llm_route_to_model_map.get(&route).cloned(),
+        };
+        match model {
+            Some(model) => Some((route, model)),
+            None => {
+                warn!(
+                    "system prompt names unknown route `{}`, leaving it to the router",
+                    route
+                );
+                None
+            }
         }
     }
 
@@ -177,14 +372,45 @@ impl RouterModel for RouterModelV1 {
             return Ok(None);
         }
         let router_resp_fixed = fix_json_response(content);
-        let router_response: LlmRouterResponse = serde_json::from_str(router_resp_fixed.as_str())?;
+        // a misconfigured router endpoint (e.g. a plain chat model) answers with prose,
+        // treat anything that isn't route JSON as "no route" rather than an error
+        let router_response: LlmRouterResponse =
+            match serde_json::from_str(router_resp_fixed.as_str()) {
+                Ok(router_response) => router_response,
+                Err(err) => {
+                    warn!(
+                        "router response is not a valid route selection, err: {}, content: {}",
+                        err,
+                        content.replace('\n', "\\n")
+                    );
+                    return Ok(None);
+                }
+            };
 
-        let selected_route = router_response.route.unwrap_or_default().to_string();
+        let mut selected_route = router_response.route.unwrap_or_default().to_string();
 
         if selected_route.is_empty() || selected_route == "other" {
+            self.explain_route(
+                content,
+                usage_preferences.as_ref(),
+                "router chose no route, falling back to the default model",
+            );
             return Ok(None);
         }
 
+        if let Some(max_distance) = self.options.route_match_distance {
+            let candidates = self.candidate_routes(usage_preferences.as_ref());
+            if !candidates.contains(&selected_route.as_str()) {
+                if let Some(route) = closest_route(&selected_route, &candidates, max_distance) {
+                    info!(
+                        "router answered with unknown route `{}`, using closest match `{}`",
+                        selected_route, route
+                    );
+                    selected_route = route.to_string();
+                }
+            }
+        }
+
         if let Some(usage_preferences) = usage_preferences {
             // If usage preferences are defined, we need to find the model that matches the selected route
             let model_name: Option<String> = usage_preferences
@@ -198,6 +424,14 @@ impl RouterModel for RouterModelV1 {
                 .find_map(|model| model);
 
             if let Some(model_name) = model_name {
+                self.explain_route(
+                    content,
+                    Some(usage_preferences),
+                    &format!(
+                        "route `{}` matched a request usage preference served by {}",
+                        selected_route, model_name
+                    ),
+                );
                 return Ok(Some((selected_route, model_name)));
             } else {
                 warn!(
@@ -210,6 +444,14 @@ impl RouterModel for RouterModelV1 {
 
         // If no usage preferences are passed in request then use the default routing model preferences
         if let Some(model) = self.llm_route_to_model_map.get(&selected_route).cloned() {
+            self.explain_route(
+                content,
+                usage_preferences.as_ref(),
+                &format!(
+                    "route `{}` matched a configured routing preference served by {}",
+                    selected_route, model
+                ),
+            );
             return Ok(Some((selected_route, model)));
         }
 
@@ -226,13 +468,149 @@ impl RouterModel for RouterModelV1 {
     }
 }
 
-fn generate_router_message(prefs: &str, selected_conversation_list: &Vec<Message>) -> String {
-    ARCH_ROUTER_V1_SYSTEM_PROMPT
-        .replace("{routes}", prefs)
-        .replace(
-            "{conversation}",
-            &serde_json::to_string(&selected_conversation_list).unwrap_or_default(),
-        )
+impl RouterModelV1 {
+    /// Names of the routes the router can choose from, sorted
+    fn candidate_routes<'a>(
+        &'a self,
+        usage_preferences: Option<&'a Vec<ModelUsagePreference>>,
+    ) -> Vec<&'a str> {
+        let mut candidates: Vec<&str> = match usage_preferences {
+            Some(prefs) => prefs
+                .iter()
+                .flat_map(|pref| pref.routing_preferences.iter())
+                .map(|routing_pref| routing_pref.name.as_str())
+                .collect(),
+            None => self
+                .llm_route_to_model_map
+                .keys()
+                .map(String::as_str)
+                .collect(),
+        };
+        candidates.sort_unstable();
+        candidates
+    }
+
+    /// Debug log of why a routing decision came out the way it did: the raw router output,
+    /// the routes it could pick from and the outcome. Only assembled when debug logging is on.
+    fn explain_route(
+        &self,
+        content: &str,
+        usage_preferences: Option<&Vec<ModelUsagePreference>>,
+        reason: &str,
+    ) {
+        if !enabled!(Level::DEBUG) {
+            return;
+        }
+        let candidates = self.candidate_routes(usage_preferences);
+        debug!(
+            "routing rationale: {}, candidate routes: {:?}, router output: {}",
+            reason,
+            candidates,
+            content.replace('\n', "\\n")
+        );
+    }
+}
+
+/// Fills the routes, the conversation and, if any, the tool names into the system prompt.
+/// Runs on every routed request, so everything is written into a single buffer sized up
+/// front, with the conversation serialized straight into it.
+fn generate_router_message(
+    prefs: &str,
+    selected_conversation_list: &[Message],
+    tool_names: Option<&str>,
+) -> String {
+    let (before_routes, after_routes) =
+        ARCH_ROUTER_V1_SYSTEM_PROMPT.split_once("{routes}").unwrap();
+    let (before_conversation, after_conversation) =
+        after_routes.split_once("{conversation}").unwrap();
+    let (conversation_end, after_conversation) =
+        after_conversation.split_once("</conversation>\n").unwrap();
+
+    let content_len: usize = selected_conversation_list
+        .iter()
+        .filter_map(|m| match &m.content {
+            Some(ContentType::Text(text)) => Some(text.len() + m.role.len() + 32),
+            _ => None,
+        })
+        .sum();
+    let mut buffer = Vec::with_capacity(
+        ARCH_ROUTER_V1_SYSTEM_PROMPT.len()
+            + prefs.len()
+            + content_len
+            + tool_names.map_or(0, |tools| {
+                ROUTER_TOOLS_PROMPT_START.len() + tools.len() + ROUTER_TOOLS_PROMPT_END.len()
+            }),
+    );
+    buffer.extend_from_slice(before_routes.as_bytes());
+    buffer.extend_from_slice(prefs.as_bytes());
+    buffer.extend_from_slice(before_conversation.as_bytes());
+    if serde_json::to_writer(&mut buffer, selected_conversation_list).is_err() {
+        // same as serializing to a string and falling back to an empty one
+        buffer.truncate(before_routes.len() + prefs.len() + before_conversation.len());
+    }
+    buffer.extend_from_slice(conversation_end.as_bytes());
+    buffer.extend_from_slice(b"</conversation>\n");
+    if let Some(tool_names) = tool_names {
+        buffer.extend_from_slice(ROUTER_TOOLS_PROMPT_START.as_bytes());
+        buffer.extend_from_slice(tool_names.as_bytes());
+        buffer.extend_from_slice(ROUTER_TOOLS_PROMPT_END.as_bytes());
+    }
+    buffer.extend_from_slice(after_conversation.as_bytes());
+    // only ever holds valid UTF-8: str slices and serde_json output
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Lowercase with runs of `-`, `_` and whitespace collapsed into a single space
+fn normalize_route_name(name: &str) -> String {
+    name.split(|c: char| c == '-' || c == '_' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `route` after normalization, if within `max_distance` edits.
+/// A tie between candidates is ambiguous and matches nothing.
+fn closest_route<'a>(route: &str, candidates: &[&'a str], max_distance: usize) -> Option<&'a str> {
+    let route = normalize_route_name(route);
+    let mut distances: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|candidate| {
+            (
+                levenshtein(&route, &normalize_route_name(candidate)),
+                *candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    distances.sort_unstable();
+    match distances.as_slice() {
+        [(best, candidate), (second, _), ..] if best < second => Some(candidate),
+        [(_, candidate)] => Some(candidate),
+        _ => None,
+    }
+}
+
+/// Function names of OpenAI style tool definitions
+fn tool_names(tools: &[Value]) -> Vec<&str> {
+    tools
+        .iter()
+        .filter_map(|tool| tool.pointer("/function/name").and_then(Value::as_str))
+        .collect()
 }
 
 fn convert_to_router_preferences(
@@ -294,6 +672,53 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    /// The prompt as it was built before it was written into a single buffer
+    fn reference_router_message(
+        prefs: &str,
+        selected_conversation_list: &[Message],
+        tool_names: Option<&str>,
+    ) -> String {
+        let router_message = ARCH_ROUTER_V1_SYSTEM_PROMPT
+            .replace("{routes}", prefs)
+            .replace(
+                "{conversation}",
+                &serde_json::to_string(&selected_conversation_list).unwrap_or_default(),
+            );
+        match tool_names {
+            Some(tool_names) => router_message.replacen(
+                "</conversation>\n",
+                &format!(
+                    "</conversation>\n\nThe user can call the tools listed within <tools></tools> XML tags:\n<tools>\n{}\n</tools>\n",
+                    tool_names
+                ),
+                1,
+            ),
+            None => router_message,
+        }
+    }
+
+    #[test]
+    fn test_router_message_matches_reference() {
+        let prefs = r#"[{"name":"code generation","description":"writing \"new\" code"}]"#;
+        let conversations = [
+            vec![],
+            vec![text_message(USER_ROLE, "hi".to_string())],
+            vec![
+                text_message(USER_ROLE, "write a {conversation} parser".to_string()),
+                text_message("assistant", "here:\n</conversation>\n".to_string()),
+                text_message(USER_ROLE, "now in Rüst 🦀, with \"quotes\"".to_string()),
+            ],
+        ];
+        for conversation in &conversations {
+            for tool_names in [None, Some(r#"["get_weather","search"]"#)] {
+                assert_eq!(
+                    generate_router_message(prefs, conversation, tool_names),
+                    reference_router_message(prefs, conversation, tool_names)
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_system_prompt_format() {
         let expected_prompt = r#"
@@ -325,7 +750,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), usize::MAX);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -345,7 +775,9 @@ Based on your analysis, provide your response in the following JSON formats if y
         "#;
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
@@ -383,7 +815,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), usize::MAX);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -410,7 +847,9 @@ Based on your analysis, provide your response in the following JSON formats if y
                 description: "generating new code snippets, functions, or boilerplate based on user prompts or requirements".to_string(),
             }],
         }]);
-        let req = router.generate_request(&conversation, &usage_preferences);
+        let req = router
+            .generate_request(&conversation, &[], None, &usage_preferences)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
@@ -449,7 +888,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), 235);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            235,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -470,7 +914,9 @@ Based on your analysis, provide your response in the following JSON formats if y
 
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
@@ -510,7 +956,12 @@ Based on your analysis, provide your response in the following JSON formats if y
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
 
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), 200);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            200,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -531,13 +982,134 @@ Based on your analysis, provide your response in the following JSON formats if y
 
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
         assert_eq!(expected_prompt, prompt.to_string());
     }
 
+    #[test]
+    fn test_conversation_huge_latest_message_is_trimmed() {
+        let llm_routes = serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(
+            r#"{"gpt-4o": [{"name": "Image generation", "description": "generating image"}]}"#,
+        )
+        .unwrap();
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            MAX_TOKEN_LEN,
+            RouterSampling::default(),
+        );
+
+        let ask = "so given all that, draw Bart and Lisa in the style of Andy Warhol";
+        let huge_message = format!("{}{}", "some pasted context ".repeat(2000), ask);
+        let conversation = vec![
+            Message::new("hi".to_string()),
+            Message::new(huge_message.clone()),
+        ];
+
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        let prompt = req.messages[0].content.as_ref().unwrap().to_string();
+        let conversation_json = prompt
+            .split_once("<conversation>\n")
+            .and_then(|(_, rest)| rest.split_once("\n</conversation>"))
+            .map(|(conversation, _)| conversation)
+            .unwrap();
+        let selected: Vec<Message> = serde_json::from_str(conversation_json).unwrap();
+
+        // only the end of the message is kept, sized to what the system prompt leaves over
+        assert_eq!(selected.len(), 1);
+        let content = selected[0].content.as_ref().unwrap().to_string();
+        assert!(content.ends_with(ask));
+        assert!(content.len() < huge_message.len());
+        assert_eq!(
+            content.len(),
+            (MAX_TOKEN_LEN - ARCH_ROUTER_V1_SYSTEM_PROMPT.len() / TOKEN_LENGTH_DIVISOR)
+                * TOKEN_LENGTH_DIVISOR
+        );
+    }
+
+    #[test]
+    fn test_routing_request_uses_configured_sampling() {
+        let llm_routes = serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(
+            r#"{"gpt-4o": [{"name": "Image generation", "description": "generating image"}]}"#,
+        )
+        .unwrap();
+        let conversation = vec![Message::new("draw a cat".to_string())];
+
+        let router = RouterModelV1::new(
+            llm_routes.clone(),
+            "test-model".to_string(),
+            MAX_TOKEN_LEN,
+            RouterSampling::default(),
+        );
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        assert_eq!(req.temperature, Some(0.01));
+        assert_eq!(req.top_p, None);
+        assert_eq!(req.max_tokens, None);
+
+        let sampling = RouterSampling {
+            temperature: Some(0.0),
+            top_p: Some(0.5),
+            max_tokens: Some(32),
+        };
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            MAX_TOKEN_LEN,
+            sampling,
+        );
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        assert_eq!(req.temperature, Some(0.0));
+        assert_eq!(req.top_p, Some(0.5));
+        assert_eq!(req.max_tokens, Some(32));
+    }
+
+    #[test]
+    fn test_routing_request_propagates_seed() {
+        let llm_routes = serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(
+            r#"{"gpt-4o": [{"name": "Image generation", "description": "generating image"}]}"#,
+        )
+        .unwrap();
+        let conversation = vec![Message::new("draw a cat".to_string())];
+        let router = |propagate_seed| {
+            RouterModelV1::new(
+                llm_routes.clone(),
+                "test-model".to_string(),
+                MAX_TOKEN_LEN,
+                RouterSampling::default(),
+            )
+            .with_options(RouterModelOptions {
+                propagate_seed,
+                ..Default::default()
+            })
+        };
+
+        let req = router(true)
+            .generate_request(&conversation, &[], Some(42), &None)
+            .unwrap();
+        assert_eq!(serde_json::to_value(&req).unwrap()["seed"], 42);
+
+        // no seed in the request, or propagation off: the routing request has none either
+        let req = router(true)
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        assert!(!req.extra.contains_key("seed"));
+        let req = router(false)
+            .generate_request(&conversation, &[], Some(42), &None)
+            .unwrap();
+        assert!(serde_json::to_value(&req).unwrap().get("seed").is_none());
+    }
+
     #[test]
     fn test_conversation_trim_upto_user_message() {
         let expected_prompt = r#"
@@ -570,7 +1142,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), 230);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            230,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -599,7 +1176,9 @@ Based on your analysis, provide your response in the following JSON formats if y
 
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
@@ -637,7 +1216,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), usize::MAX);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                     [
@@ -668,13 +1252,53 @@ Based on your analysis, provide your response in the following JSON formats if y
         "#;
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
         assert_eq!(expected_prompt, prompt.to_string());
     }
 
+    #[test]
+    fn test_image_only_message() {
+        let llm_routes = serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(
+            r#"{"gpt-4o": [{"name": "Image understanding", "description": "describing images"}]}"#,
+        )
+        .unwrap();
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            MAX_TOKEN_LEN,
+            RouterSampling::default(),
+        );
+        let conversation: Vec<Message> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "Hello! How can I assist you today?"},
+            {
+                "role": "user",
+                "content": [
+                    {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/b.png"}},
+                    {"type": "text", "text": " "}
+                ]
+            }
+        ]))
+        .unwrap();
+
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        let prompt = req.messages[0].content.as_ref().unwrap().to_string();
+        assert!(
+            prompt.contains(r#"{"role":"user","content":"[image] [image]"}"#),
+            "{}",
+            prompt
+        );
+        assert!(!prompt.contains(r#""content":"""#), "{}", prompt);
+    }
+
     #[test]
     fn test_skip_tool_call() {
         let expected_prompt = r#"
@@ -706,7 +1330,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
         let routing_model = "test-model".to_string();
-        let router = RouterModelV1::new(llm_routes, routing_model.clone(), usize::MAX);
+        let router = RouterModelV1::new(
+            llm_routes,
+            routing_model.clone(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
 
         let conversation_str = r#"
                                                 [
@@ -763,7 +1392,9 @@ Based on your analysis, provide your response in the following JSON formats if y
 
         let conversation: Vec<Message> = serde_json::from_str(conversation_str).unwrap();
 
-        let req = router.generate_request(&conversation, &None);
+        let req = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
 
         let prompt = req.messages[0].content.as_ref().unwrap();
 
@@ -782,7 +1413,12 @@ Based on your analysis, provide your response in the following JSON formats if y
         let llm_routes =
             serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
 
-        let router = RouterModelV1::new(llm_routes, "test-model".to_string(), 2000);
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        );
 
         // Case 1: Valid JSON with non-empty route
         let input = r#"{"route": "Image generation"}"#;
@@ -814,8 +1450,8 @@ Based on your analysis, provide your response in the following JSON formats if y
 
         // Case 5: Malformed JSON
         let input = r#"{"route": "route1""#; // missing closing }
-        let result = router.parse_response(input, &None);
-        assert!(result.is_err());
+        let result = router.parse_response(input, &None).unwrap();
+        assert_eq!(result, None);
 
         // Case 6: Single quotes and \n in JSON
         let input = "{'route': 'Image generation'}\\n";
@@ -833,4 +1469,361 @@ Based on your analysis, provide your response in the following JSON formats if y
             Some(("Image generation".to_string(), "gpt-4o".to_string()))
         );
     }
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run `f` with a subscriber logging at `level`, returning what it logged
+    fn capture_logs(level: Level, f: impl FnOnce()) -> String {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        let logs = logs.0.lock().unwrap();
+        String::from_utf8(logs.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_response_rationale_logged_at_debug() {
+        let llm_routes = HashMap::from([
+            (
+                "gpt-4o".to_string(),
+                vec![RoutingPreference {
+                    name: "Image generation".to_string(),
+                    description: "generating image".to_string(),
+                }],
+            ),
+            (
+                "claude-3-5-sonnet".to_string(),
+                vec![RoutingPreference {
+                    name: "Code generation".to_string(),
+                    description: "generating code".to_string(),
+                }],
+            ),
+        ]);
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        );
+        let input = r#"{"route": "Image generation"}"#;
+
+        let logs = capture_logs(Level::DEBUG, || {
+            let result = router.parse_response(input, &None).unwrap();
+            assert_eq!(
+                result,
+                Some(("Image generation".to_string(), "gpt-4o".to_string()))
+            );
+        });
+        assert!(logs.contains("DEBUG"));
+        assert!(logs.contains(
+            "routing rationale: route `Image generation` matched a configured routing preference served by gpt-4o"
+        ));
+        assert!(logs.contains(r#"candidate routes: ["Code generation", "Image generation"]"#));
+        assert!(logs.contains(r#"router output: {"route": "Image generation"}"#));
+
+        let logs = capture_logs(Level::DEBUG, || {
+            router
+                .parse_response(r#"{"route": "other"}"#, &None)
+                .unwrap();
+        });
+        assert!(logs.contains("routing rationale: router chose no route"));
+
+        // nothing is logged above debug level
+        let logs = capture_logs(Level::INFO, || {
+            router.parse_response(input, &None).unwrap();
+        });
+        assert!(!logs.contains("routing rationale"));
+    }
+
+    #[test]
+    fn test_parse_response_fuzzy_route_name() {
+        let llm_routes = HashMap::from([
+            (
+                "gpt-4o".to_string(),
+                vec![RoutingPreference {
+                    name: "Image generation".to_string(),
+                    description: "generating image".to_string(),
+                }],
+            ),
+            (
+                "claude-3-5-sonnet".to_string(),
+                vec![
+                    RoutingPreference {
+                        name: "code rewrite".to_string(),
+                        description: "rewriting code".to_string(),
+                    },
+                    RoutingPreference {
+                        name: "code review".to_string(),
+                        description: "reviewing code".to_string(),
+                    },
+                ],
+            ),
+        ]);
+        let image_generation = Some(("Image generation".to_string(), "gpt-4o".to_string()));
+
+        // exact names only by default
+        let router = RouterModelV1::new(
+            llm_routes.clone(),
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        );
+        let result = router
+            .parse_response(r#"{"route": "image-generation"}"#, &None)
+            .unwrap();
+        assert_eq!(result, None);
+
+        let router = RouterModelV1::new(
+            llm_routes.clone(),
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        )
+        .with_options(RouterModelOptions {
+            route_match_distance: Some(0),
+            ..Default::default()
+        });
+        let result = router
+            .parse_response(r#"{"route": "image-generation"}"#, &None)
+            .unwrap();
+        assert_eq!(result, image_generation);
+        let result = router
+            .parse_response(r#"{"route": "  IMAGE_generation "}"#, &None)
+            .unwrap();
+        assert_eq!(result, image_generation);
+        // a typo needs an edit distance
+        let result = router
+            .parse_response(r#"{"route": "image-generaton"}"#, &None)
+            .unwrap();
+        assert_eq!(result, None);
+
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        )
+        .with_options(RouterModelOptions {
+            route_match_distance: Some(2),
+            ..Default::default()
+        });
+        let result = router
+            .parse_response(r#"{"route": "image-generaton"}"#, &None)
+            .unwrap();
+        assert_eq!(result, image_generation);
+        // equally close to two routes is ambiguous
+        let result = router
+            .parse_response(r#"{"route": "code revite"}"#, &None)
+            .unwrap();
+        assert_eq!(result, None);
+        // too far from any route
+        let result = router
+            .parse_response(r#"{"route": "video generation"}"#, &None)
+            .unwrap();
+        assert_eq!(result, None);
+
+        // routes from the request's usage preferences are matched the same way
+        let usage_preferences = Some(vec![ModelUsagePreference {
+            model: "gpt-4o-mini".to_string(),
+            routing_preferences: vec![RoutingPreference {
+                name: "Summarization".to_string(),
+                description: "summarizing text".to_string(),
+            }],
+        }]);
+        let result = router
+            .parse_response(r#"{"route": "summarisation"}"#, &usage_preferences)
+            .unwrap();
+        assert_eq!(
+            result,
+            Some(("Summarization".to_string(), "gpt-4o-mini".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_response_chat_prose() {
+        let routes_str = r#"
+          {
+            "gpt-4o": [
+              {"name": "Image generation", "description": "generating image"}
+            ]
+        }
+        "#;
+        let llm_routes =
+            serde_json::from_str::<HashMap<String, Vec<RoutingPreference>>>(routes_str).unwrap();
+
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            2000,
+            RouterSampling::default(),
+        );
+
+        // Plain prose from a chat model that was configured as the router
+        let input = "Sure! I'd be happy to help you generate an image of a sunset.";
+        let result = router.parse_response(input, &None).unwrap();
+        assert_eq!(result, None);
+
+        // Prose with quotes that fix_json_response rewrites, still not route JSON
+        let input = "I think the 'Image generation' route fits best.";
+        let result = router.parse_response(input, &None).unwrap();
+        assert_eq!(result, None);
+
+        // Prose wrapped around the route JSON is not trusted either
+        let input = r#"The best route is {"route": "Image generation"}"#;
+        let result = router.parse_response(input, &None).unwrap();
+        assert_eq!(result, None);
+
+        // JSON that is not an object
+        let input = r#""Image generation""#;
+        let result = router.parse_response(input, &None).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_tool_names_in_prompt() {
+        let llm_routes = HashMap::from([(
+            "gpt-4o".to_string(),
+            vec![RoutingPreference {
+                name: "weather".to_string(),
+                description: "questions about the weather".to_string(),
+            }],
+        )]);
+        let conversation = vec![Message::new("what's the weather in Paris?".to_string())];
+        let tools = vec![serde_json::json!({
+            "type": "function",
+            "function": {"name": "get_weather", "parameters": {"type": "object"}}
+        })];
+
+        // off by default, the prompt stays what the router model was trained on
+        let router = RouterModelV1::new(
+            llm_routes.clone(),
+            "test-model".to_string(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
+        let prompt = router
+            .generate_request(&conversation, &tools, None, &None)
+            .unwrap()
+            .messages[0]
+            .content
+            .as_ref()
+            .unwrap()
+            .to_string();
+        assert!(!prompt.contains("<tools>"));
+
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            usize::MAX,
+            RouterSampling::default(),
+        )
+        .with_options(RouterModelOptions {
+            include_tools: true,
+            ..Default::default()
+        });
+        let prompt = router
+            .generate_request(&conversation, &tools, None, &None)
+            .unwrap()
+            .messages[0]
+            .content
+            .as_ref()
+            .unwrap()
+            .to_string();
+        assert!(prompt.contains(
+            "</conversation>\n\nThe user can call the tools listed within <tools></tools> XML tags:\n<tools>\n[\"get_weather\"]\n</tools>\n"
+        ));
+
+        // no tools, no tools section
+        let prompt = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap()
+            .messages[0]
+            .content
+            .as_ref()
+            .unwrap()
+            .to_string();
+        assert!(!prompt.contains("<tools>"));
+    }
+
+    #[test]
+    fn test_assistant_terminated_conversation() {
+        let llm_routes = HashMap::from([(
+            "gpt-4o".to_string(),
+            vec![RoutingPreference {
+                name: "code generation".to_string(),
+                description: "generating new code snippets".to_string(),
+            }],
+        )]);
+        let conversation: Vec<Message> = serde_json::from_value(serde_json::json!([
+            {"role": "assistant", "content": "Hi! How can I help?"},
+            {"role": "user", "content": "write a quicksort in rust"},
+            {"role": "assistant", "content": "Here it is: fn quicksort() {}"}
+        ]))
+        .unwrap();
+        let conversation_of = |request: ChatCompletionsRequest| {
+            let prompt = request.messages[0].content.as_ref().unwrap().to_string();
+            prompt
+                .split("<conversation>\n")
+                .nth(1)
+                .and_then(|rest| rest.split("\n</conversation>").next())
+                .unwrap()
+                .to_string()
+        };
+
+        // lenient: everything goes into the prompt as is
+        let router = RouterModelV1::new(
+            llm_routes.clone(),
+            "test-model".to_string(),
+            usize::MAX,
+            RouterSampling::default(),
+        );
+        let request = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        assert_eq!(
+            conversation_of(request),
+            r#"[{"role":"assistant","content":"Hi! How can I help?"},{"role":"user","content":"write a quicksort in rust"},{"role":"assistant","content":"Here it is: fn quicksort() {}"}]"#
+        );
+
+        // strict: trimmed to start and end on the user
+        let strict = RouterModelOptions {
+            strict_conversation: true,
+            ..Default::default()
+        };
+        let router = RouterModelV1::new(
+            llm_routes,
+            "test-model".to_string(),
+            usize::MAX,
+            RouterSampling::default(),
+        )
+        .with_options(strict);
+        let request = router
+            .generate_request(&conversation, &[], None, &None)
+            .unwrap();
+        assert_eq!(
+            conversation_of(request),
+            r#"[{"role":"user","content":"write a quicksort in rust"}]"#
+        );
+
+        // strict: nothing from the user, nothing to route on
+        let result = router.generate_request(&conversation[..1], &[], None, &None);
+        assert!(matches!(result, Err(RoutingModelError::NoUserMessage)));
+    }
 }
@@ -0,0 +1,168 @@
+use std::fmt::Write;
+use std::{env, fs};
+
+use common::configuration::{Configuration, ConfigurationError};
+use thiserror::Error;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./arch_config_rendered.yaml";
+
+/// Command line flag that validates the config and exits instead of starting the server
+pub const CONFIG_CHECK_FLAG: &str = "--config-check";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse {path}: {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+
+    #[error("{path} has {} configuration error(s):{}", .errors.len(), list_errors(.errors))]
+    Invalid {
+        path: String,
+        errors: Vec<ConfigurationError>,
+    },
+}
+
+fn list_errors(errors: &[ConfigurationError]) -> String {
+    errors.iter().fold(String::new(), |mut output, err| {
+        let _ = write!(output, "\n  - {}", err);
+        output
+    })
+}
+
+/// Path of the rendered arch config, from `ARCH_CONFIG_PATH_RENDERED` or the default location
+pub fn config_path() -> String {
+    env::var("ARCH_CONFIG_PATH_RENDERED").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+}
+
+/// Read, parse and validate the arch config
+pub fn load_config(path: &str) -> Result<Configuration, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_string(),
+        source,
+    })?;
+
+    let config: Configuration =
+        serde_yaml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_string(),
+            source,
+        })?;
+
+    config.validate().map_err(|errors| ConfigError::Invalid {
+        path: path.to_string(),
+        errors,
+    })?;
+
+    Ok(config)
+}
+
+/// Human readable overview of the providers and the routes they serve
+pub fn config_summary(config: &Configuration) -> String {
+    let mut summary = format!("{} llm provider(s):", config.llm_providers.len());
+    for provider in &config.llm_providers {
+        let _ = write!(summary, "\n  - {}", provider.name);
+        if let Some(model) = &provider.model {
+            let _ = write!(summary, " ({})", model);
+        }
+        if provider.default.unwrap_or_default() {
+            summary.push_str(" [default]");
+        }
+        for pref in provider.routing_preferences.iter().flatten() {
+            let _ = write!(summary, "\n      route: {}", pref.name);
+        }
+    }
+    summary
+}
+
+/// Load and validate the config at `path` without starting anything. Returns the summary
+/// to print on success.
+pub fn check_config(path: &str) -> Result<String, ConfigError> {
+    load_config(path).map(|config| format!("{} is valid\n{}", path, config_summary(&config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!(
+            "brightstaff-config-check-{}-{}.yaml",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_check_valid_config() {
+        let path = write_config(
+            "valid",
+            r#"
+version: v0.1.0
+llm_providers:
+  - name: arch-router
+    provider_interface: arch
+    model: Arch-Router
+  - name: gpt-4o
+    provider_interface: openai
+    model: gpt-4o
+    default: true
+    routing_preferences:
+      - name: code generation
+        description: generating new code snippets
+"#,
+        );
+
+        let summary = check_config(&path).unwrap();
+        assert_eq!(
+            summary,
+            format!(
+                "{} is valid\n2 llm provider(s):\n  - arch-router (Arch-Router)\n  - gpt-4o (gpt-4o) [default]\n      route: code generation",
+                path
+            )
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_check_invalid_config() {
+        let path = write_config(
+            "invalid",
+            r#"
+version: v0.1.0
+llm_providers:
+  - name: gpt-4o
+    provider_interface: openai
+    routing_preferences: []
+"#,
+        );
+
+        let err = check_config(&path).unwrap_err();
+        assert!(matches!(&err, ConfigError::Invalid { errors, .. } if errors.len() == 2));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} has 2 configuration error(s):\n  - routing.llm_provider `arch-router` is not defined in llm_providers\n  - llm_provider `gpt-4o` declares routing_preferences but lists none",
+                path
+            )
+        );
+        fs::remove_file(path).unwrap();
+
+        let path = write_config("unparsable", "llm_providers: 42\n");
+        assert!(matches!(
+            check_config(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(check_config(&path), Err(ConfigError::Read { .. })));
+    }
+}
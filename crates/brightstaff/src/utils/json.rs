@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+
+/// How JSON response bodies are written out. Streaming responses are always compact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonFormat {
+    #[default]
+    Compact,
+    /// Indented output, handy when debugging by hand
+    Pretty,
+}
+
+impl JsonFormat {
+    /// Pretty output or not, as set by `gateway.pretty_json`
+    pub fn new(pretty: bool) -> Self {
+        if pretty {
+            JsonFormat::Pretty
+        } else {
+            JsonFormat::Compact
+        }
+    }
+
+    pub fn serialize<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+        match self {
+            JsonFormat::Compact => serde_json::to_string(value),
+            JsonFormat::Pretty => serde_json::to_string_pretty(value),
+        }
+    }
+
+    /// Re-indent a JSON body produced elsewhere, e.g. by the upstream. Compact mode and bodies
+    /// that are not JSON are passed through untouched.
+    pub fn format_body(self, body: Bytes) -> Bytes {
+        if self == JsonFormat::Compact {
+            return body;
+        }
+        match serde_json::from_slice::<serde_json::Value>(&body) {
+            Ok(value) => self.serialize(&value).map(Bytes::from).unwrap_or(body),
+            Err(_) => body,
+        }
+    }
+}
+
+/// A response with a JSON `body` that is already serialized
+pub fn json_response(status: StatusCode, body: String) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = Full::new(Bytes::from(body))
+        .map_err(|never| match never {})
+        .boxed();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pretty_toggle_changes_formatting() {
+        let value = json!({"object": "list", "data": [{"id": "gpt-4o"}]});
+
+        let compact = JsonFormat::Compact.serialize(&value).unwrap();
+        assert_eq!(compact, r#"{"data":[{"id":"gpt-4o"}],"object":"list"}"#);
+
+        let pretty = JsonFormat::Pretty.serialize(&value).unwrap();
+        assert!(pretty.contains("\n  \"data\": ["));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap(),
+            value
+        );
+
+        // bodies from the upstream are only touched in pretty mode, and only when they are JSON
+        let body = Bytes::from(compact.clone());
+        assert_eq!(JsonFormat::Compact.format_body(body.clone()), body);
+        assert_eq!(JsonFormat::Pretty.format_body(body), pretty);
+        let not_json = Bytes::from_static(b"upstream error");
+        assert_eq!(JsonFormat::Pretty.format_body(not_json.clone()), not_json);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use hermesllm::providers::openai::types::RequestFeatures;
+use serde_json::Value;
+
+use crate::router::llm_router::{DecisionSource, RoutingDecision};
+
+/// Chat completion requests seen since startup, served in the Prometheus text format
+pub static REQUEST_FEATURES: FeatureCounters = FeatureCounters::new();
+
+/// Routing decisions made since startup, served in the Prometheus text format
+pub static ROUTING_DECISIONS: RoutingCounters = RoutingCounters::new();
+
+/// Token usage reported by streamed chat completions since startup, served in the Prometheus
+/// text format
+pub static STREAM_USAGE: UsageCounters = UsageCounters::new();
+
+/// Counts chat completion requests, overall and per feature they use
+#[derive(Debug, Default)]
+pub struct FeatureCounters {
+    requests: AtomicU64,
+    tools: AtomicU64,
+    streaming: AtomicU64,
+    vision: AtomicU64,
+    json_mode: AtomicU64,
+}
+
+impl FeatureCounters {
+    pub const fn new() -> Self {
+        FeatureCounters {
+            requests: AtomicU64::new(0),
+            tools: AtomicU64::new(0),
+            streaming: AtomicU64::new(0),
+            vision: AtomicU64::new(0),
+            json_mode: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, features: &RequestFeatures) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        for (used, counter) in [
+            (features.tools, &self.tools),
+            (features.streaming, &self.streaming),
+            (features.vision, &self.vision),
+            (features.json_mode, &self.json_mode),
+        ] {
+            if used {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP archgw_chat_completion_requests_total Chat completion requests received.\n\
+             # TYPE archgw_chat_completion_requests_total counter\n",
+        );
+        text.push_str(&format!(
+            "archgw_chat_completion_requests_total {}\n",
+            self.requests.load(Ordering::Relaxed)
+        ));
+        text.push_str(
+            "# HELP archgw_chat_completion_feature_requests_total Chat completion requests using a feature.\n\
+             # TYPE archgw_chat_completion_feature_requests_total counter\n",
+        );
+        for (feature, counter) in [
+            ("tools", &self.tools),
+            ("streaming", &self.streaming),
+            ("vision", &self.vision),
+            ("json_mode", &self.json_mode),
+        ] {
+            text.push_str(&format!(
+                "archgw_chat_completion_feature_requests_total{{feature=\"{}\"}} {}\n",
+                feature,
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+        text
+    }
+}
+
+/// Counts routing decisions per source, and sums up the time spent waiting for the router
+#[derive(Debug, Default)]
+pub struct RoutingCounters {
+    forced: AtomicU64,
+    router: AtomicU64,
+    directive: AtomicU64,
+    fallback: AtomicU64,
+    router_calls: AtomicU64,
+    router_latency_micros: AtomicU64,
+}
+
+impl RoutingCounters {
+    pub const fn new() -> Self {
+        RoutingCounters {
+            forced: AtomicU64::new(0),
+            router: AtomicU64::new(0),
+            directive: AtomicU64::new(0),
+            fallback: AtomicU64::new(0),
+            router_calls: AtomicU64::new(0),
+            router_latency_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, decision: &RoutingDecision) {
+        let counter = match decision.source {
+            DecisionSource::Forced => &self.forced,
+            DecisionSource::Router => &self.router,
+            DecisionSource::Directive => &self.directive,
+            DecisionSource::Fallback => &self.fallback,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if !decision.latency.is_zero() {
+            self.router_calls.fetch_add(1, Ordering::Relaxed);
+            self.router_latency_micros
+                .fetch_add(decision.latency.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP archgw_routing_decisions_total Routing decisions, by how the provider was chosen.\n\
+             # TYPE archgw_routing_decisions_total counter\n",
+        );
+        for (source, counter) in [
+            (DecisionSource::Forced, &self.forced),
+            (DecisionSource::Router, &self.router),
+            (DecisionSource::Directive, &self.directive),
+            (DecisionSource::Fallback, &self.fallback),
+        ] {
+            text.push_str(&format!(
+                "archgw_routing_decisions_total{{source=\"{}\"}} {}\n",
+                source.as_str(),
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+        text.push_str(
+            "# HELP archgw_router_latency_seconds Time spent waiting for the router model.\n\
+             # TYPE archgw_router_latency_seconds summary\n",
+        );
+        text.push_str(&format!(
+            "archgw_router_latency_seconds_sum {}\narchgw_router_latency_seconds_count {}\n",
+            self.router_latency_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            self.router_calls.load(Ordering::Relaxed)
+        ));
+        text
+    }
+}
+
+/// Sums up the usage chunks ending streamed chat completions
+#[derive(Debug, Default)]
+pub struct UsageCounters {
+    reports: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+}
+
+impl UsageCounters {
+    pub const fn new() -> Self {
+        UsageCounters {
+            reports: AtomicU64::new(0),
+            prompt_tokens: AtomicU64::new(0),
+            completion_tokens: AtomicU64::new(0),
+        }
+    }
+
+    /// Record the `usage` object of an OpenAI chat completion
+    pub fn record(&self, usage: &Value) {
+        self.reports.fetch_add(1, Ordering::Relaxed);
+        for (field, counter) in [
+            ("prompt_tokens", &self.prompt_tokens),
+            ("completion_tokens", &self.completion_tokens),
+        ] {
+            if let Some(tokens) = usage.get(field).and_then(Value::as_u64) {
+                counter.fetch_add(tokens, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP archgw_stream_usage_reports_total Streamed chat completions that reported usage.\n\
+             # TYPE archgw_stream_usage_reports_total counter\n",
+        );
+        text.push_str(&format!(
+            "archgw_stream_usage_reports_total {}\n",
+            self.reports.load(Ordering::Relaxed)
+        ));
+        text.push_str(
+            "# HELP archgw_stream_usage_tokens_total Tokens used by streamed chat completions.\n\
+             # TYPE archgw_stream_usage_tokens_total counter\n",
+        );
+        for (kind, counter) in [
+            ("prompt", &self.prompt_tokens),
+            ("completion", &self.completion_tokens),
+        ] {
+            text.push_str(&format!(
+                "archgw_stream_usage_tokens_total{{kind=\"{}\"}} {}\n",
+                kind,
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_feature_counters() {
+        let counters = FeatureCounters::new();
+        counters.record(&RequestFeatures::default());
+        counters.record(&RequestFeatures {
+            tools: true,
+            streaming: true,
+            ..Default::default()
+        });
+        counters.record(&RequestFeatures {
+            streaming: true,
+            vision: true,
+            ..Default::default()
+        });
+
+        let text = counters.render();
+        assert!(text.contains("archgw_chat_completion_requests_total 3\n"));
+        for (feature, count) in [
+            ("tools", 1),
+            ("streaming", 2),
+            ("vision", 1),
+            ("json_mode", 0),
+        ] {
+            assert!(text.contains(&format!(
+                "archgw_chat_completion_feature_requests_total{{feature=\"{}\"}} {}\n",
+                feature, count
+            )));
+        }
+    }
+
+    #[test]
+    fn test_routing_counters() {
+        let counters = RoutingCounters::new();
+        let decision = |source, latency_ms| RoutingDecision {
+            route: None,
+            model: None,
+            latency: Duration::from_millis(latency_ms),
+            source,
+        };
+        counters.record(&decision(DecisionSource::Router, 250));
+        counters.record(&decision(DecisionSource::Fallback, 250));
+        counters.record(&decision(DecisionSource::Fallback, 0));
+        counters.record(&decision(DecisionSource::Forced, 0));
+
+        let text = counters.render();
+        for (source, count) in [
+            ("forced", 1),
+            ("router", 1),
+            ("directive", 0),
+            ("fallback", 2),
+        ] {
+            assert!(text.contains(&format!(
+                "archgw_routing_decisions_total{{source=\"{}\"}} {}\n",
+                source, count
+            )));
+        }
+        assert!(text.contains("archgw_router_latency_seconds_sum 0.5\n"));
+        assert!(text.contains("archgw_router_latency_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn test_usage_counters() {
+        let counters = UsageCounters::new();
+        counters.record(&json!({"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}));
+        counters.record(&json!({"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}));
+
+        let text = counters.render();
+        assert!(text.contains("archgw_stream_usage_reports_total 2\n"));
+        assert!(text.contains("archgw_stream_usage_tokens_total{kind=\"prompt\"} 13\n"));
+        assert!(text.contains("archgw_stream_usage_tokens_total{kind=\"completion\"} 6\n"));
+    }
+}
@@ -1 +1,10 @@
+pub mod body;
+pub mod config;
+pub mod json;
+pub mod metrics;
+pub mod request_id;
+pub mod response;
+pub mod retry;
+pub mod streaming;
+pub mod tls;
 pub mod tracing;
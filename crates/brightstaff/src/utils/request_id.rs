@@ -0,0 +1,71 @@
+use common::consts::REQUEST_ID_HEADER;
+use hyper::header::{HeaderMap, HeaderValue};
+use uuid::Uuid;
+
+/// Returns the request id carried in the headers, generating and inserting a new UUID
+/// when the client did not send one (or sent one that isn't valid text).
+pub fn ensure_request_id(headers: &mut HeaderMap) -> String {
+    if let Some(request_id) = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+    {
+        return request_id.to_string();
+    }
+
+    let request_id = Uuid::new_v4().to_string();
+    headers.insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(&request_id).unwrap(),
+    );
+    request_id
+}
+
+/// Echo the request id back to the client on the response headers.
+pub fn set_request_id(headers: &mut HeaderMap, request_id: &str) {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_generated_when_absent() {
+        let mut request_headers = HeaderMap::new();
+        let request_id = ensure_request_id(&mut request_headers);
+
+        assert!(Uuid::parse_str(&request_id).is_ok());
+        // forwarded upstream with the request headers
+        assert_eq!(request_headers.get(REQUEST_ID_HEADER).unwrap(), &request_id);
+
+        let mut response_headers = HeaderMap::new();
+        set_request_id(&mut response_headers, &request_id);
+        assert_eq!(
+            response_headers.get(REQUEST_ID_HEADER).unwrap(),
+            &request_id
+        );
+    }
+
+    #[test]
+    fn test_client_request_id_preserved() {
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("client-id-123"));
+
+        let request_id = ensure_request_id(&mut request_headers);
+        assert_eq!(request_id, "client-id-123");
+        assert_eq!(
+            request_headers.get(REQUEST_ID_HEADER).unwrap(),
+            "client-id-123"
+        );
+
+        let mut response_headers = HeaderMap::new();
+        set_request_id(&mut response_headers, &request_id);
+        assert_eq!(
+            response_headers.get(REQUEST_ID_HEADER).unwrap(),
+            "client-id-123"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use bytes::Bytes;
+use common::configuration::{Gateway, MissingUsage, ResponseModel, StreamUsage};
+use hermesllm::apis::{FinishReason, MessagesStopReason};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+fn fill_missing_usage(missing_usage: MissingUsage, completion: &mut Map<String, Value>) {
+    let usage = match missing_usage {
+        MissingUsage::Omit => return,
+        MissingUsage::Null => Value::Null,
+        MissingUsage::Zeros => {
+            json!({"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0})
+        }
+    };
+    match completion.get("usage") {
+        // a null usage is only replaced when zeros were asked for
+        Some(Value::Null) if missing_usage == MissingUsage::Zeros => {}
+        Some(_) => return,
+        None => {}
+    }
+    completion.insert("usage".to_string(), usage);
+}
+
+/// Changes made to successful chat completions before they reach the client, all off by
+/// default. Only `stream_usage` applies to streaming responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResponseRewrites {
+    pub missing_usage: MissingUsage,
+    pub model: ResponseModel,
+    pub stream_usage: StreamUsage,
+}
+
+impl ResponseRewrites {
+    pub fn from_settings(gateway: &Gateway) -> Self {
+        ResponseRewrites {
+            missing_usage: gateway.missing_usage.unwrap_or_default(),
+            model: gateway.response_model.unwrap_or_default(),
+            stream_usage: gateway.stream_usage.unwrap_or_default(),
+        }
+    }
+
+    /// Apply the rewrites to a chat completion body. Bodies that are not chat completions,
+    /// e.g. upstream errors, are passed through untouched.
+    pub fn apply(self, body: Bytes, requested_model: &str, routed_model: &str) -> Bytes {
+        if self.missing_usage == MissingUsage::Omit && self.model == ResponseModel::Upstream {
+            return body;
+        }
+        let Ok(Value::Object(mut completion)) = serde_json::from_slice::<Value>(&body) else {
+            return body;
+        };
+        if completion.get("object").and_then(Value::as_str) != Some("chat.completion") {
+            return body;
+        }
+
+        fill_missing_usage(self.missing_usage, &mut completion);
+        let model = match self.model {
+            ResponseModel::Upstream => None,
+            ResponseModel::Requested => Some(requested_model),
+            ResponseModel::Routed => Some(routed_model),
+        };
+        if let Some(model) = model {
+            completion.insert("model".to_string(), Value::from(model));
+        }
+
+        serde_json::to_vec(&completion)
+            .map(Bytes::from)
+            .unwrap_or(body)
+    }
+}
+
+/// Whether a complete response, a chat completion or an Anthropic message, was cut off at
+/// the token limit
+pub fn is_truncated(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body).is_ok_and(|response| reports_truncation(&response))
+}
+
+/// Whether a response or stream chunk stopped at the token limit: a choice finishing with
+/// `length`, or an Anthropic `stop_reason` of `max_tokens`, which is what `length` converts to
+pub fn reports_truncation(response: &Value) -> bool {
+    let mut finish_reasons = response
+        .get("choices")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|choice| choice.get("finish_reason"));
+    let mut stop_reasons = [
+        response.get("stop_reason"),
+        response.pointer("/delta/stop_reason"),
+    ]
+    .into_iter()
+    .flatten();
+    finish_reasons
+        .any(|reason| FinishReason::deserialize(reason).is_ok_and(|reason| reason.is_truncated()))
+        || stop_reasons.any(|reason| {
+            MessagesStopReason::deserialize(reason).is_ok_and(|reason| reason.is_truncated())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion() -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o-2024-08-06",
+            "choices": []
+        })
+    }
+
+    fn rewrite(rewrites: ResponseRewrites, body: &Value) -> Value {
+        let body = rewrites.apply(Bytes::from(body.to_string()), "gpt-4o", "gpt-4o-router");
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn test_missing_usage_policy() {
+        let missing_usage = |missing_usage| ResponseRewrites {
+            missing_usage,
+            ..Default::default()
+        };
+
+        // omitted by default
+        let body = Bytes::from(completion().to_string());
+        assert_eq!(
+            ResponseRewrites::default().apply(body.clone(), "gpt-4o", "gpt-4o"),
+            body
+        );
+
+        let rewritten = rewrite(missing_usage(MissingUsage::Null), &completion());
+        assert_eq!(rewritten.get("usage"), Some(&Value::Null));
+
+        let rewritten = rewrite(missing_usage(MissingUsage::Zeros), &completion());
+        assert_eq!(
+            rewritten["usage"],
+            json!({"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0})
+        );
+
+        // usage reported by the upstream is kept
+        let mut reported = completion();
+        reported["usage"] = json!({"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4});
+        assert_eq!(
+            rewrite(missing_usage(MissingUsage::Zeros), &reported),
+            reported
+        );
+
+        // other bodies are left alone
+        let error = Bytes::from_static(br#"{"error": {"message": "rate limited"}}"#);
+        assert_eq!(
+            missing_usage(MissingUsage::Null).apply(error.clone(), "gpt-4o", "gpt-4o"),
+            error
+        );
+    }
+
+    #[test]
+    fn test_response_model_policy() {
+        let model = |model| ResponseRewrites {
+            model,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            rewrite(model(ResponseModel::Upstream), &completion())["model"],
+            "gpt-4o-2024-08-06"
+        );
+        assert_eq!(
+            rewrite(model(ResponseModel::Requested), &completion())["model"],
+            "gpt-4o"
+        );
+        assert_eq!(
+            rewrite(model(ResponseModel::Routed), &completion())["model"],
+            "gpt-4o-router"
+        );
+    }
+
+    #[test]
+    fn test_is_truncated() {
+        let finishing = |finish_reason: &str| {
+            let mut completion = completion();
+            completion["choices"] = json!([{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Once upon a"},
+                "finish_reason": finish_reason
+            }]);
+            completion.to_string()
+        };
+        assert!(is_truncated(finishing("length").as_bytes()));
+        assert!(!is_truncated(finishing("stop").as_bytes()));
+        assert!(!is_truncated(finishing("tool_calls").as_bytes()));
+
+        // Anthropic messages and message deltas report it as max_tokens
+        assert!(is_truncated(
+            br#"{"type":"message","stop_reason":"max_tokens"}"#
+        ));
+        assert!(!is_truncated(
+            br#"{"type":"message","stop_reason":"end_turn"}"#
+        ));
+        assert!(reports_truncation(
+            &json!({"type": "message_delta", "delta": {"stop_reason": "max_tokens"}})
+        ));
+
+        assert!(!is_truncated(b"not json"));
+    }
+}
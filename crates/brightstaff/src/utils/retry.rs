@@ -0,0 +1,152 @@
+use std::time::{Duration, SystemTime};
+
+use common::configuration::Upstream;
+use hyper::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use hyper::StatusCode;
+
+/// `Retry-After` sent with the 503s brightstaff generates itself
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 1;
+
+const DEFAULT_MAX_RETRY_WAIT_SECS: u64 = 10;
+
+/// How often, and how long, brightstaff may wait on an upstream that asked to be retried later.
+/// Retries are off unless `gateway.upstream.max_retries` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryBudget {
+    pub max_retries: u32,
+    pub max_wait: Duration,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            max_wait: Duration::from_secs(DEFAULT_MAX_RETRY_WAIT_SECS),
+        }
+    }
+}
+
+impl RetryBudget {
+    pub fn from_settings(upstream: &Upstream) -> Self {
+        let default = Self::default();
+        Self {
+            max_retries: upstream.max_retries.unwrap_or(default.max_retries),
+            max_wait: upstream
+                .max_retry_wait_secs
+                .map(Duration::from_secs)
+                .unwrap_or(default.max_wait),
+        }
+    }
+
+    /// How long to wait before retrying an upstream response, `None` when it should be
+    /// returned to the client as-is. Only `429` and `503` responses carrying a `Retry-After`
+    /// within the budget's maximum wait are retried.
+    pub fn retry_delay(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries
+            || !matches!(
+                status,
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+            )
+        {
+            return None;
+        }
+
+        let delay = parse_retry_after(headers.get(RETRY_AFTER)?, SystemTime::now())?;
+        (delay <= self.max_wait).then_some(delay)
+    }
+}
+
+/// Parse a `Retry-After` value, either delay-seconds or an HTTP date.
+/// Dates in the past mean the request can be retried right away.
+pub fn parse_retry_after(value: &HeaderValue, now: SystemTime) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+        assert_eq!(
+            parse_retry_after(&HeaderValue::from_static("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(
+                &HeaderValue::from_static("Wed, 21 Oct 2015 07:28:30 GMT"),
+                now
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after(
+                &HeaderValue::from_static("Wed, 21 Oct 2015 07:27:00 GMT"),
+                now
+            ),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            parse_retry_after(&HeaderValue::from_static("soon"), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_respects_budget() {
+        let budget = RetryBudget {
+            max_retries: 2,
+            max_wait: Duration::from_secs(5),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+
+        assert_eq!(
+            budget.retry_delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            budget.retry_delay(StatusCode::SERVICE_UNAVAILABLE, &headers, 1),
+            Some(Duration::from_secs(3))
+        );
+        // out of retries
+        assert_eq!(
+            budget.retry_delay(StatusCode::TOO_MANY_REQUESTS, &headers, 2),
+            None
+        );
+        // not a retryable status
+        assert_eq!(
+            budget.retry_delay(StatusCode::INTERNAL_SERVER_ERROR, &headers, 0),
+            None
+        );
+        // asks for a longer wait than the budget allows
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("60"));
+        assert_eq!(
+            budget.retry_delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0),
+            None
+        );
+        // no Retry-After, nothing to honor
+        assert_eq!(
+            budget.retry_delay(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), 0),
+            None
+        );
+        // retries are disabled by default
+        assert_eq!(
+            RetryBudget::default().retry_delay(StatusCode::TOO_MANY_REQUESTS, &headers, 0),
+            None
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use common::configuration::StreamUsage;
+use hermesllm::clients::streaming::SseLineBuffer;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, info, warn};
+
+use crate::utils::metrics::STREAM_USAGE;
+use crate::utils::response::reports_truncation;
+
+/// Make a streaming request body ask the upstream for usage. Returns the tap to run the
+/// response stream through, or `None` when usage isn't enforced.
+pub fn enforce_stream_usage(stream_usage: StreamUsage, request: &mut Value) -> Option<UsageTap> {
+    if stream_usage == StreamUsage::Passthrough {
+        return None;
+    }
+    let request = request.as_object_mut()?;
+    let client_requested = request
+        .get("stream_options")
+        .and_then(|options| options.get("include_usage"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if !client_requested {
+        let options = request.entry("stream_options").or_insert_with(|| json!({}));
+        match options.as_object_mut() {
+            Some(options) => {
+                options.insert("include_usage".to_string(), Value::Bool(true));
+            }
+            None => *options = json!({"include_usage": true}),
+        }
+    }
+    Some(UsageTap::new(client_requested))
+}
+
+/// Records the usage chunk of a chat completion stream as it goes by, dropping it unless the
+/// client asked for it
+#[derive(Debug, Default)]
+pub struct UsageTap {
+    forward_usage: bool,
+    lines: SseLineBuffer,
+    /// Whether the blank line ending a dropped usage event is still to come
+    drop_blank_line: bool,
+    usage: Option<Value>,
+}
+
+/// Rewrites a response stream on its way to the client, see [`tap_stream`]
+pub trait StreamTap {
+    /// Feed the next chunk of the upstream stream, returning what to forward to the client.
+    /// Lines are held back until they are complete, which a well-formed stream's final
+    /// `[DONE]` event guarantees for everything before it.
+    fn filter(&mut self, chunk: &[u8]) -> Bytes;
+
+    /// Take whatever is still held back once the upstream stream has ended
+    fn finish(&mut self) -> Bytes;
+}
+
+impl UsageTap {
+    pub fn new(forward_usage: bool) -> Self {
+        UsageTap {
+            forward_usage,
+            ..Default::default()
+        }
+    }
+
+    /// The usage reported by the stream so far
+    pub fn usage(&self) -> Option<&Value> {
+        self.usage.as_ref()
+    }
+
+    fn forward(&mut self, complete: Vec<u8>) -> Bytes {
+        let Ok(lines) = std::str::from_utf8(&complete) else {
+            return Bytes::from(complete);
+        };
+
+        let mut forward = String::with_capacity(lines.len());
+        for line in lines.split_inclusive('\n') {
+            let drop_blank_line = std::mem::take(&mut self.drop_blank_line);
+            if line.trim().is_empty() && drop_blank_line {
+                continue;
+            }
+            if let Some(usage) = usage_of(line) {
+                info!("stream usage: {}", usage);
+                STREAM_USAGE.record(&usage);
+                self.usage = Some(usage);
+                if !self.forward_usage {
+                    self.drop_blank_line = true;
+                    continue;
+                }
+            }
+            forward.push_str(line);
+        }
+        Bytes::from(forward)
+    }
+}
+
+impl StreamTap for UsageTap {
+    fn filter(&mut self, chunk: &[u8]) -> Bytes {
+        let complete = self.lines.push(chunk);
+        self.forward(complete)
+    }
+
+    fn finish(&mut self) -> Bytes {
+        let rest = self.lines.finish();
+        self.forward(rest)
+    }
+}
+
+/// Run a response stream through a [`StreamTap`], flushing what it holds back once the
+/// upstream stream ends. Chunks the tap swallows whole aren't passed on.
+pub fn tap_stream<S, E, T>(upstream: S, tap: T) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    T: StreamTap,
+{
+    futures::stream::unfold((upstream, Some(tap)), |(mut upstream, tap)| async move {
+        let mut tap = tap?;
+        match upstream.next().await {
+            Some(chunk) => {
+                let item = chunk.map(|chunk| tap.filter(&chunk));
+                Some((item, (upstream, Some(tap))))
+            }
+            None => Some((Ok(tap.finish()), (upstream, None))),
+        }
+    })
+    .filter(|chunk| !matches!(chunk, Ok(chunk) if chunk.is_empty()))
+}
+
+/// Watches a response stream for a chunk finishing at the token limit. Once one goes by it
+/// sets the shared flag, and follows the chunk with a [`truncation_chunk`] so clients that
+/// can't receive trailers learn about it too.
+#[derive(Debug, Default)]
+pub struct TruncationTap {
+    lines: SseLineBuffer,
+    model: String,
+    /// Whether the event finishing at the token limit still has to end before the flag goes out
+    flag_pending: bool,
+    truncated: Arc<AtomicBool>,
+}
+
+impl TruncationTap {
+    pub fn new(model: &str, truncated: Arc<AtomicBool>) -> Self {
+        TruncationTap {
+            model: model.to_string(),
+            truncated,
+            ..Default::default()
+        }
+    }
+
+    fn forward(&mut self, complete: Vec<u8>) -> Bytes {
+        let Ok(lines) = std::str::from_utf8(&complete) else {
+            return Bytes::from(complete);
+        };
+
+        let mut forward = String::with_capacity(lines.len());
+        for line in lines.split_inclusive('\n') {
+            forward.push_str(line);
+            if line.trim().is_empty() {
+                if std::mem::take(&mut self.flag_pending) {
+                    forward.push_str(&truncation_chunk(&self.model));
+                }
+            } else if reports_truncation_line(line) {
+                self.truncated.store(true, Ordering::Relaxed);
+                self.flag_pending = true;
+            }
+        }
+        Bytes::from(forward)
+    }
+}
+
+impl StreamTap for TruncationTap {
+    fn filter(&mut self, chunk: &[u8]) -> Bytes {
+        let complete = self.lines.push(chunk);
+        self.forward(complete)
+    }
+
+    fn finish(&mut self) -> Bytes {
+        let rest = self.lines.finish();
+        let mut forward = self.forward(rest).to_vec();
+        // the stream ended right after the finishing event, without the blank line closing it
+        if std::mem::take(&mut self.flag_pending) {
+            forward.extend_from_slice(b"\n\n");
+            forward.extend_from_slice(truncation_chunk(&self.model).as_bytes());
+        }
+        Bytes::from(forward)
+    }
+}
+
+fn reports_truncation_line(line: &str) -> bool {
+    line.strip_prefix("data:")
+        .and_then(|data| serde_json::from_str::<Value>(data.trim()).ok())
+        .is_some_and(|chunk| reports_truncation(&chunk))
+}
+
+/// The usage of an SSE line, if it is the data of the usage-only chunk ending an OpenAI stream
+fn usage_of(line: &str) -> Option<Value> {
+    let data = line.strip_prefix("data:")?.trim();
+    let mut chunk: Value = serde_json::from_str(data).ok()?;
+    let no_choices = chunk
+        .get("choices")
+        .and_then(Value::as_array)
+        .is_some_and(|choices| choices.is_empty());
+    match chunk.get_mut("usage").map(Value::take) {
+        Some(usage @ Value::Object(_)) if no_choices => Some(usage),
+        _ => None,
+    }
+}
+
+/// Turn a streaming request body into a non-streaming one
+pub fn disable_streaming(request: &mut Value) {
+    if let Some(request) = request.as_object_mut() {
+        request.insert("stream".to_string(), Value::Bool(false));
+        request.remove("stream_options");
+    }
+}
+
+/// Field of the routing metadata chunk carrying the routing decision
+pub const ROUTE_METADATA_FIELD: &str = "x-archgw-route";
+
+/// SSE chunk telling the client where its request was routed before any content streams:
+/// a `chat.completion.chunk` with an empty delta, the routing decision in
+/// [`ROUTE_METADATA_FIELD`]. `route` is `None` when no routing preference matched.
+pub fn route_metadata_chunk(model: &str, route: Option<&str>) -> String {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let chunk = json!({
+        "id": "archgw-route",
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{"index": 0, "delta": {}, "finish_reason": null}],
+        ROUTE_METADATA_FIELD: {"model": model, "route": route},
+    });
+    format!("data: {}\n\n", chunk)
+}
+
+/// Field of the truncation chunk flagging a response cut off at the token limit
+pub const TRUNCATION_FIELD: &str = "x-archgw-truncated";
+
+/// SSE chunk telling the client the response was cut off at the token limit, sent right after
+/// the chunk that finished it. It has no choices, like OpenAI's usage chunk, so clients skip
+/// it unless they look for [`TRUNCATION_FIELD`].
+pub fn truncation_chunk(model: &str) -> String {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let chunk = json!({
+        "id": "archgw-truncated",
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [],
+        TRUNCATION_FIELD: true,
+    });
+    format!("data: {}\n\n", chunk)
+}
+
+/// Pipe an upstream response body into the channel feeding the client's response. Returns
+/// as soon as the client goes away, even while waiting on the upstream, dropping the upstream
+/// stream so its connection is closed instead of generating tokens nobody reads.
+pub async fn forward_stream<S, E>(upstream: S, tx: mpsc::Sender<Bytes>)
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Debug,
+{
+    let mut upstream = upstream;
+    loop {
+        let item = tokio::select! {
+            item = upstream.next() => item,
+            _ = tx.closed() => {
+                debug!("client disconnected, cancelling upstream stream");
+                return;
+            }
+        };
+
+        let item = match item {
+            Some(Ok(item)) => item,
+            Some(Err(err)) => {
+                warn!("Error receiving chunk: {:?}", err);
+                return;
+            }
+            None => return,
+        };
+
+        if tx.send(item).await.is_err() {
+            warn!("Receiver dropped, cancelling upstream stream");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio_stream::wrappers::ReceiverStream;
+
+    #[test]
+    fn test_route_metadata_chunk() {
+        let sse = route_metadata_chunk("gpt-4o", Some("code generation"));
+        let frame = sse
+            .strip_prefix("data: ")
+            .and_then(|frame| frame.strip_suffix("\n\n"))
+            .unwrap();
+
+        let chunk: Value = serde_json::from_str(frame).unwrap();
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["model"], "gpt-4o");
+        assert_eq!(chunk["choices"][0]["delta"], json!({}));
+        assert_eq!(
+            chunk[ROUTE_METADATA_FIELD],
+            json!({"model": "gpt-4o", "route": "code generation"})
+        );
+
+        // it parses as a regular stream chunk
+        let parsed: hermesllm::providers::openai::types::ChatCompletionStreamResponse =
+            serde_json::from_str(frame).unwrap();
+        assert_eq!(parsed.choices[0].delta.content, None);
+    }
+
+    #[tokio::test]
+    async fn test_forward_stream_stops_on_client_disconnect() {
+        let (upstream_tx, upstream_rx) = mpsc::channel::<Result<Bytes, String>>(4);
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+        let forwarding = tokio::spawn(forward_stream(ReceiverStream::new(upstream_rx), tx));
+
+        upstream_tx
+            .send(Ok(Bytes::from_static(b"data: first\n\n")))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "data: first\n\n");
+
+        // the client goes away while the upstream is still working on the next chunk
+        drop(rx);
+        tokio::time::timeout(Duration::from_secs(1), forwarding)
+            .await
+            .expect("forwarding kept waiting on the upstream after the client left")
+            .unwrap();
+
+        // the upstream stream was dropped, closing its side of the connection
+        assert!(upstream_tx.is_closed());
+    }
+
+    #[test]
+    fn test_stream_usage_enforced() {
+        // passing through leaves the request alone
+        let mut request = json!({"stream": true});
+        assert!(enforce_stream_usage(StreamUsage::Passthrough, &mut request).is_none());
+        assert_eq!(request, json!({"stream": true}));
+
+        let mut request = json!({"stream": true, "stream_options": {"include_usage": false}});
+        let mut tap = enforce_stream_usage(StreamUsage::Enforce, &mut request).unwrap();
+        assert_eq!(request["stream_options"], json!({"include_usage": true}));
+
+        let content = "data: {\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"}}]}\n\n";
+        let usage = "data: {\"object\":\"chat.completion.chunk\",\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\n";
+        let stream = format!("{}{}data: [DONE]\n\n", content, usage);
+
+        // events split across chunks are held back until complete
+        let (first, rest) = stream.split_at(content.len() + 10);
+        let mut forwarded = tap.filter(first.as_bytes()).to_vec();
+        assert_eq!(forwarded, content.as_bytes());
+        forwarded.extend_from_slice(&tap.filter(rest.as_bytes()));
+
+        // the client didn't ask for usage, so it only gets the content, but usage is recorded
+        assert_eq!(
+            String::from_utf8(forwarded).unwrap(),
+            format!("{}data: [DONE]\n\n", content)
+        );
+        assert_eq!(tap.usage().unwrap()["total_tokens"], 4);
+
+        // clients asking for usage get it as well
+        let mut request = json!({"stream": true, "stream_options": {"include_usage": true}});
+        let mut tap = enforce_stream_usage(StreamUsage::Enforce, &mut request).unwrap();
+        assert_eq!(tap.filter(stream.as_bytes()), stream);
+        assert_eq!(tap.usage().unwrap()["prompt_tokens"], 3);
+
+        // CRLF-framed streams are split into events the same way
+        let mut tap = UsageTap::new(false);
+        let forwarded = tap.filter(stream.replace('\n', "\r\n").as_bytes());
+        assert_eq!(
+            String::from_utf8(forwarded.to_vec()).unwrap(),
+            format!("{}data: [DONE]\n\n", content).replace('\n', "\r\n")
+        );
+        assert_eq!(tap.usage().unwrap()["completion_tokens"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_tap_stream_flushes_at_end_of_stream() {
+        let mut request = json!({"stream": true});
+        let usage_tap = enforce_stream_usage(StreamUsage::Enforce, &mut request).unwrap();
+
+        // the upstream closes the stream without a newline after the last event
+        let content = "data: {\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"}}]}\n\n";
+        let last = "data: {\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}";
+        let upstream = tokio_stream::iter([
+            Ok::<_, std::io::Error>(Bytes::from(content)),
+            Ok(Bytes::from(last)),
+        ]);
+        let forwarded: Vec<Bytes> = tap_stream(upstream, usage_tap)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(forwarded, vec![Bytes::from(content), Bytes::from(last)]);
+    }
+
+    #[test]
+    fn test_truncation_tap() {
+        let content = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"},\"finish_reason\":null}]}\n\n";
+        let finish =
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"length\"}]}\n\n";
+        let truncated = Arc::new(AtomicBool::new(false));
+        let mut tap = TruncationTap::new("gpt-4o", truncated.clone());
+
+        assert_eq!(tap.filter(content.as_bytes()), content);
+        assert!(!truncated.load(Ordering::Relaxed));
+        // the finishing chunk arrives split in two
+        let (first, second) = finish.split_at(40);
+        assert!(tap.filter(first.as_bytes()).is_empty());
+        assert!(!truncated.load(Ordering::Relaxed));
+        let forwarded = tap.filter(format!("{}data: [DONE]\n\n", second).as_bytes());
+        assert!(truncated.load(Ordering::Relaxed));
+
+        // the flag follows the finishing chunk, before [DONE]
+        let frames: Vec<&str> = std::str::from_utf8(&forwarded)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(frames.len(), 3);
+        let chunk: Value = serde_json::from_str(frames[1]).unwrap();
+        assert_eq!(chunk[TRUNCATION_FIELD], true);
+        assert_eq!(chunk["model"], "gpt-4o");
+        assert_eq!(chunk["choices"], json!([]));
+        assert_eq!(frames[2], "[DONE]");
+        assert!(tap.finish().is_empty());
+
+        let truncated = Arc::new(AtomicBool::new(false));
+        let mut tap = TruncationTap::new("gpt-4o", truncated.clone());
+        let finished = "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n";
+        assert_eq!(tap.filter(finished.as_bytes()), finished);
+        assert!(!truncated.load(Ordering::Relaxed));
+
+        // CRLF framing, and a stream ending right after the finishing chunk
+        let truncated = Arc::new(AtomicBool::new(false));
+        let mut tap = TruncationTap::new("gpt-4o", truncated.clone());
+        let forwarded = tap.filter(finish.replace('\n', "\r\n").as_bytes());
+        assert!(truncated.load(Ordering::Relaxed));
+        assert!(String::from_utf8(forwarded.to_vec())
+            .unwrap()
+            .contains(TRUNCATION_FIELD));
+
+        let mut tap = TruncationTap::new("gpt-4o", Arc::new(AtomicBool::new(false)));
+        assert!(tap.filter(finish.trim_end().as_bytes()).is_empty());
+        let forwarded = tap.finish();
+        let (first, rest) = std::str::from_utf8(&forwarded)
+            .unwrap()
+            .split_once("\n\n")
+            .unwrap();
+        assert_eq!(first, finish.trim_end());
+        let chunk: Value =
+            serde_json::from_str(rest.strip_prefix("data: ").unwrap().trim()).unwrap();
+        assert_eq!(chunk[TRUNCATION_FIELD], true);
+    }
+
+    #[test]
+    fn test_disable_streaming() {
+        let mut request = json!({"stream": true, "stream_options": {"include_usage": true}});
+        disable_streaming(&mut request);
+        assert_eq!(request, json!({"stream": false}));
+    }
+}
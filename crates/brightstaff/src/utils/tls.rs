@@ -0,0 +1,205 @@
+use std::fs;
+
+use common::configuration::Upstream;
+use reqwest::{Certificate, Client, ClientBuilder, Identity};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("invalid {what}: {source}")]
+    Invalid {
+        what: &'static str,
+        source: reqwest::Error,
+    },
+
+    #[error("failed to build upstream http client: {0}")]
+    Client(reqwest::Error),
+}
+
+/// TLS settings for connections to upstreams, on top of the system's trusted roots
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamTls {
+    root_ca: Option<Vec<u8>>,
+    client_identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl UpstreamTls {
+    /// Read the files named by `gateway.upstream`: `ca_bundle` (PEM certificates to trust)
+    /// and `client_cert` with `client_key` (PEM certificate and PKCS#8 key presented for
+    /// mTLS). Nothing set means plain defaults.
+    pub fn from_settings(upstream: &Upstream) -> Result<Self, TlsError> {
+        let read = |path: &Option<String>| -> Result<Option<Vec<u8>>, TlsError> {
+            match path {
+                Some(path) => fs::read(path).map(Some).map_err(|source| TlsError::Read {
+                    path: path.clone(),
+                    source,
+                }),
+                None => Ok(None),
+            }
+        };
+
+        let mut tls = UpstreamTls::default();
+        if let Some(root_ca) = read(&upstream.ca_bundle)? {
+            tls = tls.with_root_ca(root_ca);
+        }
+        // a certificate without its key is rejected by Configuration::validate
+        if let (Some(cert), Some(key)) = (read(&upstream.client_cert)?, read(&upstream.client_key)?)
+        {
+            tls = tls.with_client_identity(cert, key);
+        }
+        Ok(tls)
+    }
+
+    /// Trust the certificates of a PEM bundle, e.g. a private CA
+    pub fn with_root_ca(mut self, pem: Vec<u8>) -> Self {
+        self.root_ca = Some(pem);
+        self
+    }
+
+    /// Present a client certificate, with its PKCS#8 private key, to upstreams asking for one
+    pub fn with_client_identity(mut self, cert_pem: Vec<u8>, key_pem: Vec<u8>) -> Self {
+        self.client_identity = Some((cert_pem, key_pem));
+        self
+    }
+
+    /// The http client shared by every upstream call
+    pub fn build_client(&self) -> Result<Client, TlsError> {
+        self.client_builder()?.build().map_err(TlsError::Client)
+    }
+
+    /// A client builder with these settings, for clients that need more of their own
+    pub fn client_builder(&self) -> Result<ClientBuilder, TlsError> {
+        let mut builder = Client::builder();
+        if let Some(root_ca) = &self.root_ca {
+            let certificates =
+                Certificate::from_pem_bundle(root_ca).map_err(|source| TlsError::Invalid {
+                    what: "CA bundle",
+                    source,
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some((cert, key)) = &self.client_identity {
+            let identity =
+                Identity::from_pkcs8_pem(cert, key).map_err(|source| TlsError::Invalid {
+                    what: "client certificate",
+                    source,
+                })?;
+            builder = builder.identity(identity);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+    use openssl::x509::X509;
+
+    const CA: &[u8] = include_bytes!("../../testdata/tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../../testdata/tls/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("../../testdata/tls/server.key");
+    const CLIENT_CERT: &[u8] = include_bytes!("../../testdata/tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../../testdata/tls/client.key");
+
+    /// Serve `ok` over TLS with a certificate from the test CA, optionally requiring a
+    /// client certificate from the same CA. Returns the url to call.
+    fn start_upstream(require_client_cert: bool) -> String {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate(&X509::from_pem(SERVER_CERT).unwrap())
+            .unwrap();
+        acceptor
+            .set_private_key(&openssl::pkey::PKey::private_key_from_pem(SERVER_KEY).unwrap())
+            .unwrap();
+        if require_client_cert {
+            acceptor
+                .cert_store_mut()
+                .add_cert(X509::from_pem(CA).unwrap())
+                .unwrap();
+            acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+        let acceptor = Arc::new(acceptor.build());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://127.0.0.1:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // failed handshakes are what some of the tests expect, keep serving
+                let Ok(mut stream) = acceptor.accept(stream) else {
+                    continue;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buf[..read]),
+                    }
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                );
+                let _ = stream.shutdown();
+            }
+        });
+        url
+    }
+
+    async fn call(tls: &UpstreamTls, url: &str) -> Result<String, reqwest::Error> {
+        tls.build_client()
+            .unwrap()
+            .get(url)
+            .send()
+            .await?
+            .text()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_custom_root_ca() {
+        let url = start_upstream(false);
+
+        // the test CA is not trusted by default
+        assert!(call(&UpstreamTls::default(), &url).await.is_err());
+
+        let tls = UpstreamTls::default().with_root_ca(CA.to_vec());
+        assert_eq!(call(&tls, &url).await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate() {
+        let url = start_upstream(true);
+
+        // an upstream requiring mTLS refuses clients without a certificate
+        let tls = UpstreamTls::default().with_root_ca(CA.to_vec());
+        assert!(call(&tls, &url).await.is_err());
+
+        let tls = tls.with_client_identity(CLIENT_CERT.to_vec(), CLIENT_KEY.to_vec());
+        assert_eq!(call(&tls, &url).await.unwrap(), "ok");
+
+        let tls = UpstreamTls::default().with_client_identity(CLIENT_CERT.to_vec(), Vec::new());
+        assert!(matches!(
+            tls.build_client(),
+            Err(TlsError::Invalid {
+                what: "client certificate",
+                ..
+            })
+        ));
+    }
+}
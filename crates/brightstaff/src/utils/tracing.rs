@@ -1,8 +1,11 @@
 use std::sync::OnceLock;
 
 use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
 use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
 use opentelemetry_stdout::SpanExporter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
 static INIT_LOGGER: OnceLock<SdkTracerProvider> = OnceLock::new();
@@ -18,10 +21,11 @@ pub fn init_tracer() -> &'static SdkTracerProvider {
 
         global::set_tracer_provider(provider.clone());
 
-        tracing_subscriber::fmt()
-            .with_env_filter(
-                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-            )
+        // tracing spans, e.g. the one around the router call, are exported as OpenTelemetry spans
+        tracing_subscriber::registry()
+            .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("brightstaff")))
             .init();
 
         provider
@@ -2,7 +2,7 @@ use crate::{
     configuration::LlmProvider,
     consts::{ARCH_FC_MODEL_NAME, ASSISTANT_ROLE},
 };
-use core::{panic, str};
+use core::str;
 use serde::{ser::SerializeMap, Deserialize, Serialize};
 use serde_yaml::Value;
 use std::{
@@ -164,6 +164,9 @@ pub enum MultiPartContentType {
     Text,
     #[serde(rename = "image_url")]
     ImageUrl,
+    /// Any part type this gateway doesn't know about yet
+    #[serde(other, rename = "unknown")]
+    Unknown,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -187,14 +190,13 @@ impl Display for ContentType {
             ContentType::MultiPart(multi_part) => {
                 let text_parts: Vec<String> = multi_part
                     .iter()
-                    .filter_map(|part| {
-                        if part.content_type == MultiPartContentType::Text {
-                            part.text.clone()
-                        } else if part.content_type == MultiPartContentType::ImageUrl {
-                            // skip image URLs or their data in text representation
+                    .filter_map(|part| match part.content_type {
+                        MultiPartContentType::Text => part.text.clone(),
+                        // skip image URLs or their data in text representation
+                        MultiPartContentType::ImageUrl => None,
+                        MultiPartContentType::Unknown => {
+                            log::debug!("skipping unsupported content part in text representation");
                             None
-                        } else {
-                            panic!("Unsupported content type: {:?}", part.content_type);
                         }
                     })
                     .collect();
@@ -848,6 +850,23 @@ data: [DONE]
         }
     }
 
+    #[test]
+    fn test_unknown_content_part_is_skipped() {
+        let content: ContentType = serde_json::from_str(
+            r#"[{"type": "text", "text": "what is in this clip?"}, {"type": "video_url"}]"#,
+        )
+        .unwrap();
+        if let ContentType::MultiPart(multi_part_content) = &content {
+            assert_eq!(
+                multi_part_content[1].content_type,
+                MultiPartContentType::Unknown
+            );
+        } else {
+            panic!("Expected MultiPartContent");
+        }
+        assert_eq!(content.to_string(), "what is in this clip?");
+    }
+
     #[test]
     fn test_chat_completions_request_text_type_array() {
         const CHAT_COMPLETIONS_REQUEST: &str = r#"
@@ -1,3 +1,4 @@
+use hermesllm::clients::{ConversionOptions, EnabledEndpoints};
 use hermesllm::providers::openai::types::{ModelDetail, ModelObject, Models};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -6,11 +7,138 @@ use std::fmt::Display;
 use crate::api::open_ai::{
     ChatCompletionTool, FunctionDefinition, FunctionParameter, FunctionParameters, ParameterType,
 };
+use crate::consts::DEFAULT_ROUTING_LLM_PROVIDER;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Routing {
     pub llm_provider: Option<String>,
     pub model: Option<String>,
+    /// Mention the names of the tools a request declares in the routing prompt
+    pub include_tools: Option<bool>,
+    /// Only route on conversations that start and end with a user message, trimming the rest
+    pub strict_conversation: Option<bool>,
+    /// Accept a route name from the router within this many edits of a configured one,
+    /// after ignoring case and separators. Unset means exact names only.
+    pub route_match_distance: Option<usize>,
+    /// Pass the request's `seed` on to the routing model, for reproducible routing
+    pub propagate_seed: Option<bool>,
+    /// Route requests whose system prompt has an `archgw-route: <route name>` line to that
+    /// route without calling the routing model
+    pub system_route_directive: Option<bool>,
+    /// Settings of the http client calling the routing model
+    pub client: Option<RoutingClient>,
+    /// Sampling parameters of the routing call
+    pub sampling: Option<RoutingSampling>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingSampling {
+    /// Defaults to 0.01 when unset
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutingClient {
+    /// Limit on a whole call to the routing model, none when unset
+    pub timeout_ms: Option<u64>,
+    /// Limit on connecting to the routing model, none when unset
+    pub connect_timeout_ms: Option<u64>,
+    /// Idle connections to keep open to the routing model
+    pub pool_max_idle_per_host: Option<usize>,
+}
+
+/// How brightstaff handles requests and responses, everything off by default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Gateway {
+    /// Retries and TLS of the calls to upstreams
+    pub upstream: Option<Upstream>,
+    /// What to do with a `stream: true` request routed to a provider that can't stream
+    pub unstreamable_requests: Option<StreamingFallback>,
+    /// Whether usage is collected for every streaming request
+    pub stream_usage: Option<StreamUsage>,
+    /// What a non-streaming chat completion without `usage` is sent to the client with
+    pub missing_usage: Option<MissingUsage>,
+    /// Which model a non-streaming chat completion names in its `model` field
+    pub response_model: Option<ResponseModel>,
+    /// Indent JSON response bodies, handy when debugging by hand
+    pub pretty_json: Option<bool>,
+    /// Replace message content with placeholders in logged request and response bodies
+    pub redact_logs: Option<bool>,
+    /// Hosts the remote images of requests may point at, since upstreams fetch them. Any host
+    /// is allowed when unset; the scheme is always restricted to `http`, `https` and `data`.
+    pub image_hosts: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Upstream {
+    /// Retries of upstream responses asking to be retried later, none when unset
+    pub max_retries: Option<u32>,
+    /// Longest `Retry-After` that is waited for, 10 seconds when unset
+    pub max_retry_wait_secs: Option<u64>,
+    /// PEM bundle of certificates to trust on top of the system's roots, e.g. a private CA
+    pub ca_bundle: Option<String>,
+    /// PEM client certificate presented to upstreams asking for one, with `client_key`
+    pub client_cert: Option<String>,
+    /// PKCS#8 PEM private key of `client_cert`
+    pub client_key: Option<String>,
+}
+
+/// What to do with a `stream: true` request routed to a provider that can't stream
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum StreamingFallback {
+    /// Answer with a 400 telling the client to retry without streaming
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    /// Ask the provider for a complete response and replay it to the client as a stream
+    #[serde(rename = "buffer")]
+    Buffer,
+}
+
+/// Whether usage is collected for every streaming request, e.g. for accurate billing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum StreamUsage {
+    /// Usage is only streamed to clients that ask for it with `stream_options.include_usage`
+    #[default]
+    #[serde(rename = "passthrough")]
+    Passthrough,
+    /// Always ask the upstream for usage and record it, stripping the usage chunk from the
+    /// stream of clients that didn't ask for it
+    #[serde(rename = "enforce")]
+    Enforce,
+}
+
+/// What a non-streaming chat completion without `usage` is sent to the client with. Some
+/// strict OpenAI clients fail on responses where the field is missing altogether.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum MissingUsage {
+    /// Leave the response as the upstream sent it
+    #[default]
+    #[serde(rename = "omit")]
+    Omit,
+    /// Add `"usage": null`
+    #[serde(rename = "null")]
+    Null,
+    /// Add a usage object with all counts at zero
+    #[serde(rename = "zeros")]
+    Zeros,
+}
+
+/// Which model a non-streaming chat completion names in its `model` field
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum ResponseModel {
+    /// Whatever the upstream reported, often a versioned internal id
+    #[default]
+    #[serde(rename = "upstream")]
+    Upstream,
+    /// The model the client asked for
+    #[serde(rename = "requested")]
+    Requested,
+    /// The provider the request was routed to
+    #[serde(rename = "routed")]
+    Routed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +155,268 @@ pub struct Configuration {
     pub tracing: Option<Tracing>,
     pub mode: Option<GatewayMode>,
     pub routing: Option<Routing>,
+    /// API endpoints served by the gateway, e.g. only `/v1/chat/completions`. Every supported
+    /// endpoint is served when unset.
+    pub enabled_endpoints: Option<Vec<String>>,
+    pub gateway: Option<Gateway>,
+}
+
+/// A cross-field problem in an otherwise well-formed configuration
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ConfigurationError {
+    #[error("routing.llm_provider `{0}` is not defined in llm_providers")]
+    UnknownRoutingProvider(String),
+    #[error("llm_provider `{0}` has an empty usage")]
+    EmptyUsage(String),
+    #[error("llm_provider `{0}` declares routing_preferences but lists none")]
+    EmptyRoutingPreferences(String),
+    #[error("llm_provider `{provider}` has a routing preference with an empty {field}")]
+    BlankRoutingPreference {
+        provider: String,
+        field: &'static str,
+    },
+    #[error("llm_provider `{provider}` has an invalid base_url `{url}`: {reason}")]
+    InvalidBaseUrl {
+        provider: String,
+        url: String,
+        reason: String,
+    },
+    #[error("llm_provider `{provider}` has an invalid upstream {field}: {reason}")]
+    InvalidUpstreamRequest {
+        provider: String,
+        field: &'static str,
+        reason: String,
+    },
+    #[error("enabled_endpoints lists `{0}`, which is not a supported endpoint")]
+    UnsupportedEndpoint(String),
+    #[error("only one llm_provider can be the default, got {}", .0.join(", "))]
+    MultipleDefaultProviders(Vec<String>),
+    #[error("endpoint `{name}` has an invalid address `{address}`: {reason}")]
+    InvalidEndpoint {
+        name: String,
+        address: String,
+        reason: String,
+    },
+    #[error("gateway.upstream.client_cert and gateway.upstream.client_key must be set together")]
+    IncompleteClientIdentity,
+}
+
+impl Configuration {
+    /// Check invariants that span several fields and that deserialization alone can't catch.
+    /// Every problem is reported, not just the first one.
+    pub fn validate(&self) -> Result<(), Vec<ConfigurationError>> {
+        let mut errors = Vec::new();
+
+        let routing_defined = self
+            .llm_providers
+            .iter()
+            .any(|provider| provider.routing_preferences.is_some());
+        if routing_defined {
+            let routing_provider = self
+                .routing
+                .as_ref()
+                .and_then(|routing| routing.llm_provider.as_deref())
+                .unwrap_or(DEFAULT_ROUTING_LLM_PROVIDER);
+            if !self
+                .llm_providers
+                .iter()
+                .any(|provider| provider.name == routing_provider)
+            {
+                errors.push(ConfigurationError::UnknownRoutingProvider(
+                    routing_provider.to_string(),
+                ));
+            }
+        }
+
+        for provider in &self.llm_providers {
+            if provider
+                .usage
+                .as_ref()
+                .is_some_and(|usage| usage.trim().is_empty())
+            {
+                errors.push(ConfigurationError::EmptyUsage(provider.name.clone()));
+            }
+
+            match &provider.routing_preferences {
+                Some(prefs) if prefs.is_empty() => errors.push(
+                    ConfigurationError::EmptyRoutingPreferences(provider.name.clone()),
+                ),
+                Some(prefs) => {
+                    for pref in prefs {
+                        for (field, value) in
+                            [("name", &pref.name), ("description", &pref.description)]
+                        {
+                            if value.trim().is_empty() {
+                                errors.push(ConfigurationError::BlankRoutingPreference {
+                                    provider: provider.name.clone(),
+                                    field,
+                                });
+                            }
+                        }
+                    }
+                }
+                None => {}
+            }
+
+            if let Some(base_url) = &provider.base_url {
+                if let Err(reason) = validate_base_url(base_url) {
+                    errors.push(ConfigurationError::InvalidBaseUrl {
+                        provider: provider.name.clone(),
+                        url: base_url.clone(),
+                        reason,
+                    });
+                }
+            }
+
+            for (field, reason) in validate_upstream_request(provider) {
+                errors.push(ConfigurationError::InvalidUpstreamRequest {
+                    provider: provider.name.clone(),
+                    field,
+                    reason,
+                });
+            }
+        }
+
+        let default_providers = self
+            .llm_providers
+            .iter()
+            .filter(|provider| provider.default.unwrap_or_default())
+            .map(|provider| provider.name.clone())
+            .collect::<Vec<String>>();
+        if default_providers.len() > 1 {
+            errors.push(ConfigurationError::MultipleDefaultProviders(
+                default_providers,
+            ));
+        }
+
+        let mut endpoints = self
+            .endpoints
+            .iter()
+            .flatten()
+            .collect::<Vec<(&String, &Endpoint)>>();
+        endpoints.sort_by_key(|(name, _)| name.as_str());
+        for (name, endpoint) in endpoints {
+            if let Some(address) = &endpoint.endpoint {
+                if let Err(reason) = validate_endpoint_address(address) {
+                    errors.push(ConfigurationError::InvalidEndpoint {
+                        name: name.clone(),
+                        address: address.clone(),
+                        reason,
+                    });
+                }
+            }
+        }
+
+        for endpoint in self.enabled_endpoints.iter().flatten() {
+            if let Err(endpoint) = EnabledEndpoints::only(&[endpoint]) {
+                errors.push(ConfigurationError::UnsupportedEndpoint(endpoint));
+            }
+        }
+
+        if let Some(upstream) = self
+            .gateway
+            .as_ref()
+            .and_then(|gateway| gateway.upstream.as_ref())
+        {
+            if upstream.client_cert.is_some() != upstream.client_key.is_some() {
+                errors.push(ConfigurationError::IncompleteClientIdentity);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The API endpoints to serve. Unsupported paths are left out, see [`Self::validate`].
+    pub fn enabled_endpoints(&self) -> EnabledEndpoints {
+        match &self.enabled_endpoints {
+            Some(endpoints) => {
+                let supported = endpoints
+                    .iter()
+                    .filter(|endpoint| EnabledEndpoints::only(&[endpoint]).is_ok())
+                    .collect::<Vec<&String>>();
+                EnabledEndpoints::only(&supported).unwrap_or_default()
+            }
+            None => EnabledEndpoints::all(),
+        }
+    }
+}
+
+/// A base url is an http(s) url with a host and no path
+fn validate_base_url(base_url: &str) -> Result<(), String> {
+    let url = url::Url::parse(base_url).map_err(|err| err.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "scheme must be http or https, got {}",
+            url.scheme()
+        ));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err("missing host".to_string());
+    }
+    if !matches!(url.path(), "" | "/") {
+        return Err(format!("must not have a path, got {}", url.path()));
+    }
+    Ok(())
+}
+
+/// Problems with a provider's `method`, `path` and `query_params`, which only apply to
+/// requests sent to its `base_url`
+fn validate_upstream_request(provider: &LlmProvider) -> Vec<(&'static str, String)> {
+    let mut problems = Vec::new();
+    if provider.base_url.is_none() {
+        for (field, set) in [
+            ("method", provider.method.is_some()),
+            ("path", provider.path.is_some()),
+            ("query_params", provider.query_params.is_some()),
+        ] {
+            if set {
+                problems.push((field, "only applies together with base_url".to_string()));
+            }
+        }
+        return problems;
+    }
+
+    if let Some(method) = &provider.method {
+        if method.is_empty() || !method.chars().all(|c| c.is_ascii_alphabetic()) {
+            problems.push(("method", format!("`{}` is not an http method", method)));
+        }
+    }
+    if let Some(path) = &provider.path {
+        if !path.starts_with('/') {
+            problems.push(("path", format!("`{}` must start with /", path)));
+        }
+        if let Err(reason) = provider.render_template(path) {
+            problems.push(("path", reason));
+        }
+    }
+    let mut query_params = provider
+        .query_params
+        .iter()
+        .flatten()
+        .collect::<Vec<(&String, &String)>>();
+    query_params.sort();
+    for (_, value) in query_params {
+        if let Err(reason) = provider.render_template(value) {
+            problems.push(("query_params", reason));
+        }
+    }
+    problems
+}
+
+/// Endpoint addresses are `host` or `host:port`, without a scheme or path
+fn validate_endpoint_address(address: &str) -> Result<(), String> {
+    if address.contains("://") {
+        return Err("expected host or host:port, not a url".to_string());
+    }
+    let url = url::Url::parse(&format!("http://{}", address)).map_err(|err| err.to_string())?;
+    if url.path() != "/" || url.query().is_some() {
+        return Err("expected host or host:port, without a path".to_string());
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -177,7 +567,7 @@ impl Display for LlmProviderType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ModelUsagePreference {
     pub model: String,
     pub routing_preferences: Vec<RoutingPreference>,
@@ -197,9 +587,30 @@ pub struct LlmProvider {
     pub access_key: Option<String>,
     pub model: Option<String>,
     pub default: Option<bool>,
+    /// Set to `false` for providers that can't stream responses
     pub stream: Option<bool>,
     pub endpoint: Option<String>,
     pub port: Option<u16>,
+    /// Base URL (scheme, host and optional port) brightstaff sends this provider's requests to,
+    /// instead of the global upstream endpoint
+    pub base_url: Option<String>,
+    /// HTTP method of requests to `base_url`, `POST` by default
+    pub method: Option<String>,
+    /// Path requests to `base_url` go to instead of the client's request path, e.g.
+    /// `/openai/deployments/{model}/chat/completions`. `{model}` and `{name}` are replaced
+    /// with the provider's model and name.
+    pub path: Option<String>,
+    /// Query parameters added to requests to `base_url`, e.g. `api-version`. Values are
+    /// templated like `path`.
+    pub query_params: Option<HashMap<String, String>>,
+    /// Whether the model accepts image input; providers without it are skipped when
+    /// routing a request whose latest user message carries images
+    pub vision: Option<bool>,
+    /// Set to `false` for models that can't call tools; they are skipped when routing a
+    /// request that declares `tools`
+    pub tools: Option<bool>,
+    /// Cache system prompts of at least this many characters with Anthropic's prompt caching
+    pub cache_system_prompt_min_chars: Option<usize>,
     pub rate_limits: Option<LlmRatelimit>,
     pub usage: Option<String>,
     pub routing_preferences: Option<Vec<RoutingPreference>>,
@@ -236,9 +647,16 @@ impl Default for LlmProvider {
             access_key: None,
             model: None,
             default: Some(true),
-            stream: Some(false),
+            stream: None,
             endpoint: None,
             port: None,
+            base_url: None,
+            method: None,
+            path: None,
+            query_params: None,
+            vision: None,
+            tools: None,
+            cache_system_prompt_min_chars: None,
             rate_limits: None,
             usage: None,
             routing_preferences: None,
@@ -246,6 +664,42 @@ impl Default for LlmProvider {
     }
 }
 
+impl LlmProvider {
+    /// Options for converting requests into this provider's API
+    pub fn conversion_options(&self) -> ConversionOptions {
+        ConversionOptions {
+            cache_system_prompt_min_chars: self.cache_system_prompt_min_chars,
+            ..Default::default()
+        }
+    }
+
+    /// Fill in the `{model}` and `{name}` placeholders of a `path` or query parameter template.
+    /// The model defaults to the provider name when unset.
+    pub fn render_template(&self, template: &str) -> Result<String, String> {
+        let mut rendered = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                return Err(format!("unclosed placeholder in `{}`", template));
+            };
+            match &rest[start + 1..start + end] {
+                "model" => rendered.push_str(self.model.as_deref().unwrap_or(&self.name)),
+                "name" => rendered.push_str(&self.name),
+                placeholder => {
+                    return Err(format!(
+                        "unknown placeholder `{{{}}}`, expected {{model}} or {{name}}",
+                        placeholder
+                    ))
+                }
+            }
+            rest = &rest[start + end + 1..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
 impl Display for LlmProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
@@ -483,4 +937,256 @@ mod test {
             crate::api::open_ai::ParameterType::Bool
         );
     }
+
+    #[test]
+    fn test_reference_configuration_is_valid() {
+        let ref_config = fs::read_to_string(
+            "../../docs/source/resources/includes/arch_config_full_reference_rendered.yaml",
+        )
+        .expect("reference config file not found");
+        let config: super::Configuration = serde_yaml::from_str(&ref_config).unwrap();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let config: super::Configuration = serde_yaml::from_str(
+            r#"
+version: v0.1.0
+routing:
+  llm_provider: my-router
+endpoints:
+  app_server:
+    endpoint: http://127.0.0.1:80/agent
+  bad_port:
+    endpoint: 127.0.0.1:99999
+  ok_server:
+    endpoint: host.docker.internal:18083
+llm_providers:
+  - name: gpt-4o
+    provider_interface: openai
+    routing_preferences:
+      - name: code generation
+        description: " "
+  - name: gpt-4o-mini
+    provider_interface: openai
+    usage: ""
+    routing_preferences: []
+  - name: local-llama
+    provider_interface: openai
+    base_url: ftp://localhost:11434
+  - name: vllm
+    provider_interface: openai
+    base_url: http://vllm.internal:8000/v1
+  - name: azure-gpt-4o
+    provider_interface: openai
+    base_url: https://example.openai.azure.com
+    method: "P O S T"
+    path: /openai/deployments/{deployment}/chat/completions
+  - name: ollama
+    provider_interface: openai
+    path: /api/chat
+"#,
+        )
+        .unwrap();
+
+        let errors = config.validate().unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "routing.llm_provider `my-router` is not defined in llm_providers",
+                "llm_provider `gpt-4o` has a routing preference with an empty description",
+                "llm_provider `gpt-4o-mini` has an empty usage",
+                "llm_provider `gpt-4o-mini` declares routing_preferences but lists none",
+                "llm_provider `local-llama` has an invalid base_url `ftp://localhost:11434`: scheme must be http or https, got ftp",
+                "llm_provider `vllm` has an invalid base_url `http://vllm.internal:8000/v1`: must not have a path, got /v1",
+                "llm_provider `azure-gpt-4o` has an invalid upstream method: `P O S T` is not an http method",
+                "llm_provider `azure-gpt-4o` has an invalid upstream path: unknown placeholder `{deployment}`, expected {model} or {name}",
+                "llm_provider `ollama` has an invalid upstream path: only applies together with base_url",
+                "endpoint `app_server` has an invalid address `http://127.0.0.1:80/agent`: expected host or host:port, not a url",
+                "endpoint `bad_port` has an invalid address `127.0.0.1:99999`: invalid port number",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cache_system_prompt_in_anthropic_request() {
+        use hermesllm::clients::convert_chat_completions_request;
+
+        let provider: super::LlmProvider = serde_yaml::from_str(
+            r#"
+name: claude-sonnet
+provider_interface: claude
+model: claude-sonnet-4-20250514
+cache_system_prompt_min_chars: 20
+"#,
+        )
+        .unwrap();
+
+        let request: hermesllm::apis::openai::ChatCompletionsRequest =
+            serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4-20250514",
+                "messages": [
+                    {"role": "system", "content": "You are a support agent for ACME."},
+                    {"role": "user", "content": "how do I reset my password?"}
+                ]
+            }))
+            .unwrap();
+        let anthropic_request =
+            convert_chat_completions_request(request, &provider.conversion_options()).unwrap();
+
+        let body = serde_json::to_value(&anthropic_request).unwrap();
+        assert_eq!(
+            body["system"],
+            serde_json::json!([{
+                "type": "text",
+                "text": "You are a support agent for ACME.",
+                "cache_control": {"type": "ephemeral"}
+            }])
+        );
+
+        // providers without the setting never cache
+        let provider = super::LlmProvider::default();
+        assert_eq!(provider.conversion_options(), Default::default());
+    }
+
+    #[test]
+    fn test_validate_default_routing_provider() {
+        let config_with = |providers: &str| -> super::Configuration {
+            serde_yaml::from_str(&format!("version: v0.1.0\nllm_providers:\n{}", providers))
+                .unwrap()
+        };
+        let routed = r#"
+  - name: gpt-4o
+    provider_interface: openai
+    routing_preferences:
+      - name: code generation
+        description: generating new code snippets
+"#;
+
+        // without routing.llm_provider the router is expected under its default name
+        assert_eq!(
+            config_with(routed).validate(),
+            Err(vec![super::ConfigurationError::UnknownRoutingProvider(
+                "arch-router".to_string()
+            )])
+        );
+
+        let with_router = format!(
+            "{}  - name: arch-router\n    provider_interface: arch\n",
+            routed
+        );
+        assert_eq!(config_with(&with_router).validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_single_default_provider() {
+        let config_with = |providers: &str| -> super::Configuration {
+            serde_yaml::from_str(&format!("version: v0.1.0\nllm_providers:\n{}", providers))
+                .unwrap()
+        };
+
+        let one_default = r#"
+  - name: gpt-4o
+    provider_interface: openai
+    default: true
+  - name: gpt-4o-mini
+    provider_interface: openai
+"#;
+        assert_eq!(config_with(one_default).validate(), Ok(()));
+
+        let two_defaults = format!(
+            "{}  - name: claude\n    provider_interface: claude\n    default: true\n",
+            one_default
+        );
+        assert_eq!(
+            config_with(&two_defaults).validate(),
+            Err(vec![super::ConfigurationError::MultipleDefaultProviders(
+                vec!["gpt-4o".to_string(), "claude".to_string()]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_enabled_endpoints() {
+        let config_with = |enabled_endpoints: &str| -> super::Configuration {
+            serde_yaml::from_str(&format!(
+                "version: v0.1.0\nllm_providers:\n  - name: gpt-4o\n    provider_interface: openai\n{}",
+                enabled_endpoints
+            ))
+            .unwrap()
+        };
+
+        // everything is served by default
+        let config = config_with("");
+        assert_eq!(config.validate(), Ok(()));
+        assert!(config.enabled_endpoints().is_enabled("/v1/messages"));
+
+        let config = config_with("enabled_endpoints: [/v1/chat/completions]");
+        assert_eq!(config.validate(), Ok(()));
+        let enabled = config.enabled_endpoints();
+        assert!(enabled.is_enabled("/v1/chat/completions"));
+        assert!(!enabled.is_enabled("/v1/messages"));
+
+        let config = config_with("enabled_endpoints: [/v1/chat/completions, /v1/audio/speech]");
+        assert_eq!(
+            config.validate(),
+            Err(vec![super::ConfigurationError::UnsupportedEndpoint(
+                "/v1/audio/speech".to_string()
+            )])
+        );
+        assert!(config
+            .enabled_endpoints()
+            .is_enabled("/v1/chat/completions"));
+    }
+
+    #[test]
+    fn test_gateway_settings() {
+        let config_with = |gateway: &str| -> super::Configuration {
+            serde_yaml::from_str(&format!(
+                "version: v0.1.0\nllm_providers:\n  - name: gpt-4o\n    provider_interface: openai\n{}",
+                gateway
+            ))
+            .unwrap()
+        };
+
+        let config = config_with(
+            r#"
+gateway:
+  unstreamable_requests: buffer
+  stream_usage: enforce
+  missing_usage: zeros
+  response_model: routed
+  pretty_json: true
+  upstream:
+    max_retries: 2
+    client_cert: /etc/archgw/client.pem
+    client_key: /etc/archgw/client.key
+"#,
+        );
+        assert_eq!(config.validate(), Ok(()));
+        let gateway = config.gateway.unwrap();
+        assert_eq!(
+            gateway.unstreamable_requests,
+            Some(super::StreamingFallback::Buffer)
+        );
+        assert_eq!(gateway.stream_usage, Some(super::StreamUsage::Enforce));
+        assert_eq!(gateway.missing_usage, Some(super::MissingUsage::Zeros));
+        assert_eq!(gateway.response_model, Some(super::ResponseModel::Routed));
+        assert_eq!(gateway.upstream.unwrap().max_retries, Some(2));
+
+        // unknown values are rejected rather than silently falling back to the default
+        assert!(serde_yaml::from_str::<super::Configuration>(
+            "version: v0.1.0\nllm_providers: []\ngateway:\n  missing_usage: zero\n"
+        )
+        .is_err());
+
+        let config =
+            config_with("gateway:\n  upstream:\n    client_cert: /etc/archgw/client.pem\n");
+        assert_eq!(
+            config.validate(),
+            Err(vec![super::ConfigurationError::IncompleteClientIdentity])
+        );
+    }
 }
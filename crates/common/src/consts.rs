@@ -11,6 +11,10 @@ pub const MODEL_SERVER_NAME: &str = "model_server";
 pub const ARCH_ROUTING_HEADER: &str = "x-arch-llm-provider";
 pub const MESSAGES_KEY: &str = "messages";
 pub const ARCH_PROVIDER_HINT_HEADER: &str = "x-arch-llm-provider-hint";
+pub const ARCH_FORCE_PROVIDER_HEADER: &str = "x-archgw-force-provider";
+pub const ARCH_DRY_RUN_HEADER: &str = "x-archgw-dry-run";
+pub const ARCH_ROUTE_METADATA_HEADER: &str = "x-archgw-route-metadata";
+pub const ARCH_TRUNCATED_HEADER: &str = "x-archgw-truncated";
 pub const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
 pub const HEALTHZ_PATH: &str = "/healthz";
 pub const X_ARCH_STATE_HEADER: &str = "x-arch-state";
@@ -18,6 +22,7 @@ pub const X_ARCH_API_RESPONSE: &str = "x-arch-api-response-message";
 pub const X_ARCH_TOOL_CALL: &str = "x-arch-tool-call-message";
 pub const X_ARCH_FC_MODEL_RESPONSE: &str = "x-arch-fc-model-response";
 pub const ARCH_FC_MODEL_NAME: &str = "Arch-Function";
+pub const DEFAULT_ROUTING_LLM_PROVIDER: &str = "arch-router";
 pub const REQUEST_ID_HEADER: &str = "x-request-id";
 pub const TRACE_PARENT_HEADER: &str = "traceparent";
 pub const ARCH_INTERNAL_CLUSTER_NAME: &str = "arch_internal";
@@ -52,20 +52,15 @@ pub fn replace_params_in_path(
 
     // add default values
     for param in prompt_target_params.iter() {
-        if !vars_replaced.contains(&param.name) && param.default.is_some() {
-            params.insert(param.name.clone(), param.default.clone().unwrap());
+        if vars_replaced.contains(&param.name) {
+            continue;
+        }
+        if let Some(default) = param.default.as_ref() {
+            params.insert(param.name.clone(), default.clone());
             if query_string_replaced.contains("?") {
-                query_string_replaced.push_str(&format!(
-                    "&{}={}",
-                    param.name,
-                    param.default.as_ref().unwrap()
-                ));
+                query_string_replaced.push_str(&format!("&{}={}", param.name, default));
             } else {
-                query_string_replaced.push_str(&format!(
-                    "?{}={}",
-                    param.name,
-                    param.default.as_ref().unwrap()
-                ));
+                query_string_replaced.push_str(&format!("?{}={}", param.name, default));
             }
         }
     }
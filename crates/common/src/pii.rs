@@ -1,3 +1,8 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
 pub fn obfuscate_auth_header(headers: &mut [(String, String)]) -> &[(String, String)] {
     headers.iter_mut().for_each(|(key, value)| {
         if key.to_lowercase() == "authorization" {
@@ -12,9 +17,98 @@ pub fn obfuscate_auth_header(headers: &mut [(String, String)]) -> &[(String, Str
     headers
 }
 
+/// Fields whose string values are user content, along with everything nested under them
+const CONTENT_FIELDS: [&str; 2] = ["content", "arguments"];
+
+/// How message content shows up in logged request and response bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogRedaction {
+    /// Log bodies as they are
+    #[default]
+    Off,
+    /// Replace message content with its length and a short hash, keeping roles, models and
+    /// the rest of the structure readable
+    Content,
+}
+
+impl LogRedaction {
+    /// Redact content or not, as set by `gateway.redact_logs`
+    pub fn new(redact: bool) -> Self {
+        if redact {
+            LogRedaction::Content
+        } else {
+            LogRedaction::Off
+        }
+    }
+
+    /// A JSON body for the logs. Bodies that are not JSON are redacted whole.
+    pub fn body(self, body: &[u8]) -> String {
+        if self == LogRedaction::Off {
+            return String::from_utf8_lossy(body).into_owned();
+        }
+        match serde_json::from_slice::<Value>(body) {
+            Ok(value) => self.value(&value),
+            Err(_) => placeholder(&String::from_utf8_lossy(body)),
+        }
+    }
+
+    /// A serializable request or response for the logs
+    pub fn value<T: Serialize + ?Sized>(self, value: &T) -> String {
+        let Ok(mut value) = serde_json::to_value(value) else {
+            return String::new();
+        };
+        if self == LogRedaction::Content {
+            redact_value(&mut value, false);
+        }
+        value.to_string()
+    }
+
+    /// A piece of message text for the logs
+    pub fn text(self, text: &str) -> String {
+        match self {
+            LogRedaction::Off => text.to_string(),
+            LogRedaction::Content => placeholder(text),
+        }
+    }
+}
+
+/// Stands in for redacted text. The hash only tells equal texts apart across log lines, it
+/// is not meant to resist guessing.
+fn placeholder(text: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    format!(
+        "[redacted {} chars, hash {:08x}]",
+        text.chars().count(),
+        hasher.finish() as u32
+    )
+}
+
+fn redact_value(value: &mut Value, in_content: bool) {
+    match value {
+        Value::String(text) if in_content => *text = placeholder(text),
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| redact_value(item, in_content)),
+        Value::Object(fields) => redact_fields(fields, in_content),
+        _ => {}
+    }
+}
+
+fn redact_fields(fields: &mut Map<String, Value>, in_content: bool) {
+    for (key, value) in fields.iter_mut() {
+        // part types, e.g. `text` or `image_url`, are structure rather than content
+        if in_content && key == "type" {
+            continue;
+        }
+        redact_value(value, in_content || CONTENT_FIELDS.contains(&key.as_str()));
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::pii::obfuscate_auth_header;
+    use crate::pii::{obfuscate_auth_header, LogRedaction};
+    use serde_json::{json, Value};
 
     #[test]
     pub fn test_obfuscate_auth_header() {
@@ -41,4 +135,69 @@ mod test {
             ]
         );
     }
+
+    const SECRET: &str = "my card number is 4111 1111 1111 1111";
+
+    fn request() -> Value {
+        json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "You are a helpful assistant"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": SECRET},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "pay", "arguments": format!("{{\"note\": \"{}\"}}", SECRET)}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": SECRET}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_redacted_logs_hide_content() {
+        let logged = LogRedaction::Content.value(&request());
+        assert!(!logged.contains("4111"), "{}", logged);
+        assert!(!logged.contains("helpful assistant"), "{}", logged);
+        assert!(!logged.contains("iVBORw0KGgo"), "{}", logged);
+
+        // structure stays readable
+        let logged: Value = serde_json::from_str(&logged).unwrap();
+        assert_eq!(logged["model"], "gpt-4o");
+        assert_eq!(logged["messages"][1]["role"], "user");
+        assert_eq!(logged["messages"][1]["content"][0]["type"], "text");
+        assert_eq!(logged["messages"][1]["content"][1]["type"], "image_url");
+        assert_eq!(
+            logged["messages"][2]["tool_calls"][0]["function"]["name"],
+            "pay"
+        );
+        assert_eq!(logged["messages"][3]["tool_call_id"], "call_1");
+
+        // equal texts get equal placeholders
+        let placeholder = logged["messages"][3]["content"].as_str().unwrap();
+        assert_eq!(logged["messages"][1]["content"][0]["text"], placeholder);
+        assert!(
+            placeholder.starts_with("[redacted 37 chars, hash "),
+            "{}",
+            placeholder
+        );
+    }
+
+    #[test]
+    fn test_redaction_of_raw_bodies_and_text() {
+        let body = request().to_string();
+        assert!(!LogRedaction::Content.body(body.as_bytes()).contains("4111"));
+        assert!(!LogRedaction::Content
+            .body(SECRET.as_bytes())
+            .contains("4111"));
+        assert!(!LogRedaction::Content.text(SECRET).contains("4111"));
+
+        // nothing changes when off
+        assert_eq!(LogRedaction::Off.body(body.as_bytes()), body);
+        assert_eq!(LogRedaction::Off.value(&request()), body);
+        assert_eq!(LogRedaction::Off.text(SECRET), SECRET);
+    }
 }
@@ -1,7 +1,65 @@
+const SHORTEN_STRING_MAX_LEN: usize = 80;
+
+/// Shorten a string for logging, keeping at most 80 bytes
 pub fn shorten_string(s: &str) -> String {
-    if s.len() > 80 {
-        format!("{}...", &s[..80])
-    } else {
-        s.to_string()
+    truncate_with_ellipsis(s, SHORTEN_STRING_MAX_LEN)
+}
+
+/// Truncate to at most `max_len` bytes without splitting a UTF-8 character,
+/// appending "..." only when something was cut off
+pub fn truncate_with_ellipsis(s: &str, max_len: usize) -> String {
+    if s.len() <= max_len {
+        return s.to_string();
+    }
+
+    let end = s
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .take_while(|&idx| idx <= max_len)
+        .last()
+        .unwrap_or(0);
+
+    format!("{}...", &s[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorten_string_short_input_unchanged() {
+        assert_eq!(shorten_string("hello"), "hello");
+        assert_eq!(shorten_string(&"a".repeat(80)), "a".repeat(80));
+    }
+
+    #[test]
+    fn test_shorten_string_ascii() {
+        assert_eq!(
+            shorten_string(&"a".repeat(100)),
+            format!("{}...", "a".repeat(80))
+        );
+    }
+
+    #[test]
+    fn test_shorten_string_multibyte_at_boundary() {
+        // 79 ascii bytes followed by a 2-byte character spanning bytes 79..81
+        let s = format!("{}é{}", "a".repeat(79), "b".repeat(10));
+        assert_eq!(shorten_string(&s), format!("{}...", "a".repeat(79)));
+
+        // 4-byte characters never line up with byte 80 after a single ascii prefix byte
+        let s = format!("a{}", "😀".repeat(30));
+        assert_eq!(shorten_string(&s), format!("a{}...", "😀".repeat(19)));
+
+        // 3-byte characters: 26 of them fill 78 bytes, the 27th would end at byte 81
+        let s = "日".repeat(40);
+        assert_eq!(shorten_string(&s), format!("{}...", "日".repeat(26)));
+    }
+
+    #[test]
+    fn test_truncate_with_ellipsis() {
+        assert_eq!(truncate_with_ellipsis("héllo", 2), "h...");
+        assert_eq!(truncate_with_ellipsis("héllo", 3), "hé...");
+        assert_eq!(truncate_with_ellipsis("héllo", 6), "héllo");
+        assert_eq!(truncate_with_ellipsis("😀", 0), "...");
     }
 }
@@ -0,0 +1,89 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use hermesllm::apis::{
+    AnthropicApi, ChatCompletionsStreamResponse, MessagesStreamEvent, OpenAIApi,
+};
+use hermesllm::clients::streaming::transform_sse_chunk;
+use hermesllm::clients::DetectedApi;
+
+const OPENAI_CHUNK: &str = r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1234567890,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Hello, how can I help you today?"}}]}"#;
+
+const ANTHROPIC_EVENT: &str = r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello, how can I help you today?"}}"#;
+
+/// A typical streamed response: 64 content chunks followed by the terminator
+fn openai_stream() -> String {
+    let mut stream = String::new();
+    for _ in 0..64 {
+        stream.push_str("data: ");
+        stream.push_str(OPENAI_CHUNK);
+        stream.push_str("\n\n");
+    }
+    stream.push_str("data: [DONE]\n\n");
+    stream
+}
+
+fn anthropic_stream() -> String {
+    let mut stream = String::new();
+    for _ in 0..64 {
+        stream.push_str("event: content_block_delta\ndata: ");
+        stream.push_str(ANTHROPIC_EVENT);
+        stream.push_str("\n\n");
+    }
+    stream.push_str("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n");
+    stream
+}
+
+fn bench_chunk_conversion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunk_conversion");
+
+    group.bench_function("openai_to_anthropic", |b| {
+        b.iter(|| {
+            let chunk: ChatCompletionsStreamResponse =
+                serde_json::from_str(black_box(OPENAI_CHUNK)).unwrap();
+            let event: MessagesStreamEvent = chunk.try_into().unwrap();
+            serde_json::to_string(&event).unwrap()
+        })
+    });
+
+    group.bench_function("anthropic_to_openai", |b| {
+        b.iter(|| {
+            let event: MessagesStreamEvent =
+                serde_json::from_str(black_box(ANTHROPIC_EVENT)).unwrap();
+            let chunk: ChatCompletionsStreamResponse = event.try_into().unwrap();
+            serde_json::to_string(&chunk).unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_passthrough_vs_transform(c: &mut Criterion) {
+    let openai = DetectedApi::OpenAI(OpenAIApi::ChatCompletions);
+    let anthropic = DetectedApi::Anthropic(AnthropicApi::Messages);
+    let openai_stream = openai_stream();
+    let anthropic_stream = anthropic_stream();
+
+    let mut group = c.benchmark_group("sse_stream");
+    group.throughput(Throughput::Bytes(openai_stream.len() as u64));
+
+    group.bench_function("openai_passthrough", |b| {
+        b.iter(|| transform_sse_chunk(black_box(openai_stream.as_bytes()), &openai, &openai).unwrap())
+    });
+
+    group.bench_function("openai_to_anthropic", |b| {
+        b.iter(|| {
+            transform_sse_chunk(black_box(openai_stream.as_bytes()), &openai, &anthropic).unwrap()
+        })
+    });
+
+    group.bench_function("anthropic_to_openai", |b| {
+        b.iter(|| {
+            transform_sse_chunk(black_box(anthropic_stream.as_bytes()), &anthropic, &openai)
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_chunk_conversion, bench_passthrough_vs_transform);
+criterion_main!(benches);
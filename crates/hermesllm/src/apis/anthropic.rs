@@ -9,6 +9,7 @@ use super::ApiDefinition;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AnthropicApi {
     Messages,
+    CountTokens,
     // Future APIs can be added here:
     // Embeddings,
     // etc.
@@ -18,12 +19,14 @@ impl ApiDefinition for AnthropicApi {
     fn endpoint(&self) -> &'static str {
         match self {
             AnthropicApi::Messages => "/v1/messages",
+            AnthropicApi::CountTokens => "/v1/messages/count_tokens",
         }
     }
 
     fn from_endpoint(endpoint: &str) -> Option<Self> {
         match endpoint {
             "/v1/messages" => Some(AnthropicApi::Messages),
+            "/v1/messages/count_tokens" => Some(AnthropicApi::CountTokens),
             _ => None,
         }
     }
@@ -31,24 +34,26 @@ impl ApiDefinition for AnthropicApi {
     fn supports_streaming(&self) -> bool {
         match self {
             AnthropicApi::Messages => true,
+            AnthropicApi::CountTokens => false,
         }
     }
 
     fn supports_tools(&self) -> bool {
         match self {
-            AnthropicApi::Messages => true,
+            AnthropicApi::Messages | AnthropicApi::CountTokens => true,
         }
     }
 
     fn supports_vision(&self) -> bool {
         match self {
-            AnthropicApi::Messages => true,
+            AnthropicApi::Messages | AnthropicApi::CountTokens => true,
         }
     }
 
     fn all_variants() -> Vec<Self> {
         vec![
             AnthropicApi::Messages,
+            AnthropicApi::CountTokens,
         ]
     }
 }
@@ -68,6 +73,27 @@ pub struct ThinkingConfig {
     pub enabled: bool,
 }
 
+/// Prompt caching breakpoint, see Anthropic's prompt caching docs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: CacheControlType,
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        CacheControl {
+            cache_type: CacheControlType::Ephemeral,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheControlType {
+    Ephemeral,
+}
+
 // MCP Server types
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -88,11 +114,34 @@ pub struct McpServer {
     pub name: String,
     #[serde(rename = "type")]
     pub server_type: McpServerType,
+    #[serde(deserialize_with = "deserialize_mcp_server_url")]
     pub url: String,
     pub authorization_token: Option<String>,
     pub tool_configuration: Option<McpToolConfiguration>,
 }
 
+/// MCP servers are reached over HTTP(S), so reject anything that isn't an absolute http(s) URL
+fn deserialize_mcp_server_url<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    let host = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or_default();
+
+    if host.is_empty() || url.chars().any(char::is_whitespace) {
+        return Err(serde::de::Error::custom(format!(
+            "invalid MCP server url: {:?}, expected an absolute http(s) URL",
+            url
+        )));
+    }
+
+    Ok(url)
+}
+
 
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -133,6 +182,8 @@ pub enum MessagesRole {
 pub enum MessagesContentBlock {
     Text {
         text: String,
+        /// Caches the prompt up to and including this block
+        cache_control: Option<CacheControl>,
     },
     Thinking {
         text: String,
@@ -271,6 +322,13 @@ pub enum MessagesStopReason {
     Refusal,
 }
 
+impl MessagesStopReason {
+    /// Whether the output was cut off at the token limit rather than finished
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, MessagesStopReason::MaxTokens)
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessagesUsage {
@@ -282,7 +340,7 @@ pub struct MessagesUsage {
 
 // Container response object
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MessagesContainer {
     pub id: String,
     #[serde(rename = "type")]
@@ -306,7 +364,7 @@ pub struct MessagesResponse {
     pub container: Option<MessagesContainer>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 pub enum MessagesStreamEvent {
@@ -326,10 +384,77 @@ pub enum MessagesStreamEvent {
     },
     MessageDelta {
         delta: MessagesMessageDelta,
-        usage: MessagesUsage,
+        /// Omitted when the upstream didn't report usage, rather than sent as zeros
+        #[serde(skip_serializing_if = "Option::is_none")]
+        usage: Option<MessagesUsage>,
     },
     MessageStop,
     Ping,
+    /// An event type this version doesn't know about, e.g. one added by a newer API version.
+    /// It's kept as-is so streams don't break and can be passed through untouched.
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// The known stream events, for deserializing by their `type` tag. Kept apart from
+/// [`MessagesStreamEvent`] so a malformed known event fails instead of becoming `Unknown`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum KnownMessagesStreamEvent {
+    MessageStart { message: MessagesStreamMessage },
+    ContentBlockStart { index: u32, content_block: MessagesContentBlock },
+    ContentBlockDelta { index: u32, delta: MessagesContentDelta },
+    ContentBlockStop { index: u32 },
+    MessageDelta { delta: MessagesMessageDelta, usage: Option<MessagesUsage> },
+    MessageStop,
+    Ping,
+}
+
+const KNOWN_STREAM_EVENT_TYPES: &[&str] = &[
+    "message_start",
+    "content_block_start",
+    "content_block_delta",
+    "content_block_stop",
+    "message_delta",
+    "message_stop",
+    "ping",
+];
+
+impl From<KnownMessagesStreamEvent> for MessagesStreamEvent {
+    fn from(event: KnownMessagesStreamEvent) -> Self {
+        match event {
+            KnownMessagesStreamEvent::MessageStart { message } => MessagesStreamEvent::MessageStart { message },
+            KnownMessagesStreamEvent::ContentBlockStart { index, content_block } => {
+                MessagesStreamEvent::ContentBlockStart { index, content_block }
+            }
+            KnownMessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                MessagesStreamEvent::ContentBlockDelta { index, delta }
+            }
+            KnownMessagesStreamEvent::ContentBlockStop { index } => MessagesStreamEvent::ContentBlockStop { index },
+            KnownMessagesStreamEvent::MessageDelta { delta, usage } => MessagesStreamEvent::MessageDelta { delta, usage },
+            KnownMessagesStreamEvent::MessageStop => MessagesStreamEvent::MessageStop,
+            KnownMessagesStreamEvent::Ping => MessagesStreamEvent::Ping,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessagesStreamEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let event = Value::deserialize(deserializer)?;
+        match event.get("type").and_then(Value::as_str) {
+            None => Err(serde::de::Error::missing_field("type")),
+            Some(event_type) if KNOWN_STREAM_EVENT_TYPES.contains(&event_type) => {
+                KnownMessagesStreamEvent::deserialize(event)
+                    .map(MessagesStreamEvent::from)
+                    .map_err(serde::de::Error::custom)
+            }
+            Some(_) => Ok(MessagesStreamEvent::Unknown(event)),
+        }
+    }
 }
 
 #[skip_serializing_none]
@@ -362,6 +487,71 @@ pub struct MessagesMessageDelta {
     pub stop_sequence: Option<String>,
 }
 
+/// Request for `/v1/messages/count_tokens`: the prompt part of a messages request
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessagesCountTokensRequest {
+    pub model: String,
+    pub messages: Vec<MessagesMessage>,
+    pub system: Option<MessagesSystemPrompt>,
+    pub tools: Option<Vec<MessagesTool>>,
+    pub tool_choice: Option<MessagesToolChoice>,
+    pub thinking: Option<ThinkingConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessagesCountTokensResponse {
+    pub input_tokens: u32,
+}
+
+impl MessagesCountTokensRequest {
+    /// All text that counts towards the prompt: the system prompt, message text, tool calls
+    /// and results, and the tool definitions. Images and documents are not included.
+    pub fn prompt_text(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        match &self.system {
+            Some(MessagesSystemPrompt::Single(text)) => parts.push(text.clone()),
+            Some(MessagesSystemPrompt::Blocks(blocks)) => collect_block_text(blocks, &mut parts),
+            None => {}
+        }
+        for message in &self.messages {
+            match &message.content {
+                MessagesMessageContent::Single(text) => parts.push(text.clone()),
+                MessagesMessageContent::Blocks(blocks) => collect_block_text(blocks, &mut parts),
+            }
+        }
+        for tool in self.tools.iter().flatten() {
+            parts.push(tool.name.clone());
+            parts.extend(tool.description.clone());
+            parts.push(tool.input_schema.to_string());
+        }
+        parts.join("\n")
+    }
+}
+
+fn collect_block_text(blocks: &[MessagesContentBlock], parts: &mut Vec<String>) {
+    for block in blocks {
+        match block {
+            MessagesContentBlock::Text { text, .. } | MessagesContentBlock::Thinking { text } => {
+                parts.push(text.clone())
+            }
+            MessagesContentBlock::ToolUse { name, input, .. }
+            | MessagesContentBlock::ServerToolUse { name, input, .. }
+            | MessagesContentBlock::McpToolUse { name, input, .. } => {
+                parts.push(name.clone());
+                parts.push(input.to_string());
+            }
+            MessagesContentBlock::ToolResult { content, .. }
+            | MessagesContentBlock::WebSearchToolResult { content, .. }
+            | MessagesContentBlock::CodeExecutionToolResult { content, .. }
+            | MessagesContentBlock::McpToolResult { content, .. } => collect_block_text(content, parts),
+            MessagesContentBlock::Image { .. }
+            | MessagesContentBlock::Document { .. }
+            | MessagesContentBlock::ContainerUpload { .. } => {}
+        }
+    }
+}
+
 // Helper functions for API detection and conversion
 impl MessagesRequest {
     pub fn api_type() -> AnthropicApi {
@@ -381,6 +571,12 @@ impl MessagesStreamEvent {
     }
 }
 
+impl MessagesCountTokensRequest {
+    pub fn api_type() -> AnthropicApi {
+        AnthropicApi::CountTokens
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -480,7 +676,7 @@ mod tests {
         }
 
         if let Some(thinking) = &deserialized_request.thinking {
-            assert_eq!(thinking.enabled, true);
+            assert!(thinking.enabled);
         } else {
             panic!("Expected thinking config");
         }
@@ -607,7 +803,7 @@ mod tests {
             assert_eq!(content_blocks.len(), 2);
 
             // Validate text content block
-            if let MessagesContentBlock::Text { text } = &content_blocks[0] {
+            if let MessagesContentBlock::Text { text, .. } = &content_blocks[0] {
                 assert_eq!(text, "What can you see in this image and what's the weather like?");
             } else {
                 panic!("Expected text content block");
@@ -642,7 +838,7 @@ mod tests {
             }
 
             // Validate text content block
-            if let MessagesContentBlock::Text { text } = &content_blocks[1] {
+            if let MessagesContentBlock::Text { text, .. } = &content_blocks[1] {
                 assert_eq!(text, "I can see the image. Let me check the weather for you.");
             } else {
                 panic!("Expected text content block");
@@ -681,7 +877,7 @@ mod tests {
         assert!(deserialized_request.system.is_some());
         if let Some(MessagesSystemPrompt::Blocks(ref system_blocks)) = deserialized_request.system {
             assert_eq!(system_blocks.len(), 1);
-            if let MessagesContentBlock::Text { text } = &system_blocks[0] {
+            if let MessagesContentBlock::Text { text, .. } = &system_blocks[0] {
                 assert_eq!(text, "You are a helpful assistant that can analyze images and provide weather information.");
             } else {
                 panic!("Expected text content block in system prompt");
@@ -743,6 +939,19 @@ mod tests {
         assert_eq!(minimal_mcp_json, serialized_minimal_json);
     }
 
+    #[test]
+    fn test_anthropic_mcp_server_url_validation() {
+        for url in ["https://example.com/mcp", "http://localhost:8080", "https://example.com?x=1"] {
+            let mcp_json = json!({"name": "server", "type": "url", "url": url});
+            assert!(serde_json::from_value::<McpServer>(mcp_json).is_ok(), "{} should be valid", url);
+        }
+
+        for url in ["", "example.com/mcp", "ftp://example.com", "https://", "https:///mcp", "https://exa mple.com"] {
+            let mcp_json = json!({"name": "server", "type": "url", "url": url});
+            assert!(serde_json::from_value::<McpServer>(mcp_json).is_err(), "{} should be invalid", url);
+        }
+    }
+
     #[test]
     fn test_anthropic_response_types() {
         // Test MessagesResponse deserialization
@@ -777,7 +986,7 @@ mod tests {
 
         // Check content
         assert_eq!(deserialized_response.content.len(), 1);
-        if let MessagesContentBlock::Text { text } = &deserialized_response.content[0] {
+        if let MessagesContentBlock::Text { text, .. } = &deserialized_response.content[0] {
             assert_eq!(text, "Hello! How can I help you today?");
         } else {
             panic!("Expected text content block");
@@ -859,7 +1068,7 @@ mod tests {
             assert_eq!(tool_use_id, "toolu_01ABC123");
             assert!(is_error.is_none());
             assert_eq!(content.len(), 1);
-            if let MessagesContentBlock::Text { text } = &content[0] {
+            if let MessagesContentBlock::Text { text, .. } = &content[0] {
                 assert_eq!(text, "The weather in San Francisco is sunny, 72°F");
             } else {
                 panic!("Expected text content in tool result");
@@ -892,7 +1101,69 @@ mod tests {
 
         // Test all_variants
         let all_variants = AnthropicApi::all_variants();
-        assert_eq!(all_variants.len(), 1);
+        assert_eq!(all_variants.len(), 2);
         assert_eq!(all_variants[0], AnthropicApi::Messages);
+
+        // Token counting answers in one go
+        let count_tokens = AnthropicApi::from_endpoint("/v1/messages/count_tokens");
+        assert_eq!(count_tokens, Some(AnthropicApi::CountTokens));
+        assert_eq!(AnthropicApi::CountTokens.endpoint(), "/v1/messages/count_tokens");
+        assert!(!AnthropicApi::CountTokens.supports_streaming());
+    }
+
+    #[test]
+    fn test_count_tokens_request_prompt_text() {
+        let request: MessagesCountTokensRequest = serde_json::from_value(json!({
+            "model": "claude-3-5-sonnet-20241022",
+            "system": "You are terse.",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "Sunny"}]},
+                    {"type": "image", "source": {"url": {"url": "https://example.com/a.png"}}}
+                ]}
+            ],
+            "tools": [{"name": "get_weather", "description": "Current weather", "input_schema": {"type": "object"}}]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            request.prompt_text(),
+            "You are terse.\nWeather in Paris?\nget_weather\n{\"city\":\"Paris\"}\nSunny\nget_weather\nCurrent weather\n{\"type\":\"object\"}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_stream_event_type_is_preserved() {
+        let future_event = json!({
+            "type": "citation_summary",
+            "index": 0,
+            "citations": [{"url": "https://example.com"}]
+        });
+
+        let event: MessagesStreamEvent = serde_json::from_value(future_event.clone()).unwrap();
+        match &event {
+            MessagesStreamEvent::Unknown(value) => assert_eq!(value, &future_event),
+            _ => panic!("Expected unknown event"),
+        }
+        // re-serializes exactly as received, so it can be passed through
+        assert_eq!(serde_json::to_value(&event).unwrap(), future_event);
+
+        // known events are unaffected
+        let ping: MessagesStreamEvent = serde_json::from_value(json!({"type": "ping"})).unwrap();
+        assert!(matches!(ping, MessagesStreamEvent::Ping));
+    }
+
+    #[test]
+    fn test_malformed_known_stream_event_is_an_error() {
+        // a content_block_delta without its delta is broken, not a new event type
+        let malformed = json!({"type": "content_block_delta", "index": 0});
+        assert!(serde_json::from_value::<MessagesStreamEvent>(malformed).is_err());
+
+        let untyped = json!({"index": 0});
+        assert!(serde_json::from_value::<MessagesStreamEvent>(untyped).is_err());
     }
 }
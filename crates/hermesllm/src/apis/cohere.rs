@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use super::ApiDefinition;
+
+// Re-export the Cohere wire types so they are available alongside the API enum
+pub use crate::providers::cohere::types::*;
+
+// Enum for all supported Cohere APIs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CohereApi {
+    Chat,
+}
+
+impl ApiDefinition for CohereApi {
+    fn endpoint(&self) -> &'static str {
+        match self {
+            CohereApi::Chat => "/v1/chat",
+        }
+    }
+
+    fn from_endpoint(endpoint: &str) -> Option<Self> {
+        match endpoint {
+            "/v1/chat" => Some(CohereApi::Chat),
+            _ => None,
+        }
+    }
+
+    fn supports_streaming(&self) -> bool {
+        match self {
+            CohereApi::Chat => true,
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        match self {
+            CohereApi::Chat => true,
+        }
+    }
+
+    fn supports_vision(&self) -> bool {
+        match self {
+            CohereApi::Chat => false,
+        }
+    }
+
+    fn all_variants() -> Vec<Self> {
+        vec![
+            CohereApi::Chat,
+        ]
+    }
+}
+
+// Helper functions for API detection and conversion
+impl CohereChatRequest {
+    pub fn api_type() -> CohereApi {
+        CohereApi::Chat
+    }
+}
+
+impl CohereChatResponse {
+    pub fn api_type() -> CohereApi {
+        CohereApi::Chat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cohere_api_provider_trait_implementation() {
+        let api = CohereApi::Chat;
+
+        assert_eq!(api.endpoint(), "/v1/chat");
+        assert!(api.supports_streaming());
+        assert!(api.supports_tools());
+        assert!(!api.supports_vision());
+
+        assert_eq!(CohereApi::from_endpoint("/v1/chat"), Some(CohereApi::Chat));
+        assert_eq!(CohereApi::from_endpoint("/v1/chat/completions"), None);
+
+        let all_variants = CohereApi::all_variants();
+        assert_eq!(all_variants.len(), 1);
+        assert_eq!(all_variants[0], CohereApi::Chat);
+    }
+}
@@ -1,8 +1,13 @@
 pub mod anthropic;
+pub mod cohere;
 pub mod openai;
 
+#[cfg(test)]
+mod proptests;
+
 // Re-export all types for convenience
 pub use anthropic::*;
+pub use cohere::*;
 pub use openai::*;
 
 /// Common trait that all API definitions must implement
@@ -144,6 +149,7 @@ mod tests {
 
         test_api(&OpenAIApi::ChatCompletions);
         test_api(&AnthropicApi::Messages);
+        test_api(&CohereApi::Chat);
     }
 
     #[test]
@@ -152,6 +158,7 @@ mod tests {
         let endpoints = vec![
             "/v1/chat/completions",
             "/v1/messages",
+            "/v1/chat",
             "/v1/unknown"
         ];
 
@@ -162,6 +169,8 @@ mod tests {
                 detected_apis.push(format!("OpenAI: {:?}", api));
             } else if let Some(api) = AnthropicApi::from_endpoint(endpoint) {
                 detected_apis.push(format!("Anthropic: {:?}", api));
+            } else if let Some(api) = CohereApi::from_endpoint(endpoint) {
+                detected_apis.push(format!("Cohere: {:?}", api));
             } else {
                 detected_apis.push("Unknown API".to_string());
             }
@@ -170,6 +179,7 @@ mod tests {
         assert_eq!(detected_apis, vec![
             "OpenAI: ChatCompletions",
             "Anthropic: Messages",
+            "Cohere: Chat",
             "Unknown API"
         ]);
     }
@@ -178,12 +188,14 @@ mod tests {
     fn test_all_variants_method() {
         // Test that all_variants returns the expected variants
         let openai_variants = OpenAIApi::all_variants();
-        assert_eq!(openai_variants.len(), 1);
+        assert_eq!(openai_variants.len(), 2);
         assert!(openai_variants.contains(&OpenAIApi::ChatCompletions));
+        assert!(openai_variants.contains(&OpenAIApi::Embeddings));
 
         let anthropic_variants = AnthropicApi::all_variants();
-        assert_eq!(anthropic_variants.len(), 1);
+        assert_eq!(anthropic_variants.len(), 2);
         assert!(anthropic_variants.contains(&AnthropicApi::Messages));
+        assert!(anthropic_variants.contains(&AnthropicApi::CountTokens));
 
         // Verify each variant has a valid endpoint
         for variant in openai_variants {
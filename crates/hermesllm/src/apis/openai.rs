@@ -13,8 +13,8 @@ use super::ApiDefinition;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OpenAIApi {
     ChatCompletions,
+    Embeddings,
     // Future APIs can be added here:
-    // Embeddings,
     // FineTuning,
     // etc.
 }
@@ -23,12 +23,14 @@ impl ApiDefinition for OpenAIApi {
     fn endpoint(&self) -> &'static str {
         match self {
             OpenAIApi::ChatCompletions => "/v1/chat/completions",
+            OpenAIApi::Embeddings => "/v1/embeddings",
         }
     }
 
     fn from_endpoint(endpoint: &str) -> Option<Self> {
         match endpoint {
             "/v1/chat/completions" => Some(OpenAIApi::ChatCompletions),
+            "/v1/embeddings" => Some(OpenAIApi::Embeddings),
             _ => None,
         }
     }
@@ -36,24 +38,28 @@ impl ApiDefinition for OpenAIApi {
     fn supports_streaming(&self) -> bool {
         match self {
             OpenAIApi::ChatCompletions => true,
+            OpenAIApi::Embeddings => false,
         }
     }
 
     fn supports_tools(&self) -> bool {
          match self {
             OpenAIApi::ChatCompletions => true,
+            OpenAIApi::Embeddings => false,
         }
     }
 
     fn supports_vision(&self) -> bool {
         match self {
             OpenAIApi::ChatCompletions => true,
+            OpenAIApi::Embeddings => false,
         }
     }
 
     fn all_variants() -> Vec<Self> {
         vec![
             OpenAIApi::ChatCompletions,
+            OpenAIApi::Embeddings,
         ]
     }
 }
@@ -85,6 +91,7 @@ pub struct ChatCompletionsRequest {
     // pub safety_identifier: Option<String>, // GOOD FIRST ISSUE: Future support for safety identifiers
     pub seed: Option<i32>,
     pub service_tier: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_stop")]
     pub stop: Option<Vec<String>>,
     pub store: Option<bool>,
     pub stream: Option<bool>,
@@ -98,6 +105,25 @@ pub struct ChatCompletionsRequest {
     // pub web_search: Option<bool>, // GOOD FIRST ISSUE: Future support for web search
 }
 
+/// `stop` may be sent either as a single string or as an array of strings
+fn deserialize_stop<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stop {
+        Single(String),
+        Multiple(Vec<String>),
+    }
+
+    Ok(match Option::<Stop>::deserialize(deserializer)? {
+        Some(Stop::Single(stop)) => Some(vec![stop]),
+        Some(Stop::Multiple(stops)) => Some(stops),
+        None => None,
+    })
+}
+
 // ============================================================================
 // CHAT COMPLETIONS API TYPES
 // ============================================================================
@@ -107,6 +133,8 @@ pub struct ChatCompletionsRequest {
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     System,
+    /// System instructions under the name newer OpenAI models use for them
+    Developer,
     User,
     Assistant,
     Tool,
@@ -188,9 +216,6 @@ pub struct ImageUrl {
     pub detail: Option<String>,
 }
 
-/// A single message in a chat conversation
-
-
 /// A tool call made by the assistant
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ToolCall {
@@ -249,6 +274,12 @@ pub enum ToolChoice {
         choice_type: String,
         function: FunctionChoice,
     },
+    /// Restrict the model to a subset of the declared tools
+    AllowedTools {
+        #[serde(rename = "type")]
+        choice_type: String,
+        allowed_tools: AllowedTools,
+    },
 }
 
 /// Specific function choice
@@ -257,6 +288,38 @@ pub struct FunctionChoice {
     pub name: String,
 }
 
+/// How the model may use the tools listed in an `allowed_tools` choice
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowedToolsMode {
+    /// The model may call one of the allowed tools or reply with a message
+    Auto,
+    /// The model must call one of the allowed tools
+    Required,
+}
+
+/// Subset of the request's tools the model is allowed to call
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AllowedTools {
+    pub mode: AllowedToolsMode,
+    pub tools: Vec<AllowedTool>,
+}
+
+impl AllowedTools {
+    /// Whether a function with the given name is in the allowed set
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.iter().any(|tool| tool.function.name == name)
+    }
+}
+
+/// Reference to a declared tool by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AllowedTool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionChoice,
+}
+
 /// Static content for prediction/prefill functionality
 ///
 /// Static predicted output content, such as the content of a text file
@@ -302,6 +365,10 @@ pub struct ChatCompletionsResponse {
     pub choices: Vec<Choice>,
     pub usage: Usage,
     pub system_fingerprint: Option<String>,
+    /// Provider data OpenAI has no field for, kept so a later conversion back to the
+    /// provider's format can restore it (e.g. Anthropic's code execution `container`, or a
+    /// stop reason `finish_reason` can't express)
+    pub metadata: Option<HashMap<String, Value>>,
 }
 
 /// Finish reason for completion
@@ -315,6 +382,13 @@ pub enum FinishReason {
     FunctionCall, // Legacy
 }
 
+impl FinishReason {
+    /// Whether the output was cut off at the token limit rather than finished
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, FinishReason::Length)
+    }
+}
+
 /// Token usage information
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -351,9 +425,35 @@ pub struct Choice {
     pub index: u32,
     pub message: ResponseMessage,
     pub finish_reason: Option<FinishReason>,
-    pub logprobs: Option<Value>,
+    pub logprobs: Option<LogProbs>,
+}
+
+/// Log probabilities of a choice's tokens, returned when the request sets `logprobs`
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogProbs {
+    pub content: Option<Vec<TokenLogProb>>,
+    pub refusal: Option<Vec<TokenLogProb>>,
+}
+
+/// A generated token with its log probability and the most likely alternatives
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenLogProb {
+    pub token: String,
+    pub logprob: f64,
+    /// UTF-8 bytes of the token, `null` for tokens without a byte representation
+    pub bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogProb>,
 }
 
+/// One of the `top_logprobs` alternatives for a token position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopLogProb {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>,
+}
 
 // ============================================================================
 // STREAMING API TYPES
@@ -382,7 +482,9 @@ pub struct StreamChoice {
     pub index: u32,
     pub delta: MessageDelta,
     pub finish_reason: Option<FinishReason>,
-    pub logprobs: Option<Value>,
+    pub logprobs: Option<LogProbs>,
+    /// Usage for this choice alone, reported by some upstreams when `n > 1`
+    pub usage: Option<Usage>,
 }
 
 /// Message delta for streaming updates
@@ -417,6 +519,94 @@ pub struct FunctionCallDelta {
     pub arguments: Option<String>,
 }
 
+impl ChatCompletionsResponse {
+    /// The chunks a streaming request would have produced for this response, to fake a stream
+    /// from a buffered response. Each choice gets a role chunk, a chunk with its content, one
+    /// per tool call, and a last chunk with its finish reason; the very last chunk also
+    /// carries the usage. The `[DONE]` sentinel is left to whoever writes the SSE frames.
+    pub fn to_stream_chunks(&self) -> Vec<ChatCompletionsStreamResponse> {
+        let chunk = |choice: StreamChoice| ChatCompletionsStreamResponse {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![choice],
+            usage: None,
+            system_fingerprint: self.system_fingerprint.clone(),
+            service_tier: None,
+        };
+        let delta_choice = |index: u32, delta: MessageDelta| StreamChoice {
+            index,
+            delta,
+            finish_reason: None,
+            logprobs: None,
+            usage: None,
+        };
+        let empty_delta = MessageDelta {
+            role: None,
+            content: None,
+            refusal: None,
+            function_call: None,
+            tool_calls: None,
+        };
+
+        let mut chunks = Vec::new();
+        for choice in &self.choices {
+            let message = &choice.message;
+            chunks.push(chunk(delta_choice(
+                choice.index,
+                MessageDelta {
+                    role: Some(message.role.clone()),
+                    ..empty_delta.clone()
+                },
+            )));
+
+            if message.content.is_some() || message.refusal.is_some() || message.function_call.is_some() {
+                chunks.push(chunk(StreamChoice {
+                    logprobs: choice.logprobs.clone(),
+                    ..delta_choice(
+                        choice.index,
+                        MessageDelta {
+                            content: message.content.clone(),
+                            refusal: message.refusal.clone(),
+                            function_call: message.function_call.clone(),
+                            ..empty_delta.clone()
+                        },
+                    )
+                }));
+            }
+
+            for (index, tool_call) in message.tool_calls.iter().flatten().enumerate() {
+                chunks.push(chunk(delta_choice(
+                    choice.index,
+                    MessageDelta {
+                        tool_calls: Some(vec![ToolCallDelta {
+                            index: index as u32,
+                            id: Some(tool_call.id.clone()),
+                            call_type: Some(tool_call.call_type.clone()),
+                            function: Some(FunctionCallDelta {
+                                name: Some(tool_call.function.name.clone()),
+                                arguments: Some(tool_call.function.arguments.clone()),
+                            }),
+                        }]),
+                        ..empty_delta.clone()
+                    },
+                )));
+            }
+
+            chunks.push(chunk(StreamChoice {
+                finish_reason: choice.finish_reason.clone(),
+                ..delta_choice(choice.index, empty_delta.clone())
+            }));
+        }
+
+        if let Some(last) = chunks.last_mut() {
+            last.usage = Some(self.usage.clone());
+        }
+        chunks
+    }
+}
+
 /// Stream options for controlling streaming behavior
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -424,6 +614,76 @@ pub struct StreamOptions {
     pub include_usage: Option<bool>,
 }
 
+// ============================================================================
+// EMBEDDINGS API TYPES
+// ============================================================================
+
+/// Embeddings API request
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddingsRequest {
+    pub input: EmbeddingInput,
+    pub model: String,
+    pub encoding_format: Option<EncodingFormat>,
+    /// Size of the returned vectors, for models that can shorten them
+    pub dimensions: Option<u32>,
+    pub user: Option<String>,
+}
+
+impl EmbeddingsRequest {
+    pub fn api_type() -> OpenAIApi {
+        OpenAIApi::Embeddings
+    }
+}
+
+/// Text to embed, either as strings or as already tokenized input
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Text(String),
+    TextArray(Vec<String>),
+    Tokens(Vec<u32>),
+    TokenArrays(Vec<Vec<u32>>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingFormat {
+    Float,
+    Base64,
+}
+
+/// Embeddings API response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddingsResponse {
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingsUsage,
+}
+
+/// The embedding of one input, in the order of the request's inputs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddingData {
+    pub object: String,
+    pub embedding: Embedding,
+    pub index: u32,
+}
+
+/// A vector of floats, or the base64 of its little-endian bytes with `encoding_format: base64`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Embedding {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingsUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -737,10 +997,78 @@ mod tests {
 
         // Test all_variants
         let all_variants = OpenAIApi::all_variants();
-        assert_eq!(all_variants.len(), 1);
+        assert_eq!(all_variants.len(), 2);
         assert_eq!(all_variants[0], OpenAIApi::ChatCompletions);
     }
 
+    #[test]
+    fn test_embeddings_api() {
+        let api = OpenAIApi::Embeddings;
+        assert_eq!(api.endpoint(), "/v1/embeddings");
+        assert!(!api.supports_streaming());
+        assert!(!api.supports_tools());
+        assert!(!api.supports_vision());
+        assert_eq!(OpenAIApi::from_endpoint("/v1/embeddings"), Some(api));
+        assert_eq!(EmbeddingsRequest::api_type(), OpenAIApi::Embeddings);
+    }
+
+    #[test]
+    fn test_embeddings_request_round_trip() {
+        let requests = [
+            json!({"input": "The food was delicious", "model": "text-embedding-3-small"}),
+            json!({
+                "input": ["first text", "second text"],
+                "model": "text-embedding-3-large",
+                "encoding_format": "float",
+                "dimensions": 256,
+                "user": "user-1234"
+            }),
+            json!({"input": [1212, 318, 257], "model": "text-embedding-3-small", "encoding_format": "base64"}),
+            json!({"input": [[1212, 318], [257]], "model": "text-embedding-3-small"}),
+        ];
+        let inputs: Vec<EmbeddingsRequest> = requests
+            .iter()
+            .map(|request| serde_json::from_value(request.clone()).unwrap())
+            .collect();
+
+        assert_eq!(inputs[0].input, EmbeddingInput::Text("The food was delicious".to_string()));
+        assert_eq!(inputs[1].input, EmbeddingInput::TextArray(vec!["first text".to_string(), "second text".to_string()]));
+        assert_eq!(inputs[1].encoding_format, Some(EncodingFormat::Float));
+        assert_eq!(inputs[1].dimensions, Some(256));
+        assert_eq!(inputs[2].input, EmbeddingInput::Tokens(vec![1212, 318, 257]));
+        assert_eq!(inputs[2].encoding_format, Some(EncodingFormat::Base64));
+        assert_eq!(inputs[3].input, EmbeddingInput::TokenArrays(vec![vec![1212, 318], vec![257]]));
+
+        for (request, parsed) in requests.iter().zip(&inputs) {
+            assert_eq!(&serde_json::to_value(parsed).unwrap(), request);
+        }
+
+        let missing_input = json!({"model": "text-embedding-3-small"});
+        assert!(serde_json::from_value::<EmbeddingsRequest>(missing_input).is_err());
+    }
+
+    #[test]
+    fn test_embeddings_response_round_trip() {
+        let response = json!({
+            "object": "list",
+            "data": [
+                {"object": "embedding", "embedding": [0.5, -0.25, 0.125], "index": 0},
+                {"object": "embedding", "embedding": "AAAAPwAAgL4AAAA+", "index": 1}
+            ],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 8, "total_tokens": 8}
+        });
+
+        let parsed: EmbeddingsResponse = serde_json::from_value(response.clone()).unwrap();
+        assert_eq!(parsed.data.len(), 2);
+        assert_eq!(parsed.data[0].embedding, Embedding::Float(vec![0.5, -0.25, 0.125]));
+        assert_eq!(parsed.data[1].embedding, Embedding::Base64("AAAAPwAAgL4AAAA+".to_string()));
+        assert_eq!(parsed.data[1].index, 1);
+        assert_eq!(parsed.usage, EmbeddingsUsage { prompt_tokens: 8, total_tokens: 8 });
+
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), response);
+    }
+
     #[test]
     fn test_role_specific_behavior() {
         // Test 1: User message - basic content, no tool-related fields
@@ -879,5 +1207,112 @@ mod tests {
         // Test that invalid string values fail deserialization (type safety!)
         let invalid_result: Result<ToolChoice, _> = serde_json::from_value(json!("invalid"));
         assert!(invalid_result.is_err());
+
+        // Object forms: a named function and an allowed_tools subset
+        let allowed_json = json!({
+            "type": "allowed_tools",
+            "allowed_tools": {
+                "mode": "required",
+                "tools": [{"type": "function", "function": {"name": "get_weather"}}]
+            }
+        });
+        let allowed: ToolChoice = serde_json::from_value(allowed_json.clone()).unwrap();
+        if let ToolChoice::AllowedTools { allowed_tools, .. } = &allowed {
+            assert_eq!(allowed_tools.mode, AllowedToolsMode::Required);
+            assert!(allowed_tools.contains("get_weather"));
+            assert!(!allowed_tools.contains("get_time"));
+        } else {
+            panic!("Expected allowed_tools choice");
+        }
+        assert_eq!(serde_json::to_value(&allowed).unwrap(), allowed_json);
+
+        let function_json = json!({"type": "function", "function": {"name": "get_weather"}});
+        let function: ToolChoice = serde_json::from_value(function_json).unwrap();
+        assert!(matches!(function, ToolChoice::Function { .. }));
+    }
+
+    #[test]
+    fn test_response_to_stream_chunks() {
+        let response: ChatCompletionsResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Checking both cities.",
+                    "tool_calls": [
+                        {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                        {"id": "call_2", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Rome\"}"}}
+                    ]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": {"prompt_tokens": 20, "completion_tokens": 30, "total_tokens": 50}
+        }))
+        .unwrap();
+
+        let chunks = response.to_stream_chunks();
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|chunk| chunk.id == "chatcmpl-123" && chunk.object == "chat.completion.chunk"));
+        // usage only comes with the last chunk
+        assert!(chunks[..4].iter().all(|chunk| chunk.usage.is_none()));
+        assert_eq!(chunks[4].usage.as_ref().unwrap().total_tokens, 50);
+
+        // Reassemble the message from the deltas the way a streaming client would
+        let mut role = None;
+        let mut content = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut finish_reason = None;
+        for chunk in &chunks {
+            let choice = &chunk.choices[0];
+            assert_eq!(choice.index, 0);
+            role = role.or(choice.delta.role.clone());
+            content.push_str(choice.delta.content.as_deref().unwrap_or_default());
+            for delta in choice.delta.tool_calls.iter().flatten() {
+                assert_eq!(delta.index as usize, tool_calls.len());
+                let function = delta.function.clone().unwrap();
+                tool_calls.push(ToolCall {
+                    id: delta.id.clone().unwrap(),
+                    call_type: delta.call_type.clone().unwrap(),
+                    function: FunctionCall {
+                        name: function.name.unwrap(),
+                        arguments: function.arguments.unwrap(),
+                    },
+                });
+            }
+            finish_reason = finish_reason.or(choice.finish_reason.clone());
+        }
+
+        let message = &response.choices[0].message;
+        assert_eq!(role, Some(Role::Assistant));
+        assert_eq!(Some(content), message.content);
+        assert_eq!(Some(tool_calls), message.tool_calls);
+        assert_eq!(finish_reason, Some(FinishReason::ToolCalls));
+    }
+
+    #[test]
+    fn test_stop_accepts_string_or_array() {
+        let single: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4",
+            "messages": [],
+            "stop": "\n"
+        })).unwrap();
+        assert_eq!(single.stop, Some(vec!["\n".to_string()]));
+
+        let multiple: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4",
+            "messages": [],
+            "stop": ["\n", "END"]
+        })).unwrap();
+        assert_eq!(multiple.stop, Some(vec!["\n".to_string(), "END".to_string()]));
+
+        let missing: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4",
+            "messages": []
+        })).unwrap();
+        assert_eq!(missing.stop, None);
     }
 }
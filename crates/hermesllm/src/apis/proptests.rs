@@ -0,0 +1,505 @@
+//! Property tests for the `apis` wire types
+//!
+//! Every generated value must survive serialize → deserialize → serialize unchanged, and
+//! the `TryFrom` conversions between providers must return `Err` rather than panic.
+//! Generators are built from small leaf strategies so failures shrink to minimal messages.
+
+use proptest::collection::{hash_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::*;
+use crate::clients::TransformError;
+
+// ============================================================================
+// LEAF GENERATORS
+// ============================================================================
+
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-zA-Z0-9 ,.!?]{0,24}",
+        any::<String>(),
+    ]
+}
+
+fn identifier() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9_]{0,12}"
+}
+
+/// Finite floats only: serde_json cannot represent NaN or infinity
+fn sampling_value() -> impl Strategy<Value = f32> {
+    0.0f32..2.0f32
+}
+
+/// Small JSON objects, as used for tool inputs and schemas
+fn json_object() -> impl Strategy<Value = Value> {
+    hash_map(identifier(), prop_oneof![
+        text().prop_map(Value::from),
+        any::<i32>().prop_map(Value::from),
+        any::<bool>().prop_map(Value::from),
+    ], 0..4)
+        .prop_map(|map| json!(map))
+}
+
+// ============================================================================
+// ANTHROPIC GENERATORS
+// ============================================================================
+
+fn messages_image_source() -> impl Strategy<Value = MessagesImageSource> {
+    prop_oneof![
+        ("image/(png|jpeg|gif|webp)", "[A-Za-z0-9+/]{0,32}")
+            .prop_map(|(media_type, data)| MessagesImageSource::Base64 { media_type, data }),
+        "https://example\\.com/[a-z]{1,8}\\.png".prop_map(|url| MessagesImageSource::Url { url }),
+    ]
+}
+
+/// Content blocks that can appear at the top level of a message, tool results nest them
+fn messages_content_block() -> impl Strategy<Value = MessagesContentBlock> {
+    let leaf = prop_oneof![
+        text().prop_map(|text| MessagesContentBlock::Text { text, cache_control: None }),
+        text().prop_map(|text| MessagesContentBlock::Thinking { text }),
+        messages_image_source().prop_map(|source| MessagesContentBlock::Image { source }),
+        (identifier(), identifier(), json_object())
+            .prop_map(|(id, name, input)| MessagesContentBlock::ToolUse { id, name, input }),
+        (identifier(), identifier(), json_object())
+            .prop_map(|(id, name, input)| MessagesContentBlock::McpToolUse { id, name, input }),
+    ];
+
+    leaf.prop_recursive(2, 8, 3, |inner| {
+        prop_oneof![
+            (identifier(), option::of(any::<bool>()), vec(inner.clone(), 0..3)).prop_map(
+                |(tool_use_id, is_error, content)| MessagesContentBlock::ToolResult {
+                    tool_use_id,
+                    is_error,
+                    content,
+                }
+            ),
+            (identifier(), option::of(any::<bool>()), vec(inner, 0..3)).prop_map(
+                |(tool_use_id, is_error, content)| MessagesContentBlock::McpToolResult {
+                    tool_use_id,
+                    is_error,
+                    content,
+                }
+            ),
+        ]
+    })
+}
+
+fn messages_role() -> impl Strategy<Value = MessagesRole> {
+    prop_oneof![Just(MessagesRole::User), Just(MessagesRole::Assistant)]
+}
+
+fn messages_message() -> impl Strategy<Value = MessagesMessage> {
+    (
+        messages_role(),
+        prop_oneof![
+            text().prop_map(MessagesMessageContent::Single),
+            vec(messages_content_block(), 0..4).prop_map(MessagesMessageContent::Blocks),
+        ],
+    )
+        .prop_map(|(role, content)| MessagesMessage { role, content })
+}
+
+fn messages_tool() -> impl Strategy<Value = MessagesTool> {
+    (identifier(), option::of(text()), json_object()).prop_map(|(name, description, input_schema)| {
+        MessagesTool {
+            name,
+            description,
+            input_schema,
+        }
+    })
+}
+
+fn messages_tool_choice() -> impl Strategy<Value = MessagesToolChoice> {
+    (
+        prop_oneof![
+            Just(MessagesToolChoiceType::Auto),
+            Just(MessagesToolChoiceType::Any),
+            Just(MessagesToolChoiceType::Tool),
+            Just(MessagesToolChoiceType::None),
+        ],
+        option::of(identifier()),
+        option::of(any::<bool>()),
+    )
+        .prop_map(|(kind, name, disable_parallel_tool_use)| MessagesToolChoice {
+            kind,
+            name,
+            disable_parallel_tool_use,
+        })
+}
+
+fn messages_request() -> impl Strategy<Value = MessagesRequest> {
+    (
+        (
+            identifier(),
+            vec(messages_message(), 0..4),
+            1u32..100_000,
+            option::of(prop_oneof![
+                text().prop_map(MessagesSystemPrompt::Single),
+                vec(messages_content_block(), 0..3).prop_map(MessagesSystemPrompt::Blocks),
+            ]),
+        ),
+        (
+            option::of(sampling_value()),
+            option::of(sampling_value()),
+            option::of(0u32..500),
+            option::of(any::<bool>()),
+            option::of(vec(text(), 0..3)),
+            option::of(vec(messages_tool(), 0..3)),
+            option::of(messages_tool_choice()),
+        ),
+    )
+        .prop_map(
+            |(
+                (model, messages, max_tokens, system),
+                (temperature, top_p, top_k, stream, stop_sequences, tools, tool_choice),
+            )| MessagesRequest {
+                model,
+                messages,
+                max_tokens,
+                container: None,
+                mcp_servers: None,
+                system,
+                metadata: None,
+                service_tier: None,
+                thinking: None,
+                temperature,
+                top_p,
+                top_k,
+                stream,
+                stop_sequences,
+                tools,
+                tool_choice,
+            },
+        )
+}
+
+fn messages_stop_reason() -> impl Strategy<Value = MessagesStopReason> {
+    prop_oneof![
+        Just(MessagesStopReason::EndTurn),
+        Just(MessagesStopReason::MaxTokens),
+        Just(MessagesStopReason::StopSequence),
+        Just(MessagesStopReason::ToolUse),
+        Just(MessagesStopReason::PauseTurn),
+        Just(MessagesStopReason::Refusal),
+    ]
+}
+
+fn messages_usage() -> impl Strategy<Value = MessagesUsage> {
+    (0u32..100_000, 0u32..100_000, option::of(0u32..1000), option::of(0u32..1000)).prop_map(
+        |(input_tokens, output_tokens, cache_creation_input_tokens, cache_read_input_tokens)| {
+            MessagesUsage {
+                input_tokens,
+                output_tokens,
+                cache_creation_input_tokens,
+                cache_read_input_tokens,
+            }
+        },
+    )
+}
+
+fn messages_response() -> impl Strategy<Value = MessagesResponse> {
+    (
+        identifier(),
+        vec(messages_content_block(), 0..4),
+        identifier(),
+        messages_stop_reason(),
+        option::of(text()),
+        messages_usage(),
+    )
+        .prop_map(|(id, content, model, stop_reason, stop_sequence, usage)| MessagesResponse {
+            id,
+            obj_type: "message".to_string(),
+            role: MessagesRole::Assistant,
+            content,
+            model,
+            stop_reason,
+            stop_sequence,
+            usage,
+            container: None,
+        })
+}
+
+// ============================================================================
+// OPENAI GENERATORS
+// ============================================================================
+
+fn role() -> impl Strategy<Value = Role> {
+    prop_oneof![
+        Just(Role::System),
+        Just(Role::User),
+        Just(Role::Assistant),
+        Just(Role::Tool),
+    ]
+}
+
+fn content_part() -> impl Strategy<Value = ContentPart> {
+    prop_oneof![
+        text().prop_map(|text| ContentPart::Text { text }),
+        (
+            prop_oneof![
+                "https://example\\.com/[a-z]{1,8}\\.png",
+                "data:image/png;base64,[A-Za-z0-9+/]{0,32}",
+            ],
+            option::of(prop_oneof![Just("auto".to_string()), Just("low".to_string())]),
+        )
+            .prop_map(|(url, detail)| ContentPart::ImageUrl {
+                image_url: ImageUrl { url, detail },
+            }),
+    ]
+}
+
+fn message_content() -> impl Strategy<Value = MessageContent> {
+    prop_oneof![
+        text().prop_map(MessageContent::Text),
+        vec(content_part(), 0..4).prop_map(MessageContent::Parts),
+    ]
+}
+
+fn tool_call() -> impl Strategy<Value = ToolCall> {
+    (
+        identifier(),
+        identifier(),
+        prop_oneof![
+            json_object().prop_map(|args| args.to_string()),
+            // arguments are model output and not guaranteed to be valid JSON
+            text(),
+        ],
+    )
+        .prop_map(|(id, name, arguments)| ToolCall {
+            id,
+            call_type: "function".to_string(),
+            function: FunctionCall { name, arguments },
+        })
+}
+
+fn message() -> impl Strategy<Value = Message> {
+    (
+        role(),
+        message_content(),
+        option::of(identifier()),
+        option::of(vec(tool_call(), 1..3)),
+        option::of(identifier()),
+    )
+        .prop_map(|(role, content, name, tool_calls, tool_call_id)| Message {
+            role,
+            content,
+            name,
+            tool_calls,
+            tool_call_id,
+        })
+}
+
+fn tool() -> impl Strategy<Value = Tool> {
+    (identifier(), option::of(text()), json_object(), option::of(any::<bool>())).prop_map(
+        |(name, description, parameters, strict)| Tool {
+            tool_type: "function".to_string(),
+            function: Function {
+                name,
+                description,
+                parameters,
+                strict,
+            },
+        },
+    )
+}
+
+fn tool_choice() -> impl Strategy<Value = ToolChoice> {
+    prop_oneof![
+        Just(ToolChoice::Type(ToolChoiceType::Auto)),
+        Just(ToolChoice::Type(ToolChoiceType::Required)),
+        Just(ToolChoice::Type(ToolChoiceType::None)),
+        identifier().prop_map(|name| ToolChoice::Function {
+            choice_type: "function".to_string(),
+            function: FunctionChoice { name },
+        }),
+        (
+            prop_oneof![Just(AllowedToolsMode::Auto), Just(AllowedToolsMode::Required)],
+            vec(identifier(), 0..3),
+        )
+            .prop_map(|(mode, names)| ToolChoice::AllowedTools {
+                choice_type: "allowed_tools".to_string(),
+                allowed_tools: AllowedTools {
+                    mode,
+                    tools: names
+                        .into_iter()
+                        .map(|name| AllowedTool {
+                            tool_type: "function".to_string(),
+                            function: FunctionChoice { name },
+                        })
+                        .collect(),
+                },
+            }),
+    ]
+}
+
+fn chat_completions_request() -> impl Strategy<Value = ChatCompletionsRequest> {
+    (
+        (identifier(), vec(message(), 0..5)),
+        (
+            option::of(sampling_value()),
+            option::of(sampling_value()),
+            option::of(1u32..100_000),
+            option::of(any::<bool>()),
+            option::of(vec(text(), 0..3)),
+            option::of(vec(tool(), 0..3)),
+            option::of(tool_choice()),
+            option::of(any::<bool>()),
+            option::of(any::<i32>()),
+        ),
+    )
+        .prop_map(
+            |(
+                (model, messages),
+                (temperature, top_p, max_tokens, stream, stop, tools, tool_choice, parallel_tool_calls, seed),
+            )| ChatCompletionsRequest {
+                model,
+                messages,
+                temperature,
+                top_p,
+                max_tokens,
+                stream,
+                stop,
+                tools,
+                tool_choice,
+                parallel_tool_calls,
+                seed,
+                ..Default::default()
+            },
+        )
+}
+
+fn finish_reason() -> impl Strategy<Value = FinishReason> {
+    prop_oneof![
+        Just(FinishReason::Stop),
+        Just(FinishReason::Length),
+        Just(FinishReason::ToolCalls),
+        Just(FinishReason::ContentFilter),
+        Just(FinishReason::FunctionCall),
+    ]
+}
+
+fn usage() -> impl Strategy<Value = Usage> {
+    (0u32..100_000, 0u32..100_000).prop_map(|(prompt_tokens, completion_tokens)| Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        prompt_tokens_details: None,
+        completion_tokens_details: None,
+    })
+}
+
+fn chat_completions_response() -> impl Strategy<Value = ChatCompletionsResponse> {
+    (
+        identifier(),
+        identifier(),
+        vec(
+            (
+                option::of(text()),
+                option::of(vec(tool_call(), 1..3)),
+                option::of(finish_reason()),
+            ),
+            0..3,
+        ),
+        usage(),
+        option::of(identifier()),
+    )
+        .prop_map(|(id, model, choices, usage, system_fingerprint)| ChatCompletionsResponse {
+            id,
+            object: "chat.completion".to_string(),
+            created: 1234567890,
+            model,
+            choices: choices
+                .into_iter()
+                .enumerate()
+                .map(|(index, (content, tool_calls, finish_reason))| Choice {
+                    index: index as u32,
+                    message: ResponseMessage {
+                        role: Role::Assistant,
+                        content,
+                        refusal: None,
+                        annotations: None,
+                        audio: None,
+                        function_call: None,
+                        tool_calls,
+                    },
+                    finish_reason,
+                    logprobs: None,
+                })
+                .collect(),
+            usage,
+            system_fingerprint,
+            metadata: None,
+        })
+}
+
+// ============================================================================
+// PROPERTIES
+// ============================================================================
+
+/// Serialize, deserialize and serialize again; the two JSON documents must be identical
+fn assert_json_round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Result<(), TestCaseError> {
+    let first = serde_json::to_value(value).map_err(|e| TestCaseError::fail(e.to_string()))?;
+    let decoded: T = serde_json::from_value(first.clone()).map_err(|e| TestCaseError::fail(format!("{}: {}", e, first)))?;
+    let second = serde_json::to_value(&decoded).map_err(|e| TestCaseError::fail(e.to_string()))?;
+    prop_assert_eq!(first, second);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn messages_request_round_trips(request in messages_request()) {
+        assert_json_round_trip(&request)?;
+    }
+
+    #[test]
+    fn messages_response_round_trips(response in messages_response()) {
+        assert_json_round_trip(&response)?;
+    }
+
+    #[test]
+    fn chat_completions_request_round_trips(request in chat_completions_request()) {
+        assert_json_round_trip(&request)?;
+    }
+
+    #[test]
+    fn chat_completions_response_round_trips(response in chat_completions_response()) {
+        assert_json_round_trip(&response)?;
+    }
+
+    #[test]
+    fn messages_request_converts_without_panic(request in messages_request()) {
+        let converted: Result<ChatCompletionsRequest, TransformError> = request.try_into();
+        if let Ok(converted) = converted {
+            assert_json_round_trip(&converted)?;
+        }
+    }
+
+    #[test]
+    fn chat_completions_request_converts_without_panic(request in chat_completions_request()) {
+        let converted: Result<MessagesRequest, TransformError> = request.clone().try_into();
+        if let Ok(converted) = converted {
+            assert_json_round_trip(&converted)?;
+        }
+        let _: Result<CohereChatRequest, TransformError> = request.try_into();
+    }
+
+    #[test]
+    fn messages_response_converts_without_panic(response in messages_response()) {
+        let converted: Result<ChatCompletionsResponse, TransformError> = response.try_into();
+        if let Ok(converted) = converted {
+            assert_json_round_trip(&converted)?;
+        }
+    }
+
+    #[test]
+    fn chat_completions_response_converts_without_panic(response in chat_completions_response()) {
+        let converted: Result<MessagesResponse, TransformError> = response.try_into();
+        if let Ok(converted) = converted {
+            assert_json_round_trip(&converted)?;
+        }
+    }
+}
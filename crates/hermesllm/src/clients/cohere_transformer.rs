@@ -0,0 +1,489 @@
+//! API request/response transformers between OpenAI and Cohere APIs
+//!
+//! Cohere's `/v1/chat` API differs from OpenAI chat completions in a few structural ways:
+//!
+//! - The system prompt is carried separately as `preamble`
+//! - The current user turn is sent as `message`, everything before it as `chat_history`
+//! - Tool outputs are sent as `tool_results`, keyed by the originating call rather than an id
+//! - Tool parameters are described with `parameter_definitions` instead of a JSON schema
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hermesllm::apis::{ChatCompletionsRequest, CohereChatRequest, Message, MessageContent, Role};
+//! use hermesllm::clients::TransformError;
+//! use std::convert::TryInto;
+//!
+//! let openai_req = ChatCompletionsRequest {
+//!     model: "command-r-plus".to_string(),
+//!     messages: vec![Message {
+//!         role: Role::User,
+//!         content: MessageContent::Text("Hello!".to_string()),
+//!         name: None,
+//!         tool_calls: None,
+//!         tool_call_id: None,
+//!     }],
+//!     ..Default::default()
+//! };
+//! let cohere_req: CohereChatRequest = openai_req.try_into()?;
+//! assert_eq!(cohere_req.message, "Hello!");
+//! # Ok::<(), TransformError>(())
+//! ```
+
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::apis::*;
+use super::transformer::current_timestamp;
+use super::TransformError;
+
+// ============================================================================
+// MAIN REQUEST TRANSFORMATIONS
+// ============================================================================
+
+impl TryFrom<ChatCompletionsRequest> for CohereChatRequest {
+    type Error = TransformError;
+
+    fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
+        let mut preamble_parts: Vec<String> = Vec::new();
+        let mut history: Vec<CohereChatMessage> = Vec::new();
+        // Cohere identifies tool results by the call itself, so remember every call by id
+        let mut tool_calls_by_id: HashMap<String, CohereToolCall> = HashMap::new();
+
+        for message in req.messages {
+            match message.role {
+                Role::System | Role::Developer => {
+                    preamble_parts.push(extract_cohere_text(&message.content)?);
+                }
+                Role::User => {
+                    history.push(CohereChatMessage {
+                        role: CohereRole::User,
+                        message: Some(extract_cohere_text(&message.content)?),
+                        tool_calls: None,
+                        tool_results: None,
+                    });
+                }
+                Role::Assistant => {
+                    let tool_calls = match message.tool_calls {
+                        Some(tool_calls) => {
+                            let mut cohere_calls = Vec::new();
+                            for tool_call in tool_calls {
+                                let cohere_call: CohereToolCall = tool_call.function.try_into()?;
+                                tool_calls_by_id.insert(tool_call.id, cohere_call.clone());
+                                cohere_calls.push(cohere_call);
+                            }
+                            Some(cohere_calls)
+                        }
+                        None => None,
+                    };
+                    let text = extract_cohere_text(&message.content)?;
+
+                    history.push(CohereChatMessage {
+                        role: CohereRole::Chatbot,
+                        message: if text.is_empty() { None } else { Some(text) },
+                        tool_calls,
+                        tool_results: None,
+                    });
+                }
+                Role::Tool => {
+                    let tool_call_id = message.tool_call_id
+                        .ok_or_else(|| TransformError::MissingField("tool_call_id required for Tool messages".to_string()))?;
+                    let call = tool_calls_by_id.get(&tool_call_id)
+                        .cloned()
+                        .ok_or_else(|| TransformError::InvalidField {
+                            field: "tool_call_id".to_string(),
+                            reason: format!("no assistant tool call with id {}", tool_call_id),
+                        })?;
+                    let tool_result = CohereToolResult {
+                        call,
+                        outputs: vec![convert_tool_output(extract_cohere_text(&message.content)?)],
+                    };
+
+                    // Consecutive tool messages answer the same assistant turn, keep them together
+                    match history.last_mut() {
+                        Some(CohereChatMessage { role: CohereRole::Tool, tool_results: Some(results), .. }) => {
+                            results.push(tool_result);
+                        }
+                        _ => history.push(CohereChatMessage {
+                            role: CohereRole::Tool,
+                            message: None,
+                            tool_calls: None,
+                            tool_results: Some(vec![tool_result]),
+                        }),
+                    }
+                }
+            }
+        }
+
+        // The final turn becomes the top-level `message` (or `tool_results`), the rest is history
+        let (message, tool_results) = match history.pop() {
+            Some(CohereChatMessage { role: CohereRole::User, message, .. }) => (message.unwrap_or_default(), None),
+            Some(CohereChatMessage { role: CohereRole::Tool, tool_results, .. }) => (String::new(), tool_results),
+            _ => {
+                return Err(TransformError::InvalidField {
+                    field: "messages".to_string(),
+                    reason: "conversation must end with a user or tool message".to_string(),
+                })
+            }
+        };
+
+        let preamble = if preamble_parts.is_empty() { None } else { Some(preamble_parts.join("\n")) };
+        let tools = req.tools.map(convert_openai_tools_to_cohere);
+
+        Ok(CohereChatRequest {
+            message,
+            model: Some(req.model),
+            preamble,
+            chat_history: if history.is_empty() { None } else { Some(history) },
+            stream: req.stream,
+            temperature: req.temperature,
+            max_tokens: req.max_completion_tokens.or(req.max_tokens),
+            p: req.top_p,
+            k: None, // OpenAI doesn't have top_k
+            seed: req.seed,
+            stop_sequences: req.stop,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            tools,
+            tool_results,
+        })
+    }
+}
+
+// ============================================================================
+// MAIN RESPONSE TRANSFORMATIONS
+// ============================================================================
+
+impl TryFrom<CohereChatResponse> for ChatCompletionsResponse {
+    type Error = TransformError;
+
+    fn try_from(resp: CohereChatResponse) -> Result<Self, Self::Error> {
+        let tool_calls = match resp.tool_calls {
+            Some(calls) if !calls.is_empty() => {
+                let mut openai_calls = Vec::new();
+                for (index, call) in calls.into_iter().enumerate() {
+                    openai_calls.push(ToolCall {
+                        id: format!("call_{}", index),
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: call.name,
+                            arguments: serde_json::to_string(&call.parameters)?,
+                        },
+                    });
+                }
+                Some(openai_calls)
+            }
+            _ => None,
+        };
+
+        let finish_reason = resp.finish_reason.map(FinishReason::from);
+
+        let message = ResponseMessage {
+            role: Role::Assistant,
+            content: if resp.text.is_empty() { None } else { Some(resp.text) },
+            refusal: None,
+            annotations: None,
+            audio: None,
+            function_call: None,
+            tool_calls,
+        };
+
+        let choice = Choice {
+            index: 0,
+            message,
+            finish_reason,
+            logprobs: None,
+        };
+
+        // Prefer billed units, which is what Cohere reports for usage accounting
+        let (prompt_tokens, completion_tokens) = resp.meta
+            .map(|meta| match (meta.billed_units, meta.tokens) {
+                (Some(billed), _) => (billed.input_tokens.unwrap_or(0), billed.output_tokens.unwrap_or(0)),
+                (None, Some(tokens)) => (tokens.input_tokens.unwrap_or(0), tokens.output_tokens.unwrap_or(0)),
+                (None, None) => (0, 0),
+            })
+            .unwrap_or((0, 0));
+
+        let usage = Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            prompt_tokens_details: None,
+            completion_tokens_details: None,
+        };
+
+        Ok(ChatCompletionsResponse {
+            id: resp.response_id.or(resp.generation_id).unwrap_or_default(),
+            object: "chat.completion".to_string(),
+            created: current_timestamp(),
+            // Cohere does not echo the model back, callers fill it in from the request
+            model: String::new(),
+            choices: vec![choice],
+            usage,
+            system_fingerprint: None,
+            metadata: None,
+        })
+    }
+}
+
+// ============================================================================
+// STANDARD RUST TRAIT IMPLEMENTATIONS
+// ============================================================================
+
+// Tool Call Conversions
+impl TryFrom<FunctionCall> for CohereToolCall {
+    type Error = TransformError;
+
+    fn try_from(function: FunctionCall) -> Result<Self, Self::Error> {
+        let parameters = if function.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(&function.arguments).map_err(|err| TransformError::InvalidField {
+                field: format!("tool_calls.{}.arguments", function.name),
+                reason: err.to_string(),
+            })?
+        };
+
+        Ok(CohereToolCall {
+            name: function.name,
+            parameters,
+        })
+    }
+}
+
+// Finish Reason Conversions
+impl From<CohereFinishReason> for FinishReason {
+    fn from(value: CohereFinishReason) -> Self {
+        match value {
+            CohereFinishReason::Complete => FinishReason::Stop,
+            CohereFinishReason::StopSequence => FinishReason::Stop,
+            CohereFinishReason::MaxTokens => FinishReason::Length,
+            CohereFinishReason::ToolCall => FinishReason::ToolCalls,
+            CohereFinishReason::ErrorToxic => FinishReason::ContentFilter,
+            CohereFinishReason::ErrorLimit => FinishReason::Length,
+            CohereFinishReason::Error => FinishReason::Stop,
+            CohereFinishReason::UserCancel => FinishReason::Stop,
+        }
+    }
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Extract the text of an OpenAI message; Cohere chat does not accept images
+fn extract_cohere_text(content: &MessageContent) -> Result<String, TransformError> {
+    match content {
+        MessageContent::Text(text) => Ok(text.clone()),
+        MessageContent::Parts(parts) => {
+            let mut texts = Vec::new();
+            for part in parts {
+                match part {
+                    ContentPart::Text { text } => texts.push(text.as_str()),
+                    ContentPart::ImageUrl { .. } => {
+                        return Err(TransformError::UnsupportedContent("image_url is not supported by Cohere chat".to_string()));
+                    }
+                }
+            }
+            Ok(texts.join("\n"))
+        }
+    }
+}
+
+/// Cohere expects each tool output to be a JSON object, wrap anything else
+fn convert_tool_output(output: String) -> Value {
+    match serde_json::from_str::<Value>(&output) {
+        Ok(Value::Object(object)) => Value::Object(object),
+        _ => json!({ "result": output }),
+    }
+}
+
+/// Convert OpenAI tools to Cohere format
+fn convert_openai_tools_to_cohere(tools: Vec<Tool>) -> Vec<CohereTool> {
+    tools.into_iter()
+        .map(|tool| CohereTool {
+            parameter_definitions: convert_json_schema_to_parameter_definitions(&tool.function.parameters),
+            name: tool.function.name,
+            description: tool.function.description.unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Flatten the top-level properties of a JSON schema into Cohere parameter definitions
+fn convert_json_schema_to_parameter_definitions(schema: &Value) -> Option<HashMap<String, CohereParameterDefinition>> {
+    let properties = schema.get("properties").and_then(Value::as_object)?;
+    let required: Vec<&str> = schema.get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let definitions = properties.iter()
+        .map(|(name, property)| {
+            let property = property.as_object().cloned().unwrap_or_else(Map::new);
+            let definition = CohereParameterDefinition {
+                description: property.get("description").and_then(Value::as_str).map(str::to_string),
+                param_type: property.get("type").and_then(Value::as_str).unwrap_or("str").to_string(),
+                required: Some(required.contains(&name.as_str())),
+            };
+            (name.clone(), definition)
+        })
+        .collect();
+
+    Some(definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_openai_to_cohere_request_with_history() {
+        let openai_req: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "command-r-plus",
+            "messages": [
+                {"role": "system", "content": "You are a helpful assistant"},
+                {"role": "user", "content": "What's the weather like in Tokyo?"},
+                {"role": "assistant", "content": "It is sunny in Tokyo."},
+                {"role": "user", "content": "What about in New York?"}
+            ],
+            "top_p": 0.9,
+            "max_completion_tokens": 256,
+            "stop": ["\n\n"]
+        })).unwrap();
+
+        let cohere_req: CohereChatRequest = openai_req.try_into().unwrap();
+
+        assert_eq!(cohere_req.message, "What about in New York?");
+        assert_eq!(cohere_req.model, Some("command-r-plus".to_string()));
+        assert_eq!(cohere_req.preamble, Some("You are a helpful assistant".to_string()));
+        assert_eq!(cohere_req.p, Some(0.9));
+        assert_eq!(cohere_req.max_tokens, Some(256));
+        assert_eq!(cohere_req.stop_sequences, Some(vec!["\n\n".to_string()]));
+        assert!(cohere_req.tool_results.is_none());
+
+        let history = cohere_req.chat_history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].role, CohereRole::User);
+        assert_eq!(history[1].role, CohereRole::Chatbot);
+        assert_eq!(history[1].message, Some("It is sunny in Tokyo.".to_string()));
+    }
+
+    #[test]
+    fn test_openai_to_cohere_request_with_tools() {
+        let openai_req: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "command-r-plus",
+            "messages": [
+                {"role": "user", "content": "What's the weather in Paris?"},
+                {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}
+                    }]
+                },
+                {"role": "tool", "tool_call_id": "call_1", "content": "{\"temperature\": 22}"}
+            ],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Get the current weather",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "location": {"type": "string", "description": "City name"},
+                            "unit": {"type": "string"}
+                        },
+                        "required": ["location"]
+                    }
+                }
+            }]
+        })).unwrap();
+
+        let cohere_req: CohereChatRequest = openai_req.try_into().unwrap();
+
+        // A trailing tool message is sent as top-level tool results with an empty message
+        assert_eq!(cohere_req.message, "");
+        let tool_results = cohere_req.tool_results.unwrap();
+        assert_eq!(tool_results.len(), 1);
+        assert_eq!(tool_results[0].call.name, "get_weather");
+        assert_eq!(tool_results[0].call.parameters, json!({"location": "Paris"}));
+        assert_eq!(tool_results[0].outputs, vec![json!({"temperature": 22})]);
+
+        let history = cohere_req.chat_history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].role, CohereRole::Chatbot);
+        assert!(history[1].message.is_none());
+        assert_eq!(history[1].tool_calls.as_ref().unwrap()[0].name, "get_weather");
+
+        let tools = cohere_req.tools.unwrap();
+        let definitions = tools[0].parameter_definitions.as_ref().unwrap();
+        assert_eq!(tools[0].description, "Get the current weather");
+        assert_eq!(definitions["location"].param_type, "string");
+        assert_eq!(definitions["location"].required, Some(true));
+        assert_eq!(definitions["unit"].required, Some(false));
+    }
+
+    #[test]
+    fn test_openai_to_cohere_request_requires_final_user_turn() {
+        let openai_req: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "command-r-plus",
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"}
+            ]
+        })).unwrap();
+
+        let result: Result<CohereChatRequest, TransformError> = openai_req.try_into();
+        assert!(matches!(
+            result,
+            Err(TransformError::InvalidField { field, reason })
+                if field == "messages" && reason == "conversation must end with a user or tool message"
+        ));
+    }
+
+    #[test]
+    fn test_cohere_to_openai_response() {
+        let cohere_resp: CohereChatResponse = serde_json::from_value(json!({
+            "text": "",
+            "response_id": "resp-123",
+            "generation_id": "gen-123",
+            "finish_reason": "TOOL_CALL",
+            "tool_calls": [
+                {"name": "get_weather", "parameters": {"location": "New York"}}
+            ],
+            "meta": {
+                "billed_units": {"input_tokens": 12, "output_tokens": 7}
+            }
+        })).unwrap();
+
+        let openai_resp: ChatCompletionsResponse = cohere_resp.try_into().unwrap();
+
+        assert_eq!(openai_resp.id, "resp-123");
+        assert_eq!(openai_resp.object, "chat.completion");
+        assert_eq!(openai_resp.usage.prompt_tokens, 12);
+        assert_eq!(openai_resp.usage.completion_tokens, 7);
+        assert_eq!(openai_resp.usage.total_tokens, 19);
+
+        let choice = &openai_resp.choices[0];
+        assert_eq!(choice.finish_reason, Some(FinishReason::ToolCalls));
+        assert!(choice.message.content.is_none());
+
+        let tool_calls = choice.message.tool_calls.as_ref().unwrap();
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+        let arguments: Value = serde_json::from_str(&tool_calls[0].function.arguments).unwrap();
+        assert_eq!(arguments, json!({"location": "New York"}));
+    }
+
+    #[test]
+    fn test_cohere_finish_reason_mapping() {
+        assert_eq!(FinishReason::from(CohereFinishReason::Complete), FinishReason::Stop);
+        assert_eq!(FinishReason::from(CohereFinishReason::StopSequence), FinishReason::Stop);
+        assert_eq!(FinishReason::from(CohereFinishReason::MaxTokens), FinishReason::Length);
+        assert_eq!(FinishReason::from(CohereFinishReason::ToolCall), FinishReason::ToolCalls);
+        assert_eq!(FinishReason::from(CohereFinishReason::ErrorToxic), FinishReason::ContentFilter);
+    }
+}
@@ -6,18 +6,27 @@
 //! # Examples
 //!
 //! ```rust
-//! use hermesllm::clients::endpoints::{is_supported_endpoint, supported_endpoints};
+//! use hermesllm::clients::endpoints::{identify_api, is_supported_endpoint, supported_endpoints, DetectedApi};
+//! use hermesllm::apis::OpenAIApi;
 //!
 //! // Check if we support an endpoint
 //! assert!(is_supported_endpoint("/v1/chat/completions"));
+//! assert!(is_supported_endpoint("/v1/embeddings"));
 //! assert!(is_supported_endpoint("/v1/messages"));
+//! assert!(is_supported_endpoint("/v1/messages/count_tokens"));
 //! assert!(!is_supported_endpoint("/v1/unknown"));
 //!
 //! // Get all supported endpoints
 //! let endpoints = supported_endpoints();
-//! assert_eq!(endpoints.len(), 2);
+//! assert_eq!(endpoints.len(), 4);
 //! assert!(endpoints.contains(&"/v1/chat/completions"));
 //! assert!(endpoints.contains(&"/v1/messages"));
+//!
+//! // Identify the API behind an endpoint
+//! assert_eq!(
+//!     identify_api("/v1/chat/completions"),
+//!     Some(DetectedApi::OpenAI(OpenAIApi::ChatCompletions))
+//! );
 //! ```
 
 use crate::apis::{AnthropicApi, OpenAIApi, ApiDefinition};
@@ -54,19 +63,87 @@ pub fn supported_endpoints() -> Vec<&'static str> {
     endpoints
 }
 
-/// Identify which provider supports a given endpoint
-pub fn identify_provider(endpoint: &str) -> Option<&'static str> {
-    if OpenAIApi::from_endpoint(endpoint).is_some() {
-        return Some("openai");
+/// The supported endpoints a deployment serves, all of them unless narrowed down
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnabledEndpoints {
+    /// `None` enables every supported endpoint
+    only: Option<Vec<&'static str>>,
+}
+
+impl EnabledEndpoints {
+    /// Every supported endpoint
+    pub fn all() -> Self {
+        Self::default()
     }
 
-    if AnthropicApi::from_endpoint(endpoint).is_some() {
-        return Some("anthropic");
+    /// Only the given endpoints. Fails with the first path that isn't a supported endpoint.
+    pub fn only<S: AsRef<str>>(endpoints: &[S]) -> Result<Self, String> {
+        let supported = supported_endpoints();
+        let only = endpoints
+            .iter()
+            .map(|endpoint| {
+                supported
+                    .iter()
+                    .find(|supported| **supported == endpoint.as_ref())
+                    .copied()
+                    .ok_or_else(|| endpoint.as_ref().to_string())
+            })
+            .collect::<Result<Vec<&'static str>, String>>()?;
+        Ok(Self { only: Some(only) })
+    }
+
+    /// Whether `endpoint` is supported and enabled
+    pub fn is_enabled(&self, endpoint: &str) -> bool {
+        match &self.only {
+            Some(only) => only.contains(&endpoint),
+            None => is_supported_endpoint(endpoint),
+        }
+    }
+}
+
+/// The API an endpoint path belongs to, along with the provider that defines it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetectedApi {
+    OpenAI(OpenAIApi),
+    Anthropic(AnthropicApi),
+}
+
+impl DetectedApi {
+    /// Lowercase name of the provider that defines this API
+    pub fn provider_name(&self) -> &'static str {
+        match self {
+            DetectedApi::OpenAI(_) => "openai",
+            DetectedApi::Anthropic(_) => "anthropic",
+        }
+    }
+
+    /// Whether responses from this API can be streamed
+    pub fn supports_streaming(&self) -> bool {
+        match self {
+            DetectedApi::OpenAI(api) => api.supports_streaming(),
+            DetectedApi::Anthropic(api) => api.supports_streaming(),
+        }
+    }
+}
+
+/// Identify which API a given endpoint belongs to
+pub fn identify_api(endpoint: &str) -> Option<DetectedApi> {
+    if let Some(api) = OpenAIApi::from_endpoint(endpoint) {
+        return Some(DetectedApi::OpenAI(api));
+    }
+
+    if let Some(api) = AnthropicApi::from_endpoint(endpoint) {
+        return Some(DetectedApi::Anthropic(api));
     }
 
     None
 }
 
+/// Identify which provider supports a given endpoint
+pub fn identify_provider(endpoint: &str) -> Option<&'static str> {
+    identify_api(endpoint).map(|api| api.provider_name())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,6 +156,9 @@ mod tests {
         // Anthropic endpoints
         assert!(is_supported_endpoint("/v1/messages"));
 
+        // Cohere's chat has no handler, so it isn't served
+        assert!(!is_supported_endpoint("/v1/chat"));
+
         // Unsupported endpoints
         assert!(!is_supported_endpoint("/v1/unknown"));
         assert!(!is_supported_endpoint("/v2/chat"));
@@ -88,9 +168,59 @@ mod tests {
     #[test]
     fn test_supported_endpoints() {
         let endpoints = supported_endpoints();
-        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints.len(), 4);
         assert!(endpoints.contains(&"/v1/chat/completions"));
+        assert!(endpoints.contains(&"/v1/embeddings"));
         assert!(endpoints.contains(&"/v1/messages"));
+        assert!(endpoints.contains(&"/v1/messages/count_tokens"));
+    }
+
+    #[test]
+    fn test_identify_api() {
+        assert_eq!(
+            identify_api("/v1/chat/completions"),
+            Some(DetectedApi::OpenAI(OpenAIApi::ChatCompletions))
+        );
+        assert_eq!(
+            identify_api("/v1/embeddings"),
+            Some(DetectedApi::OpenAI(OpenAIApi::Embeddings))
+        );
+        assert_eq!(
+            identify_api("/v1/messages"),
+            Some(DetectedApi::Anthropic(AnthropicApi::Messages))
+        );
+        assert_eq!(
+            identify_api("/v1/messages/count_tokens"),
+            Some(DetectedApi::Anthropic(AnthropicApi::CountTokens))
+        );
+        assert_eq!(identify_api("/v1/unknown"), None);
+        assert_eq!(identify_api(""), None);
+
+        // Every supported endpoint is identified, and agrees with the string version
+        for endpoint in supported_endpoints() {
+            let api = identify_api(endpoint).expect("supported endpoint must be identified");
+            assert_eq!(identify_provider(endpoint), Some(api.provider_name()));
+        }
+    }
+
+    #[test]
+    fn test_enabled_endpoints() {
+        let all = EnabledEndpoints::all();
+        for endpoint in supported_endpoints() {
+            assert!(all.is_enabled(endpoint));
+        }
+        assert!(!all.is_enabled("/v1/unknown"));
+
+        let chat_only = EnabledEndpoints::only(&["/v1/chat/completions"]).unwrap();
+        assert!(chat_only.is_enabled("/v1/chat/completions"));
+        assert!(!chat_only.is_enabled("/v1/messages"));
+        assert!(!chat_only.is_enabled("/v1/messages/count_tokens"));
+
+        // the static registry decides what can be enabled at all
+        assert_eq!(
+            EnabledEndpoints::only(&["/v1/chat/completions", "/v1/audio/speech"]),
+            Err("/v1/audio/speech".to_string())
+        );
     }
 
     #[test]
@@ -125,6 +255,9 @@ mod tests {
         }
 
         // Total should match
-        assert_eq!(endpoints.len(), OpenAIApi::all_variants().len() + AnthropicApi::all_variants().len());
+        assert_eq!(
+            endpoints.len(),
+            OpenAIApi::all_variants().len() + AnthropicApi::all_variants().len()
+        );
     }
 }
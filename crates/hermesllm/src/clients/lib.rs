@@ -1,6 +1,8 @@
 //! Helper functions and utilities for API transformations
 //! Contains error types and shared utilities
 
+use http::StatusCode;
+use serde_json::{json, Value};
 use thiserror::Error;
 
 // ============================================================================
@@ -17,8 +19,60 @@ pub enum TransformError {
     InvalidToolInput,
     #[error("Missing required field: {0}")]
     MissingField(String),
+    #[error("Invalid {field}: {reason}")]
+    InvalidField { field: String, reason: String },
     #[error("Unsupported conversion: {0}")]
     UnsupportedConversion(String),
+    #[error("Disallowed image url: {0}")]
+    DisallowedImageUrl(String),
+}
+
+impl TransformError {
+    /// Status to answer a client with when its request can't be converted: `422` for valid
+    /// requests the target format can't express, `400` for malformed ones
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            TransformError::UnsupportedContent(_) | TransformError::UnsupportedConversion(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            TransformError::JsonError(_)
+            | TransformError::InvalidToolInput
+            | TransformError::MissingField(_)
+            | TransformError::InvalidField { .. }
+            | TransformError::DisallowedImageUrl(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Machine readable name of the error, the envelope's `code`
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransformError::JsonError(_) => "invalid_json",
+            TransformError::UnsupportedContent(_) => "unsupported_content",
+            TransformError::InvalidToolInput => "invalid_tool_input",
+            TransformError::MissingField(_) => "missing_field",
+            TransformError::InvalidField { .. } => "invalid_field",
+            TransformError::UnsupportedConversion(_) => "unsupported_conversion",
+            TransformError::DisallowedImageUrl(_) => "disallowed_image_url",
+        }
+    }
+
+    /// The error in OpenAI's error envelope, naming the offending field in `param` when known
+    pub fn to_openai_error(&self) -> Value {
+        let param = match self {
+            TransformError::MissingField(field) | TransformError::InvalidField { field, .. } => {
+                Some(field.as_str())
+            }
+            _ => None,
+        };
+        json!({
+            "error": {
+                "message": self.to_string(),
+                "type": "invalid_request_error",
+                "param": param,
+                "code": self.code(),
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -29,5 +83,51 @@ mod tests {
     fn test_error_types() {
         let error = TransformError::MissingField("test".to_string());
         assert!(matches!(error, TransformError::MissingField(_)));
+
+        let error = TransformError::InvalidField {
+            field: "temperature".to_string(),
+            reason: "must be between 0 and 1".to_string(),
+        };
+        assert_eq!(error.to_string(), "Invalid temperature: must be between 0 and 1");
+    }
+
+    #[test]
+    fn test_status_codes() {
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let cases = [
+            (TransformError::UnsupportedContent("audio".to_string()), StatusCode::UNPROCESSABLE_ENTITY),
+            (TransformError::UnsupportedConversion("cohere to anthropic".to_string()), StatusCode::UNPROCESSABLE_ENTITY),
+            (TransformError::MissingField("messages".to_string()), StatusCode::BAD_REQUEST),
+            (TransformError::InvalidToolInput, StatusCode::BAD_REQUEST),
+            (TransformError::JsonError(json_error), StatusCode::BAD_REQUEST),
+            (
+                TransformError::InvalidField { field: "temperature".to_string(), reason: "too high".to_string() },
+                StatusCode::BAD_REQUEST,
+            ),
+            (TransformError::DisallowedImageUrl("http://10.0.0.1/a.png".to_string()), StatusCode::BAD_REQUEST),
+        ];
+        for (error, status) in cases {
+            assert_eq!(error.status_code(), status, "{}", error);
+            assert_eq!(error.to_openai_error()["error"]["code"], error.code());
+        }
+    }
+
+    #[test]
+    fn test_openai_error_envelope() {
+        let error = TransformError::MissingField("messages".to_string());
+        assert_eq!(
+            error.to_openai_error(),
+            json!({
+                "error": {
+                    "message": "Missing required field: messages",
+                    "type": "invalid_request_error",
+                    "param": "messages",
+                    "code": "missing_field"
+                }
+            })
+        );
+
+        let error = TransformError::UnsupportedContent("audio".to_string());
+        assert_eq!(error.to_openai_error()["error"]["param"], Value::Null);
     }
 }
@@ -1,9 +1,16 @@
 pub mod lib;
 pub mod transformer;
+pub mod cohere_transformer;
 pub mod endpoints;
+pub mod streaming;
 
 // Re-export the main items for easier access
 pub use lib::*;
-pub use endpoints::{is_supported_endpoint, supported_endpoints, identify_provider};
+pub use endpoints::{is_supported_endpoint, supported_endpoints, identify_api, identify_provider, DetectedApi, EnabledEndpoints};
+pub use transformer::{
+    approximate_token_count, convert_chat_completions_request, convert_messages_request, validate_image_url, validate_tool_schema,
+    ConversionOptions, ANTHROPIC_CONTAINER_METADATA_KEY, ANTHROPIC_STOP_REASON_METADATA_KEY,
+    ANTHROPIC_TOP_K_METADATA_KEY, DEFAULT_MAX_TOKENS, TOKEN_LENGTH_DIVISOR,
+};
 
-// Note: transformer module contains TryFrom trait implementations that are automatically available
+// Note: transformer modules contain TryFrom trait implementations that are automatically available
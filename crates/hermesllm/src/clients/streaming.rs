@@ -0,0 +1,503 @@
+//! Server-sent events stream transformation between API formats
+//!
+//! Upstream streaming responses arrive as SSE frames (`data: {...}` lines, plus `event:` lines
+//! for Anthropic). [`transform_sse_chunk`] rewrites a buffer of complete frames from the
+//! upstream format into the format the client asked for. When both formats are the same the
+//! bytes are forwarded untouched, without parsing a single event.
+//!
+//! Network reads don't respect line boundaries, so a live stream goes through an
+//! [`SseLineBuffer`] first, which holds back a partial line until the rest of it arrives, and
+//! then through an [`SseTransformer`], which keeps the conversion state between chunks.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hermesllm::apis::OpenAIApi;
+//! use hermesllm::clients::streaming::transform_sse_chunk;
+//! use hermesllm::clients::DetectedApi;
+//! use std::borrow::Cow;
+//!
+//! let openai = DetectedApi::OpenAI(OpenAIApi::ChatCompletions);
+//! let chunk = b"data: [DONE]\n\n";
+//!
+//! // Same format on both sides: the input is borrowed, not copied
+//! let output = transform_sse_chunk(chunk, &openai, &openai).unwrap();
+//! assert!(matches!(output, Cow::Borrowed(_)));
+//! assert_eq!(output.as_ref(), chunk);
+//! ```
+
+use serde_json::Value;
+use std::borrow::Cow;
+
+use crate::apis::*;
+use super::endpoints::DetectedApi;
+use super::transformer::ChatCompletionsStreamConverter;
+use super::TransformError;
+
+const SSE_DATA_PREFIX: &str = "data:";
+const SSE_DONE: &str = "[DONE]";
+
+/// Reassembles SSE lines split across network reads.
+///
+/// Each [`push`](SseLineBuffer::push) returns the bytes up to and including the last newline seen
+/// so far, and keeps the trailing partial line for the next read. Bytes are buffered rather than
+/// text, so a multi-byte character split between reads is also put back together.
+#[derive(Debug, Default)]
+pub struct SseLineBuffer {
+    pending: Vec<u8>,
+}
+
+impl SseLineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a network chunk and take every complete line buffered so far. Returns an empty
+    /// buffer while no line is complete yet.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        match self.pending.iter().rposition(|&b| b == b'\n') {
+            Some(last_newline) => {
+                let rest = self.pending.split_off(last_newline + 1);
+                std::mem::replace(&mut self.pending, rest)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Take whatever is left once the stream has ended, i.e. a last line without a newline
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Transform a buffer of complete SSE frames from the `from` API format into the `to` format.
+///
+/// Matching formats take a zero-copy fast path that returns the input as-is, including
+/// `[DONE]` and ping frames. Otherwise each `data:` line is parsed, converted and re-encoded;
+/// keep-alive pings with no equivalent in the target format are dropped.
+///
+/// The buffer is treated as the whole stream. A live stream arriving chunk by chunk goes
+/// through an [`SseTransformer`] instead, which carries state from one chunk to the next.
+pub fn transform_sse_chunk<'a>(
+    chunk: &'a [u8],
+    from: &DetectedApi,
+    to: &DetectedApi,
+) -> Result<Cow<'a, [u8]>, TransformError> {
+    let mut transformer = SseTransformer::new(from.clone(), to.clone());
+    let output = transformer.transform(chunk)?;
+    let rest = transformer.finish()?;
+    if rest.is_empty() {
+        return Ok(output);
+    }
+    let mut output = output.into_owned();
+    output.extend_from_slice(&rest);
+    Ok(Cow::Owned(output))
+}
+
+/// Transforms a live SSE stream from the `from` API format into the `to` format, one buffer of
+/// complete frames at a time.
+///
+/// OpenAI reports the finish reason and the usage in separate chunks when the client set
+/// `stream_options.include_usage`: the usage follows in a chunk of its own with no choices.
+/// Anthropic puts both in `message_delta`, so the stop reason is held back until the usage
+/// chunk or `[DONE]` arrives, and `message_delta` omits the usage if none was reported.
+#[derive(Debug)]
+pub struct SseTransformer {
+    from: DetectedApi,
+    to: DetectedApi,
+    pending_delta: Option<MessagesMessageDelta>,
+    /// Anthropic → OpenAI conversion state: stream id and model, tool call positions
+    converter: ChatCompletionsStreamConverter,
+}
+
+impl SseTransformer {
+    pub fn new(from: DetectedApi, to: DetectedApi) -> Self {
+        Self {
+            from,
+            to,
+            pending_delta: None,
+            converter: ChatCompletionsStreamConverter::new(),
+        }
+    }
+
+    /// Transform the next buffer of complete frames, see [`transform_sse_chunk`]
+    pub fn transform<'a>(&mut self, chunk: &'a [u8]) -> Result<Cow<'a, [u8]>, TransformError> {
+        if self.from == self.to {
+            return Ok(Cow::Borrowed(chunk));
+        }
+
+        let text = std::str::from_utf8(chunk)
+            .map_err(|e| TransformError::UnsupportedContent(format!("stream chunk is not valid utf-8: {}", e)))?;
+
+        let mut output = String::with_capacity(chunk.len());
+        for data in text.lines().filter_map(sse_data) {
+            match (&self.from, &self.to) {
+                (DetectedApi::OpenAI(_), DetectedApi::Anthropic(_)) => {
+                    // Anthropic streams end with message_stop, there is no [DONE] sentinel
+                    if data == SSE_DONE {
+                        self.end_message(&mut output, None)?;
+                        continue;
+                    }
+                    let openai_chunk = match serde_json::from_str::<ChatCompletionsStreamResponse>(data) {
+                        Ok(openai_chunk) => openai_chunk,
+                        // some OpenAI-compatible upstreams interleave Anthropic-style keep-alive pings
+                        Err(_) if is_ping(data) => {
+                            write_anthropic_event(&mut output, &MessagesStreamEvent::Ping)?;
+                            continue;
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
+                    if self.pending_delta.is_some() && openai_chunk.choices.is_empty() && openai_chunk.usage.is_some() {
+                        self.end_message(&mut output, openai_chunk.usage.map(MessagesUsage::from))?;
+                        continue;
+                    }
+                    match openai_chunk.try_into()? {
+                        // the finish chunk carries the stop reason and ends the message
+                        MessagesStreamEvent::MessageDelta { delta, usage } => {
+                            self.pending_delta = Some(delta);
+                            if usage.is_some() {
+                                self.end_message(&mut output, usage)?;
+                            }
+                        }
+                        event => write_anthropic_event(&mut output, &event)?,
+                    }
+                }
+                (DetectedApi::Anthropic(_), DetectedApi::OpenAI(_)) => {
+                    let event: MessagesStreamEvent = serde_json::from_str(data)?;
+                    if matches!(event, MessagesStreamEvent::Ping | MessagesStreamEvent::Unknown(_)) {
+                        continue;
+                    }
+                    let is_stop = matches!(event, MessagesStreamEvent::MessageStop);
+                    let openai_chunk = self.converter.transform(event)?;
+                    write_sse_data(&mut output, &serde_json::to_string(&openai_chunk)?);
+                    if is_stop {
+                        write_sse_data(&mut output, SSE_DONE);
+                    }
+                }
+                (from, to) => {
+                    return Err(TransformError::UnsupportedConversion(format!(
+                        "streaming from {} to {}",
+                        from.provider_name(),
+                        to.provider_name()
+                    )));
+                }
+            }
+        }
+
+        Ok(Cow::Owned(output.into_bytes()))
+    }
+
+    /// Take whatever is still held back once the upstream stream has ended, for streams that
+    /// stop without a `[DONE]` sentinel
+    pub fn finish(&mut self) -> Result<Vec<u8>, TransformError> {
+        let mut output = String::new();
+        self.end_message(&mut output, None)?;
+        Ok(output.into_bytes())
+    }
+
+    /// Write the held back `message_delta` with `usage`, followed by `message_stop`
+    fn end_message(&mut self, output: &mut String, usage: Option<MessagesUsage>) -> Result<(), TransformError> {
+        if let Some(delta) = self.pending_delta.take() {
+            write_anthropic_event(output, &MessagesStreamEvent::MessageDelta { delta, usage })?;
+            write_anthropic_event(output, &MessagesStreamEvent::MessageStop)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render a complete, non-streaming response body in the `api` format as the SSE stream a
+/// streaming request would have returned, for upstreams that can only answer in one piece.
+/// Only OpenAI chat completions can be replayed this way.
+pub fn synthesize_sse(response: &[u8], api: &DetectedApi) -> Result<Vec<u8>, TransformError> {
+    let mut output = String::new();
+    match api {
+        DetectedApi::OpenAI(_) => {
+            let response: ChatCompletionsResponse = serde_json::from_slice(response)?;
+            for chunk in response.to_stream_chunks() {
+                write_sse_data(&mut output, &serde_json::to_string(&chunk)?);
+            }
+            write_sse_data(&mut output, SSE_DONE);
+        }
+        _ => {
+            return Err(TransformError::UnsupportedConversion(format!(
+                "streaming a {} response",
+                api.provider_name()
+            )));
+        }
+    }
+    Ok(output.into_bytes())
+}
+
+/// Payload of an SSE `data:` line, `None` for every other line
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix(SSE_DATA_PREFIX).map(str::trim)
+}
+
+fn is_ping(data: &str) -> bool {
+    serde_json::from_str::<Value>(data)
+        .is_ok_and(|value| value.get("type").and_then(Value::as_str) == Some("ping"))
+}
+
+fn write_sse_data(output: &mut String, data: &str) {
+    output.push_str("data: ");
+    output.push_str(data);
+    output.push_str("\n\n");
+}
+
+/// Anthropic frames name the event type in an `event:` line before the data
+fn write_anthropic_event(output: &mut String, event: &MessagesStreamEvent) -> Result<(), TransformError> {
+    let value = serde_json::to_value(event)?;
+    let event_type = value.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+    output.push_str("event: ");
+    output.push_str(event_type);
+    output.push('\n');
+    write_sse_data(output, &value.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPENAI_STREAM: &str = concat!(
+        "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+        "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+        "data: {\"type\": \"ping\"}\n\n",
+        "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: [DONE]\n\n",
+    );
+
+    const ANTHROPIC_STREAM: &str = concat!(
+        "event: content_block_delta\n",
+        "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+        "event: ping\n",
+        "data: {\"type\":\"ping\"}\n\n",
+        "event: citation_summary\n",
+        "data: {\"type\":\"citation_summary\",\"index\":0,\"citations\":[]}\n\n",
+        "event: message_stop\n",
+        "data: {\"type\":\"message_stop\"}\n\n",
+    );
+
+    fn openai() -> DetectedApi {
+        DetectedApi::OpenAI(OpenAIApi::ChatCompletions)
+    }
+
+    fn anthropic() -> DetectedApi {
+        DetectedApi::Anthropic(AnthropicApi::Messages)
+    }
+
+    #[test]
+    fn test_passthrough_output_equals_input() {
+        let output = transform_sse_chunk(OPENAI_STREAM.as_bytes(), &openai(), &openai()).unwrap();
+        assert!(matches!(output, Cow::Borrowed(_)));
+        assert_eq!(output.as_ref(), OPENAI_STREAM.as_bytes());
+
+        let output = transform_sse_chunk(ANTHROPIC_STREAM.as_bytes(), &anthropic(), &anthropic()).unwrap();
+        assert!(matches!(output, Cow::Borrowed(_)));
+        assert_eq!(output.as_ref(), ANTHROPIC_STREAM.as_bytes());
+    }
+
+    #[test]
+    fn test_anthropic_to_openai_stream() {
+        let output = transform_sse_chunk(ANTHROPIC_STREAM.as_bytes(), &anthropic(), &openai()).unwrap();
+        let output = std::str::from_utf8(&output).unwrap();
+
+        let frames: Vec<&str> = output.lines().filter_map(sse_data).collect();
+        // text delta, message stop and the [DONE] sentinel; the ping and the unknown event are dropped
+        assert_eq!(frames.len(), 3);
+
+        let text_chunk: ChatCompletionsStreamResponse = serde_json::from_str(frames[0]).unwrap();
+        assert_eq!(text_chunk.choices[0].delta.content, Some("Hello".to_string()));
+
+        let stop_chunk: ChatCompletionsStreamResponse = serde_json::from_str(frames[1]).unwrap();
+        assert_eq!(stop_chunk.choices[0].finish_reason, Some(FinishReason::Stop));
+
+        assert_eq!(frames[2], SSE_DONE);
+    }
+
+    #[test]
+    fn test_openai_to_anthropic_stream() {
+        let output = transform_sse_chunk(OPENAI_STREAM.as_bytes(), &openai(), &anthropic()).unwrap();
+        let output = std::str::from_utf8(&output).unwrap();
+
+        let events: Vec<&str> = output.lines().filter(|line| line.starts_with("event: ")).collect();
+        assert_eq!(
+            events,
+            vec![
+                "event: message_start",
+                "event: content_block_delta",
+                "event: ping",
+                "event: message_delta",
+                "event: message_stop"
+            ]
+        );
+        // the finish chunk reports no usage, its stop reason still gets through
+        assert!(output.contains(r#""delta":{"stop_reason":"end_turn"}"#));
+        assert!(!output.contains(SSE_DONE));
+
+        let result = transform_sse_chunk(b"data: {\"not\": \"a chunk\"}\n\n", &openai(), &anthropic());
+        assert!(matches!(result, Err(TransformError::JsonError(_))));
+    }
+
+    #[test]
+    fn test_finish_reason_without_usage() {
+        let chunk = "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\ndata: [DONE]\n\n";
+        let output = transform_sse_chunk(chunk.as_bytes(), &openai(), &anthropic()).unwrap();
+        let output = std::str::from_utf8(&output).unwrap();
+
+        let events: Vec<MessagesStreamEvent> = output
+            .lines()
+            .filter_map(sse_data)
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            MessagesStreamEvent::MessageDelta { delta, usage } => {
+                assert_eq!(delta.stop_reason, MessagesStopReason::ToolUse);
+                assert!(usage.is_none());
+            }
+            other => panic!("Expected MessageDelta, got {:?}", other),
+        }
+        assert!(matches!(events[1], MessagesStreamEvent::MessageStop));
+        assert!(!output.contains("usage"));
+    }
+
+    #[test]
+    fn test_message_delta_waits_for_usage_chunk() {
+        let finish = "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n";
+        let usage = "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":7,\"total_tokens\":19}}\n\n";
+        let mut transformer = SseTransformer::new(openai(), anthropic());
+
+        // the finish reason alone doesn't end the message, the usage may still follow
+        assert!(transformer.transform(finish.as_bytes()).unwrap().is_empty());
+
+        let output = transformer.transform(usage.as_bytes()).unwrap();
+        let output = std::str::from_utf8(&output).unwrap();
+        let events: Vec<MessagesStreamEvent> = output
+            .lines()
+            .filter_map(sse_data)
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            MessagesStreamEvent::MessageDelta { delta, usage } => {
+                assert_eq!(delta.stop_reason, MessagesStopReason::EndTurn);
+                let usage = usage.as_ref().unwrap();
+                assert_eq!((usage.input_tokens, usage.output_tokens), (12, 7));
+            }
+            other => panic!("Expected MessageDelta, got {:?}", other),
+        }
+        assert!(matches!(events[1], MessagesStreamEvent::MessageStop));
+
+        assert!(transformer.transform(b"data: [DONE]\n\n").unwrap().is_empty());
+        assert!(transformer.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_finish_flushes_held_message_delta() {
+        let finish = "data: {\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1234567890,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"length\"}]}\n\n";
+        let mut transformer = SseTransformer::new(openai(), anthropic());
+        assert!(transformer.transform(finish.as_bytes()).unwrap().is_empty());
+
+        // the upstream closed the stream without [DONE]
+        let output = transformer.finish().unwrap();
+        let output = std::str::from_utf8(&output).unwrap();
+        let events: Vec<MessagesStreamEvent> = output
+            .lines()
+            .filter_map(sse_data)
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert!(matches!(
+            &events[..],
+            [MessagesStreamEvent::MessageDelta { usage: None, .. }, MessagesStreamEvent::MessageStop]
+        ));
+    }
+
+    #[test]
+    fn test_line_buffer_reassembles_split_lines() {
+        // converted chunks are stamped with the time they were created, which is left out
+        let frames = |output: &[u8]| -> Vec<Value> {
+            std::str::from_utf8(output)
+                .unwrap()
+                .lines()
+                .filter_map(sse_data)
+                .map(|data| {
+                    let mut frame = serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.to_string()));
+                    if let Some(frame) = frame.as_object_mut() {
+                        frame.remove("created");
+                    }
+                    frame
+                })
+                .collect()
+        };
+        let whole = frames(&transform_sse_chunk(ANTHROPIC_STREAM.as_bytes(), &anthropic(), &openai()).unwrap());
+
+        // split the stream at every possible byte, including in the middle of `data:` lines
+        for split in 1..ANTHROPIC_STREAM.len() {
+            let (first, second) = ANTHROPIC_STREAM.as_bytes().split_at(split);
+            let mut buffer = SseLineBuffer::new();
+            let mut output = Vec::new();
+            for chunk in [first, second] {
+                let lines = buffer.push(chunk);
+                output.extend_from_slice(&transform_sse_chunk(&lines, &anthropic(), &openai()).unwrap());
+            }
+            assert!(buffer.finish().is_empty());
+            assert_eq!(frames(&output), whole, "split at byte {}", split);
+        }
+    }
+
+    #[test]
+    fn test_line_buffer_feeds_chat_completion_iter() {
+        use crate::providers::openai::types::SseChatCompletionIter;
+
+        let stream = OPENAI_STREAM.replace("Hello", "Héllo");
+        let mut buffer = SseLineBuffer::new();
+        let mut contents = Vec::new();
+        // tiny reads split most lines, and the two bytes of 'é' at some point
+        for chunk in stream.as_bytes().chunks(7) {
+            let lines = buffer.push(chunk);
+            for event in SseChatCompletionIter::try_from(lines.as_slice()).unwrap() {
+                contents.push(event.unwrap().choices[0].delta.content.as_ref().map(ToString::to_string));
+            }
+        }
+
+        assert_eq!(contents, vec![None, Some("Héllo".to_string()), None]);
+    }
+
+    #[test]
+    fn test_line_buffer_finish_returns_partial_line() {
+        let mut buffer = SseLineBuffer::new();
+        assert!(buffer.push(b"data: [DO").is_empty());
+        assert_eq!(buffer.push(b"NE]\n\ndata: {"), b"data: [DONE]\n\n");
+        assert_eq!(buffer.finish(), b"data: {");
+        assert!(buffer.finish().is_empty());
+    }
+
+    #[test]
+    fn test_synthesize_sse() {
+        let response = br#"{"id":"chatcmpl-123","object":"chat.completion","created":1234567890,"model":"gpt-4o","choices":[{"index":0,"message":{"role":"assistant","content":"Hello"},"finish_reason":"stop"}],"usage":{"prompt_tokens":5,"completion_tokens":1,"total_tokens":6}}"#;
+        let output = String::from_utf8(synthesize_sse(response, &openai()).unwrap()).unwrap();
+
+        let frames: Vec<&str> = output.split_terminator("\n\n").collect();
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|frame| frame.starts_with("data: ")));
+        assert!(frames[1].contains("\"content\":\"Hello\""));
+        assert!(frames[2].contains("\"finish_reason\":\"stop\""));
+        assert_eq!(frames[3], "data: [DONE]");
+
+        let anthropic = DetectedApi::Anthropic(AnthropicApi::Messages);
+        let response = br#"{"id":"msg_123","type":"message","role":"assistant","content":[{"type":"text","text":"Hello"}],"model":"claude-3-sonnet-20240229","stop_reason":"end_turn","usage":{"input_tokens":5,"output_tokens":1}}"#;
+        assert!(matches!(
+            synthesize_sse(response, &anthropic),
+            Err(TransformError::UnsupportedConversion(_))
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_stream_conversion() {
+        let embeddings = DetectedApi::OpenAI(OpenAIApi::Embeddings);
+        let result = transform_sse_chunk(OPENAI_STREAM.as_bytes(), &openai(), &embeddings);
+        assert!(matches!(result, Err(TransformError::UnsupportedConversion(_))));
+    }
+}
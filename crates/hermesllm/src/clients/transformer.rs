@@ -13,14 +13,14 @@
 //!
 //! ```rust
 //! use hermesllm::apis::{
-//!     AnthropicMessagesRequest, ChatCompletionsRequest, MessagesRole, MessagesMessage,
+//!     MessagesRequest, ChatCompletionsRequest, MessagesRole, MessagesMessage,
 //!     MessagesMessageContent, MessagesSystemPrompt,
 //! };
 //! use hermesllm::clients::TransformError;
 //! use std::convert::TryInto;
 //!
 //! // Transform Anthropic to OpenAI
-//! let anthropic_req = AnthropicMessagesRequest {
+//! let anthropic_req = MessagesRequest {
 //!     model: "claude-3-sonnet".to_string(),
 //!     system: None,
 //!     messages: vec![],
@@ -43,18 +43,47 @@
 //! ```
 
 use serde_json::Value;
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
 
 // Import centralized types
 use crate::apis::*;
+use crate::providers::models::max_output_tokens;
 use super::TransformError;
 
 // ============================================================================
 // CONSTANTS
 // ============================================================================
 
-/// Default maximum tokens when converting from OpenAI to Anthropic and no max_tokens is specified
-const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Default maximum tokens when converting from OpenAI to Anthropic and no max_tokens is specified,
+/// unless the deployment overrides it through [`ConversionOptions`]
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// `ChatCompletionsResponse::metadata` key carrying Anthropic's code execution container
+pub const ANTHROPIC_CONTAINER_METADATA_KEY: &str = "anthropic_container";
+
+/// `ChatCompletionsResponse::metadata` key carrying Anthropic's stop reason when
+/// `finish_reason` can't tell it apart, e.g. `stop_sequence` and `pause_turn`, which both
+/// surface as `stop`
+pub const ANTHROPIC_STOP_REASON_METADATA_KEY: &str = "anthropic_stop_reason";
+
+/// `ChatCompletionsRequest::metadata` key carrying Anthropic's `top_k`, see
+/// [`ConversionOptions::preserve_unmapped`]
+pub const ANTHROPIC_TOP_K_METADATA_KEY: &str = "anthropic_top_k";
+
+/// `name` of the OpenAI tool message carrying an Anthropic code execution result, the same
+/// name as the server tool that produced it. Such tool messages convert back into a
+/// `code_execution_tool_result` block instead of a plain `tool_result`, provided their
+/// `tool_call_id` also has the [`SERVER_TOOL_USE_ID_PREFIX`].
+pub const CODE_EXECUTION_TOOL_NAME: &str = "code_execution";
+
+/// Prefix Anthropic gives the ids of server tool uses, telling them apart from calls to
+/// user-defined tools that happen to share a server tool's name.
+pub const SERVER_TOOL_USE_ID_PREFIX: &str = "srvtoolu_";
+
+/// Approximate number of characters per token, see [`approximate_token_count`]
+pub const TOKEN_LENGTH_DIVISOR: usize = 4;
 
 // ============================================================================
 // UTILITY TRAITS - Shared traits for content manipulation
@@ -65,10 +94,21 @@ trait ExtractText {
     fn extract_text(&self) -> String;
 }
 
+/// A tool result extracted from Anthropic content, with the `name` its OpenAI tool message
+/// gets when the result's kind has to survive the conversion
+struct OpenAIToolResult {
+    tool_use_id: String,
+    text: String,
+    name: Option<String>,
+}
+
+/// Content parts, tool calls and tool results extracted from a block of Anthropic content
+type OpenAIContentSplit = (Vec<ContentPart>, Vec<ToolCall>, Vec<OpenAIToolResult>);
+
 /// Trait for utility functions on content collections
 trait ContentUtils<T> {
     fn extract_tool_calls(&self) -> Result<Option<Vec<ToolCall>>, TransformError>;
-    fn split_for_openai(&self) -> Result<(Vec<ContentPart>, Vec<ToolCall>, Vec<(String, String, bool)>), TransformError>;
+    fn split_for_openai(&self) -> Result<OpenAIContentSplit, TransformError>;
 }
 
 // ============================================================================
@@ -82,36 +122,100 @@ impl TryFrom<AnthropicMessagesRequest> for ChatCompletionsRequest {
     type Error = TransformError;
 
     fn try_from(req: AnthropicMessagesRequest) -> Result<Self, Self::Error> {
-        let mut openai_messages: Vec<Message> = Vec::new();
-
-        // Convert system prompt to system message if present
-        if let Some(system) = req.system {
-            openai_messages.push(system.into());
-        }
-
-        // Convert messages
-        for message in req.messages {
-            let converted_messages: Vec<Message> = message.try_into()?;
-            openai_messages.extend(converted_messages);
-        }
-
-        // Convert tools and tool choice
-        let openai_tools = req.tools.map(|tools| convert_anthropic_tools(tools));
-        let (openai_tool_choice, parallel_tool_calls) = convert_anthropic_tool_choice(req.tool_choice);
-
-        Ok(ChatCompletionsRequest {
-            model: req.model,
-            messages: openai_messages,
-            temperature: req.temperature,
-            top_p: req.top_p,
-            max_tokens: Some(req.max_tokens),
-            stream: req.stream,
-            stop: req.stop_sequences,
-            tools: openai_tools,
-            tool_choice: openai_tool_choice,
-            parallel_tool_calls,
-            ..Default::default()
-        })
+        convert_messages_request(req, &ConversionOptions::default())
+    }
+}
+
+/// Convert an Anthropic request to OpenAI using the given deployment options
+pub fn convert_messages_request(
+    req: AnthropicMessagesRequest,
+    options: &ConversionOptions,
+) -> Result<ChatCompletionsRequest, TransformError> {
+    // OpenAI has no equivalent of MCP servers, refuse rather than silently drop them
+    if req.mcp_servers.as_ref().is_some_and(|servers| !servers.is_empty()) {
+        return Err(TransformError::UnsupportedConversion(
+            "mcp_servers cannot be represented in an OpenAI chat completions request".to_string(),
+        ));
+    }
+
+    let mut openai_messages: Vec<Message> = Vec::new();
+
+    // Convert system prompt to system message if present
+    if let Some(system) = req.system {
+        openai_messages.push(system.into());
+    }
+
+    // Convert messages
+    for message in req.messages {
+        let converted_messages: Vec<Message> = message.try_into()?;
+        openai_messages.extend(converted_messages);
+    }
+
+    // Convert tools and tool choice
+    let openai_tools = req.tools.map(convert_anthropic_tools);
+    let (openai_tool_choice, parallel_tool_calls) = convert_anthropic_tool_choice(req.tool_choice);
+
+    let metadata = match req.top_k {
+        Some(top_k) if options.preserve_unmapped => {
+            Some(HashMap::from([(ANTHROPIC_TOP_K_METADATA_KEY.to_string(), top_k.to_string())]))
+        }
+        _ => None,
+    };
+
+    Ok(ChatCompletionsRequest {
+        model: req.model,
+        messages: openai_messages,
+        temperature: req.temperature,
+        top_p: req.top_p,
+        max_tokens: Some(req.max_tokens),
+        stream: req.stream,
+        stop: req.stop_sequences,
+        tools: openai_tools,
+        tool_choice: openai_tool_choice,
+        parallel_tool_calls,
+        metadata,
+        ..Default::default()
+    })
+}
+
+/// Deployment-specific settings for request conversions
+///
+/// The `TryFrom` implementations use [`ConversionOptions::default`]; deployments that need
+/// different defaults call the `convert_*` functions directly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionOptions {
+    /// `max_tokens` sent to Anthropic when the OpenAI request doesn't set one
+    pub default_max_tokens: u32,
+    /// Reject tools whose parameter schema is not a JSON Schema object with a `type`.
+    /// Off by default since upstreams accept plenty of loosely written schemas.
+    pub validate_tool_schemas: bool,
+    /// Reject `tool` messages without a `tool_call_id` instead of pairing them with the
+    /// most recent unanswered tool call
+    pub strict_tool_call_ids: bool,
+    /// Hosts remote images may be fetched from. `None` allows any host; the scheme is
+    /// always restricted to `http`, `https` and inline `data:` URLs.
+    pub image_hosts: Option<Vec<String>>,
+    /// Mark system prompts of at least this many characters for Anthropic's prompt caching,
+    /// which makes repeated long prompts much cheaper. `None` never caches; Anthropic ignores
+    /// the mark on prompts under its minimum cacheable length.
+    pub cache_system_prompt_min_chars: Option<usize>,
+    /// Carry Anthropic-only request fields (`top_k`) through the OpenAI request's `metadata`
+    /// and restore them when the request is converted back to Anthropic, so a round trip
+    /// through OpenAI doesn't lose them. Off by default, as OpenAI upstreams would see, and
+    /// possibly store, the extra metadata.
+    pub preserve_unmapped: bool,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self {
+            default_max_tokens: DEFAULT_MAX_TOKENS,
+            validate_tool_schemas: false,
+            strict_tool_call_ids: false,
+            image_hosts: None,
+            cache_system_prompt_min_chars: None,
+            preserve_unmapped: false,
+        }
     }
 }
 
@@ -119,44 +223,106 @@ impl TryFrom<ChatCompletionsRequest> for AnthropicMessagesRequest {
     type Error = TransformError;
 
     fn try_from(req: ChatCompletionsRequest) -> Result<Self, Self::Error> {
-        let mut system_prompt = None;
-        let mut messages = Vec::new();
+        convert_chat_completions_request(req, &ConversionOptions::default())
+    }
+}
 
-        for message in req.messages {
-            match message.role {
-                Role::System => {
-                    system_prompt = Some(message.into());
+/// Convert an OpenAI request to Anthropic using the given deployment options
+pub fn convert_chat_completions_request(
+    req: ChatCompletionsRequest,
+    options: &ConversionOptions,
+) -> Result<AnthropicMessagesRequest, TransformError> {
+    let mut system_prompt = None;
+    let mut messages = Vec::new();
+    // Tool calls the conversation hasn't answered yet, oldest first
+    let mut unanswered_tool_calls: Vec<String> = Vec::new();
+
+    for mut message in req.messages {
+        match message.role {
+            Role::System | Role::Developer => {
+                // Anthropic has a single system prompt, keep every instruction rather than the last
+                system_prompt = Some(append_system_prompt(system_prompt, message.into()));
+            }
+            _ => {
+                if let MessageContent::Parts(parts) = &message.content {
+                    for part in parts {
+                        if let ContentPart::ImageUrl { image_url } = part {
+                            validate_image_url(&image_url.url, options.image_hosts.as_deref())?;
+                        }
+                    }
                 }
-                _ => {
-                    let anthropic_message: MessagesMessage = message.try_into()?;
-                    messages.push(anthropic_message);
+                for tool_call in message.tool_calls.iter().flatten() {
+                    unanswered_tool_calls.push(tool_call.id.clone());
                 }
+                if message.role == Role::Tool {
+                    match &message.tool_call_id {
+                        Some(id) => unanswered_tool_calls.retain(|pending| pending != id),
+                        // Some clients leave the id out when there was a single call to answer
+                        None if !options.strict_tool_call_ids => {
+                            message.tool_call_id = unanswered_tool_calls.pop();
+                        }
+                        None => {}
+                    }
+                }
+                let anthropic_message: MessagesMessage = message.try_into()?;
+                push_alternating(&mut messages, anthropic_message);
             }
         }
+    }
 
-        // Convert tools and tool choice
-        let anthropic_tools = req.tools.map(|tools| convert_openai_tools(tools));
-        let anthropic_tool_choice = convert_openai_tool_choice(req.tool_choice, req.parallel_tool_calls);
+    if let Some(min_chars) = options.cache_system_prompt_min_chars {
+        system_prompt = system_prompt.map(|prompt| cache_system_prompt(prompt, min_chars));
+    }
 
-        Ok(AnthropicMessagesRequest {
-            model: req.model,
-            system: system_prompt,
-            messages,
-            max_tokens: req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
-            container: None,
-            mcp_servers: None,
-            service_tier: None,
-            thinking: None,
-            temperature: req.temperature,
-            top_p: req.top_p,
-            top_k: None, // OpenAI doesn't have top_k
-            stream: req.stream,
-            stop_sequences: req.stop,
-            tools: anthropic_tools,
-            tool_choice: anthropic_tool_choice,
-            metadata: None,
-        })
+    // Convert tools and tool choice, keeping only the allowed subset when one is given
+    let tools = match &req.tool_choice {
+        Some(ToolChoice::AllowedTools { allowed_tools, .. }) => req.tools.map(|tools| {
+            tools.into_iter().filter(|tool| allowed_tools.contains(&tool.function.name)).collect()
+        }),
+        _ => req.tools,
+    };
+    if options.validate_tool_schemas {
+        for tool in tools.iter().flatten() {
+            validate_tool_schema(&tool.function.parameters).map_err(|err| match err {
+                TransformError::InvalidField { reason, .. } => TransformError::InvalidField {
+                    field: format!("tools.{}.parameters", tool.function.name),
+                    reason,
+                },
+                err => err,
+            })?;
+        }
     }
+    let anthropic_tools = tools.map(convert_openai_tools);
+    let anthropic_tool_choice = convert_openai_tool_choice(req.tool_choice, req.parallel_tool_calls)?;
+
+    // Respect the target model's output ceiling, whether or not max_tokens was set
+    let max_tokens = clamp_max_tokens(
+        req.max_tokens.unwrap_or(options.default_max_tokens),
+        max_output_tokens(&req.model),
+    );
+
+    Ok(AnthropicMessagesRequest {
+        model: req.model,
+        system: system_prompt,
+        messages,
+        max_tokens,
+        container: None,
+        mcp_servers: None,
+        service_tier: None,
+        thinking: None,
+        temperature: req.temperature,
+        top_p: req.top_p,
+        // OpenAI doesn't have top_k, unless an earlier conversion stashed one
+        top_k: req.metadata.as_ref()
+            .filter(|_| options.preserve_unmapped)
+            .and_then(|metadata| metadata.get(ANTHROPIC_TOP_K_METADATA_KEY))
+            .and_then(|top_k| top_k.parse().ok()),
+        stream: req.stream,
+        stop_sequences: req.stop,
+        tools: anthropic_tools,
+        tool_choice: anthropic_tool_choice,
+        metadata: None,
+    })
 }
 
 // ============================================================================
@@ -168,17 +334,15 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
 
     fn try_from(resp: MessagesResponse) -> Result<Self, Self::Error> {
         let content = convert_anthropic_content_to_openai(&resp.content)?;
-        let finish_reason: FinishReason = resp.stop_reason.into();
+        let finish_reason: FinishReason = resp.stop_reason.clone().into();
         let tool_calls = resp.content.extract_tool_calls()?;
 
         // Convert MessageContent to String for response
         let content_string = match content {
-            MessageContent::Text(text) => Some(text),
-            MessageContent::Parts(parts) => {
-                let text = parts.extract_text();
-                if text.is_empty() { None } else { Some(text) }
-            }
+            MessageContent::Text(text) => text,
+            MessageContent::Parts(parts) => parts.extract_text(),
         };
+        let content_string = if content_string.is_empty() { None } else { Some(content_string) };
 
         let message = ResponseMessage {
             role: Role::Assistant,
@@ -205,6 +369,15 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
             completion_tokens_details: None,
         };
 
+        let mut metadata = HashMap::new();
+        if let Some(container) = resp.container {
+            metadata.insert(ANTHROPIC_CONTAINER_METADATA_KEY.to_string(), serde_json::to_value(container)?);
+        }
+        if let Some(stop_reason) = lossy_stop_reason(&resp.stop_reason) {
+            metadata.insert(ANTHROPIC_STOP_REASON_METADATA_KEY.to_string(), stop_reason);
+        }
+        let metadata = if metadata.is_empty() { None } else { Some(metadata) };
+
         Ok(ChatCompletionsResponse {
             id: resp.id,
             object: "chat.completion".to_string(),
@@ -213,6 +386,7 @@ impl TryFrom<MessagesResponse> for ChatCompletionsResponse {
             choices: vec![choice],
             usage,
             system_fingerprint: None,
+            metadata,
         })
     }
 }
@@ -224,9 +398,13 @@ impl TryFrom<ChatCompletionsResponse> for MessagesResponse {
         let choice = resp.choices.into_iter().next()
             .ok_or_else(|| TransformError::MissingField("choices".to_string()))?;
 
+        let mut metadata = resp.metadata.unwrap_or_default();
         let content = convert_openai_message_to_anthropic_content(&choice.message.to_message())?;
-        let stop_reason = choice.finish_reason
-            .map(|fr| fr.into())
+        // Prefer the original Anthropic reason when an earlier conversion preserved it
+        let preserved_stop_reason = metadata.remove(ANTHROPIC_STOP_REASON_METADATA_KEY)
+            .and_then(|reason| serde_json::from_value::<MessagesStopReason>(reason).ok());
+        let stop_reason = preserved_stop_reason
+            .or_else(|| choice.finish_reason.map(|fr| fr.into()))
             .unwrap_or(MessagesStopReason::EndTurn);
 
         let usage = MessagesUsage {
@@ -236,6 +414,9 @@ impl TryFrom<ChatCompletionsResponse> for MessagesResponse {
             cache_read_input_tokens: None,
         };
 
+        let container = metadata.remove(ANTHROPIC_CONTAINER_METADATA_KEY)
+            .and_then(|container| serde_json::from_value::<MessagesContainer>(container).ok());
+
         Ok(MessagesResponse {
             id: resp.id,
             obj_type: "message".to_string(),
@@ -245,7 +426,7 @@ impl TryFrom<ChatCompletionsResponse> for MessagesResponse {
             stop_reason,
             stop_sequence: None,
             usage,
-            container: None,
+            container,
         })
     }
 }
@@ -275,12 +456,12 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                 ))
             }
 
-            MessagesStreamEvent::ContentBlockStart { content_block, .. } => {
-                convert_content_block_start(content_block)
+            MessagesStreamEvent::ContentBlockStart { index, content_block } => {
+                convert_content_block_start(index, content_block)
             }
 
-            MessagesStreamEvent::ContentBlockDelta { delta, .. } => {
-                convert_content_delta(delta)
+            MessagesStreamEvent::ContentBlockDelta { index, delta } => {
+                convert_content_delta(index, delta)
             }
 
             MessagesStreamEvent::ContentBlockStop { .. } => {
@@ -289,7 +470,7 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
 
             MessagesStreamEvent::MessageDelta { delta, usage } => {
                 let finish_reason: Option<FinishReason> = Some(delta.stop_reason.into());
-                let openai_usage: Option<Usage> = Some(usage.into());
+                let openai_usage: Option<Usage> = usage.map(Usage::from);
 
                 Ok(create_openai_chunk(
                     "stream",
@@ -322,7 +503,8 @@ impl TryFrom<MessagesStreamEvent> for ChatCompletionsStreamResponse {
                 ))
             }
 
-            MessagesStreamEvent::Ping => {
+            // Unknown events have no OpenAI equivalent, they become an empty keep-alive chunk
+            MessagesStreamEvent::Ping | MessagesStreamEvent::Unknown(_) => {
                 Ok(ChatCompletionsStreamResponse {
                     id: "stream".to_string(),
                     object: "chat.completion.chunk".to_string(),
@@ -348,18 +530,19 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
 
         let choice = &resp.choices[0];
 
-        // Handle final chunk with usage
-        if let Some(usage) = resp.usage {
-            if let Some(finish_reason) = &choice.finish_reason {
-                let anthropic_stop_reason: MessagesStopReason = finish_reason.clone().into();
-                return Ok(MessagesStreamEvent::MessageDelta {
-                    delta: MessagesMessageDelta {
-                        stop_reason: anthropic_stop_reason,
-                        stop_sequence: None,
-                    },
-                    usage: usage.into(),
-                });
-            }
+        // Handle the final chunk. Anthropic streams a single message, so prefer the usage of
+        // the choice being converted over the response total, which covers all choices. Many
+        // upstreams report no usage with the finish reason; the stop reason still has to reach
+        // the client, without usage.
+        if let Some(finish_reason) = &choice.finish_reason {
+            let usage = choice.usage.clone().or(resp.usage).map(MessagesUsage::from);
+            return Ok(MessagesStreamEvent::MessageDelta {
+                delta: MessagesMessageDelta {
+                    stop_reason: finish_reason.clone().into(),
+                    stop_sequence: None,
+                },
+                usage,
+            });
         }
 
         // Handle role start
@@ -395,20 +578,232 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
             }
         }
 
+        // Handle refusal delta. Anthropic has no separate refusal channel, the refusal text is
+        // streamed as regular text and the message ends with a `refusal` stop reason
+        if let Some(refusal) = &choice.delta.refusal {
+            if !refusal.is_empty() {
+                return Ok(MessagesStreamEvent::ContentBlockDelta {
+                    index: 0,
+                    delta: MessagesContentDelta::TextDelta {
+                        text: refusal.clone(),
+                    },
+                });
+            }
+        }
+
         // Handle tool calls
         if let Some(tool_calls) = &choice.delta.tool_calls {
             return convert_tool_call_deltas(tool_calls.clone());
         }
 
-        // Handle finish reason
-        if let Some(finish_reason) = &choice.finish_reason {
-            if *finish_reason == FinishReason::Stop {
-                return Ok(MessagesStreamEvent::MessageStop);
+        // Default to ping for unhandled cases
+        Ok(MessagesStreamEvent::Ping)
+    }
+}
+
+/// Opt-in OpenAI → Anthropic stream converter that tracks output tokens across chunks.
+///
+/// The plain `TryFrom` conversion is stateless and can only forward usage reported by the
+/// upstream in its final chunk. This accumulator approximates output tokens from every
+/// streamed text and tool argument delta, and reports them in `MessageDelta` when the
+/// upstream does not report any.
+///
+/// It also remembers the `system_fingerprint` and `service_tier` the upstream reported, which
+/// have no place in Anthropic events, so they can be restored when converting back to OpenAI.
+/// A stream that delivered a `refusal` delta ends with a `refusal` stop reason, since OpenAI
+/// still finishes refused completions with `stop`. Only the first assistant role delta starts
+/// the message; upstreams that repeat the role don't produce a second `MessageStart`.
+#[derive(Debug, Default, Clone)]
+pub struct StreamUsageAccumulator {
+    output_tokens: u32,
+    system_fingerprint: Option<String>,
+    service_tier: Option<String>,
+    refused: bool,
+    message_started: bool,
+}
+
+impl StreamUsageAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Approximate number of output tokens streamed so far
+    pub fn output_tokens(&self) -> u32 {
+        self.output_tokens
+    }
+
+    /// `system_fingerprint` from the first upstream chunk that reported one
+    pub fn system_fingerprint(&self) -> Option<&str> {
+        self.system_fingerprint.as_deref()
+    }
+
+    /// `service_tier` from the first upstream chunk that reported one
+    pub fn service_tier(&self) -> Option<&str> {
+        self.service_tier.as_deref()
+    }
+
+    /// Convert a chunk, accumulating its output tokens. A finish chunk without upstream
+    /// usage yields a `MessageDelta` with the accumulated usage followed by `MessageStop`.
+    pub fn transform(
+        &mut self,
+        mut chunk: ChatCompletionsStreamResponse,
+    ) -> Result<Vec<MessagesStreamEvent>, TransformError> {
+        // a repeated role must not restart the message, but the rest of the delta still counts
+        let mut repeated_role = false;
+        if let Some(choice) = chunk.choices.first_mut() {
+            if choice.delta.role.is_some() {
+                repeated_role = self.message_started;
+                self.message_started = true;
+                if repeated_role {
+                    choice.delta.role = None;
+                }
             }
         }
 
-        // Default to ping for unhandled cases
-        Ok(MessagesStreamEvent::Ping)
+        if self.system_fingerprint.is_none() {
+            self.system_fingerprint = chunk.system_fingerprint.clone();
+        }
+        if self.service_tier.is_none() {
+            self.service_tier = chunk.service_tier.clone();
+        }
+
+        if let Some(choice) = chunk.choices.first() {
+            if let Some(content) = &choice.delta.content {
+                self.output_tokens += approximate_token_count(content);
+            }
+            if let Some(refusal) = choice.delta.refusal.as_ref().filter(|refusal| !refusal.is_empty()) {
+                self.output_tokens += approximate_token_count(refusal);
+                self.refused = true;
+            }
+            for tool_call in choice.delta.tool_calls.iter().flatten() {
+                if let Some(arguments) = tool_call.function.as_ref().and_then(|f| f.arguments.as_ref()) {
+                    self.output_tokens += approximate_token_count(arguments);
+                }
+            }
+        }
+
+        let finish_reason = chunk.choices.first().and_then(|choice| choice.finish_reason.clone());
+        let has_usage = chunk.usage.is_some() || chunk.choices.first().is_some_and(|choice| choice.usage.is_some());
+        if let (Some(finish_reason), false) = (&finish_reason, has_usage) {
+            return Ok(vec![
+                MessagesStreamEvent::MessageDelta {
+                    delta: MessagesMessageDelta {
+                        stop_reason: self.stop_reason(finish_reason),
+                        stop_sequence: None,
+                    },
+                    usage: Some(MessagesUsage {
+                        input_tokens: 0,
+                        output_tokens: self.output_tokens,
+                        cache_creation_input_tokens: None,
+                        cache_read_input_tokens: None,
+                    }),
+                },
+                MessagesStreamEvent::MessageStop,
+            ]);
+        }
+
+        let mut event: MessagesStreamEvent = chunk.try_into()?;
+        if repeated_role && matches!(event, MessagesStreamEvent::Ping) {
+            return Ok(vec![]);
+        }
+        if let MessagesStreamEvent::MessageDelta { usage: Some(usage), delta } = &mut event {
+            // upstream usage is authoritative, only fill in what it didn't report
+            if usage.output_tokens == 0 {
+                usage.output_tokens = self.output_tokens;
+            }
+            if self.refused {
+                delta.stop_reason = MessagesStopReason::Refusal;
+            }
+        }
+
+        Ok(vec![event])
+    }
+
+    fn stop_reason(&self, finish_reason: &FinishReason) -> MessagesStopReason {
+        if self.refused {
+            MessagesStopReason::Refusal
+        } else {
+            finish_reason.clone().into()
+        }
+    }
+}
+
+/// Opt-in Anthropic → OpenAI stream converter that keeps response metadata consistent.
+///
+/// The plain `TryFrom` conversion only sees one event at a time, so every chunk after
+/// `MessageStart` gets a placeholder id and model and no `system_fingerprint` or `service_tier`.
+/// This converter records the id and model from `MessageStart` and stamps them, together with
+/// any fingerprint and service tier the upstream reported, on every chunk it produces.
+///
+/// It also numbers tool calls the way OpenAI clients expect. The plain conversion indexes a
+/// tool call by its content block, so after a text block the first call gets index 1; here
+/// each tool use block gets the next 0-based position in `tool_calls` instead.
+#[derive(Debug, Default, Clone)]
+pub struct ChatCompletionsStreamConverter {
+    id: Option<String>,
+    model: Option<String>,
+    system_fingerprint: Option<String>,
+    service_tier: Option<String>,
+    /// Position in `tool_calls` of each tool use content block seen so far
+    tool_call_indices: HashMap<u32, u32>,
+}
+
+impl ChatCompletionsStreamConverter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed the converter with the fingerprint and service tier recorded by a
+    /// [`StreamUsageAccumulator`] on the OpenAI side of the stream
+    pub fn with_source_metadata(mut self, accumulator: &StreamUsageAccumulator) -> Self {
+        self.system_fingerprint = accumulator.system_fingerprint.clone();
+        self.service_tier = accumulator.service_tier.clone();
+        self
+    }
+
+    pub fn with_system_fingerprint(mut self, system_fingerprint: impl Into<String>) -> Self {
+        self.system_fingerprint = Some(system_fingerprint.into());
+        self
+    }
+
+    pub fn with_service_tier(mut self, service_tier: impl Into<String>) -> Self {
+        self.service_tier = Some(service_tier.into());
+        self
+    }
+
+    /// Convert an event, carrying the stream's metadata over to the resulting chunk
+    pub fn transform(
+        &mut self,
+        event: MessagesStreamEvent,
+    ) -> Result<ChatCompletionsStreamResponse, TransformError> {
+        if let MessagesStreamEvent::MessageStart { message } = &event {
+            self.id = Some(message.id.clone());
+            self.model = Some(message.model.clone());
+        }
+        let block_index = match &event {
+            MessagesStreamEvent::ContentBlockStart { index, .. }
+            | MessagesStreamEvent::ContentBlockDelta { index, .. } => Some(*index),
+            _ => None,
+        };
+
+        let mut chunk: ChatCompletionsStreamResponse = event.try_into()?;
+        if let Some(block_index) = block_index {
+            let tool_calls = chunk.choices.iter_mut().flat_map(|choice| choice.delta.tool_calls.iter_mut().flatten());
+            for tool_call in tool_calls {
+                let next_index = self.tool_call_indices.len() as u32;
+                tool_call.index = *self.tool_call_indices.entry(block_index).or_insert(next_index);
+            }
+        }
+        if let Some(id) = &self.id {
+            chunk.id = id.clone();
+        }
+        if let Some(model) = &self.model {
+            chunk.model = model.clone();
+        }
+        chunk.system_fingerprint = self.system_fingerprint.clone();
+        chunk.service_tier = self.service_tier.clone();
+
+        Ok(chunk)
     }
 }
 
@@ -417,9 +812,9 @@ impl TryFrom<ChatCompletionsStreamResponse> for MessagesStreamEvent {
 // ============================================================================
 
 // System Prompt Conversions
-impl Into<Message> for MessagesSystemPrompt {
-    fn into(self) -> Message {
-        let system_content = match self {
+impl From<MessagesSystemPrompt> for Message {
+    fn from(value: MessagesSystemPrompt) -> Self {
+        let system_content = match value {
             MessagesSystemPrompt::Single(text) => MessageContent::Text(text),
             MessagesSystemPrompt::Blocks(blocks) => {
                 MessageContent::Text(blocks.extract_text())
@@ -436,9 +831,61 @@ impl Into<Message> for MessagesSystemPrompt {
     }
 }
 
-impl Into<MessagesSystemPrompt> for Message {
-    fn into(self) -> MessagesSystemPrompt {
-        let system_text = match self.content {
+/// Add another system or developer message's text to the system prompt built so far
+fn append_system_prompt(current: Option<MessagesSystemPrompt>, next: MessagesSystemPrompt) -> MessagesSystemPrompt {
+    let text = |prompt: MessagesSystemPrompt| match prompt {
+        MessagesSystemPrompt::Single(text) => text,
+        MessagesSystemPrompt::Blocks(blocks) => blocks.extract_text(),
+    };
+    let Some(current) = current else {
+        return next;
+    };
+    let (current, next) = (text(current), text(next));
+    if is_blank(&current) {
+        MessagesSystemPrompt::Single(next)
+    } else if is_blank(&next) {
+        MessagesSystemPrompt::Single(current)
+    } else {
+        MessagesSystemPrompt::Single(format!("{}\n\n{}", current, next))
+    }
+}
+
+/// Mark a system prompt of at least `min_chars` characters for caching. Block prompts get the
+/// mark on their last text block, which caches every block up to it.
+fn cache_system_prompt(prompt: MessagesSystemPrompt, min_chars: usize) -> MessagesSystemPrompt {
+    match prompt {
+        MessagesSystemPrompt::Single(text) if text.chars().count() >= min_chars => {
+            MessagesSystemPrompt::Blocks(vec![MessagesContentBlock::Text {
+                text,
+                cache_control: Some(CacheControl::ephemeral()),
+            }])
+        }
+        MessagesSystemPrompt::Blocks(mut blocks) => {
+            let chars: usize = blocks
+                .iter()
+                .map(|block| match block {
+                    MessagesContentBlock::Text { text, .. } => text.chars().count(),
+                    _ => 0,
+                })
+                .sum();
+            if chars >= min_chars {
+                let last_text = blocks.iter_mut().rev().find_map(|block| match block {
+                    MessagesContentBlock::Text { cache_control, .. } => Some(cache_control),
+                    _ => None,
+                });
+                if let Some(cache_control) = last_text {
+                    cache_control.get_or_insert_with(CacheControl::ephemeral);
+                }
+            }
+            MessagesSystemPrompt::Blocks(blocks)
+        }
+        prompt => prompt,
+    }
+}
+
+impl From<Message> for MessagesSystemPrompt {
+    fn from(value: Message) -> Self {
+        let system_text = match value.content {
             MessageContent::Text(text) => text,
             MessageContent::Parts(parts) => parts.extract_text()
         };
@@ -478,13 +925,13 @@ impl TryFrom<MessagesMessage> for Vec<Message> {
                 result.push(main_message);
 
                 // Add tool result messages
-                for (tool_use_id, result_text, _is_error) in tool_results {
+                for tool_result in tool_results {
                     result.push(Message {
                         role: Role::Tool,
-                        content: MessageContent::Text(result_text),
-                        name: None,
+                        content: MessageContent::Text(tool_result.text),
+                        name: tool_result.name,
                         tool_calls: None,
-                        tool_call_id: Some(tool_use_id),
+                        tool_call_id: Some(tool_result.tool_use_id),
                     });
                 }
             }
@@ -498,43 +945,64 @@ impl TryFrom<Message> for MessagesMessage {
     type Error = TransformError;
 
     fn try_from(message: Message) -> Result<Self, Self::Error> {
+        let is_code_execution_result = message.role == Role::Tool
+            && message.name.as_deref() == Some(CODE_EXECUTION_TOOL_NAME)
+            && message.tool_call_id.as_deref().is_some_and(|id| id.starts_with(SERVER_TOOL_USE_ID_PREFIX));
+        if let Some(name) = message.name.as_ref().filter(|_| !is_code_execution_result) {
+            log::warn!("dropping message name `{}`, Anthropic messages have no participant names", name);
+        }
         let role = match message.role {
-            Role::User => MessagesRole::User,
-            Role::Assistant => MessagesRole::Assistant,
             Role::Tool => {
                 // Tool messages become user messages with tool results
-                let tool_call_id = message.tool_call_id
+                let tool_use_id = message.tool_call_id
                     .ok_or_else(|| TransformError::MissingField("tool_call_id required for Tool messages".to_string()))?;
+                let content = vec![MessagesContentBlock::Text {
+                    text: message.content.extract_text(),
+                    cache_control: None,
+                }];
+                let tool_result = if is_code_execution_result {
+                    MessagesContentBlock::CodeExecutionToolResult { tool_use_id, is_error: None, content }
+                } else {
+                    MessagesContentBlock::ToolResult { tool_use_id, is_error: None, content }
+                };
 
                 return Ok(MessagesMessage {
                     role: MessagesRole::User,
-                    content: MessagesMessageContent::Blocks(vec![
-                        MessagesContentBlock::ToolResult {
-                            tool_use_id: tool_call_id,
-                            is_error: None,
-                            content: vec![MessagesContentBlock::Text {
-                                text: message.content.extract_text(),
-                            }],
-                        },
-                    ]),
+                    content: normalize_content(vec![tool_result]),
                 });
             }
-            Role::System => {
-                return Err(TransformError::UnsupportedConversion("System messages should be handled separately".to_string()));
-            }
+            _ => message.role.clone().try_into()?,
         };
 
         let content_blocks = convert_openai_message_to_anthropic_content(&message)?;
-        let content = build_anthropic_content(content_blocks);
+        let content = normalize_content(content_blocks);
 
         Ok(MessagesMessage { role, content })
     }
 }
 
 // Role Conversions
-impl Into<Role> for MessagesRole {
-    fn into(self) -> Role {
-        match self {
+
+/// Anthropic messages are only ever from the user or the assistant. Tool results travel in
+/// user turns, while system and developer instructions belong in the request's system prompt
+/// and must never be passed off as a conversation turn.
+impl TryFrom<Role> for MessagesRole {
+    type Error = TransformError;
+
+    fn try_from(value: Role) -> Result<Self, Self::Error> {
+        match value {
+            Role::User | Role::Tool => Ok(MessagesRole::User),
+            Role::Assistant => Ok(MessagesRole::Assistant),
+            Role::System | Role::Developer => Err(TransformError::UnsupportedConversion(
+                "system and developer messages belong in the system prompt".to_string(),
+            )),
+        }
+    }
+}
+
+impl From<MessagesRole> for Role {
+    fn from(value: MessagesRole) -> Self {
+        match value {
             MessagesRole::User => Role::User,
             MessagesRole::Assistant => Role::Assistant,
         }
@@ -555,7 +1023,7 @@ impl ExtractText for Vec<ContentPart> {
     fn extract_text(&self) -> String {
         self.iter()
             .filter_map(|part| match part {
-                ContentPart::Text { text } => Some(text.as_str()),
+                ContentPart::Text { text } if !is_blank(text) => Some(text.as_str()),
                 _ => None,
             })
             .collect::<Vec<_>>()
@@ -567,7 +1035,7 @@ impl ExtractText for Vec<MessagesContentBlock> {
     fn extract_text(&self) -> String {
         self.iter()
             .filter_map(|block| match block {
-                MessagesContentBlock::Text { text } => Some(text.as_str()),
+                MessagesContentBlock::Text { text, .. } if !is_blank(text) => Some(text.as_str()),
                 _ => None,
             })
             .collect::<Vec<_>>()
@@ -599,15 +1067,17 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
         Ok(if tool_calls.is_empty() { None } else { Some(tool_calls) })
     }
 
-    fn split_for_openai(&self) -> Result<(Vec<ContentPart>, Vec<ToolCall>, Vec<(String, String, bool)>), TransformError> {
+    fn split_for_openai(&self) -> Result<OpenAIContentSplit, TransformError> {
         let mut content_parts = Vec::new();
         let mut tool_calls = Vec::new();
         let mut tool_results = Vec::new();
 
         for block in self {
             match block {
-                MessagesContentBlock::Text { text } => {
-                    content_parts.push(ContentPart::Text { text: text.clone() });
+                MessagesContentBlock::Text { text, .. } => {
+                    if !is_blank(text) {
+                        content_parts.push(ContentPart::Text { text: text.clone() });
+                    }
                 }
                 MessagesContentBlock::Image { source } => {
                     let url = convert_image_source_to_url(source);
@@ -628,12 +1098,21 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
                         function: FunctionCall { name: name.clone(), arguments },
                     });
                 }
-                MessagesContentBlock::ToolResult { tool_use_id, content, is_error } |
-                MessagesContentBlock::WebSearchToolResult { tool_use_id, content, is_error } |
-                MessagesContentBlock::CodeExecutionToolResult { tool_use_id, content, is_error } |
-                MessagesContentBlock::McpToolResult { tool_use_id, content, is_error } => {
-                    let result_text = content.extract_text();
-                    tool_results.push((tool_use_id.clone(), result_text, is_error.unwrap_or(false)));
+                MessagesContentBlock::ToolResult { tool_use_id, content, .. } |
+                MessagesContentBlock::WebSearchToolResult { tool_use_id, content, .. } |
+                MessagesContentBlock::McpToolResult { tool_use_id, content, .. } => {
+                    tool_results.push(OpenAIToolResult {
+                        tool_use_id: tool_use_id.clone(),
+                        text: content.extract_text(),
+                        name: None,
+                    });
+                }
+                MessagesContentBlock::CodeExecutionToolResult { tool_use_id, content, .. } => {
+                    tool_results.push(OpenAIToolResult {
+                        tool_use_id: tool_use_id.clone(),
+                        text: content.extract_text(),
+                        name: Some(CODE_EXECUTION_TOOL_NAME.to_string()),
+                    });
                 }
                 _ => {
                     // Skip unsupported content types
@@ -647,9 +1126,12 @@ impl ContentUtils<ToolCall> for Vec<MessagesContentBlock> {
 }
 
 // Stop Reason Conversions
-impl Into<FinishReason> for MessagesStopReason {
-    fn into(self) -> FinishReason {
-        match self {
+/// Anthropic stop reasons are richer than OpenAI finish reasons, so this mapping is lossy:
+/// `end_turn`, `stop_sequence` and `pause_turn` all become `stop`. Full responses keep the
+/// original under [`ANTHROPIC_STOP_REASON_METADATA_KEY`]; streamed chunks have no such field.
+impl From<MessagesStopReason> for FinishReason {
+    fn from(value: MessagesStopReason) -> Self {
+        match value {
             MessagesStopReason::EndTurn => FinishReason::Stop,
             MessagesStopReason::MaxTokens => FinishReason::Length,
             MessagesStopReason::StopSequence => FinishReason::Stop,
@@ -660,9 +1142,10 @@ impl Into<FinishReason> for MessagesStopReason {
     }
 }
 
-impl Into<MessagesStopReason> for FinishReason {
-    fn into(self) -> MessagesStopReason {
-        match self {
+/// `function_call` is the legacy spelling of `tool_calls` and maps to `tool_use` as well
+impl From<FinishReason> for MessagesStopReason {
+    fn from(value: FinishReason) -> Self {
+        match value {
             FinishReason::Stop => MessagesStopReason::EndTurn,
             FinishReason::Length => MessagesStopReason::MaxTokens,
             FinishReason::ToolCalls => MessagesStopReason::ToolUse,
@@ -672,24 +1155,32 @@ impl Into<MessagesStopReason> for FinishReason {
     }
 }
 
+/// Anthropic stop reasons that `finish_reason` can't express, as their wire name
+fn lossy_stop_reason(stop_reason: &MessagesStopReason) -> Option<Value> {
+    match stop_reason {
+        MessagesStopReason::StopSequence | MessagesStopReason::PauseTurn => serde_json::to_value(stop_reason).ok(),
+        _ => None,
+    }
+}
+
 // Usage Conversions
-impl Into<Usage> for MessagesUsage {
-    fn into(self) -> Usage {
+impl From<MessagesUsage> for Usage {
+    fn from(value: MessagesUsage) -> Self {
         Usage {
-            prompt_tokens: self.input_tokens,
-            completion_tokens: self.output_tokens,
-            total_tokens: self.input_tokens + self.output_tokens,
+            prompt_tokens: value.input_tokens,
+            completion_tokens: value.output_tokens,
+            total_tokens: value.input_tokens + value.output_tokens,
             prompt_tokens_details: None,
             completion_tokens_details: None,
         }
     }
 }
 
-impl Into<MessagesUsage> for Usage {
-    fn into(self) -> MessagesUsage {
+impl From<Usage> for MessagesUsage {
+    fn from(value: Usage) -> Self {
         MessagesUsage {
-            input_tokens: self.prompt_tokens,
-            output_tokens: self.completion_tokens,
+            input_tokens: value.prompt_tokens,
+            output_tokens: value.completion_tokens,
             cache_creation_input_tokens: None,
             cache_read_input_tokens: None,
         }
@@ -701,10 +1192,25 @@ impl Into<MessagesUsage> for Usage {
 // ============================================================================
 
 /// Helper to create a current unix timestamp
-fn current_timestamp() -> u64 {
+pub(crate) fn current_timestamp() -> u64 {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
 }
 
+/// Clamp the requested max tokens to the target model's output ceiling.
+/// Models without a known ceiling are passed through unchanged.
+fn clamp_max_tokens(requested: u32, limit: Option<u32>) -> u32 {
+    match limit {
+        Some(limit) => requested.min(limit),
+        None => requested,
+    }
+}
+
+/// Rough token estimate, about four characters per token, the same heuristic routing uses.
+/// This is the gateway's one estimate for counting tokens without asking a model.
+pub fn approximate_token_count(text: &str) -> u32 {
+    text.chars().count().div_ceil(TOKEN_LENGTH_DIVISOR) as u32
+}
+
 /// Helper to create OpenAI streaming chunk
 fn create_openai_chunk(
     id: &str,
@@ -723,6 +1229,7 @@ fn create_openai_chunk(
             delta,
             finish_reason,
             logprobs: None,
+            usage: None,
         }],
         usage,
         system_fingerprint: None,
@@ -762,6 +1269,50 @@ fn convert_anthropic_tools(tools: Vec<MessagesTool>) -> Vec<Tool> {
         .collect()
 }
 
+/// Shallow structural check of a tool parameter schema: it must be a JSON object whose
+/// `type` is a string or a list of strings. Nested schemas are left to the upstream.
+pub fn validate_tool_schema(schema: &Value) -> Result<(), TransformError> {
+    let invalid = |reason: &str| TransformError::InvalidField {
+        field: "parameters".to_string(),
+        reason: reason.to_string(),
+    };
+    let schema = schema.as_object().ok_or_else(|| invalid("schema must be a JSON object"))?;
+    match schema.get("type") {
+        Some(Value::String(_)) => Ok(()),
+        Some(Value::Array(types)) if !types.is_empty() && types.iter().all(Value::is_string) => Ok(()),
+        Some(_) => Err(invalid("schema `type` must be a string or a non-empty list of strings")),
+        None => Err(invalid("schema has no `type`")),
+    }
+}
+
+/// Check an image URL before it is handed to the upstream, which fetches remote images:
+/// only `http`, `https` and `data:` URLs are accepted, and remote ones must point at one of
+/// `allowed_hosts` when a list is given.
+pub fn validate_image_url(url: &str, allowed_hosts: Option<&[String]>) -> Result<(), TransformError> {
+    let disallowed = |reason: &str| TransformError::DisallowedImageUrl(format!("{} ({})", url, reason));
+
+    // parse the way the upstream's fetcher will, so userinfo, backslashes and the like can't
+    // make the checked host differ from the one that is fetched
+    let parsed = Url::parse(url).map_err(|_| disallowed("not an absolute url"))?;
+    match parsed.scheme() {
+        "data" => return Ok(()),
+        "http" | "https" => {}
+        _ => return Err(disallowed("scheme must be http, https or data")),
+    }
+
+    let Some(allowed_hosts) = allowed_hosts else {
+        return Ok(());
+    };
+    let host = parsed.host_str().unwrap_or_default();
+    // IPv6 literals keep their brackets
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    if allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) {
+        Ok(())
+    } else {
+        Err(disallowed("host is not allowed"))
+    }
+}
+
 /// Convert OpenAI tools to Anthropic format
 fn convert_openai_tools(tools: Vec<Tool>) -> Vec<MessagesTool> {
     tools.into_iter()
@@ -781,16 +1332,14 @@ fn convert_anthropic_tool_choice(tool_choice: Option<MessagesToolChoice>) -> (Op
                 MessagesToolChoiceType::Auto => ToolChoice::Type(ToolChoiceType::Auto),
                 MessagesToolChoiceType::Any => ToolChoice::Type(ToolChoiceType::Required),
                 MessagesToolChoiceType::None => ToolChoice::Type(ToolChoiceType::None),
-                MessagesToolChoiceType::Tool => {
-                    if let Some(name) = choice.name {
-                        ToolChoice::Function {
-                            choice_type: "function".to_string(),
-                            function: FunctionChoice { name },
-                        }
-                    } else {
-                        ToolChoice::Type(ToolChoiceType::Auto)
-                    }
-                }
+                MessagesToolChoiceType::Tool => match choice.name {
+                    Some(name) => ToolChoice::Function {
+                        choice_type: "function".to_string(),
+                        function: FunctionChoice { name },
+                    },
+                    // a forced tool without a name still forces a tool call
+                    None => ToolChoice::Type(ToolChoiceType::Required),
+                },
             };
             let parallel = choice.disable_parallel_tool_use.map(|disable| !disable);
             (Some(openai_choice), parallel)
@@ -799,13 +1348,14 @@ fn convert_anthropic_tool_choice(tool_choice: Option<MessagesToolChoice>) -> (Op
     }
 }
 
-/// Convert OpenAI tool choice to Anthropic format
+/// Convert OpenAI tool choice to Anthropic format. Requiring a call to one of no allowed
+/// tools can't be satisfied, so it's rejected.
 fn convert_openai_tool_choice(
     tool_choice: Option<ToolChoice>,
     parallel_tool_calls: Option<bool>
-) -> Option<MessagesToolChoice> {
+) -> Result<Option<MessagesToolChoice>, TransformError> {
     tool_choice.map(|choice| {
-        match choice {
+        Ok(match choice {
             ToolChoice::Type(tool_type) => match tool_type {
                 ToolChoiceType::Auto => MessagesToolChoice {
                     kind: MessagesToolChoiceType::Auto,
@@ -820,7 +1370,7 @@ fn convert_openai_tool_choice(
                 ToolChoiceType::None => MessagesToolChoice {
                     kind: MessagesToolChoiceType::None,
                     name: None,
-                    disable_parallel_tool_use: None,
+                    disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
                 },
             },
             ToolChoice::Function { function, .. } => MessagesToolChoice {
@@ -828,12 +1378,41 @@ fn convert_openai_tool_choice(
                 name: Some(function.name),
                 disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
             },
-        }
+            // Anthropic has no allowed subset; the tool list itself is narrowed by the caller
+            ToolChoice::AllowedTools { allowed_tools, .. } => {
+                let (kind, name) = match (allowed_tools.mode, allowed_tools.tools.as_slice()) {
+                    (AllowedToolsMode::Required, []) => {
+                        return Err(TransformError::InvalidField {
+                            field: "tool_choice.allowed_tools.tools".to_string(),
+                            reason: "mode `required` needs at least one allowed tool".to_string(),
+                        });
+                    }
+                    (AllowedToolsMode::Required, [tool]) => {
+                        (MessagesToolChoiceType::Tool, Some(tool.function.name.clone()))
+                    }
+                    (AllowedToolsMode::Required, _) => (MessagesToolChoiceType::Any, None),
+                    (AllowedToolsMode::Auto, _) => (MessagesToolChoiceType::Auto, None),
+                };
+                MessagesToolChoice {
+                    kind,
+                    name,
+                    disable_parallel_tool_use: parallel_tool_calls.map(|p| !p),
+                }
+            }
+        })
     })
+    .transpose()
+}
+
+/// Empty and whitespace-only text never becomes a text block or part in either direction;
+/// Anthropic rejects such blocks and joining them leaves stray separators in OpenAI text.
+fn is_blank(text: &str) -> bool {
+    text.trim().is_empty()
 }
 
 /// Build OpenAI message content from parts and tool calls
 fn build_openai_content(content_parts: Vec<ContentPart>, tool_calls: &[ToolCall]) -> MessageContent {
+    let content_parts = merge_adjacent_text(content_parts);
     if content_parts.len() == 1 && tool_calls.is_empty() {
         match &content_parts[0] {
             ContentPart::Text { text } => MessageContent::Text(text.clone()),
@@ -846,17 +1425,55 @@ fn build_openai_content(content_parts: Vec<ContentPart>, tool_calls: &[ToolCall]
     }
 }
 
-/// Build Anthropic message content from content blocks
-fn build_anthropic_content(content_blocks: Vec<MessagesContentBlock>) -> MessagesMessageContent {
-    if content_blocks.len() == 1 {
-        match &content_blocks[0] {
-            MessagesContentBlock::Text { text } => MessagesMessageContent::Single(text.clone()),
-            _ => MessagesMessageContent::Blocks(content_blocks),
+/// Join runs of consecutive text parts into one, separated by newlines. Anthropic clients
+/// often split a prompt over several text blocks, which OpenAI clients expect as one text.
+fn merge_adjacent_text(content_parts: Vec<ContentPart>) -> Vec<ContentPart> {
+    let mut merged: Vec<ContentPart> = Vec::with_capacity(content_parts.len());
+    for part in content_parts {
+        match (merged.last_mut(), part) {
+            (Some(ContentPart::Text { text }), ContentPart::Text { text: next }) => {
+                text.push('\n');
+                text.push_str(&next);
+            }
+            (_, part) => merged.push(part),
         }
-    } else if content_blocks.is_empty() {
-        MessagesMessageContent::Single("".to_string())
-    } else {
-        MessagesMessageContent::Blocks(content_blocks)
+    }
+    merged
+}
+
+/// Normalize Anthropic message content to the shape Anthropic expects.
+///
+/// The string form is shorthand for exactly one text block, so only a lone text block (or no
+/// content at all) collapses to `Single`. Any other single block (an image, a tool use or a
+/// tool result) and every multi-block message are sent as `Blocks`.
+fn normalize_content(mut content_blocks: Vec<MessagesContentBlock>) -> MessagesMessageContent {
+    match content_blocks.as_mut_slice() {
+        [] => MessagesMessageContent::Single("".to_string()),
+        [MessagesContentBlock::Text { text, .. }] => MessagesMessageContent::Single(std::mem::take(text)),
+        _ => MessagesMessageContent::Blocks(content_blocks),
+    }
+}
+
+/// Anthropic requires user and assistant turns to alternate, while OpenAI accepts runs of
+/// messages with the same role (tool results also become user turns). Fold such a run into a
+/// single message, keeping its content in order.
+fn push_alternating(messages: &mut Vec<MessagesMessage>, message: MessagesMessage) {
+    match messages.last_mut() {
+        Some(last) if last.role == message.role => {
+            let previous = std::mem::replace(&mut last.content, MessagesMessageContent::Single(String::new()));
+            let mut content_blocks = into_content_blocks(previous);
+            content_blocks.extend(into_content_blocks(message.content));
+            last.content = normalize_content(content_blocks);
+        }
+        _ => messages.push(message),
+    }
+}
+
+fn into_content_blocks(content: MessagesMessageContent) -> Vec<MessagesContentBlock> {
+    match content {
+        MessagesMessageContent::Single(text) if is_blank(&text) => Vec::new(),
+        MessagesMessageContent::Single(text) => vec![MessagesContentBlock::Text { text, cache_control: None }],
+        MessagesMessageContent::Blocks(content_blocks) => content_blocks,
     }
 }
 
@@ -866,10 +1483,12 @@ fn convert_anthropic_content_to_openai(content: &[MessagesContentBlock]) -> Resu
 
     for block in content {
         match block {
-            MessagesContentBlock::Text { text } => {
-                text_parts.push(text.clone());
+            MessagesContentBlock::Text { text, .. } => {
+                if !is_blank(text) {
+                    text_parts.push(text.clone());
+                }
             }
-            MessagesContentBlock::Thinking { text } => {
+            MessagesContentBlock::Thinking { text } if !is_blank(text) => {
                 // Include thinking as regular text for OpenAI
                 text_parts.push(format!("[Thinking: {}]", text));
             }
@@ -890,15 +1509,17 @@ fn convert_openai_message_to_anthropic_content(message: &Message) -> Result<Vec<
     // Handle regular content
     match &message.content {
         MessageContent::Text(text) => {
-            if !text.is_empty() {
-                blocks.push(MessagesContentBlock::Text { text: text.clone() });
+            if !is_blank(text) {
+                blocks.push(MessagesContentBlock::Text { text: text.clone(), cache_control: None });
             }
         }
         MessageContent::Parts(parts) => {
             for part in parts {
                 match part {
                     ContentPart::Text { text } => {
-                        blocks.push(MessagesContentBlock::Text { text: text.clone() });
+                        if !is_blank(text) {
+                            blocks.push(MessagesContentBlock::Text { text: text.clone(), cache_control: None });
+                        }
                     }
                     ContentPart::ImageUrl { image_url } => {
                         let source = convert_image_url_to_source(image_url);
@@ -960,8 +1581,10 @@ fn convert_image_url_to_source(image_url: &ImageUrl) -> MessagesImageSource {
     }
 }
 
-/// Convert content block start to OpenAI chunk
-fn convert_content_block_start(content_block: MessagesContentBlock) -> Result<ChatCompletionsStreamResponse, TransformError> {
+/// Convert content block start to OpenAI chunk. A tool call is indexed by its content block's
+/// `index`, which keeps the calls of a message apart; [`ChatCompletionsStreamConverter`]
+/// renumbers them into 0-based `tool_calls` positions.
+fn convert_content_block_start(index: u32, content_block: MessagesContentBlock) -> Result<ChatCompletionsStreamResponse, TransformError> {
     match content_block {
         MessagesContentBlock::Text { .. } => {
             // No immediate output for text block start
@@ -980,7 +1603,7 @@ fn convert_content_block_start(content_block: MessagesContentBlock) -> Result<Ch
                     refusal: None,
                     function_call: None,
                     tool_calls: Some(vec![ToolCallDelta {
-                        index: 0,
+                        index,
                         id: Some(id),
                         call_type: Some("function".to_string()),
                         function: Some(FunctionCallDelta {
@@ -997,8 +1620,8 @@ fn convert_content_block_start(content_block: MessagesContentBlock) -> Result<Ch
     }
 }
 
-/// Convert content delta to OpenAI chunk
-fn convert_content_delta(delta: MessagesContentDelta) -> Result<ChatCompletionsStreamResponse, TransformError> {
+/// Convert content delta to OpenAI chunk, see [`convert_content_block_start`] for `index`
+fn convert_content_delta(index: u32, delta: MessagesContentDelta) -> Result<ChatCompletionsStreamResponse, TransformError> {
     match delta {
         MessagesContentDelta::TextDelta { text } => {
             Ok(create_openai_chunk(
@@ -1025,7 +1648,7 @@ fn convert_content_delta(delta: MessagesContentDelta) -> Result<ChatCompletionsS
                     refusal: None,
                     function_call: None,
                     tool_calls: Some(vec![ToolCallDelta {
-                        index: 0,
+                        index,
                         id: None,
                         call_type: None,
                         function: Some(FunctionCallDelta {
@@ -1119,6 +1742,25 @@ mod tests {
         assert_eq!(openai_req.stop, Some(vec!["STOP".to_string()]));
     }
 
+    #[test]
+    fn test_truncation_survives_stop_reason_mapping() {
+        let stop_reasons = [
+            MessagesStopReason::EndTurn,
+            MessagesStopReason::MaxTokens,
+            MessagesStopReason::StopSequence,
+            MessagesStopReason::ToolUse,
+            MessagesStopReason::PauseTurn,
+            MessagesStopReason::Refusal,
+        ];
+        for stop_reason in stop_reasons {
+            let finish_reason = FinishReason::from(stop_reason.clone());
+            assert_eq!(finish_reason.is_truncated(), stop_reason.is_truncated());
+            assert_eq!(MessagesStopReason::from(finish_reason).is_truncated(), stop_reason.is_truncated());
+        }
+        assert!(FinishReason::Length.is_truncated());
+        assert!(!FinishReason::FunctionCall.is_truncated());
+    }
+
     #[test]
     fn test_roundtrip_consistency() {
         // Test that converting back and forth maintains consistency
@@ -1157,6 +1799,27 @@ mod tests {
         assert_eq!(original_anthropic.messages.len(), roundtrip_anthropic.messages.len());
     }
 
+    #[test]
+    fn test_anthropic_mcp_servers_to_openai() {
+        let anthropic_req: AnthropicMessagesRequest = serde_json::from_value(json!({
+            "model": "claude-3-sonnet-20240229",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "Hello"}],
+            "mcp_servers": [{"name": "search", "type": "url", "url": "https://example.com/mcp"}]
+        })).unwrap();
+
+        let result: Result<ChatCompletionsRequest, TransformError> = anthropic_req.clone().try_into();
+        assert!(matches!(result, Err(TransformError::UnsupportedConversion(msg)) if msg.contains("mcp_servers")));
+
+        // An empty list carries no configuration and is dropped
+        let empty_mcp_req = AnthropicMessagesRequest {
+            mcp_servers: Some(vec![]),
+            ..anthropic_req
+        };
+        let openai_req: ChatCompletionsRequest = empty_mcp_req.try_into().unwrap();
+        assert_eq!(openai_req.messages.len(), 1);
+    }
+
     #[test]
     fn test_tool_choice_auto() {
         let anthropic_req = AnthropicMessagesRequest {
@@ -1201,24 +1864,612 @@ mod tests {
     }
 
     #[test]
-    fn test_default_max_tokens_used_when_openai_has_none() {
-        // Test that DEFAULT_MAX_TOKENS is used when OpenAI request has no max_tokens
-        let openai_req = ChatCompletionsRequest {
-            model: "gpt-4".to_string(),
-            messages: vec![Message {
-                role: Role::User,
-                content: MessageContent::Text("Hello".to_string()),
-                name: None,
-                tool_calls: None,
-                tool_call_id: None,
-            }],
-            max_tokens: None, // No max_tokens specified
-            ..Default::default()
+    fn test_tool_choice_conversion_table() {
+        let function = |name: &str| ToolChoice::Function {
+            choice_type: "function".to_string(),
+            function: FunctionChoice { name: name.to_string() },
+        };
+        let allowed = |mode: AllowedToolsMode, names: &[&str]| ToolChoice::AllowedTools {
+            choice_type: "allowed_tools".to_string(),
+            allowed_tools: AllowedTools {
+                mode,
+                tools: names
+                    .iter()
+                    .map(|name| AllowedTool {
+                        tool_type: "function".to_string(),
+                        function: FunctionChoice { name: name.to_string() },
+                    })
+                    .collect(),
+            },
         };
 
-        let anthropic_req: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+        // (openai choice, parallel_tool_calls, anthropic kind, anthropic name, openai choice after round trip)
+        let cases = vec![
+            (ToolChoice::Type(ToolChoiceType::Auto), None, MessagesToolChoiceType::Auto, None, ToolChoice::Type(ToolChoiceType::Auto)),
+            (ToolChoice::Type(ToolChoiceType::Required), Some(false), MessagesToolChoiceType::Any, None, ToolChoice::Type(ToolChoiceType::Required)),
+            (ToolChoice::Type(ToolChoiceType::None), Some(true), MessagesToolChoiceType::None, None, ToolChoice::Type(ToolChoiceType::None)),
+            (function("get_weather"), Some(false), MessagesToolChoiceType::Tool, Some("get_weather"), function("get_weather")),
+            (allowed(AllowedToolsMode::Required, &["get_weather"]), None, MessagesToolChoiceType::Tool, Some("get_weather"), function("get_weather")),
+            (allowed(AllowedToolsMode::Required, &["get_weather", "get_time"]), None, MessagesToolChoiceType::Any, None, ToolChoice::Type(ToolChoiceType::Required)),
+            (allowed(AllowedToolsMode::Auto, &["get_weather"]), None, MessagesToolChoiceType::Auto, None, ToolChoice::Type(ToolChoiceType::Auto)),
+        ];
 
-        assert_eq!(anthropic_req.max_tokens, DEFAULT_MAX_TOKENS);
+        for (openai_choice, parallel, expected_kind, expected_name, expected_round_trip) in cases {
+            let anthropic_choice = convert_openai_tool_choice(Some(openai_choice.clone()), parallel).unwrap().unwrap();
+            assert_eq!(anthropic_choice.kind, expected_kind, "{:?}", openai_choice);
+            assert_eq!(anthropic_choice.name.as_deref(), expected_name, "{:?}", openai_choice);
+            assert_eq!(anthropic_choice.disable_parallel_tool_use, parallel.map(|p| !p));
+
+            let (round_trip, round_trip_parallel) = convert_anthropic_tool_choice(Some(anthropic_choice));
+            assert_eq!(round_trip, Some(expected_round_trip), "{:?}", openai_choice);
+            assert_eq!(round_trip_parallel, parallel);
+        }
+
+        // Anthropic forcing a tool without naming it still requires a tool call
+        let (unnamed, _) = convert_anthropic_tool_choice(Some(MessagesToolChoice {
+            kind: MessagesToolChoiceType::Tool,
+            name: None,
+            disable_parallel_tool_use: None,
+        }));
+        assert_eq!(unnamed, Some(ToolChoice::Type(ToolChoiceType::Required)));
+
+        // no tool can satisfy a required call among no allowed tools
+        assert!(matches!(
+            convert_openai_tool_choice(Some(allowed(AllowedToolsMode::Required, &[])), None),
+            Err(TransformError::InvalidField { .. })
+        ));
+        assert!(convert_openai_tool_choice(Some(allowed(AllowedToolsMode::Auto, &[])), None).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_tools_narrow_anthropic_tools() {
+        let tool = |name: &str| Tool {
+            tool_type: "function".to_string(),
+            function: Function {
+                name: name.to_string(),
+                description: None,
+                parameters: json!({"type": "object"}),
+                strict: None,
+            },
+        };
+        let openai_req = ChatCompletionsRequest {
+            model: "claude-3-5-sonnet".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("What's the weather?".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            tools: Some(vec![tool("get_weather"), tool("get_time"), tool("get_news")]),
+            tool_choice: Some(ToolChoice::AllowedTools {
+                choice_type: "allowed_tools".to_string(),
+                allowed_tools: AllowedTools {
+                    mode: AllowedToolsMode::Auto,
+                    tools: vec![
+                        AllowedTool {
+                            tool_type: "function".to_string(),
+                            function: FunctionChoice { name: "get_weather".to_string() },
+                        },
+                        AllowedTool {
+                            tool_type: "function".to_string(),
+                            function: FunctionChoice { name: "get_time".to_string() },
+                        },
+                    ],
+                },
+            }),
+            ..Default::default()
+        };
+
+        let anthropic_req: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+        let names: Vec<String> = anthropic_req.tools.unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["get_weather", "get_time"]);
+        assert_eq!(anthropic_req.tool_choice.unwrap().kind, MessagesToolChoiceType::Auto);
+    }
+
+    #[test]
+    fn test_openai_empty_text_not_sent_to_anthropic() {
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{\"city\":\"Paris\"}".to_string(),
+            },
+        };
+        let text = |text: &str| ContentPart::Text { text: text.to_string() };
+
+        for content in [
+            MessageContent::Text("".to_string()),
+            MessageContent::Text("  \n".to_string()),
+            MessageContent::Parts(vec![text(""), text("Checking the weather"), text(" ")]),
+        ] {
+            let message = Message {
+                role: Role::Assistant,
+                content,
+                name: None,
+                tool_calls: Some(vec![tool_call.clone()]),
+                tool_call_id: None,
+            };
+            let blocks = convert_openai_message_to_anthropic_content(&message).unwrap();
+
+            assert!(blocks.iter().all(|block| match block {
+                MessagesContentBlock::Text { text, .. } => !text.trim().is_empty(),
+                _ => true,
+            }));
+            assert!(matches!(blocks.last(), Some(MessagesContentBlock::ToolUse { .. })));
+        }
+    }
+
+    #[test]
+    fn test_anthropic_empty_text_not_sent_to_openai() {
+        let text = |text: &str| MessagesContentBlock::Text { text: text.to_string(), cache_control: None };
+        let tool_use = MessagesContentBlock::ToolUse {
+            id: "toolu_1".to_string(),
+            name: "get_weather".to_string(),
+            input: json!({"city": "Paris"}),
+        };
+        let response = |content: Vec<MessagesContentBlock>| MessagesResponse {
+            id: "msg_1".to_string(),
+            obj_type: "message".to_string(),
+            role: MessagesRole::Assistant,
+            content,
+            model: "claude-3-5-sonnet".to_string(),
+            stop_reason: MessagesStopReason::ToolUse,
+            stop_sequence: None,
+            usage: MessagesUsage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            },
+            container: None,
+        };
+
+        // Empty segments around tool use don't leave stray newlines in the joined text
+        let openai_resp: ChatCompletionsResponse =
+            response(vec![text(""), tool_use.clone(), text("  "), text("Done")]).try_into().unwrap();
+        let message = &openai_resp.choices[0].message;
+        assert_eq!(message.content, Some("Done".to_string()));
+        assert_eq!(message.tool_calls.as_ref().unwrap().len(), 1);
+
+        // Only blank text: no content at all
+        let openai_resp: ChatCompletionsResponse =
+            response(vec![text(""), tool_use.clone(), text("\n")]).try_into().unwrap();
+        assert_eq!(openai_resp.choices[0].message.content, None);
+
+        // Request messages: blank blocks never become content parts
+        let anthropic_message = MessagesMessage {
+            role: MessagesRole::Assistant,
+            content: MessagesMessageContent::Blocks(vec![text(""), tool_use, text(" "), text("Let me check")]),
+        };
+        let openai_messages: Vec<Message> = anthropic_message.try_into().unwrap();
+        assert_eq!(openai_messages.len(), 1);
+        assert_eq!(openai_messages[0].content.extract_text(), "Let me check");
+        if let MessageContent::Parts(parts) = &openai_messages[0].content {
+            assert_eq!(parts.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_code_execution_result_round_trip() {
+        let text = |text: &str| MessagesContentBlock::Text { text: text.to_string(), cache_control: None };
+        let anthropic_message = MessagesMessage {
+            role: MessagesRole::User,
+            content: MessagesMessageContent::Blocks(vec![
+                MessagesContentBlock::CodeExecutionToolResult {
+                    tool_use_id: "srvtoolu_1".to_string(),
+                    is_error: None,
+                    content: vec![text("4")],
+                },
+                MessagesContentBlock::ToolResult {
+                    tool_use_id: "toolu_2".to_string(),
+                    is_error: None,
+                    content: vec![text("Sunny")],
+                },
+            ]),
+        };
+
+        let openai_messages: Vec<Message> = anthropic_message.try_into().unwrap();
+        let tool_messages: Vec<&Message> = openai_messages.iter().filter(|m| m.role == Role::Tool).collect();
+        assert_eq!(tool_messages.len(), 2);
+        assert_eq!(tool_messages[0].name.as_deref(), Some(CODE_EXECUTION_TOOL_NAME));
+        assert_eq!(tool_messages[1].name, None);
+
+        let blocks: Vec<MessagesContentBlock> = tool_messages
+            .into_iter()
+            .map(|message| {
+                let converted: MessagesMessage = message.clone().try_into().unwrap();
+                match converted.content {
+                    MessagesMessageContent::Blocks(mut blocks) => blocks.remove(0),
+                    MessagesMessageContent::Single(_) => panic!("Expected blocks"),
+                }
+            })
+            .collect();
+        assert!(matches!(
+            &blocks[0],
+            MessagesContentBlock::CodeExecutionToolResult { tool_use_id, content, .. }
+                if tool_use_id == "srvtoolu_1" && content.extract_text() == "4"
+        ));
+        assert!(matches!(
+            &blocks[1],
+            MessagesContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == "toolu_2"
+        ));
+    }
+
+    #[test]
+    fn test_user_tool_named_code_execution_stays_a_tool_result() {
+        let message = Message {
+            role: Role::Tool,
+            content: MessageContent::Text("4".to_string()),
+            name: Some(CODE_EXECUTION_TOOL_NAME.to_string()),
+            tool_calls: None,
+            tool_call_id: Some("call_1".to_string()),
+        };
+
+        let converted: MessagesMessage = message.try_into().unwrap();
+        match converted.content {
+            MessagesMessageContent::Blocks(blocks) => assert!(matches!(
+                &blocks[0],
+                MessagesContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == "call_1"
+            )),
+            MessagesMessageContent::Single(_) => panic!("Expected blocks"),
+        }
+    }
+
+    #[test]
+    fn test_normalize_content() {
+        let text = |text: &str| MessagesContentBlock::Text { text: text.to_string(), cache_control: None };
+        let image = MessagesContentBlock::Image {
+            source: MessagesImageSource::Url { url: "https://example.com/cat.png".to_string() },
+        };
+        let tool_use = MessagesContentBlock::ToolUse {
+            id: "toolu_1".to_string(),
+            name: "get_weather".to_string(),
+            input: json!({"city": "Paris"}),
+        };
+        let tool_result = MessagesContentBlock::ToolResult {
+            tool_use_id: "toolu_1".to_string(),
+            is_error: None,
+            content: vec![text("Sunny")],
+        };
+
+        // Only a lone text block uses the string shorthand
+        assert!(matches!(normalize_content(vec![text("Hello")]), MessagesMessageContent::Single(t) if t == "Hello"));
+        assert!(matches!(normalize_content(vec![]), MessagesMessageContent::Single(t) if t.is_empty()));
+
+        // Every other single block keeps its block form
+        for block in [image.clone(), tool_use.clone(), tool_result] {
+            match normalize_content(vec![block.clone()]) {
+                MessagesMessageContent::Blocks(blocks) => assert_eq!(blocks.len(), 1),
+                MessagesMessageContent::Single(_) => panic!("{:?} must not collapse to a string", block),
+            }
+        }
+
+        // Multiple blocks, even all text, stay as blocks in order
+        match normalize_content(vec![text("Look at this"), image, text("and this")]) {
+            MessagesMessageContent::Blocks(blocks) => {
+                assert_eq!(blocks.len(), 3);
+                assert!(matches!(&blocks[1], MessagesContentBlock::Image { .. }));
+            }
+            MessagesMessageContent::Single(_) => panic!("Expected blocks"),
+        }
+        match normalize_content(vec![text("Let me check"), tool_use]) {
+            MessagesMessageContent::Blocks(blocks) => assert_eq!(blocks.len(), 2),
+            MessagesMessageContent::Single(_) => panic!("Expected blocks"),
+        }
+    }
+
+    #[test]
+    fn test_interleaved_images_keep_order() {
+        let anthropic_req: AnthropicMessagesRequest = serde_json::from_value(json!({
+            "model": "claude-3-sonnet-20240229",
+            "max_tokens": 1024,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Compare this chart"},
+                    {"type": "image", "source": {"base64": {"media_type": "image/png", "data": "iVBORw0KGgo="}}},
+                    {"type": "text", "text": "with this one"},
+                    {"type": "image", "source": {"url": {"url": "https://example.com/chart.png"}}}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let openai_req: ChatCompletionsRequest = anthropic_req.clone().try_into().unwrap();
+        assert_eq!(openai_req.messages.len(), 1);
+        let parts = match &openai_req.messages[0].content {
+            MessageContent::Parts(parts) => parts,
+            other => panic!("Expected parts, got {:?}", other),
+        };
+        assert_eq!(parts.len(), 4);
+        assert!(matches!(&parts[0], ContentPart::Text { text } if text == "Compare this chart"));
+        assert!(matches!(&parts[1], ContentPart::ImageUrl { image_url } if image_url.url == "data:image/png;base64,iVBORw0KGgo="));
+        assert!(matches!(&parts[2], ContentPart::Text { text } if text == "with this one"));
+        assert!(matches!(&parts[3], ContentPart::ImageUrl { image_url } if image_url.url == "https://example.com/chart.png"));
+
+        let roundtrip: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+        assert_eq!(
+            serde_json::to_value(&roundtrip.messages).unwrap(),
+            serde_json::to_value(&anthropic_req.messages).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_adjacent_text_blocks_are_merged() {
+        let anthropic_req: AnthropicMessagesRequest = serde_json::from_value(json!({
+            "model": "claude-3-sonnet-20240229",
+            "max_tokens": 1024,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Here is the report."},
+                    {"type": "text", "text": "Summarize it."},
+                    {"type": "text", "text": "Keep it short."}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let openai_req: ChatCompletionsRequest = anthropic_req.try_into().unwrap();
+        assert_eq!(openai_req.messages.len(), 1);
+        assert!(matches!(
+            &openai_req.messages[0].content,
+            MessageContent::Text(text) if text == "Here is the report.\nSummarize it.\nKeep it short."
+        ));
+
+        // text on either side of an image is merged separately, keeping the order
+        let parts = merge_adjacent_text(vec![
+            ContentPart::Text { text: "a".to_string() },
+            ContentPart::Text { text: "b".to_string() },
+            ContentPart::ImageUrl { image_url: ImageUrl { url: "https://example.com/cat.png".to_string(), detail: None } },
+            ContentPart::Text { text: "c".to_string() },
+        ]);
+        assert_eq!(parts.len(), 3);
+        assert!(matches!(&parts[0], ContentPart::Text { text } if text == "a\nb"));
+        assert!(matches!(&parts[2], ContentPart::Text { text } if text == "c"));
+    }
+
+    #[test]
+    fn test_default_max_tokens_used_when_openai_has_none() {
+        // Test that DEFAULT_MAX_TOKENS is used when OpenAI request has no max_tokens
+        let openai_req = ChatCompletionsRequest {
+            model: "gpt-4".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("Hello".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            max_tokens: None, // No max_tokens specified
+            ..Default::default()
+        };
+
+        let anthropic_req: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+
+        assert_eq!(anthropic_req.max_tokens, DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_custom_default_max_tokens() {
+        let build_request = |model: &str, max_tokens: Option<u32>| ChatCompletionsRequest {
+            model: model.to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("Hello".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            max_tokens,
+            ..Default::default()
+        };
+        let options = ConversionOptions { default_max_tokens: 16000, ..Default::default() };
+
+        // The deployment default replaces 4096 when max_tokens is missing
+        let anthropic_req =
+            convert_chat_completions_request(build_request("claude-sonnet-4-20250514", None), &options).unwrap();
+        assert_eq!(anthropic_req.max_tokens, 16000);
+
+        // An explicit max_tokens still wins
+        let anthropic_req =
+            convert_chat_completions_request(build_request("claude-sonnet-4-20250514", Some(1000)), &options).unwrap();
+        assert_eq!(anthropic_req.max_tokens, 1000);
+
+        // The custom default is still clamped to the model ceiling
+        let anthropic_req =
+            convert_chat_completions_request(build_request("claude-3-5-haiku-20241022", None), &options).unwrap();
+        assert_eq!(anthropic_req.max_tokens, 8192);
+
+        assert_eq!(ConversionOptions::default().default_max_tokens, DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_tool_schema_validation() {
+        let build_request = |parameters: Value| ChatCompletionsRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("What's the weather?".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            tools: Some(vec![Tool {
+                tool_type: "function".to_string(),
+                function: Function {
+                    name: "get_weather".to_string(),
+                    description: None,
+                    parameters,
+                    strict: None,
+                },
+            }]),
+            ..Default::default()
+        };
+        let object_schema = json!({"type": "object", "properties": {"city": {"type": "string"}}});
+        let strict = ConversionOptions { validate_tool_schemas: true, ..Default::default() };
+
+        let anthropic_req = convert_chat_completions_request(build_request(object_schema.clone()), &strict).unwrap();
+        assert_eq!(anthropic_req.tools.unwrap()[0].input_schema, object_schema);
+
+        let result = convert_chat_completions_request(build_request(json!("object")), &strict);
+        assert!(matches!(
+            result,
+            Err(TransformError::InvalidField { field, reason })
+                if field == "tools.get_weather.parameters" && reason == "schema must be a JSON object"
+        ));
+        let result = convert_chat_completions_request(build_request(json!({"properties": {}})), &strict);
+        assert!(matches!(
+            result,
+            Err(TransformError::InvalidField { field, reason })
+                if field == "tools.get_weather.parameters" && reason == "schema has no `type`"
+        ));
+
+        // Without the flag the schema is passed through untouched
+        let anthropic_req =
+            convert_chat_completions_request(build_request(json!("object")), &ConversionOptions::default()).unwrap();
+        assert_eq!(anthropic_req.tools.unwrap()[0].input_schema, json!("object"));
+    }
+
+    #[test]
+    fn test_image_url_validation() {
+        let build_request = |url: &str| ChatCompletionsRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Parts(vec![ContentPart::ImageUrl {
+                    image_url: ImageUrl { url: url.to_string(), detail: None },
+                }]),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            ..Default::default()
+        };
+        let options = ConversionOptions::default();
+
+        let result = convert_chat_completions_request(build_request("file:///etc/passwd"), &options);
+        assert!(matches!(result, Err(TransformError::DisallowedImageUrl(_))));
+        let result = convert_chat_completions_request(build_request("gopher://example.com/cat.png"), &options);
+        assert!(matches!(result, Err(TransformError::DisallowedImageUrl(_))));
+
+        assert!(convert_chat_completions_request(build_request("https://169.254.169.254/latest"), &options).is_ok());
+        assert!(convert_chat_completions_request(build_request("data:image/png;base64,iVBORw0K"), &options).is_ok());
+
+        let allowlisted = ConversionOptions {
+            image_hosts: Some(vec!["images.example.com".to_string()]),
+            ..Default::default()
+        };
+        let result = convert_chat_completions_request(build_request("https://169.254.169.254/latest"), &allowlisted);
+        assert!(matches!(result, Err(TransformError::DisallowedImageUrl(_))));
+        // userinfo can't be used to smuggle in an allowed host name
+        let result =
+            convert_chat_completions_request(build_request("https://images.example.com@10.0.0.1/a.png"), &allowlisted);
+        assert!(matches!(result, Err(TransformError::DisallowedImageUrl(_))));
+        // a backslash ends the host for fetchers, so the allowed name is only userinfo here
+        let result =
+            convert_chat_completions_request(build_request("https://10.0.0.1\\@images.example.com/a.png"), &allowlisted);
+        assert!(matches!(result, Err(TransformError::DisallowedImageUrl(_))));
+        assert!(convert_chat_completions_request(build_request("https://Images.Example.com:443/a.png"), &allowlisted).is_ok());
+        assert!(convert_chat_completions_request(build_request("data:image/png;base64,iVBORw0K"), &allowlisted).is_ok());
+    }
+
+    #[test]
+    fn test_tool_message_without_tool_call_id() {
+        let message = |role: Role, content: &str, tool_calls: Option<Vec<ToolCall>>, tool_call_id: Option<&str>| Message {
+            role,
+            content: MessageContent::Text(content.to_string()),
+            name: None,
+            tool_calls,
+            tool_call_id: tool_call_id.map(str::to_string),
+        };
+        let tool_call = |id: &str| ToolCall {
+            id: id.to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall { name: "get_weather".to_string(), arguments: "{}".to_string() },
+        };
+        let request = ChatCompletionsRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            messages: vec![
+                message(Role::User, "Weather in Paris and Rome?", None, None),
+                message(Role::Assistant, "", Some(vec![tool_call("call_paris"), tool_call("call_rome")]), None),
+                message(Role::Tool, "Sunny", None, Some("call_paris")),
+                message(Role::Tool, "Rainy", None, None),
+            ],
+            ..Default::default()
+        };
+
+        let anthropic_req = convert_chat_completions_request(request.clone(), &ConversionOptions::default()).unwrap();
+        // Both results end up in a single user turn, the orphan paired with the open call
+        let MessagesMessageContent::Blocks(blocks) = &anthropic_req.messages[2].content else {
+            panic!("expected tool result blocks");
+        };
+        let tool_use_ids: Vec<&str> = blocks
+            .iter()
+            .filter_map(|block| match block {
+                MessagesContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tool_use_ids, vec!["call_paris", "call_rome"]);
+
+        let strict = ConversionOptions { strict_tool_call_ids: true, ..Default::default() };
+        let result = convert_chat_completions_request(request.clone(), &strict);
+        assert!(matches!(result, Err(TransformError::MissingField(_))));
+
+        // Nothing left to answer: still an error
+        let mut request = request;
+        request.messages[2].tool_call_id = None;
+        request.messages.push(message(Role::Tool, "Windy", None, None));
+        let result = convert_chat_completions_request(request, &ConversionOptions::default());
+        assert!(matches!(result, Err(TransformError::MissingField(_))));
+    }
+
+    #[test]
+    fn test_max_tokens_clamped_to_model_limit() {
+        let build_request = |model: &str, max_tokens: Option<u32>| ChatCompletionsRequest {
+            model: model.to_string(),
+            messages: vec![Message {
+                role: Role::User,
+                content: MessageContent::Text("Hello".to_string()),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            max_tokens,
+            ..Default::default()
+        };
+
+        // Limit above the default: requests beyond the ceiling are clamped, the default is kept
+        let anthropic_req: AnthropicMessagesRequest =
+            build_request("claude-3-5-sonnet-20241022", Some(20000)).try_into().unwrap();
+        assert_eq!(anthropic_req.max_tokens, 8192);
+
+        let anthropic_req: AnthropicMessagesRequest =
+            build_request("claude-3-5-sonnet-20241022", None).try_into().unwrap();
+        assert_eq!(anthropic_req.max_tokens, DEFAULT_MAX_TOKENS);
+
+        // Models with a higher ceiling keep larger explicit requests
+        let anthropic_req: AnthropicMessagesRequest =
+            build_request("claude-sonnet-4-20250514", Some(20000)).try_into().unwrap();
+        assert_eq!(anthropic_req.max_tokens, 20000);
+
+        // Limit at the default: explicit requests above it are clamped
+        let anthropic_req: AnthropicMessagesRequest =
+            build_request("claude-3-haiku-20240307", Some(8000)).try_into().unwrap();
+        assert_eq!(anthropic_req.max_tokens, 4096);
+    }
+
+    #[test]
+    fn test_clamp_max_tokens_below_default() {
+        assert_eq!(clamp_max_tokens(DEFAULT_MAX_TOKENS, Some(2048)), 2048);
+        assert_eq!(clamp_max_tokens(1024, Some(2048)), 1024);
+        assert_eq!(clamp_max_tokens(32000, Some(16384)), 16384);
+        assert_eq!(clamp_max_tokens(DEFAULT_MAX_TOKENS, Some(16384)), DEFAULT_MAX_TOKENS);
+        // Unknown models are not clamped
+        assert_eq!(clamp_max_tokens(100000, None), 100000);
     }
 
     #[test]
@@ -1300,6 +2551,58 @@ mod tests {
         assert_eq!(tool_calls[0].function.as_ref().unwrap().name, Some("get_weather".to_string()));
     }
 
+    #[test]
+    fn test_anthropic_tool_uses_after_text_are_indexed_from_zero() {
+        let tool_use = |index: u32, id: &str, name: &str| MessagesStreamEvent::ContentBlockStart {
+            index,
+            content_block: MessagesContentBlock::ToolUse { id: id.to_string(), name: name.to_string(), input: json!({}) },
+        };
+        let input = |index: u32, partial_json: &str| MessagesStreamEvent::ContentBlockDelta {
+            index,
+            delta: MessagesContentDelta::InputJsonDelta { partial_json: partial_json.to_string() },
+        };
+        let events = vec![
+            MessagesStreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: MessagesContentBlock::Text { text: String::new(), cache_control: None },
+            },
+            MessagesStreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: MessagesContentDelta::TextDelta { text: "Checking both.".to_string() },
+            },
+            MessagesStreamEvent::ContentBlockStop { index: 0 },
+            tool_use(1, "toolu_1", "get_weather"),
+            input(1, "{\"city\": \"Paris\"}"),
+            MessagesStreamEvent::ContentBlockStop { index: 1 },
+            tool_use(2, "toolu_2", "get_time"),
+            input(2, "{\"tz\": \"CET\"}"),
+            MessagesStreamEvent::ContentBlockStop { index: 2 },
+        ];
+
+        // (index, id, arguments) of every tool call delta, in stream order
+        let mut converter = ChatCompletionsStreamConverter::new();
+        let mut tool_call_deltas = Vec::new();
+        for event in events {
+            let chunk = converter.transform(event).unwrap();
+            for choice in chunk.choices {
+                for tool_call in choice.delta.tool_calls.into_iter().flatten() {
+                    let arguments = tool_call.function.and_then(|function| function.arguments);
+                    tool_call_deltas.push((tool_call.index, tool_call.id, arguments));
+                }
+            }
+        }
+
+        assert_eq!(
+            tool_call_deltas,
+            vec![
+                (0, Some("toolu_1".to_string()), Some(String::new())),
+                (0, None, Some("{\"city\": \"Paris\"}".to_string())),
+                (1, Some("toolu_2".to_string()), Some(String::new())),
+                (1, None, Some("{\"tz\": \"CET\"}".to_string())),
+            ]
+        );
+    }
+
     #[test]
     fn test_anthropic_tool_input_delta_streaming() {
         let event = MessagesStreamEvent::ContentBlockDelta {
@@ -1327,12 +2630,12 @@ mod tests {
                 stop_reason: MessagesStopReason::EndTurn,
                 stop_sequence: None,
             },
-            usage: MessagesUsage {
+            usage: Some(MessagesUsage {
                 input_tokens: 10,
                 output_tokens: 25,
                 cache_creation_input_tokens: None,
                 cache_read_input_tokens: None,
-            },
+            }),
         };
 
         let openai_resp: ChatCompletionsStreamResponse = event.try_into().unwrap();
@@ -1387,6 +2690,7 @@ mod tests {
                 },
                 finish_reason: None,
                 logprobs: None,
+                usage: None,
             }],
             usage: None,
             system_fingerprint: None,
@@ -1423,6 +2727,7 @@ mod tests {
                 },
                 finish_reason: None,
                 logprobs: None,
+                usage: None,
             }],
             usage: None,
             system_fingerprint: None,
@@ -1471,6 +2776,7 @@ mod tests {
                 },
                 finish_reason: None,
                 logprobs: None,
+                usage: None,
             }],
             usage: None,
             system_fingerprint: None,
@@ -1512,6 +2818,7 @@ mod tests {
                 },
                 finish_reason: Some(FinishReason::Stop),
                 logprobs: None,
+                usage: None,
             }],
             usage: Some(Usage {
                 prompt_tokens: 15,
@@ -1527,7 +2834,7 @@ mod tests {
         let anthropic_event: MessagesStreamEvent = openai_resp.try_into().unwrap();
 
         match anthropic_event {
-            MessagesStreamEvent::MessageDelta { delta, usage } => {
+            MessagesStreamEvent::MessageDelta { delta, usage: Some(usage) } => {
                 assert_eq!(delta.stop_reason, MessagesStopReason::EndTurn);
                 assert_eq!(usage.input_tokens, 15);
                 assert_eq!(usage.output_tokens, 30);
@@ -1536,6 +2843,214 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_openai_refusal_stream_to_anthropic() {
+        let chunk = |refusal: Option<&str>, finish_reason: Option<FinishReason>| create_openai_chunk(
+            "chatcmpl-123",
+            "gpt-4o",
+            MessageDelta {
+                role: None,
+                content: None,
+                refusal: refusal.map(String::from),
+                function_call: None,
+                tool_calls: None,
+            },
+            finish_reason,
+            None,
+        );
+
+        // Stateless: the refusal text becomes a text delta
+        let event: MessagesStreamEvent = chunk(Some("I can't help with that."), None).try_into().unwrap();
+        match event {
+            MessagesStreamEvent::ContentBlockDelta { delta: MessagesContentDelta::TextDelta { text }, .. } => {
+                assert_eq!(text, "I can't help with that.");
+            }
+            _ => panic!("Expected text delta for refusal"),
+        }
+
+        // Stateful: the stream terminates with a refusal stop reason
+        let mut accumulator = StreamUsageAccumulator::new();
+        let mut events = Vec::new();
+        for source_chunk in [chunk(Some("I can't"), None), chunk(Some(" help with that."), None), chunk(None, Some(FinishReason::Stop))] {
+            events.extend(accumulator.transform(source_chunk).unwrap());
+        }
+
+        assert_eq!(events.len(), 4);
+        match &events[2] {
+            MessagesStreamEvent::MessageDelta { delta, usage: Some(usage) } => {
+                assert_eq!(delta.stop_reason, MessagesStopReason::Refusal);
+                assert!(usage.output_tokens > 0);
+            }
+            _ => panic!("Expected MessageDelta event"),
+        }
+        assert!(matches!(events[3], MessagesStreamEvent::MessageStop));
+    }
+
+    #[test]
+    fn test_anthropic_refusal_stream_to_openai() {
+        let event = MessagesStreamEvent::MessageDelta {
+            delta: MessagesMessageDelta {
+                stop_reason: MessagesStopReason::Refusal,
+                stop_sequence: None,
+            },
+            usage: Some(MessagesUsage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            }),
+        };
+
+        let chunk: ChatCompletionsStreamResponse = event.try_into().unwrap();
+        assert_eq!(chunk.choices[0].finish_reason, Some(FinishReason::ContentFilter));
+    }
+
+    #[test]
+    fn test_stream_metadata_survives_round_trip() {
+        let chunk = |role: Option<Role>, content: Option<&str>, finish_reason: Option<FinishReason>| {
+            let mut chunk = create_openai_chunk(
+                "chatcmpl-123",
+                "gpt-4o",
+                MessageDelta {
+                    role,
+                    content: content.map(String::from),
+                    refusal: None,
+                    function_call: None,
+                    tool_calls: None,
+                },
+                finish_reason,
+                None,
+            );
+            chunk.system_fingerprint = Some("fp_abc123".to_string());
+            chunk.service_tier = Some("default".to_string());
+            chunk
+        };
+        let source = vec![
+            chunk(Some(Role::Assistant), None, None),
+            chunk(None, Some("Hello"), None),
+            chunk(None, None, Some(FinishReason::Stop)),
+        ];
+
+        let mut accumulator = StreamUsageAccumulator::new();
+        let mut events = Vec::new();
+        for source_chunk in source {
+            events.extend(accumulator.transform(source_chunk).unwrap());
+        }
+        assert_eq!(accumulator.system_fingerprint(), Some("fp_abc123"));
+        assert_eq!(accumulator.service_tier(), Some("default"));
+
+        let mut converter = ChatCompletionsStreamConverter::new().with_source_metadata(&accumulator);
+        let converted: Vec<ChatCompletionsStreamResponse> =
+            events.into_iter().map(|event| converter.transform(event).unwrap()).collect();
+
+        assert_eq!(converted.len(), 4);
+        for converted_chunk in &converted {
+            assert_eq!(converted_chunk.system_fingerprint.as_deref(), Some("fp_abc123"));
+            assert_eq!(converted_chunk.service_tier.as_deref(), Some("default"));
+            // the id and model from message_start replace the per-event placeholders
+            assert_eq!(converted_chunk.id, "chatcmpl-123");
+            assert_eq!(converted_chunk.model, "gpt-4o");
+        }
+
+        // the stateless conversion has nothing to carry them over from
+        let stateless: ChatCompletionsStreamResponse = MessagesStreamEvent::MessageStop.try_into().unwrap();
+        assert!(stateless.system_fingerprint.is_none());
+    }
+
+    #[test]
+    fn test_stream_usage_accumulator_counts_output_tokens() {
+        let text_chunk = |text: &str| create_openai_chunk(
+            "chatcmpl-123",
+            "gpt-4",
+            MessageDelta {
+                role: None,
+                content: Some(text.to_string()),
+                refusal: None,
+                function_call: None,
+                tool_calls: None,
+            },
+            None,
+            None,
+        );
+
+        let mut accumulator = StreamUsageAccumulator::new();
+        let mut previous_output_tokens = accumulator.output_tokens();
+        for text in ["Hello", ", how can I", " help you today?"] {
+            let events = accumulator.transform(text_chunk(text)).unwrap();
+            assert!(matches!(events[..], [MessagesStreamEvent::ContentBlockDelta { .. }]));
+
+            assert!(accumulator.output_tokens() > previous_output_tokens);
+            previous_output_tokens = accumulator.output_tokens();
+        }
+
+        let finish_chunk = create_openai_chunk(
+            "chatcmpl-123",
+            "gpt-4",
+            MessageDelta {
+                role: None,
+                content: None,
+                refusal: None,
+                function_call: None,
+                tool_calls: None,
+            },
+            Some(FinishReason::Stop),
+            None,
+        );
+
+        let events = accumulator.transform(finish_chunk).unwrap();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            MessagesStreamEvent::MessageDelta { delta, usage: Some(usage) } => {
+                assert_eq!(delta.stop_reason, MessagesStopReason::EndTurn);
+                assert_eq!(usage.output_tokens, previous_output_tokens);
+                assert!(usage.output_tokens > 0);
+            }
+            _ => panic!("Expected MessageDelta event"),
+        }
+        assert!(matches!(events[1], MessagesStreamEvent::MessageStop));
+    }
+
+    #[test]
+    fn test_openai_to_anthropic_streaming_per_choice_usage() {
+        let openai_resp: ChatCompletionsStreamResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1234567890,
+            "model": "gpt-4",
+            "choices": [
+                {
+                    "index": 0,
+                    "delta": {},
+                    "finish_reason": "stop",
+                    "usage": {"prompt_tokens": 15, "completion_tokens": 12, "total_tokens": 27}
+                },
+                {
+                    "index": 1,
+                    "delta": {},
+                    "finish_reason": "length",
+                    "usage": {"prompt_tokens": 15, "completion_tokens": 40, "total_tokens": 55}
+                }
+            ],
+            "usage": {"prompt_tokens": 15, "completion_tokens": 52, "total_tokens": 67}
+        })).unwrap();
+
+        // Each choice keeps its own token counts
+        assert_eq!(openai_resp.choices[0].usage.as_ref().unwrap().completion_tokens, 12);
+        assert_eq!(openai_resp.choices[1].usage.as_ref().unwrap().completion_tokens, 40);
+
+        let anthropic_event: MessagesStreamEvent = openai_resp.try_into().unwrap();
+
+        match anthropic_event {
+            MessagesStreamEvent::MessageDelta { delta, usage: Some(usage) } => {
+                assert_eq!(delta.stop_reason, MessagesStopReason::EndTurn);
+                assert_eq!(usage.input_tokens, 15);
+                // only the converted choice's tokens, not the total across choices
+                assert_eq!(usage.output_tokens, 12);
+            }
+            _ => panic!("Expected MessageDelta event"),
+        }
+    }
+
     #[test]
     fn test_openai_empty_choices_to_anthropic_ping() {
         let openai_resp = ChatCompletionsStreamResponse {
@@ -1650,12 +3165,12 @@ mod tests {
                     stop_reason: anthropic_reason.clone(),
                     stop_sequence: None,
                 },
-                usage: MessagesUsage {
+                usage: Some(MessagesUsage {
                     input_tokens: 10,
                     output_tokens: 20,
                     cache_creation_input_tokens: None,
                     cache_read_input_tokens: None,
-                },
+                }),
             };
 
             let openai_resp: ChatCompletionsStreamResponse = event.try_into().unwrap();
@@ -1692,6 +3207,7 @@ mod tests {
                 },
                 finish_reason: None,
                 logprobs: None,
+                usage: None,
             }],
             usage: None,
             system_fingerprint: None,
@@ -1719,4 +3235,297 @@ mod tests {
         assert_eq!(choice.delta.tool_calls, None);
         assert_eq!(choice.finish_reason, None);
     }
+
+    #[test]
+    fn test_container_survives_openai_hop() {
+        let container = MessagesContainer {
+            id: "container_011CPR5CNjB747bTd36fQLFk".to_string(),
+            container_type: "code_execution".to_string(),
+            name: "sandbox".to_string(),
+            status: "running".to_string(),
+        };
+        let anthropic_resp = MessagesResponse {
+            id: "msg_1".to_string(),
+            obj_type: "message".to_string(),
+            role: MessagesRole::Assistant,
+            content: vec![MessagesContentBlock::Text { text: "The answer is 42".to_string(), cache_control: None }],
+            model: "claude-sonnet-4-20250514".to_string(),
+            stop_reason: MessagesStopReason::EndTurn,
+            stop_sequence: None,
+            usage: MessagesUsage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            },
+            container: Some(container.clone()),
+        };
+
+        // Anthropic to Anthropic keeps it as is
+        let json = serde_json::to_value(&anthropic_resp).unwrap();
+        assert_eq!(json["container"]["type"], "code_execution");
+        let parsed: MessagesResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.container, Some(container.clone()));
+
+        // Through OpenAI it rides along in the response metadata
+        let openai_resp: ChatCompletionsResponse = anthropic_resp.try_into().unwrap();
+        let openai_json = serde_json::to_value(&openai_resp).unwrap();
+        assert_eq!(openai_json["metadata"][ANTHROPIC_CONTAINER_METADATA_KEY]["id"], container.id);
+
+        let openai_resp: ChatCompletionsResponse = serde_json::from_value(openai_json).unwrap();
+        let anthropic_resp: MessagesResponse = openai_resp.try_into().unwrap();
+        assert_eq!(anthropic_resp.container, Some(container));
+    }
+
+    #[test]
+    fn test_stop_reason_round_trip() {
+        let response = |stop_reason: MessagesStopReason| MessagesResponse {
+            id: "msg_1".to_string(),
+            obj_type: "message".to_string(),
+            role: MessagesRole::Assistant,
+            content: vec![MessagesContentBlock::Text { text: "Hi".to_string(), cache_control: None }],
+            model: "claude-3-5-sonnet".to_string(),
+            stop_reason,
+            stop_sequence: None,
+            usage: MessagesUsage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            },
+            container: None,
+        };
+
+        let cases = [
+            (MessagesStopReason::EndTurn, FinishReason::Stop, None),
+            (MessagesStopReason::MaxTokens, FinishReason::Length, None),
+            (MessagesStopReason::StopSequence, FinishReason::Stop, Some("stop_sequence")),
+            (MessagesStopReason::ToolUse, FinishReason::ToolCalls, None),
+            (MessagesStopReason::PauseTurn, FinishReason::Stop, Some("pause_turn")),
+            (MessagesStopReason::Refusal, FinishReason::ContentFilter, None),
+        ];
+
+        for (stop_reason, finish_reason, preserved) in cases {
+            let openai_resp: ChatCompletionsResponse = response(stop_reason.clone()).try_into().unwrap();
+            let choice = &openai_resp.choices[0];
+            assert_eq!(choice.finish_reason, Some(finish_reason.clone()));
+            let preserved_reason = openai_resp.metadata.as_ref()
+                .and_then(|metadata| metadata.get(ANTHROPIC_STOP_REASON_METADATA_KEY))
+                .and_then(Value::as_str);
+            assert_eq!(preserved_reason, preserved);
+
+            // Full responses round-trip exactly
+            let anthropic_resp: MessagesResponse = openai_resp.try_into().unwrap();
+            assert_eq!(anthropic_resp.stop_reason, stop_reason);
+
+            // The bare finish reason is lossy: stop_sequence and pause_turn come back as end_turn
+            let from_finish_reason: MessagesStopReason = finish_reason.into();
+            let expected = match stop_reason {
+                MessagesStopReason::StopSequence | MessagesStopReason::PauseTurn => MessagesStopReason::EndTurn,
+                other => other,
+            };
+            assert_eq!(from_finish_reason, expected);
+        }
+
+        // The legacy function_call finish reason also collapses into tool_use
+        assert_eq!(MessagesStopReason::from(FinishReason::FunctionCall), MessagesStopReason::ToolUse);
+    }
+
+    #[test]
+    fn test_consecutive_same_role_messages_are_merged() {
+        let message = |role: Role, text: &str| Message {
+            role,
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        };
+        let openai_req = ChatCompletionsRequest {
+            model: "claude-3-5-sonnet".to_string(),
+            messages: vec![
+                message(Role::System, "Be brief"),
+                message(Role::User, "Hi"),
+                message(Role::User, "What's the capital of France?"),
+                message(Role::Assistant, "Paris"),
+                message(Role::User, "And Spain?"),
+            ],
+            ..Default::default()
+        };
+
+        let anthropic_req: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+        let roles: Vec<MessagesRole> = anthropic_req.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(roles, vec![MessagesRole::User, MessagesRole::Assistant, MessagesRole::User]);
+
+        match &anthropic_req.messages[0].content {
+            MessagesMessageContent::Blocks(blocks) => {
+                let texts: Vec<&str> = blocks
+                    .iter()
+                    .map(|block| match block {
+                        MessagesContentBlock::Text { text, .. } => text.as_str(),
+                        other => panic!("unexpected block {:?}", other),
+                    })
+                    .collect();
+                assert_eq!(texts, vec!["Hi", "What's the capital of France?"]);
+            }
+            other => panic!("expected merged blocks, got {:?}", other),
+        }
+        assert!(matches!(&anthropic_req.messages[2].content, MessagesMessageContent::Single(text) if text == "And Spain?"));
+    }
+
+    #[test]
+    fn test_system_and_developer_messages_stay_in_system_prompt() {
+        let message = |role: Role, text: &str| Message {
+            role,
+            content: MessageContent::Text(text.to_string()),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        };
+        let openai_req = ChatCompletionsRequest {
+            model: "claude-3-5-sonnet".to_string(),
+            messages: vec![
+                message(Role::System, "Be brief"),
+                message(Role::User, "Hi"),
+                message(Role::Developer, "Answer in French"),
+                message(Role::Assistant, "Bonjour"),
+                message(Role::User, "Capital of Spain?"),
+            ],
+            ..Default::default()
+        };
+
+        let anthropic_req: AnthropicMessagesRequest = openai_req.try_into().unwrap();
+        assert!(matches!(
+            &anthropic_req.system,
+            Some(MessagesSystemPrompt::Single(text)) if text == "Be brief\n\nAnswer in French"
+        ));
+        let roles: Vec<MessagesRole> = anthropic_req.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(roles, vec![MessagesRole::User, MessagesRole::Assistant, MessagesRole::User]);
+        for anthropic_message in &anthropic_req.messages {
+            let text = serde_json::to_string(&anthropic_message.content).unwrap();
+            assert!(!text.contains("Be brief") && !text.contains("Answer in French"));
+        }
+
+        // Converting a system or developer message on its own is refused rather than turned
+        // into a user or assistant turn
+        for role in [Role::System, Role::Developer] {
+            assert!(matches!(MessagesRole::try_from(role.clone()), Err(TransformError::UnsupportedConversion(_))));
+            assert!(MessagesMessage::try_from(message(role, "Be brief")).is_err());
+        }
+        assert_eq!(MessagesRole::try_from(Role::Tool).unwrap(), MessagesRole::User);
+
+        // The developer role is accepted from clients as-is
+        let role: Role = serde_json::from_str("\"developer\"").unwrap();
+        assert_eq!(role, Role::Developer);
+    }
+
+    #[test]
+    fn test_top_k_round_trip() {
+        let anthropic_req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-3-5-sonnet",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "max_tokens": 1024,
+            "top_k": 40
+        }))
+        .unwrap();
+        let round_trip = |options: &ConversionOptions| {
+            let openai_req = convert_messages_request(anthropic_req.clone(), options).unwrap();
+            convert_chat_completions_request(openai_req, options).unwrap()
+        };
+
+        let options = ConversionOptions { preserve_unmapped: true, ..Default::default() };
+        let openai_req = convert_messages_request(anthropic_req.clone(), &options).unwrap();
+        assert_eq!(openai_req.metadata.unwrap()[ANTHROPIC_TOP_K_METADATA_KEY], "40");
+        assert_eq!(round_trip(&options).top_k, Some(40));
+
+        // off by default: OpenAI has no top_k, so it's lost
+        let openai_req: ChatCompletionsRequest = anthropic_req.clone().try_into().unwrap();
+        assert!(openai_req.metadata.is_none());
+        assert_eq!(round_trip(&ConversionOptions::default()).top_k, None);
+    }
+
+    #[test]
+    fn test_long_system_prompt_is_cached() {
+        let request = |system: &str| ChatCompletionsRequest {
+            model: "claude-3-5-sonnet".to_string(),
+            messages: vec![
+                Message {
+                    role: Role::System,
+                    content: MessageContent::Text(system.to_string()),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+                Message {
+                    role: Role::User,
+                    content: MessageContent::Text("Hi".to_string()),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let options = ConversionOptions { cache_system_prompt_min_chars: Some(20), ..Default::default() };
+        let long_prompt = "You are a support agent for ACME.";
+
+        let anthropic_req = convert_chat_completions_request(request(long_prompt), &options).unwrap();
+        let body = serde_json::to_value(&anthropic_req).unwrap();
+        assert_eq!(
+            body["system"],
+            json!([{"type": "text", "text": long_prompt, "cache_control": {"type": "ephemeral"}}])
+        );
+
+        // short prompts aren't worth a cache entry
+        let anthropic_req = convert_chat_completions_request(request("Be brief"), &options).unwrap();
+        assert!(matches!(anthropic_req.system, Some(MessagesSystemPrompt::Single(text)) if text == "Be brief"));
+
+        // caching is off by default
+        let anthropic_req: AnthropicMessagesRequest = request(long_prompt).try_into().unwrap();
+        assert!(matches!(anthropic_req.system, Some(MessagesSystemPrompt::Single(_))));
+
+        // block prompts are cached up to their last text block
+        let text = |text: &str| MessagesContentBlock::Text { text: text.to_string(), cache_control: None };
+        let blocks = MessagesSystemPrompt::Blocks(vec![text("You are a support agent"), text(" for ACME.")]);
+        assert_eq!(
+            serde_json::to_value(cache_system_prompt(blocks.clone(), 20)).unwrap(),
+            json!([
+                {"type": "text", "text": "You are a support agent"},
+                {"type": "text", "text": " for ACME.", "cache_control": {"type": "ephemeral"}}
+            ])
+        );
+        assert_eq!(
+            serde_json::to_value(cache_system_prompt(blocks.clone(), 100)).unwrap(),
+            serde_json::to_value(blocks).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stream_usage_accumulator_starts_message_once() {
+        let delta = |role: Option<Role>, content: Option<&str>| MessageDelta {
+            role,
+            content: content.map(str::to_string),
+            refusal: None,
+            function_call: None,
+            tool_calls: None,
+        };
+        let chunks = [
+            delta(Some(Role::Assistant), None),
+            delta(Some(Role::Assistant), None),
+            delta(Some(Role::Assistant), Some("Hello")),
+        ];
+
+        let mut accumulator = StreamUsageAccumulator::new();
+        let events: Vec<MessagesStreamEvent> = chunks
+            .into_iter()
+            .flat_map(|delta| accumulator.transform(create_openai_chunk("chatcmpl-123", "gpt-4", delta, None, None)).unwrap())
+            .collect();
+
+        // the repeated role-only delta is dropped, the repeated role with content keeps its text
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], MessagesStreamEvent::MessageStart { .. }));
+        assert!(matches!(
+            &events[1],
+            MessagesStreamEvent::ContentBlockDelta { delta: MessagesContentDelta::TextDelta { text }, .. } if text == "Hello"
+        ));
+    }
 }
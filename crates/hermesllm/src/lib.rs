@@ -7,6 +7,14 @@ pub mod clients;
 
 
 use std::fmt::Display;
+
+use http::header::{HeaderName, HeaderValue, InvalidHeaderValue, AUTHORIZATION};
+
+use crate::apis::{AnthropicApi, ApiDefinition};
+
+/// `anthropic-version` sent along with Anthropic API keys
+pub const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Provider {
     Arch,
     Mistral,
@@ -18,19 +26,68 @@ pub enum Provider {
     Github,
 }
 
+impl Provider {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "arch" => Some(Provider::Arch),
+            "mistral" => Some(Provider::Mistral),
+            "deepseek" => Some(Provider::Deepseek),
+            "groq" => Some(Provider::Groq),
+            "gemini" => Some(Provider::Gemini),
+            "openai" => Some(Provider::OpenAI),
+            "claude" => Some(Provider::Claude),
+            "github" => Some(Provider::Github),
+            _ => None,
+        }
+    }
+
+    /// Split a `provider/model` string, e.g. `claude/claude-3-5-sonnet`, into the provider and
+    /// the bare model name. Returns `None` for plain model names and unknown providers.
+    pub fn from_model(model: &str) -> Option<(Provider, &str)> {
+        let (provider, model_name) = model.split_once('/')?;
+        if model_name.is_empty() {
+            return None;
+        }
+        Provider::from_name(provider).map(|provider| (provider, model_name))
+    }
+
+    /// Whether `path` belongs to the provider's own API rather than to its OpenAI compatible one
+    pub fn is_native_api(&self, path: &str) -> bool {
+        let path = path.split('?').next().unwrap_or(path);
+        match self {
+            Provider::Claude => AnthropicApi::from_endpoint(path).is_some(),
+            Provider::Gemini => path.starts_with("/v1beta/") && !path.starts_with("/v1beta/openai/"),
+            _ => false,
+        }
+    }
+
+    /// Headers authenticating a request for `path` to the provider with the API key `key`.
+    /// Native APIs get the provider's own headers, OpenAI compatible ones a bearer token.
+    /// Fails when the key can't be sent as a header value.
+    pub fn auth_headers(&self, key: &str, path: &str) -> Result<Vec<(HeaderName, HeaderValue)>, InvalidHeaderValue> {
+        let secret = |value: String| {
+            HeaderValue::try_from(value).map(|mut value| {
+                value.set_sensitive(true);
+                value
+            })
+        };
+        if !self.is_native_api(path) {
+            return Ok(vec![(AUTHORIZATION, secret(format!("Bearer {}", key))?)]);
+        }
+        Ok(match self {
+            Provider::Claude => vec![
+                (HeaderName::from_static("x-api-key"), secret(key.to_string())?),
+                (HeaderName::from_static("anthropic-version"), HeaderValue::from_static(ANTHROPIC_API_VERSION)),
+            ],
+            Provider::Gemini => vec![(HeaderName::from_static("x-goog-api-key"), secret(key.to_string())?)],
+            _ => vec![(AUTHORIZATION, secret(format!("Bearer {}", key))?)],
+        })
+    }
+}
+
 impl From<&str> for Provider {
     fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
-            "arch" => Provider::Arch,
-            "mistral" => Provider::Mistral,
-            "deepseek" => Provider::Deepseek,
-            "groq" => Provider::Groq,
-            "gemini" => Provider::Gemini,
-            "openai" => Provider::OpenAI,
-            "claude" => Provider::Claude,
-            "github" => Provider::Github,
-            _ => panic!("Unknown provider: {}", value),
-        }
+        Provider::from_name(value).unwrap_or_else(|| panic!("Unknown provider: {}", value))
     }
 }
 
@@ -52,6 +109,52 @@ impl Display for Provider {
 #[cfg(test)]
 mod tests {
     use crate::providers::openai::types::{ChatCompletionsRequest, Message};
+    use crate::{Provider, ANTHROPIC_API_VERSION};
+
+    #[test]
+    fn test_auth_headers() {
+        let headers = |provider: Provider, path: &str| -> Vec<(String, String)> {
+            provider
+                .auth_headers("sk-123", path)
+                .unwrap()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+                .collect()
+        };
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        for provider in [
+            Provider::Arch,
+            Provider::Mistral,
+            Provider::Deepseek,
+            Provider::Groq,
+            Provider::OpenAI,
+            Provider::Github,
+        ] {
+            assert_eq!(headers(provider, "/v1/chat/completions"), vec![pair("authorization", "Bearer sk-123")]);
+        }
+        assert_eq!(
+            headers(Provider::Claude, "/v1/messages"),
+            vec![pair("x-api-key", "sk-123"), pair("anthropic-version", ANTHROPIC_API_VERSION)]
+        );
+        assert_eq!(
+            headers(Provider::Gemini, "/v1beta/models/gemini-2.0-flash:generateContent?alt=sse"),
+            vec![pair("x-goog-api-key", "sk-123")]
+        );
+
+        // their OpenAI compatible APIs take a bearer token like everyone else
+        assert_eq!(headers(Provider::Claude, "/v1/chat/completions"), vec![pair("authorization", "Bearer sk-123")]);
+        assert_eq!(
+            headers(Provider::Gemini, "/v1beta/openai/chat/completions"),
+            vec![pair("authorization", "Bearer sk-123")]
+        );
+
+        // keys are kept out of debug output, and must be valid header values
+        let (_, value) = &Provider::OpenAI.auth_headers("sk-123", "/v1/chat/completions").unwrap()[0];
+        assert!(value.is_sensitive());
+        assert!(Provider::OpenAI.auth_headers("sk-1\n23", "/v1/chat/completions").is_err());
+        assert!(Provider::Claude.auth_headers("sk-1\n23", "/v1/messages").is_err());
+    }
 
     #[test]
     fn openai_builder() {
@@ -78,4 +181,52 @@ mod tests {
         assert_eq!(request.presence_penalty, Some(0.0));
         assert_eq!(request.frequency_penalty, Some(0.0));
     }
+
+    #[test]
+    fn openai_builder_extra_fields() {
+        let request =
+            ChatCompletionsRequest::builder("qwen-qwq-32b", vec![Message::new("Hi".to_string())])
+                .extra("reasoning_format", "parsed")
+                .extra("seed", 42)
+                .build()
+                .expect("Failed to build OpenAIRequest");
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["reasoning_format"], "parsed");
+        assert_eq!(body["seed"], 42);
+        assert_eq!(body["model"], "qwen-qwq-32b");
+
+        // unknown fields survive a round trip through the request type
+        let parsed: ChatCompletionsRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(parsed.extra, request.extra);
+    }
+
+    #[test]
+    fn provider_from_model_prefix() {
+        assert_eq!(
+            Provider::from_model("claude/claude-3-5-sonnet"),
+            Some((Provider::Claude, "claude-3-5-sonnet"))
+        );
+        assert_eq!(
+            Provider::from_model("mistral/mistral-large"),
+            Some((Provider::Mistral, "mistral-large"))
+        );
+        assert_eq!(Provider::from_model("OpenAI/gpt-4o"), Some((Provider::OpenAI, "gpt-4o")));
+        // only the first slash separates the provider, the rest belongs to the model
+        assert_eq!(
+            Provider::from_model("groq/meta-llama/llama-4-scout"),
+            Some((Provider::Groq, "meta-llama/llama-4-scout"))
+        );
+
+        assert_eq!(Provider::from_model("gpt-4o"), None);
+        assert_eq!(Provider::from_model("claude-3-7-sonnet"), None);
+        assert_eq!(Provider::from_model("acme/some-model"), None);
+        assert_eq!(Provider::from_model("claude/"), None);
+    }
+
+    #[test]
+    fn openai_builder_rejects_empty_messages() {
+        let result = ChatCompletionsRequest::builder("gpt-3.5-turbo", vec![]).build();
+        assert_eq!(result.unwrap_err(), "messages must not be empty");
+    }
 }
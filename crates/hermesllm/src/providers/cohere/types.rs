@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+use std::collections::HashMap;
+
+// ============================================================================
+// CHAT API REQUEST TYPES
+// ============================================================================
+
+/// Cohere `/v1/chat` request
+///
+/// Unlike OpenAI, Cohere splits the conversation into the current user turn
+/// (`message`) and everything that came before it (`chat_history`), and carries
+/// the system prompt separately as `preamble`.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CohereChatRequest {
+    pub message: String,
+    pub model: Option<String>,
+    pub preamble: Option<String>,
+    pub chat_history: Option<Vec<CohereChatMessage>>,
+    pub stream: Option<bool>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Nucleus sampling, equivalent to OpenAI `top_p`
+    pub p: Option<f32>,
+    /// Top-k sampling
+    pub k: Option<u32>,
+    pub seed: Option<i32>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub tools: Option<Vec<CohereTool>>,
+    /// Results of tool calls requested by the model in the previous turn
+    pub tool_results: Option<Vec<CohereToolResult>>,
+}
+
+/// Speaker of a message in the Cohere chat history
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CohereRole {
+    System,
+    User,
+    Chatbot,
+    Tool,
+}
+
+/// A single entry in `chat_history`
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereChatMessage {
+    pub role: CohereRole,
+    pub message: Option<String>,
+    /// Tool calls made by the model (only present for chatbot role)
+    pub tool_calls: Option<Vec<CohereToolCall>>,
+    /// Tool outputs (only present for tool role)
+    pub tool_results: Option<Vec<CohereToolResult>>,
+}
+
+/// Tool definition for function calling
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereTool {
+    pub name: String,
+    pub description: String,
+    pub parameter_definitions: Option<HashMap<String, CohereParameterDefinition>>,
+}
+
+/// Definition of a single tool parameter
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereParameterDefinition {
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub param_type: String,
+    pub required: Option<bool>,
+}
+
+/// A tool call requested by the model
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CohereToolCall {
+    pub name: String,
+    pub parameters: Value,
+}
+
+/// The output of a tool call, paired with the call that produced it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereToolResult {
+    pub call: CohereToolCall,
+    pub outputs: Vec<Value>,
+}
+
+// ============================================================================
+// CHAT API RESPONSE TYPES
+// ============================================================================
+
+/// Reason the model stopped generating
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CohereFinishReason {
+    Complete,
+    StopSequence,
+    MaxTokens,
+    ToolCall,
+    Error,
+    ErrorToxic,
+    ErrorLimit,
+    UserCancel,
+}
+
+/// Cohere `/v1/chat` response
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereChatResponse {
+    pub text: String,
+    pub generation_id: Option<String>,
+    pub response_id: Option<String>,
+    pub finish_reason: Option<CohereFinishReason>,
+    pub tool_calls: Option<Vec<CohereToolCall>>,
+    pub chat_history: Option<Vec<CohereChatMessage>>,
+    pub meta: Option<CohereMeta>,
+}
+
+/// Response metadata, including token accounting
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereMeta {
+    pub billed_units: Option<CohereBilledUnits>,
+    pub tokens: Option<CohereTokens>,
+}
+
+/// Tokens the request was billed for
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereBilledUnits {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+}
+
+/// Tokens actually consumed by the model, including prompt template tokens
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CohereTokens {
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cohere_chat_request_serialization() {
+        let original_json = json!({
+            "message": "What about in New York?",
+            "model": "command-r-plus",
+            "preamble": "You are a helpful assistant",
+            "chat_history": [
+                {
+                    "role": "USER",
+                    "message": "What's the weather like in Tokyo?"
+                },
+                {
+                    "role": "CHATBOT",
+                    "message": "It is sunny in Tokyo."
+                }
+            ],
+            "p": 0.9
+        });
+
+        let request: CohereChatRequest = serde_json::from_value(original_json.clone()).unwrap();
+        assert_eq!(request.message, "What about in New York?");
+        assert_eq!(request.preamble, Some("You are a helpful assistant".to_string()));
+
+        let history = request.chat_history.as_ref().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].role, CohereRole::User);
+        assert_eq!(history[1].role, CohereRole::Chatbot);
+
+        let serialized_json = serde_json::to_value(&request).unwrap();
+        assert_eq!(serialized_json["message"], original_json["message"]);
+        assert_eq!(serialized_json["chat_history"], original_json["chat_history"]);
+        assert_eq!(serialized_json["preamble"], original_json["preamble"]);
+    }
+
+    #[test]
+    fn test_cohere_chat_response_deserialization() {
+        let response_json = json!({
+            "text": "",
+            "generation_id": "gen-123",
+            "finish_reason": "TOOL_CALL",
+            "tool_calls": [
+                {
+                    "name": "get_weather",
+                    "parameters": {"location": "New York"}
+                }
+            ],
+            "meta": {
+                "billed_units": {
+                    "input_tokens": 12,
+                    "output_tokens": 7
+                }
+            }
+        });
+
+        let response: CohereChatResponse = serde_json::from_value(response_json.clone()).unwrap();
+        assert_eq!(response.finish_reason, Some(CohereFinishReason::ToolCall));
+        assert_eq!(response.tool_calls.as_ref().unwrap()[0].name, "get_weather");
+
+        let billed_units = response.meta.as_ref().unwrap().billed_units.as_ref().unwrap();
+        assert_eq!(billed_units.input_tokens, Some(12));
+        assert_eq!(billed_units.output_tokens, Some(7));
+
+        let serialized_json = serde_json::to_value(&response).unwrap();
+        assert_eq!(response_json, serialized_json);
+    }
+}
@@ -1 +1,3 @@
+pub mod cohere;
+pub mod models;
 pub mod openai;
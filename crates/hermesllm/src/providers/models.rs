@@ -0,0 +1,133 @@
+//! Per-model limits. Context windows and output ceilings belong to models rather than APIs,
+//! so they are looked up by model name, e.g. to reject a request that can't fit before
+//! sending it upstream or to clamp `max_tokens` to what the target model accepts.
+
+use crate::Provider;
+
+/// Token limits of a model family.
+struct ModelLimits {
+    /// Context window, prompt and completion together
+    context_window: u32,
+    /// Output ceiling, where a provider enforces one below the context window
+    max_output_tokens: Option<u32>,
+}
+
+const fn limits(context_window: u32, max_output_tokens: Option<u32>) -> ModelLimits {
+    ModelLimits { context_window, max_output_tokens }
+}
+
+/// Limits keyed by model family. A family covers its own name and any release that extends
+/// it with a `-` suffix, so dated and suffixed releases such as `gpt-4o-2024-08-06` share
+/// their family's limits, while `gpt-4.5-preview` is not mistaken for `gpt-4`. The longest
+/// matching family wins.
+const MODEL_LIMITS: &[(&str, ModelLimits)] = &[
+    ("gpt-3.5-turbo", limits(16_385, None)),
+    ("gpt-4", limits(8_192, None)),
+    ("gpt-4-32k", limits(32_768, None)),
+    ("gpt-4-turbo", limits(128_000, None)),
+    ("gpt-4o", limits(128_000, None)),
+    ("gpt-4.1", limits(1_047_576, None)),
+    ("o1", limits(200_000, None)),
+    ("o1-mini", limits(128_000, None)),
+    ("o3", limits(200_000, None)),
+    ("o4-mini", limits(200_000, None)),
+    ("claude-2.0", limits(100_000, None)),
+    ("claude-2.1", limits(200_000, None)),
+    ("claude-instant", limits(100_000, None)),
+    ("claude-3", limits(200_000, None)),
+    ("claude-3-haiku", limits(200_000, Some(4096))),
+    ("claude-3-sonnet", limits(200_000, Some(4096))),
+    ("claude-3-opus", limits(200_000, Some(4096))),
+    ("claude-3-5-haiku", limits(200_000, Some(8192))),
+    ("claude-3-5-sonnet", limits(200_000, Some(8192))),
+    ("claude-3-7-sonnet", limits(200_000, Some(64_000))),
+    ("claude-haiku-4", limits(200_000, None)),
+    ("claude-sonnet-4", limits(200_000, Some(64_000))),
+    ("claude-opus-4", limits(200_000, Some(32_000))),
+    ("gemini-1.5-flash", limits(1_048_576, None)),
+    ("gemini-1.5-pro", limits(2_097_152, None)),
+    ("gemini-2.0-flash", limits(1_048_576, None)),
+    ("gemini-2.5", limits(1_048_576, None)),
+    ("mistral-large", limits(131_072, None)),
+    ("mistral-small", limits(32_768, None)),
+    ("deepseek-chat", limits(65_536, None)),
+    ("deepseek-reasoner", limits(65_536, None)),
+];
+
+/// The limits of `model`'s family. Accepts bare model names and `provider/model` strings.
+fn model_limits(model: &str) -> Option<&'static ModelLimits> {
+    let model = Provider::from_model(model).map_or(model, |(_, model_name)| model_name);
+    MODEL_LIMITS
+        .iter()
+        .filter(|(family, _)| {
+            model
+                .strip_prefix(family)
+                .is_some_and(|release| release.is_empty() || release.starts_with('-'))
+        })
+        .max_by_key(|(family, _)| family.len())
+        .map(|(_, limits)| limits)
+}
+
+/// The context window of `model` in tokens, prompt and completion together. Accepts bare
+/// model names and `provider/model` strings; `None` when the model isn't known.
+pub fn max_context_tokens(model: &str) -> Option<u32> {
+    model_limits(model).map(|limits| limits.context_window)
+}
+
+/// The most tokens `model` will generate in one response; `None` when the model isn't known
+/// or has no ceiling below its context window.
+pub fn max_output_tokens(model: &str) -> Option<u32> {
+    model_limits(model).and_then(|limits| limits.max_output_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_models() {
+        assert_eq!(max_context_tokens("gpt-4o"), Some(128_000));
+        assert_eq!(max_context_tokens("gpt-4o-mini-2024-07-18"), Some(128_000));
+        assert_eq!(max_context_tokens("gpt-4"), Some(8_192));
+        assert_eq!(max_context_tokens("gpt-4-32k-0613"), Some(32_768));
+        assert_eq!(max_context_tokens("gpt-4.1-nano"), Some(1_047_576));
+        assert_eq!(max_context_tokens("o1-mini"), Some(128_000));
+        assert_eq!(max_context_tokens("o1-2024-12-17"), Some(200_000));
+        assert_eq!(max_context_tokens("claude-3-5-sonnet-20241022"), Some(200_000));
+        assert_eq!(max_context_tokens("claude-2.0"), Some(100_000));
+        assert_eq!(max_context_tokens("gemini-1.5-pro-002"), Some(2_097_152));
+        assert_eq!(max_context_tokens("mistral-large-latest"), Some(131_072));
+    }
+
+    #[test]
+    fn test_provider_prefixed_models() {
+        assert_eq!(max_context_tokens("openai/gpt-4o"), Some(128_000));
+        assert_eq!(max_context_tokens("claude/claude-3-haiku-20240307"), Some(200_000));
+    }
+
+    #[test]
+    fn test_unknown_models() {
+        assert_eq!(max_context_tokens("llama-3.1-8b-instant"), None);
+        assert_eq!(max_context_tokens("my-fine-tune"), None);
+        assert_eq!(max_context_tokens("openai/"), None);
+        assert_eq!(max_context_tokens(""), None);
+    }
+
+    #[test]
+    fn test_newer_families_do_not_fall_back_to_older_ones() {
+        assert_eq!(max_context_tokens("gpt-4.5-preview"), None);
+        assert_eq!(max_context_tokens("o1x"), None);
+        assert_eq!(max_context_tokens("claude-3"), Some(200_000));
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        assert_eq!(max_output_tokens("claude-opus-4-20250514"), Some(32_000));
+        assert_eq!(max_output_tokens("claude-sonnet-4-20250514"), Some(64_000));
+        assert_eq!(max_output_tokens("claude-3-5-haiku-20241022"), Some(8192));
+        assert_eq!(max_output_tokens("claude-3-haiku-20240307"), Some(4096));
+        assert_eq!(max_output_tokens("claude/claude-3-opus-20240229"), Some(4096));
+        assert_eq!(max_output_tokens("gpt-4o"), None);
+        assert_eq!(max_output_tokens("my-fine-tune"), None);
+    }
+}
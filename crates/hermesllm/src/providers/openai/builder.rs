@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
 use serde_json::Value;
 
-use crate::providers::openai::types::{ChatCompletionsRequest, Message, StreamOptions};
+use crate::providers::openai::types::{
+    validate_messages, ChatCompletionsRequest, Message, StreamOptions,
+};
 
 #[derive(Debug, Clone)]
 pub struct OpenAIRequestBuilder {
@@ -16,6 +20,7 @@ pub struct OpenAIRequestBuilder {
     frequency_penalty: Option<f32>,
     stream_options: Option<StreamOptions>,
     tools: Option<Vec<Value>>,
+    extra: HashMap<String, Value>,
 }
 
 impl OpenAIRequestBuilder {
@@ -33,6 +38,7 @@ impl OpenAIRequestBuilder {
             frequency_penalty: None,
             stream_options: None,
             tools: None,
+            extra: HashMap::new(),
         }
     }
 
@@ -87,7 +93,16 @@ impl OpenAIRequestBuilder {
         self
     }
 
+    /// Add a provider-specific parameter the builder has no method for. It is serialized at the
+    /// top level of the request body; setting the same key again replaces the earlier value.
+    pub fn extra(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+
     pub fn build(self) -> Result<ChatCompletionsRequest, &'static str> {
+        validate_messages(&self.messages)?;
+
         let request = ChatCompletionsRequest {
             model: self.model,
             messages: self.messages,
@@ -95,13 +110,16 @@ impl OpenAIRequestBuilder {
             top_p: self.top_p,
             n: self.n,
             max_tokens: self.max_tokens,
+            max_completion_tokens: None,
             stream: self.stream,
             stop: self.stop,
             presence_penalty: self.presence_penalty,
             frequency_penalty: self.frequency_penalty,
             stream_options: self.stream_options,
             tools: self.tools,
+            store: None,
             metadata: None,
+            extra: self.extra,
         };
         Ok(request)
     }
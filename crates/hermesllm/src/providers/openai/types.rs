@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,6 +9,7 @@ use std::convert::TryFrom;
 use std::str;
 use thiserror::Error;
 
+use crate::apis::openai::{FinishReason, LogProbs};
 use crate::Provider;
 
 #[derive(Debug, Error)]
@@ -23,39 +25,111 @@ pub enum OpenAIError {
     },
     #[error("unsupported provider: {provider}")]
     UnsupportedProvider { provider: String },
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
 }
 
 type Result<T> = std::result::Result<T, OpenAIError>;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
 pub enum MultiPartContentType {
-    #[serde(rename = "text")]
     Text,
-    #[serde(rename = "image_url")]
     ImageUrl,
+    InputAudio,
+    File,
+    /// Any part type this gateway doesn't know about yet, by the name it was sent with
+    Unknown(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+impl From<String> for MultiPartContentType {
+    fn from(content_type: String) -> Self {
+        match content_type.as_str() {
+            "text" => MultiPartContentType::Text,
+            "image_url" => MultiPartContentType::ImageUrl,
+            "input_audio" => MultiPartContentType::InputAudio,
+            "file" => MultiPartContentType::File,
+            _ => MultiPartContentType::Unknown(content_type),
+        }
+    }
+}
+
+impl From<MultiPartContentType> for String {
+    fn from(content_type: MultiPartContentType) -> Self {
+        match content_type {
+            MultiPartContentType::Text => "text".to_string(),
+            MultiPartContentType::ImageUrl => "image_url".to_string(),
+            MultiPartContentType::InputAudio => "input_audio".to_string(),
+            MultiPartContentType::File => "file".to_string(),
+            MultiPartContentType::Unknown(content_type) => content_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ImageUrl {
     pub url: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InputAudio {
+    /// Base64 encoded audio
+    pub data: String,
+    /// Encoding of `data`, e.g. `wav` or `mp3`
+    pub format: String,
+}
+
+/// A file attached inline (`file_data`, base64) or by a previously uploaded `file_id`
 #[skip_serializing_none]
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileContent {
+    pub file_data: Option<String>,
+    pub file_id: Option<String>,
+    pub filename: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct MultiPartContent {
     pub text: Option<String>,
     pub image_url: Option<ImageUrl>,
+    pub input_audio: Option<InputAudio>,
+    pub file: Option<FileContent>,
     #[serde(rename = "type")]
     pub content_type: MultiPartContentType,
+    /// Any other field of the part, passed on unchanged. This is all there is to a part of an
+    /// unknown type.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum ContentType {
     Text(String),
     MultiPart(Vec<MultiPartContent>),
 }
 
+impl ContentType {
+    /// URLs (or data URLs) of the image parts, in order. Plain text content has none.
+    pub fn image_urls(&self) -> Vec<&str> {
+        match self {
+            ContentType::Text(_) => Vec::new(),
+            ContentType::MultiPart(multi_part) => multi_part
+                .iter()
+                .filter(|part| part.content_type == MultiPartContentType::ImageUrl)
+                .filter_map(|part| part.image_url.as_ref())
+                .map(|image_url| image_url.url.as_str())
+                .collect(),
+        }
+    }
+
+    /// Whether the content carries any image, i.e. needs a vision capable model
+    pub fn has_images(&self) -> bool {
+        !self.image_urls().is_empty()
+    }
+}
+
 impl Display for ContentType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -63,14 +137,15 @@ impl Display for ContentType {
             ContentType::MultiPart(multi_part) => {
                 let text_parts: Vec<String> = multi_part
                     .iter()
-                    .filter_map(|part| {
-                        if part.content_type == MultiPartContentType::Text {
-                            part.text.clone()
-                        } else if part.content_type == MultiPartContentType::ImageUrl {
-                            // skip image URLs or their data in text representation
+                    .filter_map(|part| match part.content_type {
+                        MultiPartContentType::Text => part.text.clone(),
+                        // images, audio and files have no text representation
+                        MultiPartContentType::ImageUrl
+                        | MultiPartContentType::InputAudio
+                        | MultiPartContentType::File => None,
+                        MultiPartContentType::Unknown(_) => {
+                            log::debug!("skipping unsupported content part in text representation");
                             None
-                        } else {
-                            panic!("Unsupported content type: {:?}", part.content_type);
                         }
                     })
                     .collect();
@@ -82,10 +157,14 @@ impl Display for ContentType {
 }
 
 #[skip_serializing_none]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Message {
     pub role: String,
     pub content: Option<ContentType>,
+    /// Name of the participant, e.g. to tell agents of a multi-agent conversation apart
+    pub name: Option<String>,
+    /// Tools an assistant message calls, each answered by a later `tool` message
+    pub tool_calls: Option<Vec<Value>>,
 }
 
 impl Message {
@@ -93,11 +172,13 @@ impl Message {
         Self {
             role: "user".to_string(),
             content: Some(ContentType::Text(content)),
+            name: None,
+            tool_calls: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct StreamOptions {
     pub include_usage: bool,
 }
@@ -111,15 +192,72 @@ pub struct ChatCompletionsRequest {
     pub top_p: Option<f32>,
     pub n: Option<u32>,
     pub max_tokens: Option<u32>,
+    pub max_completion_tokens: Option<u32>,
     pub stream: Option<bool>,
     pub stop: Option<Vec<String>>,
     pub presence_penalty: Option<f32>,
     pub frequency_penalty: Option<f32>,
     pub stream_options: Option<StreamOptions>,
     pub tools: Option<Vec<Value>>,
+    pub store: Option<bool>,
     pub metadata: Option<HashMap<String, Value>>,
+    /// Provider-specific parameters (e.g. Groq's `reasoning_format`) that have no field of their own
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The JSON form of a value with object keys sorted and `-0.0` folded into `0.0`, so equal
+/// values have the same canonical form regardless of map insertion order. Non-finite floats
+/// serialize as `null`, which keeps the comparison reflexive.
+fn canonical_json<T: Serialize>(value: &T) -> Value {
+    fn canonicalize(value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Value::Object(entries.into_iter().map(|(key, value)| (key, canonicalize(value))).collect())
+            }
+            Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+            Value::Number(number) if number.is_f64() && number.as_f64() == Some(0.0) => Value::from(0.0),
+            value => value,
+        }
+    }
+    canonicalize(serde_json::to_value(value).unwrap_or(Value::Null))
+}
+
+/// Hash the canonical JSON form of a value, so values that compare equal hash equally
+fn hash_canonical<T: Serialize, H: Hasher>(value: &T, state: &mut H) {
+    canonical_json(value).to_string().hash(state);
+}
+
+impl Hash for ContentType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_canonical(self, state);
+    }
 }
 
+impl Hash for Message {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_canonical(self, state);
+    }
+}
+
+impl Hash for ChatCompletionsRequest {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_canonical(self, state);
+    }
+}
+
+/// Requests are equal when their canonical JSON is, which keeps equality in line with `Hash`
+/// despite the float fields.
+impl PartialEq for ChatCompletionsRequest {
+    fn eq(&self, other: &Self) -> bool {
+        canonical_json(self) == canonical_json(other)
+    }
+}
+
+impl Eq for ChatCompletionsRequest {}
+
 impl TryFrom<&[u8]> for ChatCompletionsRequest {
     type Error = OpenAIError;
     fn try_from(bytes: &[u8]) -> Result<Self> {
@@ -127,6 +265,20 @@ impl TryFrom<&[u8]> for ChatCompletionsRequest {
     }
 }
 
+pub(crate) fn validate_messages(messages: &[Message]) -> std::result::Result<(), &'static str> {
+    match messages.last() {
+        None => Err("messages must not be empty"),
+        // tool calls in the last message have no tool results yet
+        Some(last)
+            if last.role == "assistant"
+                && last.tool_calls.as_ref().is_some_and(|tool_calls| !tool_calls.is_empty()) =>
+        {
+            Err("last message must not be an assistant tool call without a response")
+        }
+        Some(_) => Ok(()),
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ChatCompletionsResponse {
@@ -135,6 +287,8 @@ pub struct ChatCompletionsResponse {
     pub created: u64,
     pub choices: Vec<Choice>,
     pub usage: Option<Usage>,
+    pub system_fingerprint: Option<String>,
+    pub service_tier: Option<String>,
 }
 
 impl TryFrom<&[u8]> for ChatCompletionsResponse {
@@ -154,6 +308,13 @@ impl<'a> TryFrom<(&'a [u8], &'a Provider)> for ChatCompletionsResponse {
 }
 
 impl ChatCompletionsRequest {
+    /// Check that the request carries a conversation an upstream model can answer:
+    /// at least one message, and not ending on an assistant turn that only holds tool calls.
+    pub fn validate(&self) -> Result<()> {
+        validate_messages(&self.messages)
+            .map_err(|reason| OpenAIError::InvalidRequest(reason.to_string()))
+    }
+
     pub fn to_bytes(&self, provider: Provider) -> Result<Vec<u8>> {
         match provider {
             Provider::OpenAI
@@ -162,7 +323,19 @@ impl ChatCompletionsRequest {
             | Provider::Mistral
             | Provider::Groq
             | Provider::Gemini
-            | Provider::Claude => serde_json::to_vec(self).map_err(OpenAIError::from),
+            | Provider::Claude => {
+                let mut body = serde_json::to_value(self)?;
+                if let Some(body) = body.as_object_mut() {
+                    // send the limit under the one name the provider understands
+                    let max_completion_tokens = body.remove("max_completion_tokens");
+                    let max_tokens = body.remove("max_tokens");
+                    if let Some(limit) = max_completion_tokens.or(max_tokens) {
+                        let field = MaxTokensField::for_model(provider, &self.model);
+                        body.insert(field.as_str().to_string(), limit);
+                    }
+                }
+                serde_json::to_vec(&body).map_err(OpenAIError::from)
+            }
             _ => Err(OpenAIError::UnsupportedProvider {
                 provider: provider.to_string(),
             }),
@@ -170,12 +343,88 @@ impl ChatCompletionsRequest {
     }
 }
 
+/// Optional features a chat completions request uses, for usage analytics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestFeatures {
+    /// Declares tools, or legacy `functions`
+    pub tools: bool,
+    pub streaming: bool,
+    /// Sends images in any message
+    pub vision: bool,
+    /// Asks for JSON output through `response_format`
+    pub json_mode: bool,
+}
+
+/// Detect which of the [`RequestFeatures`] a request uses
+pub fn request_features(request: &ChatCompletionsRequest) -> RequestFeatures {
+    let response_format = request
+        .extra
+        .get("response_format")
+        .and_then(|format| format.get("type"))
+        .and_then(Value::as_str);
+    RequestFeatures {
+        tools: request.tools.as_ref().is_some_and(|tools| !tools.is_empty())
+            || request.extra.get("functions").and_then(Value::as_array).is_some_and(|functions| !functions.is_empty()),
+        streaming: request.stream.unwrap_or(false),
+        vision: request
+            .messages
+            .iter()
+            .any(|message| message.content.as_ref().is_some_and(ContentType::has_images)),
+        json_mode: matches!(response_format, Some("json_object" | "json_schema")),
+    }
+}
+
+/// Request field that carries the output token limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxTokensField {
+    MaxTokens,
+    /// OpenAI's replacement for `max_tokens`, required by its reasoning models
+    MaxCompletionTokens,
+}
+
+/// Model families OpenAI only accepts `max_completion_tokens` for
+const OPENAI_REASONING_MODEL_PREFIXES: [&str; 4] = ["o1", "o3", "o4", "gpt-5"];
+
+impl MaxTokensField {
+    /// The field `model` takes on `provider`. Only OpenAI's reasoning models need the new name,
+    /// everything else behind an OpenAI-compatible interface (Ollama, vLLM, ...) keeps
+    /// `max_tokens`.
+    pub fn for_model(provider: Provider, model: &str) -> Self {
+        let model = model.rsplit('/').next().unwrap_or(model);
+        let is_reasoning_model = OPENAI_REASONING_MODEL_PREFIXES.iter().any(|prefix| model.starts_with(prefix));
+        match provider {
+            Provider::OpenAI if is_reasoning_model => MaxTokensField::MaxCompletionTokens,
+            _ => MaxTokensField::MaxTokens,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaxTokensField::MaxTokens => "max_tokens",
+            MaxTokensField::MaxCompletionTokens => "max_completion_tokens",
+        }
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Choice {
     pub index: u32,
     pub message: Message,
     pub finish_reason: Option<String>,
+    pub logprobs: Option<LogProbs>,
+}
+
+impl Choice {
+    /// The finish reason as the typed `apis` value, including the legacy `function_call`.
+    /// Reasons outside the OpenAI set are `None`.
+    pub fn parsed_finish_reason(&self) -> Option<FinishReason> {
+        parse_finish_reason(self.finish_reason.as_deref()?)
+    }
+}
+
+fn parse_finish_reason(finish_reason: &str) -> Option<FinishReason> {
+    serde_json::from_value(Value::String(finish_reason.to_string())).ok()
 }
 
 #[skip_serializing_none]
@@ -184,6 +433,24 @@ pub struct Usage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
     pub total_tokens: usize,
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PromptTokensDetails {
+    pub cached_tokens: Option<usize>,
+    pub audio_tokens: Option<usize>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompletionTokensDetails {
+    pub reasoning_tokens: Option<usize>,
+    pub audio_tokens: Option<usize>,
+    pub accepted_prediction_tokens: Option<usize>,
+    pub rejected_prediction_tokens: Option<usize>,
 }
 
 #[skip_serializing_none]
@@ -198,6 +465,15 @@ pub struct StreamChoice {
     pub index: u32,
     pub delta: DeltaMessage,
     pub finish_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<LogProbs>,
+}
+
+impl StreamChoice {
+    /// See [`Choice::parsed_finish_reason`]
+    pub fn parsed_finish_reason(&self) -> Option<FinishReason> {
+        parse_finish_reason(self.finish_reason.as_deref()?)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -206,6 +482,8 @@ pub struct ChatCompletionStreamResponse {
     pub object: String,
     pub created: u64,
     pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
     pub choices: Vec<StreamChoice>,
     pub usage: Option<Usage>,
 }
@@ -304,6 +582,7 @@ pub struct Models {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_content_type_display() {
@@ -315,16 +594,405 @@ mod tests {
                 text: Some("This is a text part.".to_string()),
                 content_type: MultiPartContentType::Text,
                 image_url: None,
+                input_audio: None,
+                file: None,
+                other: Default::default(),
             },
             MultiPartContent {
                 text: Some("https://example.com/image.png".to_string()),
                 content_type: MultiPartContentType::ImageUrl,
                 image_url: None,
+                input_audio: None,
+                file: None,
+                other: Default::default(),
             },
         ]);
         assert_eq!(multi_part_content.to_string(), "This is a text part.");
     }
 
+    #[test]
+    fn test_message_name_round_trip() {
+        let body = json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "user", "name": "planner", "content": "split the task"},
+                {"role": "assistant", "name": "researcher", "content": "on it"},
+                {"role": "user", "content": "go"}
+            ]
+        });
+
+        let request: ChatCompletionsRequest =
+            ChatCompletionsRequest::try_from(body.to_string().as_bytes()).unwrap();
+        assert_eq!(request.messages[0].name.as_deref(), Some("planner"));
+        assert_eq!(request.messages[2].name, None);
+
+        let forwarded: Value =
+            serde_json::from_slice(&request.to_bytes(Provider::OpenAI).unwrap()).unwrap();
+        assert_eq!(forwarded["messages"], body["messages"]);
+    }
+
+    #[test]
+    fn test_content_type_display_unknown_part() {
+        let content = ContentType::MultiPart(vec![
+            MultiPartContent {
+                text: Some("what happens in this clip?".to_string()),
+                content_type: MultiPartContentType::Text,
+                image_url: None,
+                input_audio: None,
+                file: None,
+                other: Default::default(),
+            },
+            MultiPartContent {
+                text: None,
+                content_type: MultiPartContentType::Unknown("video_url".to_string()),
+                image_url: None,
+                input_audio: None,
+                file: None,
+                other: serde_json::Map::from_iter([(
+                    "video_url".to_string(),
+                    json!({"url": "https://example.com/clip.mp4"}),
+                )]),
+            },
+        ]);
+        assert_eq!(content.to_string(), "what happens in this clip?");
+
+        // part types added by providers later parse instead of failing the whole request
+        let parsed: ContentType = serde_json::from_value(json!([
+            {"type": "text", "text": "what happens in this clip?"},
+            {"type": "video_url", "video_url": {"url": "https://example.com/clip.mp4"}}
+        ]))
+        .unwrap();
+        assert_eq!(parsed, content);
+    }
+
+    #[test]
+    fn test_unknown_part_round_trips_through_to_bytes() {
+        let messages = json!([{
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "what happens in this clip?"},
+                {"type": "video_url", "video_url": {"url": "https://example.com/clip.mp4", "detail": "low"}}
+            ]
+        }]);
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": messages,
+        }))
+        .unwrap();
+
+        let body: Value = serde_json::from_slice(&request.to_bytes(Provider::OpenAI).unwrap()).unwrap();
+        assert_eq!(body["messages"], messages);
+    }
+
+    #[test]
+    fn test_max_tokens_field_per_provider() {
+        let body_for = |request: &ChatCompletionsRequest, provider: Provider| {
+            serde_json::from_slice::<Value>(&request.to_bytes(provider).unwrap()).unwrap()
+        };
+
+        let request = ChatCompletionsRequest {
+            model: "o3-mini".to_string(),
+            messages: vec![Message::new("hello".to_string())],
+            max_tokens: Some(256),
+            ..Default::default()
+        };
+
+        let body = body_for(&request, Provider::OpenAI);
+        assert_eq!(body["max_completion_tokens"], 256);
+        assert!(body.get("max_tokens").is_none());
+
+        let body = body_for(&request, Provider::Claude);
+        assert_eq!(body["max_tokens"], 256);
+        assert!(body.get("max_completion_tokens").is_none());
+
+        // other models behind the OpenAI interface, e.g. on Ollama or vLLM, keep max_tokens
+        for model in ["gpt-4o", "llama3.1:8b", "Qwen/Qwen2.5-7B-Instruct"] {
+            let request = ChatCompletionsRequest {
+                model: model.to_string(),
+                ..request.clone()
+            };
+            let body = body_for(&request, Provider::OpenAI);
+            assert_eq!(body["max_tokens"], 256, "{}", model);
+            assert!(body.get("max_completion_tokens").is_none(), "{}", model);
+        }
+        let request = ChatCompletionsRequest {
+            model: "openai/o1".to_string(),
+            ..request
+        };
+        assert_eq!(body_for(&request, Provider::OpenAI)["max_completion_tokens"], 256);
+
+        // a request carrying both sends one, the newer field wins
+        let request = ChatCompletionsRequest {
+            max_completion_tokens: Some(512),
+            ..request
+        };
+        let body = body_for(&request, Provider::Claude);
+        assert_eq!(body["max_tokens"], 512);
+        assert!(body.get("max_completion_tokens").is_none());
+
+        let request = ChatCompletionsRequest {
+            max_tokens: None,
+            max_completion_tokens: None,
+            ..request
+        };
+        let body = body_for(&request, Provider::OpenAI);
+        assert!(body.get("max_tokens").is_none());
+        assert!(body.get("max_completion_tokens").is_none());
+    }
+
+    #[test]
+    fn test_legacy_function_call_finish_reason() {
+        use crate::apis::anthropic::MessagesStopReason;
+
+        let response: ChatCompletionsResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "choices": [
+                {"index": 0, "message": {"role": "assistant"}, "finish_reason": "function_call"},
+                {"index": 1, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"},
+                {"index": 2, "message": {"role": "assistant", "content": "hi"}, "finish_reason": "done"},
+                {"index": 3, "message": {"role": "assistant", "content": "hi"}}
+            ]
+        }))
+        .unwrap();
+
+        let finish_reasons: Vec<Option<FinishReason>> = response
+            .choices
+            .iter()
+            .map(Choice::parsed_finish_reason)
+            .collect();
+        assert_eq!(
+            finish_reasons,
+            vec![Some(FinishReason::FunctionCall), Some(FinishReason::Stop), None, None]
+        );
+
+        // on the way to Anthropic a legacy function call is a tool use
+        let stop_reason: MessagesStopReason = FinishReason::FunctionCall.into();
+        assert_eq!(stop_reason, MessagesStopReason::ToolUse);
+    }
+
+    #[test]
+    fn test_request_features() {
+        let features = |request: Value| request_features(&serde_json::from_value(request).unwrap());
+        let image = json!({"role": "user", "content": [
+            {"type": "text", "text": "what is this?"},
+            {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+        ]});
+        let text = json!({"role": "user", "content": "hello"});
+
+        assert_eq!(features(json!({"model": "gpt-4o", "messages": [text]})), RequestFeatures::default());
+        assert_eq!(
+            features(json!({
+                "model": "gpt-4o",
+                "messages": [image, text],
+                "stream": true,
+                "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+                "response_format": {"type": "json_object"}
+            })),
+            RequestFeatures { tools: true, streaming: true, vision: true, json_mode: true }
+        );
+        assert_eq!(
+            features(json!({
+                "model": "gpt-4o",
+                "messages": [text],
+                "stream": false,
+                "functions": [{"name": "get_weather"}],
+                "response_format": {"type": "json_schema", "json_schema": {"name": "answer"}}
+            })),
+            RequestFeatures { tools: true, json_mode: true, ..Default::default() }
+        );
+        // empty tool lists and plain text output don't count
+        assert_eq!(
+            features(json!({
+                "model": "gpt-4o",
+                "messages": [image],
+                "tools": [],
+                "response_format": {"type": "text"}
+            })),
+            RequestFeatures { vision: true, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn test_response_fingerprint_and_service_tier_round_trip() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+            "system_fingerprint": "fp_44709d6fcb",
+            "service_tier": "default"
+        });
+
+        let parsed: ChatCompletionsResponse = serde_json::from_value(response.clone()).unwrap();
+        assert_eq!(parsed.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+        assert_eq!(parsed.service_tier.as_deref(), Some("default"));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), response);
+
+        // both are left out when the provider didn't send them
+        let mut without = response;
+        without.as_object_mut().unwrap().remove("system_fingerprint");
+        without.as_object_mut().unwrap().remove("service_tier");
+        let parsed: ChatCompletionsResponse = serde_json::from_value(without.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), without);
+    }
+
+    #[test]
+    fn test_logprobs_round_trip() {
+        let logprobs = json!({
+            "content": [{
+                "token": "Hi",
+                "logprob": -0.25,
+                "bytes": [72, 105],
+                "top_logprobs": [
+                    {"token": "Hi", "logprob": -0.25, "bytes": [72, 105]},
+                    {"token": "Hello", "logprob": -1.5, "bytes": null}
+                ]
+            }]
+        });
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "finish_reason": "stop",
+                "logprobs": logprobs
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        });
+
+        let parsed: ChatCompletionsResponse = serde_json::from_value(response.clone()).unwrap();
+        let token = &parsed.choices[0].logprobs.as_ref().unwrap().content.as_ref().unwrap()[0];
+        assert_eq!(token.token, "Hi");
+        assert_eq!(token.bytes, Some(vec![72, 105]));
+        assert_eq!(token.top_logprobs[1].bytes, None);
+        assert_eq!(serde_json::to_value(&parsed).unwrap()["choices"][0]["logprobs"], logprobs);
+
+        // the same shape on the apis side and in stream chunks
+        let parsed: crate::apis::openai::ChatCompletionsResponse =
+            serde_json::from_value(response).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap()["choices"][0]["logprobs"], logprobs);
+
+        let chunk: ChatCompletionStreamResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "delta": {"content": "Hi"}, "finish_reason": null, "logprobs": logprobs}]
+        }))
+        .unwrap();
+        assert_eq!(serde_json::to_value(&chunk).unwrap()["choices"][0]["logprobs"], logprobs);
+    }
+
+    #[test]
+    fn test_equivalent_requests_are_equal() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash_of = |request: &ChatCompletionsRequest| {
+            let mut hasher = DefaultHasher::new();
+            request.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut request = ChatCompletionsRequest {
+            model: "gpt-4o".to_string(),
+            messages: vec![Message::new("hello".to_string())],
+            temperature: Some(0.2),
+            ..Default::default()
+        };
+        request.extra.insert("user".to_string(), json!("u-1"));
+        request.extra.insert("seed".to_string(), json!(7));
+
+        // same content, built in a different order and through deserialization
+        let equivalent: ChatCompletionsRequest = serde_json::from_value(json!({
+            "seed": 7,
+            "messages": [{"role": "user", "content": "hello"}],
+            "user": "u-1",
+            "temperature": 0.2,
+            "model": "gpt-4o"
+        }))
+        .unwrap();
+
+        assert_eq!(request, equivalent);
+        assert_eq!(hash_of(&request), hash_of(&equivalent));
+
+        let mut different = equivalent.clone();
+        different.messages[0].content = Some(ContentType::Text("hi".to_string()));
+        assert_ne!(request, different);
+        assert_ne!(hash_of(&request), hash_of(&different));
+
+        // -0.0 and 0.0 are the same temperature
+        let zero = ChatCompletionsRequest { temperature: Some(0.0), ..request.clone() };
+        let negative_zero = ChatCompletionsRequest { temperature: Some(-0.0), ..request.clone() };
+        assert_eq!(zero, negative_zero);
+        assert_eq!(hash_of(&zero), hash_of(&negative_zero));
+
+        // equality stays reflexive for values JSON can't represent
+        let nan = ChatCompletionsRequest { temperature: Some(f32::NAN), ..request.clone() };
+        assert_eq!(nan, nan.clone());
+    }
+
+    #[test]
+    fn test_chat_completions_request_validate() {
+        let request: ChatCompletionsRequest = serde_json::from_str(
+            r#"{"model": "gpt-4o", "messages": [{"role": "user", "content": "Hello"}]}"#,
+        )
+        .unwrap();
+        assert!(request.validate().is_ok());
+
+        let request: ChatCompletionsRequest =
+            serde_json::from_str(r#"{"model": "gpt-4o", "messages": []}"#).unwrap();
+        assert!(matches!(
+            request.validate(),
+            Err(OpenAIError::InvalidRequest(_))
+        ));
+
+        let request: ChatCompletionsRequest = serde_json::from_str(
+            r#"{"model": "gpt-4o", "messages": [
+                {"role": "user", "content": "What's the weather?"},
+                {"role": "assistant", "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{}"}}]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            request.validate(),
+            Err(OpenAIError::InvalidRequest(_))
+        ));
+
+        // an assistant message may end the conversation when it calls no tools, with or
+        // without content
+        for last in [
+            r#"{"role": "assistant", "content": "It's sunny."}"#,
+            r#"{"role": "assistant", "content": null, "tool_calls": []}"#,
+        ] {
+            let request: ChatCompletionsRequest = serde_json::from_str(&format!(
+                r#"{{"model": "gpt-4o", "messages": [{{"role": "user", "content": "Hi"}}, {}]}}"#,
+                last
+            ))
+            .unwrap();
+            assert!(request.validate().is_ok(), "{}", last);
+        }
+
+        // a tool call is dangling even when the assistant also wrote some text
+        let request: ChatCompletionsRequest = serde_json::from_str(
+            r#"{"model": "gpt-4o", "messages": [
+                {"role": "user", "content": "What's the weather?"},
+                {"role": "assistant", "content": "Let me check.", "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{}"}}]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(request.validate().is_err());
+    }
+
     #[test]
     fn test_chat_completions_request_text_type_array() {
         const CHAT_COMPLETIONS_REQUEST: &str = r#"
@@ -428,6 +1096,137 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_chat_completions_request_audio_and_file_content() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "gpt-4o-audio-preview",
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "what is said in this recording?"},
+                    {"type": "input_audio", "input_audio": {"data": "UklGRiQAAABXQVZF", "format": "wav"}},
+                    {"type": "file", "file": {"file_id": "file-abc123"}},
+                    {"type": "text", "text": "and summarize the attached file"}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let content = request.messages[0].content.as_ref().unwrap();
+        let ContentType::MultiPart(parts) = content else {
+            panic!("Expected MultiPartContent");
+        };
+        assert_eq!(parts[1].content_type, MultiPartContentType::InputAudio);
+        assert_eq!(
+            parts[1].input_audio,
+            Some(InputAudio {
+                data: "UklGRiQAAABXQVZF".to_string(),
+                format: "wav".to_string(),
+            })
+        );
+        assert_eq!(parts[2].content_type, MultiPartContentType::File);
+        assert_eq!(
+            parts[2].file.as_ref().and_then(|file| file.file_id.as_deref()),
+            Some("file-abc123")
+        );
+
+        // only the text parts are displayed, nothing panics on the others
+        assert_eq!(
+            content.to_string(),
+            "what is said in this recording?\nand summarize the attached file"
+        );
+        assert!(!content.has_images());
+
+        // parts round-trip without picking up empty fields
+        assert_eq!(
+            serde_json::to_value(&parts[2]).unwrap(),
+            json!({"type": "file", "file": {"file_id": "file-abc123"}})
+        );
+    }
+
+    #[test]
+    fn test_content_type_image_urls() {
+        let content: ContentType = serde_json::from_value(json!([
+            {"type": "text", "text": "compare these two"},
+            {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+            {"type": "text", "text": "and tell me which is better"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            content.image_urls(),
+            vec!["https://example.com/a.png", "data:image/png;base64,iVBORw0KGgo="]
+        );
+        assert!(content.has_images());
+        // Display still only renders the text
+        assert_eq!(content.to_string(), "compare these two\nand tell me which is better");
+
+        let text_only: ContentType = serde_json::from_value(json!([
+            {"type": "text", "text": "no pictures here"}
+        ]))
+        .unwrap();
+        assert!(text_only.image_urls().is_empty());
+        assert!(!text_only.has_images());
+
+        assert!(!ContentType::Text("https://example.com/a.png".to_string()).has_images());
+    }
+
+    #[test]
+    fn test_chat_completions_response_usage_details_round_trip() {
+        let response_json = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "o3-mini",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "42"},
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 20,
+                "completion_tokens": 150,
+                "total_tokens": 170,
+                "prompt_tokens_details": {"cached_tokens": 16},
+                "completion_tokens_details": {"reasoning_tokens": 128, "accepted_prediction_tokens": 0}
+            }
+        });
+
+        let response: ChatCompletionsResponse = serde_json::from_value(response_json.clone()).unwrap();
+        let usage = response.usage.as_ref().unwrap();
+        assert_eq!(
+            usage.completion_tokens_details.as_ref().unwrap().reasoning_tokens,
+            Some(128)
+        );
+        assert_eq!(usage.prompt_tokens_details.as_ref().unwrap().cached_tokens, Some(16));
+
+        assert_eq!(serde_json::to_value(&response).unwrap()["usage"], response_json["usage"]);
+    }
+
+    #[test]
+    fn test_stream_chunk_system_fingerprint_round_trip() {
+        let chunk_json = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-4o",
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hi"}, "finish_reason": null}],
+            "usage": null
+        });
+
+        let chunk: ChatCompletionStreamResponse = serde_json::from_value(chunk_json.clone()).unwrap();
+        assert_eq!(chunk.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+        assert_eq!(serde_json::to_value(&chunk).unwrap(), chunk_json);
+
+        // chunks without a fingerprint don't grow one
+        let mut chunk_json = chunk_json;
+        chunk_json.as_object_mut().unwrap().remove("system_fingerprint");
+        let chunk: ChatCompletionStreamResponse = serde_json::from_value(chunk_json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&chunk).unwrap(), chunk_json);
+    }
+
     #[test]
     fn test_sse_streaming() {
         let json_data = r#"data: {"id":"chatcmpl-123","object":"chat.completion.chunk","created":1700000000,"model":"gpt-3.5-turbo","choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}
@@ -10,6 +10,7 @@ use common::ratelimit::Header;
 use common::stats::{IncrementingMetric, RecordingMetric};
 use common::tracing::{Event, Span, TraceData, Traceparent};
 use common::{ratelimit, routing, tokenizer};
+use hermesllm::apis::{ApiDefinition, OpenAIApi};
 use hermesllm::providers::openai::types::{ChatCompletionsRequest, SseChatCompletionIter};
 use hermesllm::providers::openai::types::{
     ChatCompletionsResponse, ContentType, Message, StreamOptions,
@@ -33,6 +34,7 @@ pub struct StreamContext {
     streaming_response: bool,
     response_tokens: usize,
     is_chat_completions_request: bool,
+    is_embeddings_request: bool,
     llm_providers: Rc<LlmProviders>,
     llm_provider: Option<Rc<LlmProvider>>,
     request_id: Option<String>,
@@ -62,6 +64,7 @@ impl StreamContext {
             streaming_response: false,
             response_tokens: 0,
             is_chat_completions_request: false,
+            is_embeddings_request: false,
             llm_providers,
             llm_provider: None,
             request_id: None,
@@ -106,6 +109,8 @@ impl StreamContext {
                             ":path",
                             Some("/v1beta/openai/chat/completions"),
                         );
+                    } else if path == OpenAIApi::Embeddings.endpoint() {
+                        self.set_http_request_header(":path", Some("/v1beta/openai/embeddings"));
                     }
                 }
             }
@@ -132,9 +137,23 @@ impl StreamContext {
                     ),
                 })?;
 
-        let authorization_header_value = format!("Bearer {}", llm_provider_api_key_value);
-
-        self.set_http_request_header("Authorization", Some(&authorization_header_value));
+        let provider_interface = self.llm_provider().provider_interface.to_string();
+        let path = self.get_http_request_header(":path").unwrap_or_default();
+        let auth_headers = Provider::from(provider_interface.as_str())
+            .auth_headers(llm_provider_api_key_value, &path)
+            .map_err(|err| ServerError::BadRequest {
+                why: format!(
+                    "Invalid access key configured for LLM Provider \"{}\": {}",
+                    self.llm_provider(),
+                    err
+                ),
+            })?;
+
+        // whatever credential the client sent is meant for the gateway, never the provider
+        self.set_http_request_header("Authorization", None);
+        for (name, value) in auth_headers {
+            self.set_http_request_header(name.as_str(), value.to_str().ok());
+        }
 
         Ok(())
     }
@@ -210,6 +229,7 @@ impl HttpContext for StreamContext {
         }
 
         self.is_chat_completions_request = CHAT_COMPLETIONS_PATH == request_path;
+        self.is_embeddings_request = OpenAIApi::Embeddings.endpoint() == request_path;
 
         let use_agent_orchestrator = match self.overrides.as_ref() {
             Some(overrides) => overrides.use_agent_orchestrator.unwrap_or_default(),
@@ -266,6 +286,12 @@ impl HttpContext for StreamContext {
             self.context_id, body_size, end_of_stream
         );
 
+        // embeddings go to the provider as the client sent them, only with the provider's
+        // credentials set on the headers
+        if self.is_embeddings_request {
+            return Action::Continue;
+        }
+
         // Let the client send the gateway all the data before sending to the LLM_provider.
         // TODO: consider a streaming API.
 
@@ -16,10 +16,14 @@ fn wasm_module() -> String {
 }
 
 fn request_headers_expectations(module: &mut Tester, http_context: i32) {
+    request_headers_expectations_at(module, http_context, "/v1/chat/completions");
+}
+
+fn request_headers_expectations_at(module: &mut Tester, http_context: i32, path: &str) {
     module
         .call_proxy_on_request_headers(http_context, 0, false)
         .expect_get_header_map_value(Some(MapType::HttpRequestHeaders), Some(":path"))
-        .returning(Some("/v1/chat/completions"))
+        .returning(Some(path))
         .expect_get_header_map_value(
             Some(MapType::HttpRequestHeaders),
             Some("x-arch-llm-provider"),
@@ -39,9 +43,12 @@ fn request_headers_expectations(module: &mut Tester, http_context: i32) {
             Some("x-arch-llm-provider"),
             Some("openai"),
         )
+        .expect_get_header_map_value(Some(MapType::HttpRequestHeaders), Some(":path"))
+        .returning(Some(path))
+        .expect_remove_header_map_value(Some(MapType::HttpRequestHeaders), Some("Authorization"))
         .expect_replace_header_map_value(
             Some(MapType::HttpRequestHeaders),
-            Some("Authorization"),
+            Some("authorization"),
             Some("Bearer secret_key"),
         )
         .expect_remove_header_map_value(Some(MapType::HttpRequestHeaders), Some("content-length"))
@@ -539,3 +546,43 @@ fn llm_gateway_override_use_model_name_none() {
         .execute_and_expect(ReturnType::Action(Action::Continue))
         .unwrap();
 }
+
+#[test]
+#[serial]
+fn llm_gateway_passes_embeddings_through() {
+    let args = tester::MockSettings {
+        wasm_path: wasm_module(),
+        quiet: false,
+        allow_unexpected: false,
+    };
+    let mut module = tester::mock(args).unwrap();
+
+    module
+        .call_start()
+        .execute_and_expect(ReturnType::None)
+        .unwrap();
+
+    // Setup Filter
+    let filter_context = setup_filter(&mut module, default_config());
+
+    // Setup HTTP Stream
+    let http_context = 2;
+
+    module
+        .call_proxy_on_context_create(http_context, filter_context)
+        .expect_log(Some(LogLevel::Trace), None)
+        .execute_and_expect(ReturnType::None)
+        .unwrap();
+
+    // the provider's credentials replace the client's, as for chat completions
+    request_headers_expectations_at(&mut module, http_context, "/v1/embeddings");
+
+    // the body is not a chat completions request, and is forwarded untouched
+    let embeddings_request_body = r#"{"model":"text-embedding-3-small","input":["hello","world"]}"#;
+
+    module
+        .call_proxy_on_request_body(http_context, embeddings_request_body.len() as i32, true)
+        .expect_log(Some(LogLevel::Debug), None)
+        .execute_and_expect(ReturnType::Action(Action::Continue))
+        .unwrap();
+}